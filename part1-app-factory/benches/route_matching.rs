@@ -0,0 +1,85 @@
+//! Looks up paths in a table of 300 routes and checks that matching doesn't
+//! touch the allocator.
+//!
+//! Run with `cargo bench --bench route_matching`.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+#[allow(dead_code)]
+#[path = "../src/router/tree.rs"]
+mod tree;
+
+use tree::{PathPattern, RouteTree};
+
+const ITERATIONS: usize = 1_000_000;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+struct CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn main() {
+    let mut tree = RouteTree::default();
+    let mut paths = Vec::new();
+
+    for resource in 0..100 {
+        let patterns = [
+            format!("/api/v1/resource{}", resource),
+            format!("/api/v1/resource{}/:id", resource),
+            format!("/api/v1/resource{}/:id/items/:item_id", resource),
+        ];
+        for (offset, pattern) in patterns.iter().enumerate() {
+            let inserted = tree.insert(&PathPattern::parse(pattern), resource * 3 + offset);
+            assert!(inserted.is_ok(), "Failed to insert {}", pattern);
+        }
+
+        paths.push(format!("/api/v1/resource{}", resource));
+        paths.push(format!("/api/v1/resource{}/{}", resource, resource * 7));
+        paths.push(format!(
+            "/api/v1/resource{}/{}/items/{}",
+            resource,
+            resource,
+            resource * 7
+        ));
+    }
+
+    let allocations_before = ALLOCATIONS.load(Ordering::SeqCst);
+    let start = Instant::now();
+
+    for i in 0..ITERATIONS {
+        let path = &paths[i % paths.len()];
+        let matched = tree.at(black_box(path));
+        assert!(matched.is_some(), "No route for {}", path);
+        black_box(matched);
+    }
+
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::SeqCst) - allocations_before;
+
+    println!(
+        "{} lookups in {:?} ({:?} per lookup), {} allocations",
+        ITERATIONS,
+        elapsed,
+        elapsed / ITERATIONS as u32,
+        allocations
+    );
+    assert_eq!(allocations, 0, "Route matching allocated");
+}
@@ -0,0 +1,125 @@
+//! Sends requests through a `Router` of 300 routes, half of them nested two
+//! levels deep, and reports the time and allocations per request.
+//!
+//! Run with `cargo bench --bench router_call`.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use tower::{Service, ServiceExt};
+
+use part1_app_factory::{
+    http::{Body, Request},
+    router::{get, Router},
+};
+
+const ITERATIONS: usize = 200_000;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+struct CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn routes(router: Router, resources: std::ops::Range<usize>) -> Router {
+    resources.fold(router, |router, resource| {
+        router
+            .route(&format!("/resource{}", resource), get(|| async {}))
+            .route(&format!("/resource{}/:id", resource), get(|| async {}))
+            .route(
+                &format!("/resource{}/:id/items/:item_id", resource),
+                get(|| async {}),
+            )
+    })
+}
+
+/// Calls `router` with a request for each of `paths` in turn, counting only
+/// what routing and the handlers allocate, not building the requests.
+async fn run(router: &mut Router, paths: &[String]) -> (Duration, usize) {
+    let mut elapsed = Duration::ZERO;
+    let mut allocations = 0;
+
+    for i in 0..ITERATIONS {
+        let req = Request::builder()
+            .uri(paths[i % paths.len()].as_str())
+            .body(Body::empty())
+            .unwrap();
+
+        let allocations_before = ALLOCATIONS.load(Ordering::SeqCst);
+        let start = Instant::now();
+        let router = router.ready().await.unwrap();
+        let resp = router.call(black_box(req)).await.unwrap();
+        elapsed += start.elapsed();
+        allocations += ALLOCATIONS.load(Ordering::SeqCst) - allocations_before;
+
+        assert_eq!(
+            resp.status.as_u16(),
+            200,
+            "No route for {}",
+            paths[i % paths.len()]
+        );
+        drop(resp);
+    }
+
+    (elapsed, allocations)
+}
+
+fn main() {
+    let api = routes(Router::new(), 50..100);
+    let mut router =
+        routes(Router::new(), 0..50).nest("/api/:version", Router::new().nest("/v1", api));
+
+    let mut flat = Vec::new();
+    let mut nested = Vec::new();
+    for resource in 0..50 {
+        flat.push(format!("/resource{}", resource));
+        flat.push(format!("/resource{}/{}", resource, resource * 7));
+        flat.push(format!(
+            "/resource{}/{}/items/{}",
+            resource,
+            resource,
+            resource * 7
+        ));
+
+        let resource = resource + 50;
+        nested.push(format!("/api/2/v1/resource{}", resource));
+        nested.push(format!("/api/2/v1/resource{}/{}", resource, resource * 7));
+        nested.push(format!(
+            "/api/2/v1/resource{}/{}/items/{}",
+            resource,
+            resource,
+            resource * 7
+        ));
+    }
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    for (name, paths) in [("top-level", &flat), ("nested", &nested)] {
+        let (elapsed, allocations) = runtime.block_on(run(&mut router, paths));
+        println!(
+            "{} {} requests in {:?} ({:?} per request), {:.1} allocations per request",
+            ITERATIONS,
+            name,
+            elapsed,
+            elapsed / ITERATIONS as u32,
+            allocations as f64 / ITERATIONS as f64
+        );
+    }
+}
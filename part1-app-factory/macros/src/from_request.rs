@@ -0,0 +1,149 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Data, DeriveInput, Fields, Member, Type};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Trait {
+    FromRequest,
+    FromRequestParts,
+}
+
+impl Trait {
+    fn name(self) -> &'static str {
+        match self {
+            Trait::FromRequest => "FromRequest",
+            Trait::FromRequestParts => "FromRequestParts",
+        }
+    }
+}
+
+pub(crate) fn expand(input: DeriveInput, tr: Trait) -> syn::Result<TokenStream> {
+    let ident = &input.ident;
+
+    let rejection = parse_rejection(&input)?;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields.named.iter().collect::<Vec<_>>(),
+            Fields::Unnamed(fields) => fields.unnamed.iter().collect(),
+            Fields::Unit => Vec::new(),
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                ident,
+                format!("`{}` can only be derived for structs", tr.name()),
+            ))
+        }
+    };
+    if fields.is_empty() {
+        return Err(syn::Error::new_spanned(
+            ident,
+            format!("`{}` needs at least one field to extract", tr.name()),
+        ));
+    }
+
+    let map_err = match &rejection {
+        Some(rejection) => quote! { <#rejection as ::std::convert::From<_>>::from },
+        None => quote! { ::part1_app_factory::response::IntoResponse::into_response },
+    };
+    let rejection = match rejection {
+        Some(rejection) => quote! { #rejection },
+        None => quote! { ::part1_app_factory::http::Response },
+    };
+
+    let bindings: Vec<_> = (0..fields.len())
+        .map(|index| format_ident!("__field{}", index))
+        .collect();
+    let members = fields.iter().enumerate().map(|(index, field)| match &field.ident {
+        Some(ident) => Member::Named(ident.clone()),
+        None => Member::from(index),
+    });
+
+    // Everything but the last field only sees the request head. The last
+    // field gets the body too when deriving `FromRequest`, just like the last
+    // handler argument.
+    let (head, last) = match tr {
+        Trait::FromRequest => fields.split_at(fields.len() - 1),
+        Trait::FromRequestParts => (&fields[..], &[][..]),
+    };
+    let parts = match tr {
+        Trait::FromRequest => quote! { &mut parts },
+        Trait::FromRequestParts => quote! { parts },
+    };
+    let extract_head = head.iter().zip(&bindings).map(|(field, binding)| {
+        let ty = &field.ty;
+        quote! {
+            let #binding = <#ty as ::part1_app_factory::extract::FromRequestParts>::from_request_parts(#parts)
+                .await
+                .map_err(#map_err)?;
+        }
+    });
+    // The marker of the last field's `FromRequest` impl is left to inference,
+    // which picks the only impl its type has.
+    let extract_last = last.iter().zip(bindings.last()).map(|(field, binding)| {
+        let ty = &field.ty;
+        quote! {
+            let #binding = <#ty as ::part1_app_factory::extract::FromRequest<_>>::from_request(req)
+                .await
+                .map_err(#map_err)?;
+        }
+    });
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(match tr {
+        Trait::FromRequest => quote! {
+            impl #impl_generics ::part1_app_factory::extract::FromRequest for #ident #ty_generics #where_clause {
+                type Rejection = #rejection;
+
+                fn from_request(
+                    req: ::part1_app_factory::http::Request,
+                ) -> ::part1_app_factory::extract::BoxFuture<'static, ::std::result::Result<Self, Self::Rejection>> {
+                    ::std::boxed::Box::pin(async move {
+                        let (mut parts, body) = req.into_parts();
+                        #(#extract_head)*
+                        let req = ::part1_app_factory::http::Request::from_parts(parts, body);
+                        #(#extract_last)*
+                        ::std::result::Result::Ok(Self { #(#members: #bindings,)* })
+                    })
+                }
+            }
+        },
+        Trait::FromRequestParts => quote! {
+            impl #impl_generics ::part1_app_factory::extract::FromRequestParts for #ident #ty_generics #where_clause {
+                type Rejection = #rejection;
+
+                fn from_request_parts(
+                    parts: &mut ::part1_app_factory::http::Parts,
+                ) -> ::part1_app_factory::extract::BoxFuture<'_, ::std::result::Result<Self, Self::Rejection>> {
+                    ::std::boxed::Box::pin(async move {
+                        #(#extract_head)*
+                        ::std::result::Result::Ok(Self { #(#members: #bindings,)* })
+                    })
+                }
+            }
+        },
+    })
+}
+
+/// The type named by `#[from_request(rejection(MyRejection))]`, if any.
+fn parse_rejection(input: &DeriveInput) -> syn::Result<Option<Type>> {
+    let mut rejection = None;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("from_request"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rejection") {
+                let content;
+                syn::parenthesized!(content in meta.input);
+                rejection = Some(content.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `rejection(..)`"))
+            }
+        })?;
+    }
+    Ok(rejection)
+}
@@ -0,0 +1,46 @@
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput};
+
+mod from_request;
+mod typed_path;
+
+/// Derives `router::TypedPath` and `Display` for a struct from a
+/// `#[typed_path("/users/:id")]` attribute.
+///
+/// Every `:capture` in the path must have a field of the same name and every
+/// field must be captured, so route strings and the structs handlers receive
+/// can't drift apart. Fields are parsed with `FromStr`.
+#[proc_macro_derive(TypedPath, attributes(typed_path))]
+pub fn derive_typed_path(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    typed_path::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derives `extract::FromRequest` for a struct whose fields are extractors,
+/// so a group of them can be taken as a single handler argument.
+///
+/// Like handler arguments, every field but the last must implement
+/// `FromRequestParts`; the last may consume the body. The first field that
+/// fails rejects the request. Rejections become a `Response` unless the
+/// struct names its own type with `#[from_request(rejection(MyRejection))]`,
+/// which then needs a `From` impl for each field's rejection.
+#[proc_macro_derive(FromRequest, attributes(from_request))]
+pub fn derive_from_request(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    from_request::expand(input, from_request::Trait::FromRequest)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derives `extract::FromRequestParts` for a struct whose fields all
+/// implement it. Takes the same `#[from_request(..)]` attribute as
+/// [`FromRequest`](derive@FromRequest).
+#[proc_macro_derive(FromRequestParts, attributes(from_request))]
+pub fn derive_from_request_parts(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    from_request::expand(input, from_request::Trait::FromRequestParts)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
@@ -0,0 +1,153 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Fields, LitStr};
+
+pub(crate) fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let ident = &input.ident;
+
+    let attr = input
+        .attrs
+        .iter()
+        .find(|attr| attr.path().is_ident("typed_path"))
+        .ok_or_else(|| {
+            syn::Error::new_spanned(ident, "missing `#[typed_path(\"/...\")]` attribute")
+        })?;
+    let path: LitStr = attr.parse_args()?;
+    let path_value = path.value();
+
+    if !path_value.starts_with('/') {
+        return Err(syn::Error::new_spanned(
+            &path,
+            "typed paths must start with `/`",
+        ));
+    }
+
+    let fields =
+        match &input.data {
+            Data::Struct(data) => match &data.fields {
+                Fields::Named(fields) => fields.named.iter().collect(),
+                Fields::Unit => Vec::new(),
+                Fields::Unnamed(_) => return Err(syn::Error::new_spanned(
+                    ident,
+                    "`TypedPath` can only be derived for structs with named fields or unit structs",
+                )),
+            },
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    ident,
+                    "`TypedPath` can only be derived for structs",
+                ))
+            }
+        };
+
+    let captures: Vec<&str> = path_value.split('/').filter_map(capture_name).collect();
+
+    for capture in &captures {
+        if !fields.iter().any(|field| field_name(field) == *capture) {
+            return Err(syn::Error::new_spanned(
+                &path,
+                format!("`:{}` has no matching field on `{}`", capture, ident),
+            ));
+        }
+    }
+
+    for field in &fields {
+        let name = field_name(field);
+        if !captures.contains(&name.as_str()) {
+            return Err(syn::Error::new_spanned(
+                field,
+                format!("field `{}` is not captured by `{}`", name, path_value),
+            ));
+        }
+    }
+
+    let construct = if fields.is_empty() {
+        quote! {
+            let _ = params;
+            Self
+        }
+    } else {
+        let parse_fields = fields.iter().map(|field| {
+            let field_ident = &field.ident;
+            let name = field_name(field);
+            quote! { #field_ident: params.parse(#name)? }
+        });
+        quote! { Self { #(#parse_fields,)* } }
+    };
+
+    let mut format_string = String::new();
+    let mut format_args = Vec::new();
+    for segment in path_value.split('/').filter(|segment| !segment.is_empty()) {
+        format_string.push('/');
+        match capture_name(segment) {
+            Some(capture) => {
+                let field = fields
+                    .iter()
+                    .find(|field| field_name(field) == capture)
+                    .expect("captures were checked against fields");
+                format_string.push_str("{}");
+                format_args.push(&field.ident);
+            }
+            None => format_string.push_str(&segment.replace('{', "{{").replace('}', "}}")),
+        }
+    }
+    if format_string.is_empty() || path_value.len() > 1 && path_value.ends_with('/') {
+        format_string.push('/');
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::part1_app_factory::router::TypedPath for #ident #ty_generics #where_clause {
+            const PATH: &'static str = #path;
+
+            fn from_params(
+                params: &::part1_app_factory::router::PathParams,
+            ) -> ::std::result::Result<Self, ::part1_app_factory::extract::rejection::PathRejection> {
+                ::std::result::Result::Ok({ #construct })
+            }
+        }
+
+        impl #impl_generics ::part1_app_factory::extract::FromRequestParts for #ident #ty_generics #where_clause {
+            type Rejection = ::part1_app_factory::extract::rejection::PathRejection;
+
+            fn from_request_parts(
+                parts: &mut ::part1_app_factory::http::Parts,
+            ) -> ::part1_app_factory::extract::BoxFuture<'_, ::std::result::Result<Self, Self::Rejection>> {
+                let result = match parts.extensions.get::<::part1_app_factory::router::PathParams>() {
+                    ::std::option::Option::Some(params) => {
+                        <Self as ::part1_app_factory::router::TypedPath>::from_params(params)
+                    }
+                    ::std::option::Option::None => ::std::result::Result::Err(
+                        ::part1_app_factory::extract::rejection::PathRejection::MissingPathParams,
+                    ),
+                };
+                ::std::boxed::Box::pin(async move { result })
+            }
+        }
+
+        impl #impl_generics ::std::fmt::Display for #ident #ty_generics #where_clause {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                ::std::write!(
+                    f,
+                    #format_string
+                    #(, ::part1_app_factory::http::encoding::encode_path_segment(&self.#format_args.to_string()))*
+                )
+            }
+        }
+    })
+}
+
+/// The name of a `:name` or `:name{constraint}` segment.
+fn capture_name(segment: &str) -> Option<&str> {
+    let capture = segment.strip_prefix(':')?;
+    Some(capture.split('{').next().unwrap_or(capture))
+}
+
+fn field_name(field: &syn::Field) -> String {
+    field
+        .ident
+        .as_ref()
+        .map(|ident| ident.to_string().trim_start_matches("r#").to_owned())
+        .unwrap_or_default()
+}
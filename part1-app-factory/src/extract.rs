@@ -0,0 +1,251 @@
+//! Types that can be created from requests, for use as handler arguments.
+//!
+//! Extractors that only look at the request head implement
+//! [`FromRequestParts`] and can appear anywhere in a handler's argument list.
+//! Extractors that consume the body implement [`FromRequest`] and can only be
+//! the last argument, which the [`Handler`](crate::handler::Handler) impls
+//! enforce.
+
+use std::{convert::Infallible, future::Future, pin::Pin};
+
+use crate::{
+    headers::{Accept, Header},
+    http::{
+        header::{ACCEPT, CONTENT_TYPE},
+        HeaderMap, HeaderValue, Method, Parts, Request, Version,
+    },
+    response::IntoResponse,
+    router::{OriginalUri, PathParams},
+};
+
+pub use self::{
+    authenticated::Authenticated,
+    client_disconnect::ClientDisconnect,
+    connect_info::{ConnectInfo, Connected},
+    cookie::{Cookie, CookieJar, Key, PrivateCookieJar, SignedCookieJar},
+    default_body_limit::{DefaultBodyLimit, DefaultBodyLimitService},
+    multipart::{Field, Multipart, MultipartError},
+    path::{Path, PathError},
+    query::Query,
+    state::State,
+    typed_header::TypedHeader,
+};
+pub use part1_app_factory_macros::{FromRequest, FromRequestParts};
+
+pub mod rejection;
+
+mod authenticated;
+mod body;
+mod client_disconnect;
+mod connect_info;
+mod cookie;
+mod default_body_limit;
+mod multipart;
+mod path;
+mod peer_cert;
+mod query;
+mod state;
+mod typed_header;
+
+pub(crate) use self::default_body_limit::{body_limit, buffer_body, buffer_limited};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Types that can be created from the request head.
+///
+/// When extraction fails the rejection is turned into the response and the
+/// handler isn't called, see [`rejection`]. Extract `Option<T>` or
+/// `Result<T, T::Rejection>` instead to handle failures in the handler.
+pub trait FromRequestParts: Sized {
+    type Rejection: IntoResponse;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>>;
+}
+
+/// Types that can be created from the whole request, body included.
+///
+/// Every [`FromRequestParts`] type implements this too, so the last handler
+/// argument can be either. `M` only exists to keep those two kinds of
+/// implementations apart.
+pub trait FromRequest<M = private::ViaRequest>: Sized {
+    type Rejection: IntoResponse;
+
+    fn from_request(req: Request) -> BoxFuture<'static, Result<Self, Self::Rejection>>;
+}
+
+mod private {
+    #[derive(Debug, Clone, Copy)]
+    pub enum ViaParts {}
+
+    #[derive(Debug, Clone, Copy)]
+    pub enum ViaRequest {}
+}
+
+impl<T> FromRequest<private::ViaParts> for T
+where
+    T: FromRequestParts + Send + 'static,
+{
+    type Rejection = T::Rejection;
+
+    fn from_request(req: Request) -> BoxFuture<'static, Result<Self, Self::Rejection>> {
+        let (mut parts, _) = req.into_parts();
+        Box::pin(async move { T::from_request_parts(&mut parts).await })
+    }
+}
+
+/// `None` if `T` was rejected.
+impl<T> FromRequestParts for Option<T>
+where
+    T: FromRequestParts + Send + 'static,
+{
+    type Rejection = Infallible;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>> {
+        Box::pin(async move { Ok(T::from_request_parts(parts).await.ok()) })
+    }
+}
+
+/// The rejection of `T`, for handlers that want to respond to it themselves.
+impl<T> FromRequestParts for Result<T, T::Rejection>
+where
+    T: FromRequestParts + Send + 'static,
+    T::Rejection: Send,
+{
+    type Rejection = Infallible;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>> {
+        Box::pin(async move { Ok(T::from_request_parts(parts).await) })
+    }
+}
+
+/// `None` if `T` was rejected.
+impl<T> FromRequest for Option<T>
+where
+    T: FromRequest + Send + 'static,
+{
+    type Rejection = Infallible;
+
+    fn from_request(req: Request) -> BoxFuture<'static, Result<Self, Self::Rejection>> {
+        Box::pin(async move { Ok(T::from_request(req).await.ok()) })
+    }
+}
+
+/// The rejection of `T`, for handlers that want to respond to it themselves.
+impl<T> FromRequest for Result<T, T::Rejection>
+where
+    T: FromRequest + Send + 'static,
+    T::Rejection: Send,
+{
+    type Rejection = Infallible;
+
+    fn from_request(req: Request) -> BoxFuture<'static, Result<Self, Self::Rejection>> {
+        Box::pin(async move { Ok(T::from_request(req).await) })
+    }
+}
+
+impl FromRequest for Request {
+    type Rejection = Infallible;
+
+    fn from_request(req: Request) -> BoxFuture<'static, Result<Self, Self::Rejection>> {
+        Box::pin(async move { Ok(req) })
+    }
+}
+
+impl FromRequestParts for Method {
+    type Rejection = Infallible;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>> {
+        let method = parts.method;
+        Box::pin(async move { Ok(method) })
+    }
+}
+
+impl FromRequestParts for Version {
+    type Rejection = Infallible;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>> {
+        let version = parts.version;
+        Box::pin(async move { Ok(version) })
+    }
+}
+
+/// A copy of the request headers.
+impl FromRequestParts for HeaderMap {
+    type Rejection = Infallible;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>> {
+        let headers = parts.headers.clone();
+        Box::pin(async move { Ok(headers) })
+    }
+}
+
+/// The `Accept` header. Without one, or with one where no range parses, the
+/// client accepts anything, as with [`Accept::default`].
+///
+/// Use `TypedHeader<Accept>` instead to reject such requests.
+impl FromRequestParts for Accept {
+    type Rejection = Infallible;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>> {
+        let accept = parts
+            .headers
+            .get(ACCEPT)
+            .and_then(|value| Accept::decode(value.as_str()))
+            .unwrap_or_default();
+        Box::pin(async move { Ok(accept) })
+    }
+}
+
+/// Empty when the request didn't go through a [`Router`](crate::router::Router).
+impl FromRequestParts for PathParams {
+    type Rejection = Infallible;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>> {
+        let params = parts.extensions.get::<PathParams>().cloned();
+        Box::pin(async move { Ok(params.unwrap_or_default()) })
+    }
+}
+
+/// The query string, without the leading `?`, if the request has one.
+///
+/// Use [`Query`] to deserialize it instead.
+#[derive(Clone, Debug)]
+pub struct RawQuery(pub Option<String>);
+
+impl FromRequestParts for RawQuery {
+    type Rejection = Infallible;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>> {
+        let query = parts.uri.query().map(ToOwned::to_owned);
+        Box::pin(async move { Ok(RawQuery(query)) })
+    }
+}
+
+/// The request target as the server received it, before any nesting router
+/// stripped its prefix. Outside of a [`Router`](crate::router::Router) that's
+/// simply the current target.
+impl FromRequestParts for OriginalUri {
+    type Rejection = Infallible;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>> {
+        let uri = match parts.extensions.get::<OriginalUri>() {
+            Some(uri) => uri.clone(),
+            None => OriginalUri(parts.uri.clone()),
+        };
+        Box::pin(async move { Ok(uri) })
+    }
+}
+
+/// Whether the `Content-Type` header's media type, ignoring parameters such as
+/// `charset`, passes `matches`.
+pub(crate) fn has_content_type(headers: &HeaderMap, matches: fn(&str) -> bool) -> bool {
+    let content_type = headers.get(CONTENT_TYPE).map(HeaderValue::as_str);
+
+    match content_type {
+        Some(value) => {
+            let mime = value.split(';').next().unwrap_or_default();
+            matches(mime.trim())
+        }
+        None => false,
+    }
+}
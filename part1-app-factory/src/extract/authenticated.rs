@@ -0,0 +1,42 @@
+use std::any::type_name;
+
+use super::{rejection::MissingIdentity, BoxFuture, FromRequestParts};
+use crate::http::Parts;
+
+/// Extracts the identity an auth layer such as
+/// [`BearerAuthLayer`](crate::middleware::BearerAuthLayer) found the request
+/// to be from:
+///
+/// ```
+/// # use part1_app_factory::{extract::Authenticated, router::{get, Router}};
+/// # #[derive(Clone)]
+/// # struct User {
+/// #     name: String,
+/// # }
+/// async fn profile(Authenticated(user): Authenticated<User>) -> String {
+///     format!("Signed in as {}", user.name)
+/// }
+/// # let _: Router = Router::new().route("/me", get(profile));
+/// ```
+///
+/// Routes the layer leaves out, e.g. with
+/// [`Router::route_layer`](crate::router::Router::route_layer), can take an
+/// `Option<Authenticated<User>>` instead.
+#[derive(Clone, Copy, Debug)]
+pub struct Authenticated<I>(pub I);
+
+impl<I> FromRequestParts for Authenticated<I>
+where
+    I: Clone + Send + Sync + 'static,
+{
+    type Rejection = MissingIdentity;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>> {
+        let identity = parts.extensions.get::<Authenticated<I>>().cloned();
+        Box::pin(async move {
+            identity.ok_or(MissingIdentity {
+                type_name: type_name::<I>(),
+            })
+        })
+    }
+}
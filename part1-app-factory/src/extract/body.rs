@@ -0,0 +1,155 @@
+use bytes::Bytes;
+
+use super::{
+    default_body_limit::buffer_body,
+    rejection::{FailedToBufferBody, StringRejection},
+    BoxFuture, FromRequest,
+};
+use crate::{
+    headers::{ContentType, Header},
+    http::Request,
+};
+
+/// The raw request body.
+impl FromRequest for Vec<u8> {
+    type Rejection = FailedToBufferBody;
+
+    fn from_request(req: Request) -> BoxFuture<'static, Result<Self, Self::Rejection>> {
+        Box::pin(async move { Ok(buffer_body(req).await?.to_vec()) })
+    }
+}
+
+/// The raw request body.
+impl FromRequest for Bytes {
+    type Rejection = FailedToBufferBody;
+
+    fn from_request(req: Request) -> BoxFuture<'static, Result<Self, Self::Rejection>> {
+        Box::pin(buffer_body(req))
+    }
+}
+
+/// The request body as text.
+///
+/// Bodies whose `Content-Type` names a charset other than UTF-8 (or its
+/// subset US-ASCII) are rejected rather than decoded, as are bodies that
+/// aren't valid UTF-8.
+impl FromRequest for String {
+    type Rejection = StringRejection;
+
+    fn from_request(req: Request) -> BoxFuture<'static, Result<Self, Self::Rejection>> {
+        Box::pin(async move {
+            let content_type = req
+                .headers
+                .get(ContentType::NAME)
+                .and_then(|value| ContentType::decode(value.as_str()));
+            let charset = content_type.as_ref().and_then(ContentType::charset);
+
+            if let Some(charset) = charset {
+                let utf8 = ["utf-8", "utf8", "us-ascii"]
+                    .iter()
+                    .any(|supported| charset.eq_ignore_ascii_case(supported));
+                if !utf8 {
+                    return Err(StringRejection::UnsupportedCharset(charset.to_owned()));
+                }
+            }
+
+            let body = buffer_body(req).await?;
+
+            String::from_utf8(body.to_vec()).map_err(|error| StringRejection::InvalidUtf8 {
+                valid_up_to: error.utf8_error().valid_up_to(),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        http::{Method, Request, Response},
+        response::IntoResponse,
+    };
+
+    fn request(method: Method, uri: &str, headers: &[(&str, &str)], body: &'static str) -> Request {
+        let mut req = Request::builder().method(method).uri(uri);
+        for &(name, value) in headers {
+            req = req.header(name, value);
+        }
+        req.body(body).unwrap()
+    }
+
+    /// The status and body of `resp`.
+    async fn respond(resp: Response) -> (u16, String) {
+        let body = resp.body.collect().await.unwrap();
+        (
+            resp.status.as_u16(),
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    fn text(content_type: &str, body: &'static str) -> Request {
+        request(
+            Method::Post,
+            "/notes",
+            &[("Content-Type", content_type)],
+            body,
+        )
+    }
+
+    #[tokio::test]
+    async fn extracts_the_body_as_bytes() {
+        let req = request(Method::Post, "/upload", &[], "\u{1f980} bytes");
+        let body = Vec::<u8>::from_request(req).await.unwrap();
+        assert_eq!(body, "\u{1f980} bytes".as_bytes());
+
+        let req = request(Method::Post, "/upload", &[], "bytes");
+        let body = Bytes::from_request(req).await.unwrap();
+        assert_eq!(body, Bytes::from_static(b"bytes"));
+    }
+
+    #[tokio::test]
+    async fn extracts_utf8_bodies_as_strings() {
+        for content_type in [
+            "text/plain",
+            "text/plain; charset=utf-8",
+            "text/plain; charset=\"UTF-8\"",
+            "text/plain; charset=us-ascii",
+        ] {
+            let body = String::from_request(text(content_type, "hello \u{1f980}"))
+                .await
+                .unwrap();
+            assert_eq!(body, "hello \u{1f980}", "{}", content_type);
+        }
+
+        let req = request(Method::Post, "/notes", &[], "no content type");
+        assert_eq!(String::from_request(req).await.unwrap(), "no content type");
+    }
+
+    #[tokio::test]
+    async fn rejects_other_charsets() {
+        let rejection = String::from_request(text("text/plain; charset=latin1", "caf\u{e9}"))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            respond(rejection.into_response()).await,
+            (
+                415,
+                "Unsupported charset `latin1`, expected UTF-8".to_owned()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_utf8() {
+        let mut req = text("text/plain", "");
+        req.body = b"caf\xe9".to_vec().into();
+        let rejection = String::from_request(req).await.unwrap_err();
+        assert_eq!(
+            respond(rejection.into_response()).await,
+            (
+                400,
+                "Request body is not valid UTF-8, invalid byte at offset 3".to_owned()
+            )
+        );
+    }
+}
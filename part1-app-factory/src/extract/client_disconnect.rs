@@ -0,0 +1,82 @@
+use std::convert::Infallible;
+
+use tokio::sync::watch;
+
+use super::{BoxFuture, FromRequestParts};
+use crate::http::Parts;
+
+/// Tells whether the client went away before the response was sent.
+///
+/// [`Server`](crate::server::Server) stops running a handler once its client
+/// disconnects, so work done in the handler itself is abandoned. Work handed
+/// to other tasks outlives it, and can wait for [`disconnected`] to stop too:
+///
+/// ```
+/// # use part1_app_factory::{extract::ClientDisconnect, http::StatusCode};
+/// # async fn build_report() -> String {
+/// #     String::new()
+/// # }
+/// async fn report(disconnect: ClientDisconnect) -> Result<String, StatusCode> {
+///     tokio::spawn(async move {
+///         tokio::select! {
+///             report = build_report() => Ok(report),
+///             _ = disconnect.disconnected() => Err(StatusCode::REQUEST_TIMEOUT),
+///         }
+///     })
+///     .await
+///     .unwrap_or(Err(StatusCode::INTERNAL_SERVER_ERROR))
+/// }
+/// # let _ = part1_app_factory::router::get(report);
+/// ```
+///
+/// Only connections served by the server's own HTTP/1.1 implementation report
+/// disconnects. On others, e.g. those served by hyper, [`disconnected`] never
+/// resolves.
+///
+/// [`disconnected`]: ClientDisconnect::disconnected
+#[derive(Clone, Debug, Default)]
+pub struct ClientDisconnect(Option<watch::Receiver<bool>>);
+
+impl ClientDisconnect {
+    /// A `ClientDisconnect` to attach to a request, and what reports its
+    /// client disconnecting by sending `true`.
+    pub(crate) fn channel() -> (watch::Sender<bool>, Self) {
+        let (tx, rx) = watch::channel(false);
+        (tx, ClientDisconnect(Some(rx)))
+    }
+
+    /// Resolves once the client disconnects. Never resolves if the response
+    /// is sent first.
+    pub async fn disconnected(&self) {
+        if let Some(rx) = &self.0 {
+            if rx
+                .clone()
+                .wait_for(|disconnected| *disconnected)
+                .await
+                .is_ok()
+            {
+                return;
+            }
+        }
+        std::future::pending().await
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        self.0.as_ref().is_some_and(|rx| *rx.borrow())
+    }
+}
+
+/// Never fails: a request whose connection doesn't report disconnects gets
+/// one that never disconnects.
+impl FromRequestParts for ClientDisconnect {
+    type Rejection = Infallible;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>> {
+        let disconnect = parts
+            .extensions
+            .get::<ClientDisconnect>()
+            .cloned()
+            .unwrap_or_default();
+        Box::pin(async move { Ok(disconnect) })
+    }
+}
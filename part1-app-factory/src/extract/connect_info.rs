@@ -0,0 +1,59 @@
+use std::{any::type_name, net::SocketAddr};
+
+use super::{rejection::MissingConnectInfo, BoxFuture, FromRequestParts};
+use crate::http::{ConnInfo, Parts};
+
+/// Information about the connection a request arrived on, attached to each
+/// request by the server.
+///
+/// `fakeserver` and [`Server`](crate::server::Server) attach a
+/// `ConnectInfo<ConnInfo>`:
+///
+/// ```
+/// # use part1_app_factory::{extract::ConnectInfo, http::ConnInfo, router::get};
+/// async fn whoami(ConnectInfo(conn): ConnectInfo<ConnInfo>) -> String {
+///     format!("You are on {}", conn.host_and_port)
+/// }
+/// # let _ = get(whoami);
+/// ```
+///
+/// Other types are attached by apps made with
+/// [`Router::into_make_service_with_connect_info`](crate::router::Router::into_make_service_with_connect_info).
+#[derive(Clone, Copy, Debug)]
+pub struct ConnectInfo<T>(pub T);
+
+/// What can be taken from a connection's [`ConnInfo`] and attached to its
+/// requests as a `ConnectInfo<Self>`.
+pub trait Connected: Clone + Send + Sync + 'static {
+    fn connect_info(conn: &ConnInfo) -> Self;
+}
+
+impl Connected for ConnInfo {
+    fn connect_info(conn: &ConnInfo) -> Self {
+        conn.clone()
+    }
+}
+
+/// The client's address, which connections that didn't come over a network
+/// don't have.
+impl Connected for Option<SocketAddr> {
+    fn connect_info(conn: &ConnInfo) -> Self {
+        conn.peer_addr
+    }
+}
+
+impl<T> FromRequestParts for ConnectInfo<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    type Rejection = MissingConnectInfo;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>> {
+        let conn = parts.extensions.get::<ConnectInfo<T>>().cloned();
+        Box::pin(async move {
+            conn.ok_or(MissingConnectInfo {
+                type_name: type_name::<T>(),
+            })
+        })
+    }
+}
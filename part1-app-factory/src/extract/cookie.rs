@@ -0,0 +1,436 @@
+use std::convert::Infallible;
+
+pub use cookie::{Cookie, Key};
+
+use super::{rejection::MissingState, BoxFuture, FromRequestParts, State};
+use crate::{
+    http::{
+        cookie::{parse_cookie_header, SameSite, SetCookie},
+        header::COOKIE,
+        Parts, Response,
+    },
+    response::{append_header, IntoResponse, IntoResponseParts},
+};
+
+/// Extractor for the cookies sent with a request, which also sets cookies
+/// when returned with the response.
+///
+/// ```
+/// # use part1_app_factory::{extract::{Cookie, CookieJar}, router::get};
+/// async fn login(jar: CookieJar) -> (CookieJar, &'static str) {
+///     (jar.add(Cookie::new("session", "1234")), "Logged in")
+/// }
+/// # let _ = get(login);
+/// ```
+///
+/// Only cookies added or removed since extraction are sent back, as
+/// `Set-Cookie` headers.
+#[derive(Clone, Debug, Default)]
+pub struct CookieJar {
+    jar: cookie::CookieJar,
+}
+
+impl FromRequestParts for CookieJar {
+    type Rejection = Infallible;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>> {
+        let jar = jar_from_headers(parts);
+        Box::pin(async move { Ok(CookieJar { jar }) })
+    }
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Cookie<'static>> {
+        self.jar.get(name)
+    }
+
+    /// Adds `cookie`, replacing any cookie with the same name.
+    #[must_use]
+    // Named after `cookie::CookieJar::add`, not `std::ops::Add`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(mut self, cookie: Cookie<'static>) -> Self {
+        self.jar.add(cookie);
+        self
+    }
+
+    /// Removes `cookie`, telling the client to delete it too.
+    #[must_use]
+    pub fn remove(mut self, cookie: Cookie<'static>) -> Self {
+        self.jar.remove(cookie);
+        self
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Cookie<'static>> {
+        self.jar.iter()
+    }
+}
+
+impl IntoResponseParts for CookieJar {
+    fn into_response_parts(self, resp: &mut Response) {
+        with_cookies(&self.jar, resp);
+    }
+}
+
+impl IntoResponse for CookieJar {
+    fn into_response(self) -> Response {
+        (self, ()).into_response()
+    }
+}
+
+/// A [`CookieJar`] whose cookies are signed with a [`Key`], so clients can
+/// read them but not tamper with them.
+///
+/// The key is taken from the router state, see
+/// [`Router::with_state`](crate::router::Router::with_state). Cookies whose
+/// signature doesn't check out are left out of the jar.
+#[derive(Clone)]
+pub struct SignedCookieJar {
+    jar: cookie::CookieJar,
+    key: Key,
+}
+
+impl FromRequestParts for SignedCookieJar {
+    type Rejection = MissingState;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>> {
+        Box::pin(async move {
+            let State(key) = State::<Key>::from_request_parts(parts).await?;
+            let mut jar = cookie::CookieJar::new();
+            let signed = jar_from_headers(parts);
+            for cookie in signed.iter() {
+                if let Some(cookie) = signed.signed(&key).get(cookie.name()) {
+                    jar.add_original(cookie);
+                }
+            }
+
+            Ok(SignedCookieJar { jar, key })
+        })
+    }
+}
+
+impl SignedCookieJar {
+    pub fn new(key: Key) -> Self {
+        SignedCookieJar {
+            jar: cookie::CookieJar::new(),
+            key,
+        }
+    }
+
+    /// The verified cookie named `name`.
+    pub fn get(&self, name: &str) -> Option<&Cookie<'static>> {
+        self.jar.get(name)
+    }
+
+    /// Adds `cookie`, signing it when it is sent.
+    #[must_use]
+    // Named after `cookie::CookieJar::add`, not `std::ops::Add`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(mut self, cookie: Cookie<'static>) -> Self {
+        self.jar.add(cookie);
+        self
+    }
+
+    #[must_use]
+    pub fn remove(mut self, cookie: Cookie<'static>) -> Self {
+        self.jar.remove(cookie);
+        self
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Cookie<'static>> {
+        self.jar.iter()
+    }
+
+    fn into_signed(self) -> cookie::CookieJar {
+        let mut jar = cookie::CookieJar::new();
+        // Removals are expired cookies in the delta, so they go through
+        // here too and still expire the client's copy.
+        for cookie in self.jar.delta() {
+            jar.signed_mut(&self.key).add(cookie.clone());
+        }
+        jar
+    }
+}
+
+impl IntoResponseParts for SignedCookieJar {
+    fn into_response_parts(self, resp: &mut Response) {
+        with_cookies(&self.into_signed(), resp);
+    }
+}
+
+impl IntoResponse for SignedCookieJar {
+    fn into_response(self) -> Response {
+        (self, ()).into_response()
+    }
+}
+
+/// A [`CookieJar`] whose cookies are encrypted with a [`Key`], so clients can
+/// neither read nor tamper with them.
+///
+/// The key is taken from the router state like for [`SignedCookieJar`].
+/// Cookies that fail to decrypt are left out of the jar.
+#[derive(Clone)]
+pub struct PrivateCookieJar {
+    jar: cookie::CookieJar,
+    key: Key,
+}
+
+impl FromRequestParts for PrivateCookieJar {
+    type Rejection = MissingState;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>> {
+        Box::pin(async move {
+            let State(key) = State::<Key>::from_request_parts(parts).await?;
+            let mut jar = cookie::CookieJar::new();
+            let encrypted = jar_from_headers(parts);
+            for cookie in encrypted.iter() {
+                if let Some(cookie) = encrypted.private(&key).get(cookie.name()) {
+                    jar.add_original(cookie);
+                }
+            }
+
+            Ok(PrivateCookieJar { jar, key })
+        })
+    }
+}
+
+impl PrivateCookieJar {
+    pub fn new(key: Key) -> Self {
+        PrivateCookieJar {
+            jar: cookie::CookieJar::new(),
+            key,
+        }
+    }
+
+    /// The decrypted cookie named `name`.
+    pub fn get(&self, name: &str) -> Option<&Cookie<'static>> {
+        self.jar.get(name)
+    }
+
+    /// Adds `cookie`, encrypting it when it is sent.
+    #[must_use]
+    // Named after `cookie::CookieJar::add`, not `std::ops::Add`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(mut self, cookie: Cookie<'static>) -> Self {
+        self.jar.add(cookie);
+        self
+    }
+
+    #[must_use]
+    pub fn remove(mut self, cookie: Cookie<'static>) -> Self {
+        self.jar.remove(cookie);
+        self
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Cookie<'static>> {
+        self.jar.iter()
+    }
+
+    fn into_encrypted(self) -> cookie::CookieJar {
+        let mut jar = cookie::CookieJar::new();
+        // Removals are handled like in `into_signed`.
+        for cookie in self.jar.delta() {
+            jar.private_mut(&self.key).add(cookie.clone());
+        }
+        jar
+    }
+}
+
+impl IntoResponseParts for PrivateCookieJar {
+    fn into_response_parts(self, resp: &mut Response) {
+        with_cookies(&self.into_encrypted(), resp);
+    }
+}
+
+impl IntoResponse for PrivateCookieJar {
+    fn into_response(self) -> Response {
+        (self, ()).into_response()
+    }
+}
+
+fn jar_from_headers(parts: &Parts) -> cookie::CookieJar {
+    let mut jar = cookie::CookieJar::new();
+    for value in parts.headers.get_all(COOKIE) {
+        for cookie in parse_cookie_header(value.as_str()) {
+            jar.add_original(Cookie::new(
+                cookie.name().to_owned(),
+                cookie.value().to_owned(),
+            ));
+        }
+    }
+    jar
+}
+
+/// Adds a `Set-Cookie` header for every change made to `jar`, next to any
+/// the response already sets.
+fn with_cookies(jar: &cookie::CookieJar, resp: &mut Response) {
+    for cookie in jar.delta() {
+        append_header(resp, "Set-Cookie", &set_cookie(cookie).to_string());
+    }
+}
+
+fn set_cookie(cookie: &Cookie<'_>) -> SetCookie {
+    let mut set_cookie = SetCookie::new(cookie.name(), cookie.value())
+        .with_secure(cookie.secure().unwrap_or(false))
+        .with_http_only(cookie.http_only().unwrap_or(false));
+    if let Some(path) = cookie.path() {
+        set_cookie = set_cookie.with_path(path);
+    }
+    if let Some(domain) = cookie.domain() {
+        set_cookie = set_cookie.with_domain(domain);
+    }
+    if let Some(expires) = cookie.expires_datetime() {
+        set_cookie = set_cookie.with_expires(expires.into());
+    }
+    if let Some(max_age) = cookie.max_age() {
+        // Negative ages mean the cookie has expired, like zero.
+        set_cookie = set_cookie.with_max_age(max_age.try_into().unwrap_or_default());
+    }
+    if let Some(same_site) = cookie.same_site() {
+        set_cookie = set_cookie.with_same_site(match same_site {
+            cookie::SameSite::Strict => SameSite::Strict,
+            cookie::SameSite::Lax => SameSite::Lax,
+            cookie::SameSite::None => SameSite::None,
+        });
+    }
+    set_cookie
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{Method, Request};
+
+    fn request(method: Method, uri: &str, headers: &[(&str, &str)], body: &'static str) -> Request {
+        let mut req = Request::builder().method(method).uri(uri);
+        for &(name, value) in headers {
+            req = req.header(name, value);
+        }
+        req.body(body).unwrap()
+    }
+
+    /// The status and body of `resp`.
+    async fn respond(resp: Response) -> (u16, String) {
+        let body = resp.body.collect().await.unwrap();
+        (
+            resp.status.as_u16(),
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    /// The `Set-Cookie` headers of `resp`.
+    fn set_cookies(resp: &Response) -> Vec<String> {
+        resp.headers
+            .get_all("set-cookie")
+            .map(|value| value.as_str().to_owned())
+            .collect()
+    }
+
+    /// The parts of a request sending `cookies`, with `key` as its state.
+    fn parts(cookies: &str, key: &Key) -> Parts {
+        let (mut parts, _) = request(Method::Get, "/", &[("Cookie", cookies)], "").into_parts();
+        parts.extensions.insert(State(key.clone()));
+        parts
+    }
+
+    /// The `name=value` pairs of the cookies `resp` sets.
+    fn pairs(resp: &Response) -> Vec<String> {
+        set_cookies(resp)
+            .iter()
+            .map(|set_cookie| set_cookie.split(';').next().unwrap().to_owned())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn reads_and_sets_cookies() {
+        let key = Key::generate();
+        let mut parts = parts("theme=dark; lang=en", &key);
+        let jar = CookieJar::from_request_parts(&mut parts).await.unwrap();
+        assert_eq!(jar.get("theme").map(Cookie::value), Some("dark"));
+        assert_eq!(jar.get("lang").map(Cookie::value), Some("en"));
+        assert!(jar.get("session").is_none());
+
+        // Only what changed is sent back.
+        let jar = jar
+            .add(Cookie::new("theme", "light"))
+            .remove(Cookie::named("lang"));
+        let resp = (jar, "saved").into_response();
+        let sent = set_cookies(&resp);
+        assert_eq!(sent.len(), 2, "{:?}", sent);
+        assert!(sent.contains(&"theme=light".to_owned()));
+        let lang = sent.iter().find(|c| c.starts_with("lang=;")).unwrap();
+        assert!(lang.contains("Max-Age=0"), "{}", lang);
+        assert_eq!(respond(resp).await, (200, "saved".to_owned()));
+
+        let resp = CookieJar::new().into_response();
+        assert!(set_cookies(&resp).is_empty());
+    }
+
+    #[tokio::test]
+    async fn signs_cookies() {
+        let key = Key::generate();
+        let resp = SignedCookieJar::new(key.clone())
+            .add(Cookie::new("user", "ferris"))
+            .into_response();
+        let signed = pairs(&resp).remove(0);
+        assert_ne!(signed, "user=ferris");
+        assert!(
+            signed.ends_with("ferris"),
+            "the value is readable: {}",
+            signed
+        );
+
+        let jar = SignedCookieJar::from_request_parts(&mut parts(&signed, &key))
+            .await
+            .unwrap();
+        assert_eq!(jar.get("user").map(Cookie::value), Some("ferris"));
+
+        // Tampered values and other keys' signatures are dropped.
+        let tampered = signed.replace("ferris", "admin");
+        for (cookies, key) in [(&tampered, &key), (&signed, &Key::generate())] {
+            let jar = SignedCookieJar::from_request_parts(&mut parts(cookies, key))
+                .await
+                .unwrap();
+            assert!(jar.get("user").is_none(), "{}", cookies);
+        }
+    }
+
+    #[tokio::test]
+    async fn encrypts_cookies() {
+        let key = Key::generate();
+        let resp = PrivateCookieJar::new(key.clone())
+            .add(Cookie::new("user", "ferris"))
+            .into_response();
+        let encrypted = pairs(&resp).remove(0);
+        assert!(!encrypted.contains("ferris"), "{}", encrypted);
+
+        let jar = PrivateCookieJar::from_request_parts(&mut parts(&encrypted, &key))
+            .await
+            .unwrap();
+        assert_eq!(jar.get("user").map(Cookie::value), Some("ferris"));
+
+        let jar = PrivateCookieJar::from_request_parts(&mut parts(&encrypted, &Key::generate()))
+            .await
+            .unwrap();
+        assert!(jar.get("user").is_none());
+    }
+
+    #[tokio::test]
+    async fn needs_a_key_to_sign_or_encrypt() {
+        let (mut parts, _) =
+            request(Method::Get, "/", &[("Cookie", "user=ferris")], "").into_parts();
+        let rejection = SignedCookieJar::from_request_parts(&mut parts)
+            .await
+            .err()
+            .unwrap();
+        let (status, body) = respond(rejection.into_response()).await;
+        assert_eq!(status, 500);
+        assert!(body.starts_with("Missing state of type `"), "{}", body);
+        assert!(PrivateCookieJar::from_request_parts(&mut parts)
+            .await
+            .is_err());
+    }
+}
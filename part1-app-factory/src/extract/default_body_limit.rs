@@ -0,0 +1,247 @@
+use std::task::{Context, Poll};
+
+use bytes::{Bytes, BytesMut};
+use futures_util::StreamExt;
+use tower::{Layer, Service};
+
+use super::rejection::{FailedToBufferBody, LengthLimitError};
+use crate::http::{Body, Request};
+
+/// Bodies larger than this are rejected unless a [`DefaultBodyLimit`] says
+/// otherwise.
+const DEFAULT_LIMIT: usize = 2 * 1024 * 1024;
+
+/// Layer that sets how large a body the body extractors accept.
+///
+/// [`Json`](crate::json::Json), [`Form`](crate::form::Form), `Vec<u8>`,
+/// `Bytes`, `String` and [`Multipart`](super::Multipart) reject bodies over
+/// the limit with a 413, reading no more of a streamed body than the limit.
+/// Without this layer the limit is 2 MiB.
+///
+/// The innermost layer wins, so a limit set on one route overrides the one
+/// set for the whole router:
+///
+/// ```
+/// # use part1_app_factory::{extract::DefaultBodyLimit, router::{get, post, Router}};
+/// # async fn index() {}
+/// # async fn upload(_body: Vec<u8>) {}
+/// let app = Router::new()
+///     .route("/", get(index))
+///     .route("/upload", post(upload).layer(DefaultBodyLimit::max(64 * 1024 * 1024)))
+///     .route_layer(DefaultBodyLimit::max(16 * 1024));
+/// # let _: Router = app;
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct DefaultBodyLimit {
+    kind: DefaultBodyLimitKind,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum DefaultBodyLimitKind {
+    Disable,
+    Limit(usize),
+}
+
+impl DefaultBodyLimit {
+    /// Accepts bodies of up to `limit` bytes.
+    pub fn max(limit: usize) -> Self {
+        DefaultBodyLimit {
+            kind: DefaultBodyLimitKind::Limit(limit),
+        }
+    }
+
+    /// Accepts bodies of any size, e.g. for routes that do their own checks.
+    pub fn disable() -> Self {
+        DefaultBodyLimit {
+            kind: DefaultBodyLimitKind::Disable,
+        }
+    }
+}
+
+impl<S> Layer<S> for DefaultBodyLimit {
+    type Service = DefaultBodyLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        DefaultBodyLimitService {
+            inner,
+            kind: self.kind,
+        }
+    }
+}
+
+/// Service created by [`DefaultBodyLimit`].
+#[derive(Clone, Debug)]
+pub struct DefaultBodyLimitService<S> {
+    inner: S,
+    kind: DefaultBodyLimitKind,
+}
+
+impl<S> Service<Request> for DefaultBodyLimitService<S>
+where
+    S: Service<Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        req.extensions.insert(self.kind);
+        self.inner.call(req)
+    }
+}
+
+/// Reads the body of `req`, rejecting it once it's over the limit in effect
+/// for it.
+pub(crate) async fn buffer_body(req: Request) -> Result<Bytes, FailedToBufferBody> {
+    let limit = body_limit(&req);
+    buffer_limited(req.body, limit).await
+}
+
+/// The limit in effect for the body of `req`, if there's one.
+pub(crate) fn body_limit(req: &Request) -> Option<usize> {
+    match req.extensions.get::<DefaultBodyLimitKind>() {
+        Some(DefaultBodyLimitKind::Disable) => None,
+        Some(DefaultBodyLimitKind::Limit(limit)) => Some(*limit),
+        None => Some(DEFAULT_LIMIT),
+    }
+}
+
+/// Reads `body`, rejecting it once it's over `limit`.
+pub(crate) async fn buffer_limited(
+    mut body: Body,
+    limit: Option<usize>,
+) -> Result<Bytes, FailedToBufferBody> {
+    let limit = match limit {
+        Some(limit) => limit,
+        None => return body.collect().await.map_err(stream_error),
+    };
+
+    // Bodies of a known size are checked without reading them.
+    let size_hint = body.size_hint();
+    if size_hint.lower() > limit as u64 {
+        return Err(LengthLimitError { limit }.into());
+    }
+    if size_hint.upper().is_some_and(|upper| upper <= limit as u64) {
+        return body.collect().await.map_err(stream_error);
+    }
+
+    let mut buf = BytesMut::new();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(stream_error)?;
+        if buf.len() + chunk.len() > limit {
+            return Err(LengthLimitError { limit }.into());
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(buf.freeze())
+}
+
+/// The error of a body's stream, unless it's a [`LengthLimitError`] of its
+/// own, as the bodies a [`DecompressionLayer`] decompresses end with.
+///
+/// [`DecompressionLayer`]: crate::middleware::DecompressionLayer
+fn stream_error(error: anyhow::Error) -> FailedToBufferBody {
+    match error.downcast::<LengthLimitError>() {
+        Ok(error) => error.into(),
+        Err(error) => FailedToBufferBody::Stream(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use bytes::Bytes;
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+    use crate::{
+        extract::FromRequest,
+        http::{Method, Response},
+        response::IntoResponse,
+    };
+
+    fn request(method: Method, uri: &str, headers: &[(&str, &str)], body: &'static str) -> Request {
+        let mut req = Request::builder().method(method).uri(uri);
+        for &(name, value) in headers {
+            req = req.header(name, value);
+        }
+        req.body(body).unwrap()
+    }
+
+    /// The status and body of `resp`.
+    async fn respond(resp: Response) -> (u16, String) {
+        let body = resp.body.collect().await.unwrap();
+        (
+            resp.status.as_u16(),
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    /// A request with a body of `len` bytes.
+    fn sized(len: usize) -> Request {
+        let mut req = request(Method::Post, "/upload", &[], "");
+        req.body = vec![b'x'; len].into();
+        req
+    }
+
+    /// Buffers the body of `req`, answering with its size.
+    async fn buffer(req: Request) -> Result<Response, Error> {
+        Ok(match Bytes::from_request(req).await {
+            Ok(body) => format!("{} bytes", body.len()).into_response(),
+            Err(rejection) => rejection.into_response(),
+        })
+    }
+
+    async fn send(layer: Option<DefaultBodyLimit>, req: Request) -> (u16, String) {
+        let resp = match layer {
+            Some(layer) => layer.layer(service_fn(buffer)).oneshot(req).await,
+            None => buffer(req).await,
+        };
+        respond(resp.unwrap()).await
+    }
+
+    #[tokio::test]
+    async fn limits_bodies_to_two_mebibytes_by_default() {
+        assert_eq!(
+            send(None, sized(DEFAULT_LIMIT)).await,
+            (200, format!("{} bytes", DEFAULT_LIMIT))
+        );
+        assert_eq!(
+            send(None, sized(DEFAULT_LIMIT + 1)).await,
+            (
+                413,
+                "Request body is larger than the 2097152 byte limit".to_owned()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn sets_the_limit() {
+        let layer = Some(DefaultBodyLimit::max(5));
+        assert_eq!(send(layer, sized(5)).await, (200, "5 bytes".to_owned()));
+        assert_eq!(
+            send(layer, sized(6)).await,
+            (
+                413,
+                "Request body is larger than the 5 byte limit".to_owned()
+            )
+        );
+
+        // Raising it goes past the default.
+        let layer = Some(DefaultBodyLimit::max(DEFAULT_LIMIT * 2));
+        assert_eq!(send(layer, sized(DEFAULT_LIMIT + 1)).await.0, 200);
+    }
+
+    #[tokio::test]
+    async fn disables_the_limit() {
+        let layer = Some(DefaultBodyLimit::disable());
+        assert_eq!(
+            send(layer, sized(DEFAULT_LIMIT + 1)).await,
+            (200, format!("{} bytes", DEFAULT_LIMIT + 1))
+        );
+    }
+}
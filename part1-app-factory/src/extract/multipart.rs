@@ -0,0 +1,417 @@
+use std::fmt;
+
+use bytes::Bytes;
+
+use super::{buffer_body, rejection::MultipartRejection, BoxFuture, FromRequest};
+use crate::{
+    headers::{ContentType, Header},
+    http::{header::CONTENT_DISPOSITION, HeaderMap, HeaderValue, Request, Response, StatusCode},
+    response::IntoResponse,
+};
+
+/// Parts larger than this are rejected unless
+/// [`Multipart::set_part_limit`] says otherwise.
+const DEFAULT_PART_LIMIT: usize = 2 * 1024 * 1024;
+
+/// Extractor for `multipart/form-data` bodies, as sent by HTML forms with
+/// file inputs.
+///
+/// Parts are parsed one at a time as [`Multipart::next_field`] is called. It
+/// consumes the body, so it must be the last handler argument.
+///
+/// ```
+/// # use part1_app_factory::{extract::{Multipart, MultipartError}, router::post};
+/// async fn upload(mut multipart: Multipart) -> Result<String, MultipartError> {
+///     let mut names = Vec::new();
+///     while let Some(field) = multipart.next_field().await? {
+///         names.push(field.file_name().unwrap_or("-").to_owned());
+///     }
+///     Ok(names.join(", "))
+/// }
+/// # let _ = post(upload);
+/// ```
+#[derive(Debug)]
+pub struct Multipart {
+    body: Bytes,
+    delimiter: Vec<u8>,
+    pos: usize,
+    part_limit: usize,
+    done: bool,
+}
+
+impl FromRequest for Multipart {
+    type Rejection = MultipartRejection;
+
+    fn from_request(req: Request) -> BoxFuture<'static, Result<Self, Self::Rejection>> {
+        Box::pin(async move {
+            let content_type = req
+                .headers
+                .get(ContentType::NAME)
+                .and_then(|value| ContentType::decode(value.as_str()))
+                .ok_or(MultipartRejection::InvalidContentType)?;
+
+            if !content_type
+                .mime()
+                .eq_ignore_ascii_case("multipart/form-data")
+            {
+                return Err(MultipartRejection::InvalidContentType);
+            }
+
+            let boundary = content_type
+                .param("boundary")
+                .filter(|boundary| !boundary.is_empty())
+                .ok_or(MultipartRejection::MissingBoundary)?;
+            let delimiter = format!("--{}", boundary).into_bytes();
+
+            Ok(Multipart {
+                body: buffer_body(req).await?,
+                delimiter,
+                pos: 0,
+                part_limit: DEFAULT_PART_LIMIT,
+                done: false,
+            })
+        })
+    }
+}
+
+impl Multipart {
+    /// Sets the largest part, in bytes, that [`Multipart::next_field`]
+    /// accepts. Defaults to 2 MiB.
+    pub fn set_part_limit(&mut self, limit: usize) {
+        self.part_limit = limit;
+    }
+
+    /// Parses the next part, returning `None` after the last one.
+    pub async fn next_field(&mut self) -> Result<Option<Field>, MultipartError> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let result = self.parse_field();
+        if !matches!(result, Ok(Some(_))) {
+            self.done = true;
+        }
+        result
+    }
+
+    fn parse_field(&mut self) -> Result<Option<Field>, MultipartError> {
+        // The first delimiter may be preceded by a preamble, later ones start
+        // right where the previous part ended.
+        if self.pos == 0 {
+            let start = find(&self.body, &self.delimiter, 0)
+                .ok_or(MultipartError::Malformed("missing opening boundary"))?;
+            self.pos = start + self.delimiter.len();
+        }
+
+        let rest = &self.body[self.pos..];
+        if rest.starts_with(b"--") {
+            return Ok(None);
+        }
+        if !rest.starts_with(b"\r\n") {
+            return Err(MultipartError::Malformed(
+                "boundary not followed by a line break",
+            ));
+        }
+
+        let headers_start = self.pos + 2;
+        let headers_end = find(&self.body, b"\r\n\r\n", headers_start)
+            .ok_or(MultipartError::Malformed("unterminated part headers"))?;
+        let headers = parse_headers(&self.body[headers_start..headers_end])?;
+
+        let data_start = headers_end + 4;
+        let mut closing = b"\r\n".to_vec();
+        closing.extend_from_slice(&self.delimiter);
+        let data_end = find(&self.body, &closing, data_start)
+            .ok_or(MultipartError::Malformed("missing closing boundary"))?;
+
+        let disposition = headers
+            .get(CONTENT_DISPOSITION)
+            .map(HeaderValue::as_str)
+            .unwrap_or_default();
+        let name = disposition_param(disposition, "name");
+
+        if data_end - data_start > self.part_limit {
+            return Err(MultipartError::PartTooLarge {
+                name,
+                limit: self.part_limit,
+            });
+        }
+
+        self.pos = data_end + closing.len();
+
+        Ok(Some(Field {
+            name,
+            file_name: disposition_param(disposition, "filename"),
+            data: self.body.slice(data_start..data_end),
+            headers,
+        }))
+    }
+}
+
+/// A single part of a multipart body.
+#[derive(Debug)]
+pub struct Field {
+    name: Option<String>,
+    file_name: Option<String>,
+    headers: HeaderMap,
+    data: Bytes,
+}
+
+impl Field {
+    /// The form field name from `Content-Disposition`.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The original file name from `Content-Disposition`, for file inputs.
+    pub fn file_name(&self) -> Option<&str> {
+        self.file_name.as_deref()
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers.get(ContentType::NAME).map(HeaderValue::as_str)
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub async fn bytes(self) -> Result<Bytes, MultipartError> {
+        Ok(self.data)
+    }
+
+    pub async fn text(self) -> Result<String, MultipartError> {
+        String::from_utf8(self.data.to_vec())
+            .map_err(|_| MultipartError::InvalidUtf8 { name: self.name })
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|index| from + index)
+}
+
+fn parse_headers(raw: &[u8]) -> Result<HeaderMap, MultipartError> {
+    let raw = std::str::from_utf8(raw)
+        .map_err(|_| MultipartError::Malformed("part headers are not valid UTF-8"))?;
+
+    raw.split("\r\n")
+        .map(|line| {
+            let (name, value) = line
+                .split_once(':')
+                .ok_or(MultipartError::Malformed("invalid part header"))?;
+            match (name.trim().parse(), value.trim().parse()) {
+                (Ok(name), Ok(value)) => Ok((name, value)),
+                _ => Err(MultipartError::Malformed("invalid part header")),
+            }
+        })
+        .collect()
+}
+
+/// Reads a parameter such as `name="avatar"` from a `Content-Disposition`
+/// value.
+fn disposition_param(disposition: &str, param: &str) -> Option<String> {
+    disposition.split(';').skip(1).find_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        if name.trim().eq_ignore_ascii_case(param) {
+            Some(value.trim().trim_matches('"').to_owned())
+        } else {
+            None
+        }
+    })
+}
+
+/// Why a part of a multipart body couldn't be read.
+#[derive(Debug)]
+pub enum MultipartError {
+    /// The body doesn't follow the multipart format. Responds with 400.
+    Malformed(&'static str),
+    /// A part is larger than the limit. Responds with 413.
+    PartTooLarge { name: Option<String>, limit: usize },
+    /// [`Field::text`] was called on a part that isn't UTF-8. Responds with
+    /// 400.
+    InvalidUtf8 { name: Option<String> },
+}
+
+impl MultipartError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            MultipartError::Malformed(_) | MultipartError::InvalidUtf8 { .. } => {
+                StatusCode::BAD_REQUEST
+            }
+            MultipartError::PartTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+        }
+    }
+}
+
+impl fmt::Display for MultipartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MultipartError::Malformed(reason) => write!(f, "Malformed multipart body: {}", reason),
+            MultipartError::PartTooLarge { name, limit } => write!(
+                f,
+                "Multipart field `{}` exceeds the limit of {} bytes",
+                name.as_deref().unwrap_or("<unnamed>"),
+                limit
+            ),
+            MultipartError::InvalidUtf8 { name } => write!(
+                f,
+                "Multipart field `{}` is not valid UTF-8",
+                name.as_deref().unwrap_or("<unnamed>")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MultipartError {}
+
+impl IntoResponse for MultipartError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let mut resp = self.to_string().into_response();
+        resp.status = status;
+        resp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        http::{Method, Request, Response},
+        response::IntoResponse,
+    };
+
+    const FORM: &str = "preamble\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\
+        \r\n\
+        Ferris\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"avatar\"; filename=\"crab.png\"\r\n\
+        Content-Type: image/png\r\n\
+        \r\n\
+        \x00PNG\r\n\x1a\n\r\n\
+        --XyZ--\r\n";
+
+    fn request(method: Method, uri: &str, headers: &[(&str, &str)], body: &'static str) -> Request {
+        let mut req = Request::builder().method(method).uri(uri);
+        for &(name, value) in headers {
+            req = req.header(name, value);
+        }
+        req.body(body).unwrap()
+    }
+
+    /// The status and body of `resp`.
+    async fn respond(resp: Response) -> (u16, String) {
+        let body = resp.body.collect().await.unwrap();
+        (
+            resp.status.as_u16(),
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    async fn multipart(content_type: &str, body: &'static str) -> Result<Multipart, Response> {
+        let req = request(
+            Method::Post,
+            "/upload",
+            &[("Content-Type", content_type)],
+            body,
+        );
+        Multipart::from_request(req)
+            .await
+            .map_err(IntoResponse::into_response)
+    }
+
+    #[tokio::test]
+    async fn reads_fields_in_order() {
+        let mut form = multipart("multipart/form-data; boundary=XyZ", FORM)
+            .await
+            .unwrap();
+
+        let title = form.next_field().await.unwrap().unwrap();
+        assert_eq!(title.name(), Some("title"));
+        assert_eq!(title.file_name(), None);
+        assert_eq!(title.content_type(), None);
+        assert_eq!(title.text().await.unwrap(), "Ferris");
+
+        let avatar = form.next_field().await.unwrap().unwrap();
+        assert_eq!(avatar.name(), Some("avatar"));
+        assert_eq!(avatar.file_name(), Some("crab.png"));
+        assert_eq!(avatar.content_type(), Some("image/png"));
+        assert_eq!(avatar.bytes().await.unwrap(), &b"\x00PNG\r\n\x1a\n"[..]);
+
+        assert!(form.next_field().await.unwrap().is_none());
+        assert!(form.next_field().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn rejects_requests_without_a_boundary() {
+        let resp = multipart("application/x-www-form-urlencoded", FORM)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            respond(resp).await,
+            (
+                415,
+                "Expected request with `Content-Type: multipart/form-data`".to_owned()
+            )
+        );
+
+        let resp = multipart("multipart/form-data", FORM).await.unwrap_err();
+        assert_eq!(
+            respond(resp).await,
+            (
+                400,
+                "Missing `boundary` in multipart content type".to_owned()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn limits_the_size_of_parts() {
+        let mut form = multipart("multipart/form-data; boundary=XyZ", FORM)
+            .await
+            .unwrap();
+        form.set_part_limit(6);
+
+        let title = form.next_field().await.unwrap().unwrap();
+        assert_eq!(title.text().await.unwrap(), "Ferris");
+        let error = form.next_field().await.unwrap_err();
+        assert_eq!(
+            respond(error.into_response()).await,
+            (
+                413,
+                "Multipart field `avatar` exceeds the limit of 6 bytes".to_owned()
+            )
+        );
+        assert!(form.next_field().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn fails_on_malformed_bodies() {
+        for (body, reason) in [
+            ("no delimiter", "missing opening boundary"),
+            ("--XyZ oops", "boundary not followed by a line break"),
+            ("--XyZ\r\nName: value", "unterminated part headers"),
+            ("--XyZ\r\nNo colon\r\n\r\n", "invalid part header"),
+            (
+                "--XyZ\r\nName: value\r\n\r\ndata",
+                "missing closing boundary",
+            ),
+        ] {
+            let mut form = multipart("multipart/form-data; boundary=XyZ", body)
+                .await
+                .unwrap();
+            let error = form.next_field().await.unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("Malformed multipart body: {}", reason)
+            );
+            assert_eq!(respond(error.into_response()).await.0, 400);
+        }
+    }
+}
@@ -0,0 +1,504 @@
+use std::fmt;
+
+use serde::{
+    de::{self, value::BorrowedStrDeserializer, DeserializeOwned, DeserializeSeed, Visitor},
+    forward_to_deserialize_any,
+};
+
+use super::{rejection::PathRejection, BoxFuture, FromRequestParts};
+use crate::{http::Parts, router::PathParams};
+
+/// Deserializes the captured path segments into `T`.
+///
+/// Structs and maps are filled by capture name, tuples and sequences in the
+/// order the captures appear in the route. A single capture can also be
+/// extracted as a plain value.
+///
+/// ```
+/// # use part1_app_factory::{extract::Path, router::{get, Router}};
+/// // `/users/:id/posts/:post_id`
+/// async fn post(Path((id, post_id)): Path<(u64, u64)>) -> String {
+///     format!("Post {} of user {}", post_id, id)
+/// }
+/// # let _: Router = Router::new().route("/users/:id/posts/:post_id", get(post));
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Path<T>(pub T);
+
+impl<T> FromRequestParts for Path<T>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    type Rejection = PathRejection;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>> {
+        let result = match parts.extensions.get::<PathParams>() {
+            Some(params) => {
+                let params: Vec<_> = params.iter().collect();
+                T::deserialize(PathDeserializer { params: &params })
+                    .map(Path)
+                    .map_err(PathRejection::FailedToDeserialize)
+            }
+            None => Err(PathRejection::MissingPathParams),
+        };
+
+        Box::pin(async move { result })
+    }
+}
+
+/// Why the captures couldn't be deserialized.
+#[derive(Debug)]
+pub enum PathError {
+    /// The route has a different number of captures than the target type
+    /// has fields.
+    WrongNumberOfParameters { got: usize, expected: usize },
+    /// The route has no capture named `key`.
+    MissingParameter { key: String },
+    /// The segment captured as `key` isn't a valid `expected_type`.
+    ParseErrorAtKey {
+        key: String,
+        value: String,
+        expected_type: &'static str,
+    },
+    /// The target type can't be deserialized from path segments.
+    UnsupportedType { name: &'static str },
+    /// Any other error reported by the target type, such as a missing field.
+    Message(String),
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathError::WrongNumberOfParameters { got, expected } => write!(
+                f,
+                "Wrong number of path parameters, expected {} but got {}",
+                expected, got
+            ),
+            PathError::MissingParameter { key } => {
+                write!(f, "Missing path parameter `{}`", key)
+            }
+            PathError::ParseErrorAtKey {
+                key,
+                value,
+                expected_type,
+            } => write!(
+                f,
+                "Cannot parse `{}` with value `{}` as `{}`",
+                key, value, expected_type
+            ),
+            PathError::UnsupportedType { name } => {
+                write!(f, "Unsupported type `{}` for path parameters", name)
+            }
+            PathError::Message(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for PathError {}
+
+impl de::Error for PathError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        PathError::Message(msg.to_string())
+    }
+}
+
+/// Deserializes all captures of a route.
+struct PathDeserializer<'de> {
+    params: &'de [(&'de str, &'de str)],
+}
+
+impl<'de> PathDeserializer<'de> {
+    fn single(&self) -> Result<ValueDeserializer<'de>, PathError> {
+        match self.params {
+            [(key, value)] => Ok(ValueDeserializer { key, value }),
+            _ => Err(PathError::WrongNumberOfParameters {
+                got: self.params.len(),
+                expected: 1,
+            }),
+        }
+    }
+
+    fn expect_len(&self, expected: usize) -> Result<(), PathError> {
+        match self.params.len() {
+            got if got == expected => Ok(()),
+            got => Err(PathError::WrongNumberOfParameters { got, expected }),
+        }
+    }
+}
+
+macro_rules! forward_to_single {
+    ( $($method:ident)* ) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                self.single()?.$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for PathDeserializer<'de> {
+    type Error = PathError;
+
+    forward_to_single! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_i128 deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+        deserialize_u128 deserialize_f32 deserialize_f64 deserialize_char deserialize_str
+        deserialize_string deserialize_identifier
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(PathError::UnsupportedType { name: "bytes" })
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(PathError::UnsupportedType { name: "bytes" })
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_seq(ParamsAccess::new(self.params))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.expect_len(len)?;
+        visitor.visit_seq(ParamsAccess::new(self.params))
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(ParamsAccess::new(self.params))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.single()?.deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+}
+
+/// Walks the captures as a sequence of values or as a map keyed by name.
+struct ParamsAccess<'de> {
+    params: std::slice::Iter<'de, (&'de str, &'de str)>,
+    value: Option<ValueDeserializer<'de>>,
+}
+
+impl<'de> ParamsAccess<'de> {
+    fn new(params: &'de [(&'de str, &'de str)]) -> Self {
+        ParamsAccess {
+            params: params.iter(),
+            value: None,
+        }
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for ParamsAccess<'de> {
+    type Error = PathError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.params.next() {
+            Some((key, value)) => seed.deserialize(ValueDeserializer { key, value }).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.params.len())
+    }
+}
+
+impl<'de> de::MapAccess<'de> for ParamsAccess<'de> {
+    type Error = PathError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.params.next() {
+            Some((key, value)) => {
+                self.value = Some(ValueDeserializer { key, value });
+                seed.deserialize(BorrowedStrDeserializer::new(key))
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        match self.value.take() {
+            Some(value) => seed.deserialize(value),
+            None => Err(PathError::Message("Value requested before key".to_owned())),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.params.len())
+    }
+}
+
+/// Deserializes a single captured segment, remembering its name for errors.
+struct ValueDeserializer<'de> {
+    key: &'de str,
+    value: &'de str,
+}
+
+macro_rules! parse_value {
+    ( $($method:ident => $visit:ident: $ty:ty,)* ) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                match self.value.parse::<$ty>() {
+                    Ok(value) => visitor.$visit(value),
+                    Err(_) => Err(self.parse_error(stringify!($ty))),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> ValueDeserializer<'de> {
+    fn parse_error(&self, expected_type: &'static str) -> PathError {
+        PathError::ParseErrorAtKey {
+            key: self.key.to_owned(),
+            value: self.value.to_owned(),
+            expected_type,
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'de> {
+    type Error = PathError;
+
+    parse_value! {
+        deserialize_bool => visit_bool: bool,
+        deserialize_i8 => visit_i8: i8,
+        deserialize_i16 => visit_i16: i16,
+        deserialize_i32 => visit_i32: i32,
+        deserialize_i64 => visit_i64: i64,
+        deserialize_i128 => visit_i128: i128,
+        deserialize_u8 => visit_u8: u8,
+        deserialize_u16 => visit_u16: u16,
+        deserialize_u32 => visit_u32: u32,
+        deserialize_u64 => visit_u64: u64,
+        deserialize_u128 => visit_u128: u128,
+        deserialize_f32 => visit_f32: f32,
+        deserialize_f64 => visit_f64: f64,
+        deserialize_char => visit_char: char,
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_borrowed_str(self.value)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_enum(BorrowedStrDeserializer::new(self.value))
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(PathError::UnsupportedType { name: "sequence" })
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(PathError::UnsupportedType { name: "map" })
+    }
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct tuple tuple_struct struct
+        identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        http::{Method, Request, Response},
+        response::IntoResponse,
+        router::{get, Router},
+        util::app_fn,
+    };
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Params {
+        id: u32,
+        slug: String,
+    }
+
+    fn request(method: Method, uri: &str, headers: &[(&str, &str)], body: &'static str) -> Request {
+        let mut req = Request::builder().method(method).uri(uri);
+        for &(name, value) in headers {
+            req = req.header(name, value);
+        }
+        req.body(body).unwrap()
+    }
+
+    /// The status and body of `resp`.
+    async fn respond(resp: Response) -> (u16, String) {
+        let body = resp.body.collect().await.unwrap();
+        (
+            resp.status.as_u16(),
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    /// Routes `uri` to `pattern`, answering with what `Path<T>` extracts
+    /// from its captures, or with its rejection.
+    async fn extract<T>(pattern: &str, uri: &str) -> (u16, String)
+    where
+        T: DeserializeOwned + fmt::Debug + Send + 'static,
+    {
+        let app = app_fn(|req: Request| async move {
+            let (mut parts, _) = req.into_parts();
+            Ok(match Path::<T>::from_request_parts(&mut parts).await {
+                Ok(Path(value)) => format!("{:?}", value).into_response(),
+                Err(rejection) => rejection.into_response(),
+            })
+        });
+        let req = request(Method::Get, uri, &[], "");
+        let resp = Router::new().route(pattern, get(app)).oneshot(req).await;
+        respond(resp.unwrap()).await
+    }
+
+    #[tokio::test]
+    async fn deserializes_captures() {
+        assert_eq!(
+            extract::<u32>("/users/:id", "/users/7").await,
+            (200, "7".to_owned())
+        );
+        assert_eq!(
+            extract::<(u32, String)>("/users/:id/posts/:slug", "/users/7/posts/hello").await,
+            (200, r#"(7, "hello")"#.to_owned())
+        );
+        assert_eq!(
+            extract::<Params>("/users/:id/posts/:slug", "/users/7/posts/hello").await,
+            (200, r#"Params { id: 7, slug: "hello" }"#.to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_captures_that_do_not_parse() {
+        assert_eq!(
+            extract::<u32>("/users/:id", "/users/seven").await,
+            (
+                400,
+                "Invalid URL: Cannot parse `id` with value `seven` as `u32`".to_owned()
+            )
+        );
+        assert_eq!(
+            extract::<Params>("/users/:id/posts/:slug", "/users/-1/posts/hello").await,
+            (
+                400,
+                "Invalid URL: Cannot parse `id` with value `-1` as `u32`".to_owned()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_types_that_do_not_fit_the_route() {
+        assert_eq!(
+            extract::<u32>("/users/:id/posts/:slug", "/users/7/posts/hello").await,
+            (
+                500,
+                "Invalid URL: Wrong number of path parameters, expected 1 but got 2".to_owned()
+            )
+        );
+        assert_eq!(
+            extract::<(u32, String, String)>("/users/:id/posts/:slug", "/users/7/posts/hello")
+                .await
+                .0,
+            500
+        );
+
+        let (mut parts, _) = request(Method::Get, "/users/7", &[], "").into_parts();
+        let rejection = Path::<u32>::from_request_parts(&mut parts)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            respond(rejection.into_response()).await,
+            (
+                500,
+                "No path parameters found, is the handler behind a router?".to_owned()
+            )
+        );
+    }
+}
@@ -0,0 +1,16 @@
+use super::{rejection::MissingPeerCert, BoxFuture, ConnectInfo, FromRequestParts};
+use crate::http::{ConnInfo, Parts, PeerCert};
+
+/// Takes the client's certificate from the `ConnectInfo<ConnInfo>` the server
+/// attached, for connections over mutual TLS.
+impl FromRequestParts for PeerCert {
+    type Rejection = MissingPeerCert;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>> {
+        let cert = parts
+            .extensions
+            .get::<ConnectInfo<ConnInfo>>()
+            .and_then(|ConnectInfo(conn)| conn.tls.as_ref()?.peer_cert.clone());
+        Box::pin(async move { cert.ok_or(MissingPeerCert) })
+    }
+}
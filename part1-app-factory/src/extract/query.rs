@@ -0,0 +1,116 @@
+use serde::de::DeserializeOwned;
+
+use super::{rejection::QueryRejection, BoxFuture, FromRequestParts};
+use crate::http::Parts;
+
+/// Deserializes the query string into `T`.
+///
+/// A request without a query string is treated like one with an empty query
+/// string, so `T` can still be built if all its fields are optional. Keys and
+/// values are decoded like [`form_decode`](crate::http::encoding::form_decode)
+/// does, the same as [`Uri::query_pairs`](crate::http::Uri::query_pairs).
+///
+/// ```
+/// # use part1_app_factory::{extract::Query, router::get};
+/// # use serde::Deserialize;
+/// #[derive(Deserialize)]
+/// struct Pagination {
+///     page: u32,
+///     per_page: Option<u32>,
+/// }
+///
+/// async fn list(Query(pagination): Query<Pagination>) -> String {
+///     format!("Page {}", pagination.page)
+/// }
+/// # let _ = get(list);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Query<T>(pub T);
+
+impl<T> FromRequestParts for Query<T>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    type Rejection = QueryRejection;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>> {
+        let query = parts.uri.query().unwrap_or_default();
+        let result = serde_urlencoded::from_str(query)
+            .map(Query)
+            .map_err(|error| QueryRejection { error });
+
+        Box::pin(async move { result })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::{
+        http::{Method, Request, Response},
+        response::IntoResponse,
+    };
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Pagination {
+        page: u32,
+        per_page: Option<u32>,
+    }
+
+    fn request(method: Method, uri: &str, headers: &[(&str, &str)], body: &'static str) -> Request {
+        let mut req = Request::builder().method(method).uri(uri);
+        for &(name, value) in headers {
+            req = req.header(name, value);
+        }
+        req.body(body).unwrap()
+    }
+
+    /// The status and body of `resp`.
+    async fn respond(resp: Response) -> (u16, String) {
+        let body = resp.body.collect().await.unwrap();
+        (
+            resp.status.as_u16(),
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    async fn extract(uri: &str) -> Result<Pagination, Response> {
+        let (mut parts, _) = request(Method::Get, uri, &[], "").into_parts();
+        match Query::<Pagination>::from_request_parts(&mut parts).await {
+            Ok(Query(pagination)) => Ok(pagination),
+            Err(rejection) => Err(rejection.into_response()),
+        }
+    }
+
+    #[tokio::test]
+    async fn deserializes_the_query_string() {
+        let pagination = extract("/users?page=2&per_page=10").await.unwrap();
+        assert_eq!(
+            pagination,
+            Pagination {
+                page: 2,
+                per_page: Some(10)
+            }
+        );
+
+        let pagination = extract("/users?per_page=&page=3&sort=name").await;
+        assert!(pagination.is_err(), "an empty number isn't one");
+        let pagination = extract("/users?page=3&sort=name").await.unwrap();
+        assert_eq!(pagination.per_page, None);
+    }
+
+    #[tokio::test]
+    async fn rejects_what_does_not_deserialize() {
+        for uri in ["/users", "/users?page=two"] {
+            let (status, body) = respond(extract(uri).await.unwrap_err()).await;
+            assert_eq!(status, 400);
+            assert!(
+                body.starts_with("Failed to deserialize query string: "),
+                "{}",
+                body
+            );
+        }
+    }
+}
@@ -0,0 +1,729 @@
+//! Rejection types for the extractors in this crate.
+//!
+//! Every rejection has a `status` and implements `Display` and
+//! [`IntoResponse`], responding with that status and the message as a plain
+//! text body. Client mistakes get a 4xx status; rejections that mean the app
+//! is wired up wrong, such as missing state, get a 500.
+
+use std::fmt;
+
+use super::PathError;
+use crate::{
+    http::{Response, StatusCode},
+    response::IntoResponse,
+};
+
+/// Rejection for [`Query`](super::Query) when the query string can't be
+/// deserialized. Responds with 400.
+#[derive(Debug)]
+pub struct QueryRejection {
+    pub(super) error: serde_urlencoded::de::Error,
+}
+
+impl QueryRejection {
+    pub fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    pub fn error(&self) -> &serde_urlencoded::de::Error {
+        &self.error
+    }
+}
+
+impl fmt::Display for QueryRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to deserialize query string: {}", self.error)
+    }
+}
+
+impl std::error::Error for QueryRejection {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Rejection for the [`Json`](crate::json::Json) extractor.
+#[derive(Debug)]
+pub enum JsonRejection {
+    /// The request doesn't have a JSON content type. Responds with 415.
+    MissingJsonContentType,
+    /// The body isn't valid JSON. Responds with 400.
+    InvalidSyntax(serde_json::Error),
+    /// The body is valid JSON that doesn't match `T`. Responds with 422.
+    InvalidData(serde_json::Error),
+    /// The body is over the limit or couldn't be read.
+    FailedToBufferBody(FailedToBufferBody),
+}
+
+impl JsonRejection {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            JsonRejection::MissingJsonContentType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            JsonRejection::InvalidSyntax(_) => StatusCode::BAD_REQUEST,
+            JsonRejection::InvalidData(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            JsonRejection::FailedToBufferBody(error) => error.status(),
+        }
+    }
+}
+
+impl fmt::Display for JsonRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonRejection::MissingJsonContentType => {
+                f.write_str("Expected request with `Content-Type: application/json`")
+            }
+            JsonRejection::InvalidSyntax(error) => {
+                write!(f, "Failed to parse JSON body: {}", error)
+            }
+            JsonRejection::InvalidData(error) => {
+                write!(f, "Failed to deserialize JSON body: {}", error)
+            }
+            JsonRejection::FailedToBufferBody(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for JsonRejection {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            JsonRejection::MissingJsonContentType => None,
+            JsonRejection::InvalidSyntax(error) | JsonRejection::InvalidData(error) => Some(error),
+            JsonRejection::FailedToBufferBody(error) => Some(error),
+        }
+    }
+}
+
+/// Rejection for the [`Form`](crate::form::Form) extractor.
+#[derive(Debug)]
+pub enum FormRejection {
+    /// The request doesn't have a URL encoded form content type. Responds
+    /// with 415.
+    InvalidFormContentType,
+    /// The body doesn't match `T`. Responds with 422.
+    FailedToDeserialize(serde_urlencoded::de::Error),
+    /// The body is over the limit or couldn't be read.
+    FailedToBufferBody(FailedToBufferBody),
+}
+
+impl FormRejection {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            FormRejection::InvalidFormContentType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            FormRejection::FailedToDeserialize(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            FormRejection::FailedToBufferBody(error) => error.status(),
+        }
+    }
+}
+
+impl fmt::Display for FormRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormRejection::InvalidFormContentType => f.write_str(
+                "Expected request with `Content-Type: application/x-www-form-urlencoded`",
+            ),
+            FormRejection::FailedToDeserialize(error) => {
+                write!(f, "Failed to deserialize form body: {}", error)
+            }
+            FormRejection::FailedToBufferBody(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for FormRejection {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FormRejection::InvalidFormContentType => None,
+            FormRejection::FailedToDeserialize(error) => Some(error),
+            FormRejection::FailedToBufferBody(error) => Some(error),
+        }
+    }
+}
+
+/// Rejection for the [`Path`](super::Path) extractor.
+#[derive(Debug)]
+pub enum PathRejection {
+    /// The request wasn't routed by a [`Router`](crate::router::Router), so
+    /// there are no captures. Responds with 500.
+    MissingPathParams,
+    /// The captures don't fit the target type. Responds with 400 if a
+    /// segment failed to parse and with 500 if the type doesn't match the
+    /// route.
+    FailedToDeserialize(PathError),
+}
+
+impl PathRejection {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            PathRejection::MissingPathParams => StatusCode::INTERNAL_SERVER_ERROR,
+            PathRejection::FailedToDeserialize(
+                PathError::WrongNumberOfParameters { .. }
+                | PathError::MissingParameter { .. }
+                | PathError::UnsupportedType { .. },
+            ) => StatusCode::INTERNAL_SERVER_ERROR,
+            PathRejection::FailedToDeserialize(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl fmt::Display for PathRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathRejection::MissingPathParams => {
+                f.write_str("No path parameters found, is the handler behind a router?")
+            }
+            PathRejection::FailedToDeserialize(error) => {
+                write!(f, "Invalid URL: {}", error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PathRejection {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PathRejection::MissingPathParams => None,
+            PathRejection::FailedToDeserialize(error) => Some(error),
+        }
+    }
+}
+
+/// Rejection for [`TypedHeader`](super::TypedHeader). Responds with 400.
+#[derive(Debug)]
+pub struct TypedHeaderRejection {
+    pub(super) name: &'static str,
+    pub(super) reason: TypedHeaderRejectionReason,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TypedHeaderRejectionReason {
+    /// The request doesn't have the header.
+    Missing,
+    /// The header value couldn't be parsed.
+    Invalid,
+}
+
+impl TypedHeaderRejection {
+    pub fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn reason(&self) -> TypedHeaderRejectionReason {
+        self.reason
+    }
+
+    pub fn is_missing(&self) -> bool {
+        self.reason == TypedHeaderRejectionReason::Missing
+    }
+}
+
+impl fmt::Display for TypedHeaderRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.reason {
+            TypedHeaderRejectionReason::Missing => {
+                write!(f, "Header of type `{}` was missing", self.name)
+            }
+            TypedHeaderRejectionReason::Invalid => {
+                write!(f, "Header of type `{}` is invalid", self.name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TypedHeaderRejection {}
+
+/// Rejection for [`State`](super::State) when no router on the way to the
+/// handler has state of the requested type. Responds with 500.
+#[derive(Debug)]
+pub struct MissingState {
+    pub(super) type_name: &'static str,
+}
+
+impl MissingState {
+    pub fn status(&self) -> StatusCode {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+impl fmt::Display for MissingState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Missing state of type `{}`, add it with `Router::with_state`",
+            self.type_name
+        )
+    }
+}
+
+impl std::error::Error for MissingState {}
+
+/// Rejection for [`Authenticated`](super::Authenticated) when no auth layer
+/// attached an identity of the requested type. Responds with 500, as it's the
+/// app that's missing the layer.
+#[derive(Debug)]
+pub struct MissingIdentity {
+    pub(super) type_name: &'static str,
+}
+
+impl MissingIdentity {
+    pub fn status(&self) -> StatusCode {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+impl fmt::Display for MissingIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Missing identity of type `{}`, the route isn't behind an auth layer",
+            self.type_name
+        )
+    }
+}
+
+impl std::error::Error for MissingIdentity {}
+
+/// Rejection for [`RequestId`](crate::middleware::RequestId) when the route
+/// isn't behind a [`RequestIdLayer`](crate::middleware::RequestIdLayer).
+/// Responds with 500.
+#[derive(Debug)]
+pub struct MissingRequestId;
+
+impl MissingRequestId {
+    pub fn status(&self) -> StatusCode {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+impl fmt::Display for MissingRequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Missing request ID, add a `RequestIdLayer` around the route")
+    }
+}
+
+impl std::error::Error for MissingRequestId {}
+
+/// Rejection for [`CsrfToken`](crate::middleware::CsrfToken) when the route
+/// isn't behind a [`CsrfLayer`](crate::middleware::CsrfLayer). Responds
+/// with 500.
+#[derive(Debug)]
+pub struct MissingCsrfToken;
+
+impl MissingCsrfToken {
+    pub fn status(&self) -> StatusCode {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+impl fmt::Display for MissingCsrfToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Missing CSRF token, add a `CsrfLayer` around the route")
+    }
+}
+
+impl std::error::Error for MissingCsrfToken {}
+
+/// Rejection for [`Session`](crate::middleware::Session) when the route
+/// isn't behind a [`SessionLayer`](crate::middleware::SessionLayer) for the
+/// same data. Responds with 500.
+#[derive(Debug)]
+pub struct MissingSession {
+    pub(crate) type_name: &'static str,
+}
+
+impl MissingSession {
+    pub fn status(&self) -> StatusCode {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+impl fmt::Display for MissingSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Missing session of type `{}`, the route isn't behind a session layer for it",
+            self.type_name
+        )
+    }
+}
+
+impl std::error::Error for MissingSession {}
+
+/// Rejection for [`ConnectInfo`](super::ConnectInfo) when the server didn't
+/// attach connection info of the requested type. Responds with 500.
+#[derive(Debug)]
+pub struct MissingConnectInfo {
+    pub(super) type_name: &'static str,
+}
+
+impl MissingConnectInfo {
+    pub fn status(&self) -> StatusCode {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+impl fmt::Display for MissingConnectInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Missing connection info of type `{}`, the server didn't attach it to the request",
+            self.type_name
+        )
+    }
+}
+
+impl std::error::Error for MissingConnectInfo {}
+
+/// Rejection for [`PeerCert`](crate::http::PeerCert) when the client didn't
+/// authenticate with a certificate, or the connection isn't over TLS.
+/// Responds with 401.
+#[derive(Debug)]
+pub struct MissingPeerCert;
+
+impl MissingPeerCert {
+    pub fn status(&self) -> StatusCode {
+        StatusCode::UNAUTHORIZED
+    }
+}
+
+impl fmt::Display for MissingPeerCert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("A client certificate is required")
+    }
+}
+
+impl std::error::Error for MissingPeerCert {}
+
+/// Rejection for body extractors when the body is larger than the
+/// [`DefaultBodyLimit`](super::DefaultBodyLimit) in effect. Responds with 413.
+#[derive(Debug)]
+pub struct LengthLimitError {
+    pub(super) limit: usize,
+}
+
+impl LengthLimitError {
+    pub(crate) fn new(limit: usize) -> Self {
+        LengthLimitError { limit }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        StatusCode::PAYLOAD_TOO_LARGE
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+impl fmt::Display for LengthLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Request body is larger than the {} byte limit",
+            self.limit
+        )
+    }
+}
+
+impl std::error::Error for LengthLimitError {}
+
+/// Rejection for body extractors when the body couldn't be read in full.
+#[derive(Debug)]
+pub enum FailedToBufferBody {
+    /// The body is over the limit. Responds with 413.
+    LengthLimit(LengthLimitError),
+    /// The body's stream failed, e.g. because the client went away. Responds
+    /// with 400.
+    Stream(anyhow::Error),
+}
+
+impl FailedToBufferBody {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            FailedToBufferBody::LengthLimit(error) => error.status(),
+            FailedToBufferBody::Stream(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl fmt::Display for FailedToBufferBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailedToBufferBody::LengthLimit(error) => write!(f, "{}", error),
+            FailedToBufferBody::Stream(error) => {
+                write!(f, "Failed to read request body: {}", error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FailedToBufferBody {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FailedToBufferBody::LengthLimit(error) => Some(error),
+            FailedToBufferBody::Stream(error) => Some(error.as_ref()),
+        }
+    }
+}
+
+impl From<LengthLimitError> for FailedToBufferBody {
+    fn from(error: LengthLimitError) -> Self {
+        FailedToBufferBody::LengthLimit(error)
+    }
+}
+
+/// Rejection for the `String` body extractor.
+#[derive(Debug)]
+pub enum StringRejection {
+    /// The `Content-Type` names a charset that isn't UTF-8. Responds with
+    /// 415.
+    UnsupportedCharset(String),
+    /// The body isn't valid UTF-8; the first `valid_up_to` bytes are.
+    /// Responds with 400.
+    InvalidUtf8 { valid_up_to: usize },
+    /// The body is over the limit or couldn't be read.
+    FailedToBufferBody(FailedToBufferBody),
+}
+
+impl StringRejection {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            StringRejection::UnsupportedCharset(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            StringRejection::InvalidUtf8 { .. } => StatusCode::BAD_REQUEST,
+            StringRejection::FailedToBufferBody(error) => error.status(),
+        }
+    }
+}
+
+impl fmt::Display for StringRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StringRejection::UnsupportedCharset(charset) => {
+                write!(f, "Unsupported charset `{}`, expected UTF-8", charset)
+            }
+            StringRejection::InvalidUtf8 { valid_up_to } => write!(
+                f,
+                "Request body is not valid UTF-8, invalid byte at offset {}",
+                valid_up_to
+            ),
+            StringRejection::FailedToBufferBody(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for StringRejection {}
+
+/// Rejection for the [`Multipart`](super::Multipart) extractor.
+#[derive(Debug)]
+pub enum MultipartRejection {
+    /// The request doesn't have `Content-Type: multipart/form-data`.
+    /// Responds with 415.
+    InvalidContentType,
+    /// The content type has no `boundary` parameter. Responds with 400.
+    MissingBoundary,
+    /// The body is over the limit or couldn't be read.
+    FailedToBufferBody(FailedToBufferBody),
+}
+
+impl MultipartRejection {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            MultipartRejection::InvalidContentType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            MultipartRejection::MissingBoundary => StatusCode::BAD_REQUEST,
+            MultipartRejection::FailedToBufferBody(error) => error.status(),
+        }
+    }
+}
+
+impl fmt::Display for MultipartRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MultipartRejection::InvalidContentType => {
+                f.write_str("Expected request with `Content-Type: multipart/form-data`")
+            }
+            MultipartRejection::MissingBoundary => {
+                f.write_str("Missing `boundary` in multipart content type")
+            }
+            MultipartRejection::FailedToBufferBody(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for MultipartRejection {}
+
+macro_rules! impl_into_response {
+    ( $($ty:ty),* $(,)? ) => {
+        $(
+            impl IntoResponse for $ty {
+                fn into_response(self) -> Response {
+                    let status = self.status();
+                    let mut resp = self.to_string().into_response();
+                    resp.status = status;
+                    resp
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_from_failed_to_buffer_body {
+    ( $($ty:ident),* $(,)? ) => {
+        $(
+            impl From<FailedToBufferBody> for $ty {
+                fn from(error: FailedToBufferBody) -> Self {
+                    $ty::FailedToBufferBody(error)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_failed_to_buffer_body!(
+    JsonRejection,
+    FormRejection,
+    StringRejection,
+    MultipartRejection
+);
+
+impl_into_response!(
+    QueryRejection,
+    JsonRejection,
+    FormRejection,
+    PathRejection,
+    TypedHeaderRejection,
+    MissingState,
+    MissingIdentity,
+    MissingRequestId,
+    MissingCsrfToken,
+    MissingSession,
+    MissingConnectInfo,
+    MissingPeerCert,
+    LengthLimitError,
+    FailedToBufferBody,
+    StringRejection,
+    MultipartRejection,
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The status and body of `resp`.
+    async fn respond(resp: Response) -> (u16, String) {
+        let body = resp.body.collect().await.unwrap();
+        (
+            resp.status.as_u16(),
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    /// The status and body `rejection` responds with, checking the body is
+    /// its message.
+    async fn responds<R: IntoResponse + fmt::Display>(rejection: R) -> u16 {
+        let message = rejection.to_string();
+        let (status, body) = respond(rejection.into_response()).await;
+        assert_eq!(body, message);
+        status
+    }
+
+    #[tokio::test]
+    async fn blames_clients_for_what_they_sent() {
+        let error = serde_urlencoded::from_str::<Vec<(String, u32)>>("page=two").unwrap_err();
+        assert_eq!(responds(QueryRejection { error }).await, 400);
+
+        let error = serde_json::from_str::<u32>("{").unwrap_err();
+        assert_eq!(responds(JsonRejection::InvalidSyntax(error)).await, 400);
+        let error = serde_json::from_str::<u32>("\"two\"").unwrap_err();
+        assert_eq!(responds(JsonRejection::InvalidData(error)).await, 422);
+        assert_eq!(responds(JsonRejection::MissingJsonContentType).await, 415);
+
+        assert_eq!(responds(FormRejection::InvalidFormContentType).await, 415);
+        assert_eq!(responds(MultipartRejection::InvalidContentType).await, 415);
+        assert_eq!(responds(MultipartRejection::MissingBoundary).await, 400);
+        assert_eq!(
+            responds(StringRejection::UnsupportedCharset("latin1".to_owned())).await,
+            415
+        );
+        assert_eq!(
+            responds(StringRejection::InvalidUtf8 { valid_up_to: 3 }).await,
+            400
+        );
+    }
+
+    #[tokio::test]
+    async fn blames_the_app_for_how_it_is_wired() {
+        let missing_state = MissingState {
+            type_name: "app::Db",
+        };
+        assert_eq!(
+            missing_state.to_string(),
+            "Missing state of type `app::Db`, add it with `Router::with_state`"
+        );
+        assert_eq!(responds(missing_state).await, 500);
+
+        let missing_connect_info = MissingConnectInfo {
+            type_name: "std::net::SocketAddr",
+        };
+        assert_eq!(responds(missing_connect_info).await, 500);
+        assert_eq!(responds(PathRejection::MissingPathParams).await, 500);
+    }
+
+    #[tokio::test]
+    async fn tells_path_errors_of_the_route_from_those_of_the_url() {
+        let route_errors = [
+            PathError::WrongNumberOfParameters {
+                got: 2,
+                expected: 1,
+            },
+            PathError::MissingParameter {
+                key: "id".to_owned(),
+            },
+            PathError::UnsupportedType { name: "map" },
+        ];
+        for error in route_errors {
+            assert_eq!(
+                responds(PathRejection::FailedToDeserialize(error)).await,
+                500
+            );
+        }
+
+        let url_errors = [
+            PathError::ParseErrorAtKey {
+                key: "id".to_owned(),
+                value: "seven".to_owned(),
+                expected_type: "u32",
+            },
+            PathError::Message("missing field `id`".to_owned()),
+        ];
+        for error in url_errors {
+            assert_eq!(
+                responds(PathRejection::FailedToDeserialize(error)).await,
+                400
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn names_the_typed_header_at_fault() {
+        let rejection = TypedHeaderRejection {
+            name: "Host",
+            reason: TypedHeaderRejectionReason::Missing,
+        };
+        assert_eq!(rejection.name(), "Host");
+        assert!(rejection.is_missing());
+        assert_eq!(rejection.to_string(), "Header of type `Host` was missing");
+        assert_eq!(responds(rejection).await, 400);
+
+        let rejection = TypedHeaderRejection {
+            name: "Host",
+            reason: TypedHeaderRejectionReason::Invalid,
+        };
+        assert!(!rejection.is_missing());
+        assert_eq!(rejection.reason(), TypedHeaderRejectionReason::Invalid);
+        assert_eq!(rejection.to_string(), "Header of type `Host` is invalid");
+        assert_eq!(responds(rejection).await, 400);
+    }
+}
@@ -0,0 +1,39 @@
+use std::any::type_name;
+
+use super::{rejection::MissingState, BoxFuture, FromRequestParts};
+use crate::http::Parts;
+
+/// Extracts state added with [`Router::with_state`](crate::router::Router::with_state).
+///
+/// ```
+/// # use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+/// # use part1_app_factory::{extract::State, router::{get, Router}};
+/// async fn count(State(counter): State<Arc<AtomicUsize>>) -> String {
+///     counter.fetch_add(1, Ordering::SeqCst).to_string()
+/// }
+///
+/// let app = Router::new()
+///     .route("/count", get(count))
+///     .with_state(Arc::new(AtomicUsize::new(0)));
+/// ```
+///
+/// The state is cloned for every request, so wrap anything expensive to
+/// clone in an `Arc`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct State<S>(pub S);
+
+impl<S> FromRequestParts for State<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    type Rejection = MissingState;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>> {
+        let state = parts.extensions.get::<State<S>>().cloned();
+        Box::pin(async move {
+            state.ok_or(MissingState {
+                type_name: type_name::<S>(),
+            })
+        })
+    }
+}
@@ -0,0 +1,48 @@
+use super::{
+    rejection::{TypedHeaderRejection, TypedHeaderRejectionReason},
+    BoxFuture, FromRequestParts,
+};
+use crate::{
+    headers::Header,
+    http::{HeaderValue, Parts},
+};
+
+/// Extracts and parses a header, see [`crate::headers`] for the supported
+/// ones.
+///
+/// ```
+/// # use part1_app_factory::{extract::TypedHeader, headers::UserAgent, router::get};
+/// async fn whoami(TypedHeader(agent): TypedHeader<UserAgent>) -> String {
+///     format!("You are using {}", agent)
+/// }
+/// # let _ = get(whoami);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct TypedHeader<H>(pub H);
+
+impl<H> FromRequestParts for TypedHeader<H>
+where
+    H: Header + Send + 'static,
+{
+    type Rejection = TypedHeaderRejection;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>> {
+        let value = parts.headers.get(H::NAME).map(HeaderValue::as_str);
+
+        let result = match value {
+            Some(value) => match H::decode(value) {
+                Some(header) => Ok(TypedHeader(header)),
+                None => Err(TypedHeaderRejection {
+                    name: H::NAME,
+                    reason: TypedHeaderRejectionReason::Invalid,
+                }),
+            },
+            None => Err(TypedHeaderRejection {
+                name: H::NAME,
+                reason: TypedHeaderRejectionReason::Missing,
+            }),
+        };
+
+        Box::pin(async move { result })
+    }
+}
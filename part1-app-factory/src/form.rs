@@ -0,0 +1,204 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    extract::{buffer_body, has_content_type, rejection::FormRejection, BoxFuture, FromRequest},
+    http::{header::CONTENT_TYPE, HeaderValue, Request, Response, StatusCode},
+    response::IntoResponse,
+};
+
+/// URL encoded form extractor and response.
+///
+/// As an extractor, deserializes the request body into `T` if the request has
+/// `Content-Type: application/x-www-form-urlencoded`, with any parameters
+/// ignored, and rejects it with a 415 otherwise. It consumes the body, so it
+/// must be the last handler argument. Use [`Query`](crate::extract::Query) for
+/// forms submitted in the query string and [`Form::from_bytes`] for clients
+/// that leave out the header.
+///
+/// As a response, serializes `T` and sets the same content type.
+///
+/// ```
+/// # use part1_app_factory::{form::Form, router::post};
+/// # use serde::Deserialize;
+/// #[derive(Deserialize)]
+/// struct SignUp {
+///     username: String,
+///     password: String,
+/// }
+///
+/// async fn sign_up(Form(sign_up): Form<SignUp>) -> String {
+///     format!("Welcome, {}", sign_up.username)
+/// }
+/// # let _ = post(sign_up);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Form<T>(pub T);
+
+impl<T: DeserializeOwned> Form<T> {
+    /// Deserializes `bytes` without looking at any headers.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FormRejection> {
+        serde_urlencoded::from_bytes(bytes)
+            .map(Form)
+            .map_err(FormRejection::FailedToDeserialize)
+    }
+}
+
+impl<T> FromRequest for Form<T>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    type Rejection = FormRejection;
+
+    fn from_request(req: Request) -> BoxFuture<'static, Result<Self, Self::Rejection>> {
+        Box::pin(async move {
+            if !has_content_type(&req.headers, is_form) {
+                return Err(FormRejection::InvalidFormContentType);
+            }
+            let body = buffer_body(req).await?;
+
+            Form::from_bytes(&body)
+        })
+    }
+}
+
+impl<T: Serialize> IntoResponse for Form<T> {
+    fn into_response(self) -> Response {
+        match serde_urlencoded::to_string(&self.0) {
+            Ok(body) => {
+                let mut resp = body.into_response();
+                resp.headers.insert(
+                    CONTENT_TYPE,
+                    HeaderValue::from_static("application/x-www-form-urlencoded"),
+                );
+                resp
+            }
+            Err(error) => {
+                let mut resp = format!("Failed to serialize form: {}", error).into_response();
+                resp.status = StatusCode::INTERNAL_SERVER_ERROR;
+                resp
+            }
+        }
+    }
+}
+
+fn is_form(mime: &str) -> bool {
+    mime.eq_ignore_ascii_case("application/x-www-form-urlencoded")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::http::Method;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Login {
+        username: String,
+        remember: Option<bool>,
+    }
+
+    fn request(method: Method, uri: &str, headers: &[(&str, &str)], body: &'static str) -> Request {
+        let mut req = Request::builder().method(method).uri(uri);
+        for &(name, value) in headers {
+            req = req.header(name, value);
+        }
+        req.body(body).unwrap()
+    }
+
+    /// The status and body of `resp`.
+    async fn respond(resp: Response) -> (u16, String) {
+        let body = resp.body.collect().await.unwrap();
+        (
+            resp.status.as_u16(),
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    fn content_type(resp: &Response) -> Option<&str> {
+        resp.headers.get("content-type").map(|value| value.as_str())
+    }
+
+    async fn extract(content_type: &str, body: &'static str) -> Result<Login, Response> {
+        let req = request(
+            Method::Post,
+            "/login",
+            &[("Content-Type", content_type)],
+            body,
+        );
+        match Form::<Login>::from_request(req).await {
+            Ok(Form(login)) => Ok(login),
+            Err(rejection) => Err(rejection.into_response()),
+        }
+    }
+
+    #[tokio::test]
+    async fn deserializes_urlencoded_bodies() {
+        let login = extract(
+            "application/x-www-form-urlencoded",
+            "username=ferris+crab&remember=true",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            login,
+            Login {
+                username: "ferris crab".to_owned(),
+                remember: Some(true)
+            }
+        );
+
+        let login = extract("Application/X-WWW-Form-Urlencoded", "username=%F0%9F%A6%80")
+            .await
+            .unwrap();
+        assert_eq!(login.username, "🦀");
+        assert_eq!(login.remember, None);
+    }
+
+    #[tokio::test]
+    async fn rejects_other_content_types() {
+        for content_type in ["multipart/form-data", "application/json", "text/plain"] {
+            let resp = extract(content_type, "username=ferris").await.unwrap_err();
+            let (status, body) = respond(resp).await;
+            assert_eq!(status, 415, "{}", content_type);
+            assert_eq!(
+                body,
+                "Expected request with `Content-Type: application/x-www-form-urlencoded`"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_bodies_that_do_not_deserialize() {
+        for body in ["remember=true", "username=ferris&remember=often"] {
+            let resp = extract("application/x-www-form-urlencoded", body)
+                .await
+                .unwrap_err();
+            let (status, body) = respond(resp).await;
+            assert_eq!(status, 422);
+            assert!(
+                body.starts_with("Failed to deserialize form body: "),
+                "{}",
+                body
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn serializes_responses() {
+        let resp = Form([("q", "ferris crab"), ("page", "2")]).into_response();
+        assert_eq!(
+            content_type(&resp),
+            Some("application/x-www-form-urlencoded")
+        );
+        assert_eq!(
+            respond(resp).await,
+            (200, "q=ferris+crab&page=2".to_owned())
+        );
+
+        let resp = Form(vec![vec![1, 2]]).into_response();
+        let (status, body) = respond(resp).await;
+        assert_eq!(status, 500);
+        assert!(body.starts_with("Failed to serialize form: "), "{}", body);
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    fmt,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use anyhow::Error;
+use tower::{Service, ServiceExt};
+
+use crate::{
+    extract::{FromRequest, FromRequestParts},
+    http::{Request, Response},
+    response::IntoResponse,
+};
+
+/// Something that can handle a request, most commonly an async fn whose
+/// arguments are extractors and whose output implements [`IntoResponse`]:
+///
+/// ```
+/// # use part1_app_factory::router::{get, PathParams, Router};
+/// async fn hello(params: PathParams) -> String {
+///     format!("Hello, {}!", params.get("name").unwrap_or("stranger"))
+/// }
+///
+/// let app = Router::new().route("/hello/:name", get(hello));
+/// ```
+///
+/// Arguments are extracted in order and the first rejection becomes the
+/// response. Only the last argument may consume the body, that is implement
+/// [`FromRequest`] rather than [`FromRequestParts`]. Any `Service<Request>`
+/// with this crate's error type is a handler too, so services built with
+/// `app_fn` can be routed the same way.
+///
+/// `T` only exists to tell the implementations for different arities apart.
+pub trait Handler<T>: Clone + Send + Sized + 'static {
+    type Future: Future<Output = Result<Response, Error>> + Send + 'static;
+
+    fn call(self, req: Request) -> Self::Future;
+
+    /// Turns the handler into a `Service<Request>`.
+    fn into_service(self) -> HandlerService<Self, T> {
+        HandlerService {
+            handler: self,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<F, Fut, Res> Handler<()> for F
+where
+    F: FnOnce() -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = Res> + Send,
+    Res: IntoResponse,
+{
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Error>> + Send>>;
+
+    fn call(self, _req: Request) -> Self::Future {
+        Box::pin(async move { Ok(self().await.into_response()) })
+    }
+}
+
+// All arguments but the last only see the request head, the last one gets the
+// request with its body.
+macro_rules! impl_handler {
+    ( $($ty:ident),* ; $last:ident ) => {
+        #[allow(non_snake_case, unused_mut)]
+        impl<F, Fut, Res, M, $($ty,)* $last> Handler<(M, $($ty,)* $last,)> for F
+        where
+            F: FnOnce($($ty,)* $last) -> Fut + Clone + Send + 'static,
+            Fut: Future<Output = Res> + Send,
+            Res: IntoResponse,
+            $( $ty: FromRequestParts + Send, )*
+            $last: FromRequest<M> + Send,
+        {
+            type Future = Pin<Box<dyn Future<Output = Result<Response, Error>> + Send>>;
+
+            fn call(self, req: Request) -> Self::Future {
+                Box::pin(async move {
+                    let (mut parts, body) = req.into_parts();
+
+                    $(
+                        let $ty = match $ty::from_request_parts(&mut parts).await {
+                            Ok(value) => value,
+                            Err(rejection) => return Ok(rejection.into_response()),
+                        };
+                    )*
+
+                    let req = Request::from_parts(parts, body);
+                    let $last = match $last::from_request(req).await {
+                        Ok(value) => value,
+                        Err(rejection) => return Ok(rejection.into_response()),
+                    };
+
+                    Ok(self($($ty,)* $last).await.into_response())
+                })
+            }
+        }
+    };
+}
+
+impl_handler!(; T1);
+impl_handler!(T1; T2);
+impl_handler!(T1, T2; T3);
+impl_handler!(T1, T2, T3; T4);
+impl_handler!(T1, T2, T3, T4; T5);
+impl_handler!(T1, T2, T3, T4, T5; T6);
+impl_handler!(T1, T2, T3, T4, T5, T6; T7);
+impl_handler!(T1, T2, T3, T4, T5, T6, T7; T8);
+
+/// Marks the [`Handler`] implementation for services.
+#[doc(hidden)]
+pub enum ServiceMarker {}
+
+impl<S> Handler<ServiceMarker> for S
+where
+    S: Service<Request, Response = Response, Error = Error> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Future = tower::util::Oneshot<S, Request>;
+
+    fn call(self, req: Request) -> Self::Future {
+        self.oneshot(req)
+    }
+}
+
+/// A [`Handler`] turned into a service, see [`Handler::into_service`].
+pub struct HandlerService<H, T> {
+    handler: H,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<H: Clone, T> Clone for HandlerService<H, T> {
+    fn clone(&self) -> Self {
+        Self {
+            handler: self.handler.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<H, T> fmt::Debug for HandlerService<H, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HandlerService").finish()
+    }
+}
+
+impl<H, T> Service<Request> for HandlerService<H, T>
+where
+    H: Handler<T>,
+{
+    type Response = Response;
+    type Error = Error;
+    type Future = H::Future;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        self.handler.clone().call(req)
+    }
+}
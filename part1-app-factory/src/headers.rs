@@ -0,0 +1,500 @@
+//! Typed representations of common headers, for use with
+//! [`TypedHeader`](crate::extract::TypedHeader).
+
+use std::fmt;
+
+/// A header that can be parsed from and written as a header value.
+pub trait Header: Sized {
+    /// The header name, matched ignoring ASCII case.
+    const NAME: &'static str;
+
+    /// Parses the header value, returning `None` if it's malformed.
+    fn decode(value: &str) -> Option<Self>;
+
+    fn encode(&self) -> String;
+}
+
+/// `Content-Type`, the media type of the body including any parameters.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContentType(String);
+
+impl ContentType {
+    pub fn json() -> Self {
+        ContentType("application/json".to_owned())
+    }
+
+    pub fn form_url_encoded() -> Self {
+        ContentType("application/x-www-form-urlencoded".to_owned())
+    }
+
+    pub fn text() -> Self {
+        ContentType("text/plain; charset=utf-8".to_owned())
+    }
+
+    /// The media type without parameters, such as `text/plain`.
+    pub fn mime(&self) -> &str {
+        self.0.split(';').next().unwrap_or_default().trim()
+    }
+
+    /// The value of the `charset` parameter, if any.
+    pub fn charset(&self) -> Option<&str> {
+        self.param("charset")
+    }
+
+    /// The value of a parameter such as `boundary`, without quotes.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.0.split(';').skip(1).find_map(|param| {
+            let (key, value) = param.split_once('=')?;
+            if key.trim().eq_ignore_ascii_case(name) {
+                Some(value.trim().trim_matches('"'))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+impl Header for ContentType {
+    const NAME: &'static str = "Content-Type";
+
+    fn decode(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let (ty, subtype) = value.split(';').next()?.trim().split_once('/')?;
+        if ty.is_empty() || subtype.is_empty() {
+            return None;
+        }
+        Some(ContentType(value.to_owned()))
+    }
+
+    fn encode(&self) -> String {
+        self.0.clone()
+    }
+}
+
+impl fmt::Display for ContentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// `Authorization`, an authentication scheme followed by credentials.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Authorization {
+    scheme: String,
+    credentials: String,
+}
+
+impl Authorization {
+    pub fn bearer(token: &str) -> Self {
+        Authorization {
+            scheme: "Bearer".to_owned(),
+            credentials: token.to_owned(),
+        }
+    }
+
+    pub fn scheme(&self) -> &str {
+        &self.scheme
+    }
+
+    pub fn credentials(&self) -> &str {
+        &self.credentials
+    }
+
+    /// The token if the scheme is `Bearer`.
+    pub fn bearer_token(&self) -> Option<&str> {
+        if self.scheme.eq_ignore_ascii_case("bearer") {
+            Some(&self.credentials)
+        } else {
+            None
+        }
+    }
+}
+
+impl Header for Authorization {
+    const NAME: &'static str = "Authorization";
+
+    fn decode(value: &str) -> Option<Self> {
+        let (scheme, credentials) = value.trim().split_once(' ')?;
+        let credentials = credentials.trim();
+        if scheme.is_empty() || credentials.is_empty() {
+            return None;
+        }
+        Some(Authorization {
+            scheme: scheme.to_owned(),
+            credentials: credentials.to_owned(),
+        })
+    }
+
+    fn encode(&self) -> String {
+        format!("{} {}", self.scheme, self.credentials)
+    }
+}
+
+/// Leaves the credentials out so they don't end up in logs.
+impl fmt::Debug for Authorization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Authorization")
+            .field("scheme", &self.scheme)
+            .finish_non_exhaustive()
+    }
+}
+
+/// `User-Agent`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UserAgent(String);
+
+impl UserAgent {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Header for UserAgent {
+    const NAME: &'static str = "User-Agent";
+
+    fn decode(value: &str) -> Option<Self> {
+        Some(UserAgent(value.trim().to_owned()))
+    }
+
+    fn encode(&self) -> String {
+        self.0.clone()
+    }
+}
+
+impl fmt::Display for UserAgent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// `Host`, a host name with an optional port.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Host {
+    hostname: String,
+    port: Option<u16>,
+}
+
+impl Host {
+    pub fn hostname(&self) -> &str {
+        &self.hostname
+    }
+
+    pub fn port(&self) -> Option<u16> {
+        self.port
+    }
+}
+
+impl Header for Host {
+    const NAME: &'static str = "Host";
+
+    fn decode(value: &str) -> Option<Self> {
+        let value = value.trim();
+        // IPv6 addresses are bracketed and contain colons themselves.
+        let port_start = match value.rfind(':') {
+            Some(colon) if !value[colon..].contains(']') => Some(colon),
+            _ => None,
+        };
+        let (hostname, port) = match port_start {
+            Some(colon) => (&value[..colon], Some(value[colon + 1..].parse().ok()?)),
+            None => (value, None),
+        };
+
+        if hostname.is_empty() {
+            return None;
+        }
+        Some(Host {
+            hostname: hostname.to_owned(),
+            port,
+        })
+    }
+
+    fn encode(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for Host {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.hostname)?;
+        if let Some(port) = self.port {
+            write!(f, ":{}", port)?;
+        }
+        Ok(())
+    }
+}
+
+/// `Accept`, the media types a client wants in order of preference.
+///
+/// Each range has a quality from `q=0` to `q=1`, the default. Ranges that
+/// don't parse are skipped. A request without the header accepts anything,
+/// which is what [`Accept::default`] is.
+///
+/// ```
+/// # use part1_app_factory::headers::{Accept, Header};
+/// let accept = Accept::decode("text/html, application/json;q=0.9, */*;q=0.1").unwrap();
+/// assert_eq!(accept.preferred(&["application/json", "text/html"]), Some("text/html"));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Accept(Vec<MediaRange>);
+
+impl Accept {
+    pub fn media_ranges(&self) -> &[MediaRange] {
+        &self.0
+    }
+
+    /// The quality of `mime`, from the most specific range matching it, in
+    /// thousandths. `0` if nothing matches.
+    pub fn quality(&self, mime: &str) -> u16 {
+        let Some((ty, subtype)) = mime.split_once('/') else {
+            return 0;
+        };
+        self.0
+            .iter()
+            .filter(|range| range.matches(ty, subtype))
+            .max_by_key(|range| range.specificity())
+            .map_or(0, |range| range.quality)
+    }
+
+    /// The one of `offered` the client likes best, earlier ones winning ties.
+    /// `None` if it accepts none of them.
+    pub fn preferred<'a>(&self, offered: &[&'a str]) -> Option<&'a str> {
+        let mut best = None;
+        let mut best_quality = 0;
+        for &mime in offered {
+            let quality = self.quality(mime);
+            if quality > best_quality {
+                best = Some(mime);
+                best_quality = quality;
+            }
+        }
+        best
+    }
+}
+
+/// `*/*`.
+impl Default for Accept {
+    fn default() -> Self {
+        Accept(vec![MediaRange {
+            ty: "*".to_owned(),
+            subtype: "*".to_owned(),
+            quality: 1000,
+        }])
+    }
+}
+
+impl Header for Accept {
+    const NAME: &'static str = "Accept";
+
+    fn decode(value: &str) -> Option<Self> {
+        let ranges: Vec<_> = value
+            .split(',')
+            .filter(|range| !range.trim().is_empty())
+            .filter_map(MediaRange::parse)
+            .collect();
+        if ranges.is_empty() {
+            return None;
+        }
+        Some(Accept(ranges))
+    }
+
+    fn encode(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for Accept {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, range) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", range)?;
+        }
+        Ok(())
+    }
+}
+
+/// One entry of [`Accept`], such as `text/*;q=0.5`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MediaRange {
+    ty: String,
+    subtype: String,
+    quality: u16,
+}
+
+impl MediaRange {
+    /// The type, or `*`.
+    pub fn ty(&self) -> &str {
+        &self.ty
+    }
+
+    /// The subtype, or `*`.
+    pub fn subtype(&self) -> &str {
+        &self.subtype
+    }
+
+    /// In thousandths, so `q=0.5` is `500`.
+    pub fn quality(&self) -> u16 {
+        self.quality
+    }
+
+    fn parse(range: &str) -> Option<Self> {
+        let mut params = range.split(';');
+        let (ty, subtype) = params.next()?.trim().split_once('/')?;
+        if ty.is_empty() || subtype.is_empty() || ty == "*" && subtype != "*" {
+            return None;
+        }
+
+        let mut quality = 1000;
+        for param in params {
+            if let Some((key, value)) = param.split_once('=') {
+                if key.trim().eq_ignore_ascii_case("q") {
+                    quality = parse_quality(value.trim())?;
+                }
+            }
+        }
+
+        Some(MediaRange {
+            ty: ty.to_ascii_lowercase(),
+            subtype: subtype.to_ascii_lowercase(),
+            quality,
+        })
+    }
+
+    fn matches(&self, ty: &str, subtype: &str) -> bool {
+        (self.ty == "*" || self.ty.eq_ignore_ascii_case(ty))
+            && (self.subtype == "*" || self.subtype.eq_ignore_ascii_case(subtype))
+    }
+
+    fn specificity(&self) -> u8 {
+        u8::from(self.ty != "*") + u8::from(self.subtype != "*")
+    }
+}
+
+impl fmt::Display for MediaRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.ty, self.subtype)?;
+        write_quality(f, self.quality)
+    }
+}
+
+/// `Accept-Encoding`, the content codings a client can decode, such as
+/// `gzip`, with qualities like those of [`Accept`].
+///
+/// `*` stands for the codings that aren't listed, and `identity`, no coding
+/// at all, is acceptable unless refused. Codings that don't parse are
+/// skipped. A request without the header gets [`AcceptEncoding::default`],
+/// which accepts only `identity`.
+///
+/// ```
+/// # use part1_app_factory::headers::{AcceptEncoding, Header};
+/// let accept = AcceptEncoding::decode("br, gzip;q=0.8, *;q=0").unwrap();
+/// assert_eq!(accept.preferred(&["deflate", "gzip"]), Some("gzip"));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AcceptEncoding(Vec<(String, u16)>);
+
+impl AcceptEncoding {
+    /// The quality of `coding` in thousandths. `0` if it isn't acceptable.
+    pub fn quality(&self, coding: &str) -> u16 {
+        let listed = |coding: &str| {
+            self.0
+                .iter()
+                .find(|(listed, _)| listed.eq_ignore_ascii_case(coding))
+                .map(|(_, quality)| *quality)
+        };
+        let unlisted = if coding.eq_ignore_ascii_case("identity") {
+            1000
+        } else {
+            0
+        };
+        listed(coding).or_else(|| listed("*")).unwrap_or(unlisted)
+    }
+
+    /// The one of `offered` the client likes best, earlier ones winning ties.
+    /// `None` if it accepts none of them.
+    pub fn preferred<'a>(&self, offered: &[&'a str]) -> Option<&'a str> {
+        let mut best = None;
+        let mut best_quality = 0;
+        for &coding in offered {
+            let quality = self.quality(coding);
+            if quality > best_quality {
+                best = Some(coding);
+                best_quality = quality;
+            }
+        }
+        best
+    }
+}
+
+impl Header for AcceptEncoding {
+    const NAME: &'static str = "Accept-Encoding";
+
+    /// An empty value is valid, and accepts only `identity`.
+    fn decode(value: &str) -> Option<Self> {
+        let codings = value
+            .split(',')
+            .filter(|coding| !coding.trim().is_empty())
+            .filter_map(|coding| {
+                let mut params = coding.split(';');
+                let name = params.next()?.trim();
+                if name.is_empty() {
+                    return None;
+                }
+                let mut quality = 1000;
+                for param in params {
+                    if let Some((key, value)) = param.split_once('=') {
+                        if key.trim().eq_ignore_ascii_case("q") {
+                            quality = parse_quality(value.trim())?;
+                        }
+                    }
+                }
+                Some((name.to_ascii_lowercase(), quality))
+            })
+            .collect();
+        Some(AcceptEncoding(codings))
+    }
+
+    fn encode(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for AcceptEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (coding, quality)) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            f.write_str(coding)?;
+            write_quality(f, *quality)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the `;q=` parameter of `quality`, unless it's the default.
+fn write_quality(f: &mut fmt::Formatter<'_>, quality: u16) -> fmt::Result {
+    match quality {
+        1000 => Ok(()),
+        0 => f.write_str(";q=0"),
+        quality => {
+            let decimals = format!("{:03}", quality);
+            write!(f, ";q=0.{}", decimals.trim_end_matches('0'))
+        }
+    }
+}
+
+/// A `q` value, `0` to `1` with at most three decimals, in thousandths.
+fn parse_quality(value: &str) -> Option<u16> {
+    let (int, frac) = value.split_once('.').unwrap_or((value, ""));
+    if frac.len() > 3 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let thousandths = match int {
+        "0" => format!("{:0<3}", frac).parse().ok()?,
+        "1" if frac.bytes().all(|b| b == b'0') => 1000,
+        _ => return None,
+    };
+    Some(thousandths)
+}
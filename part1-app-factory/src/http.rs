@@ -0,0 +1,798 @@
+//! Request and response types.
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt,
+    net::SocketAddr,
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+pub use self::{
+    body::{Body, SizeHint},
+    cert::PeerCert,
+    header::{HeaderMap, HeaderName, HeaderValue},
+    interop::ConversionError,
+    uri::Uri,
+};
+
+pub(crate) use self::date::{format_http_date, format_log_date, parse_http_date};
+pub(crate) use self::interop::{
+    request_from_http, request_to_http, response_from_http, response_to_http,
+};
+
+mod body;
+mod cert;
+pub mod cookie;
+mod date;
+pub mod encoding;
+pub mod header;
+mod interop;
+mod uri;
+
+/// An HTTP request method.
+///
+/// Methods outside of RFC 9110, such as WebDAV's `PROPFIND`, are
+/// [`Method::Extension`]s. Parse them with `"PROPFIND".parse()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Patch,
+    Delete,
+    Options,
+    Connect,
+    Trace,
+    Extension(ExtensionMethod),
+}
+
+impl Method {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Patch => "PATCH",
+            Method::Delete => "DELETE",
+            Method::Options => "OPTIONS",
+            Method::Connect => "CONNECT",
+            Method::Trace => "TRACE",
+            Method::Extension(method) => method.as_str(),
+        }
+    }
+
+    /// Whether the method is read-only by definition: `GET`, `HEAD`,
+    /// `OPTIONS` and `TRACE`.
+    pub fn is_safe(&self) -> bool {
+        matches!(
+            self,
+            Method::Get | Method::Head | Method::Options | Method::Trace
+        )
+    }
+
+    /// Whether repeating the request has the same effect as sending it once,
+    /// which makes it safe to retry.
+    pub fn is_idempotent(&self) -> bool {
+        self.is_safe() || matches!(self, Method::Put | Method::Delete)
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Methods are case-sensitive, so `get` is an extension method rather than
+/// `GET`.
+impl FromStr for Method {
+    type Err = InvalidMethod;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let method = match s {
+            "GET" => Method::Get,
+            "HEAD" => Method::Head,
+            "POST" => Method::Post,
+            "PUT" => Method::Put,
+            "PATCH" => Method::Patch,
+            "DELETE" => Method::Delete,
+            "OPTIONS" => Method::Options,
+            "CONNECT" => Method::Connect,
+            "TRACE" => Method::Trace,
+            _ => Method::Extension(ExtensionMethod::new(s)?),
+        };
+        Ok(method)
+    }
+}
+
+/// The name of a [`Method::Extension`].
+///
+/// Names are stored inline so `Method` stays `Copy`, which limits them to
+/// 15 bytes. That fits every method registered with IANA.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExtensionMethod {
+    bytes: [u8; 15],
+    len: u8,
+}
+
+impl ExtensionMethod {
+    fn new(name: &str) -> Result<Self, InvalidMethod> {
+        if name.len() > 15 || !is_token(name) {
+            return Err(InvalidMethod(()));
+        }
+
+        let mut bytes = [0; 15];
+        bytes[..name.len()].copy_from_slice(name.as_bytes());
+        Ok(ExtensionMethod {
+            bytes,
+            len: name.len() as u8,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.bytes[..self.len as usize])
+            .expect("extension methods are ASCII tokens")
+    }
+}
+
+impl fmt::Debug for ExtensionMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+/// Error parsing a [`Method`] that isn't a token of at most 15 bytes.
+#[derive(Debug)]
+pub struct InvalidMethod(());
+
+impl fmt::Display for InvalidMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Invalid HTTP method")
+    }
+}
+
+impl std::error::Error for InvalidMethod {}
+
+/// The HTTP version of a request or response.
+///
+/// Responses are sent with the version of the request they answer, so the
+/// version of a response a handler builds only matters when it's sent on its
+/// own.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Version {
+    Http10,
+    #[default]
+    Http11,
+    Http2,
+}
+
+impl Version {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Version::Http10 => "HTTP/1.0",
+            Version::Http11 => "HTTP/1.1",
+            Version::Http2 => "HTTP/2",
+        }
+    }
+
+    /// Whether a connection stays open after a response unless a
+    /// `Connection` header says otherwise. HTTP/1.0 closes it by default.
+    pub fn is_keep_alive_default(&self) -> bool {
+        *self != Version::Http10
+    }
+
+    /// Whether a body of unknown length can be sent with
+    /// `Transfer-Encoding: chunked`. Only HTTP/1.1 has it: HTTP/1.0 ends
+    /// such a body by closing the connection and HTTP/2 frames every body.
+    pub fn supports_chunked_encoding(&self) -> bool {
+        *self == Version::Http11
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Parses the version as written on a request line, e.g. `HTTP/1.1`.
+impl FromStr for Version {
+    type Err = InvalidVersion;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "HTTP/1.0" => Ok(Version::Http10),
+            "HTTP/1.1" => Ok(Version::Http11),
+            "HTTP/2" | "HTTP/2.0" => Ok(Version::Http2),
+            _ => Err(InvalidVersion(())),
+        }
+    }
+}
+
+/// Error parsing a [`Version`] other than HTTP/1.0, 1.1 or 2.
+#[derive(Debug)]
+pub struct InvalidVersion(());
+
+impl fmt::Display for InvalidVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Invalid or unsupported HTTP version")
+    }
+}
+
+impl std::error::Error for InvalidVersion {}
+
+/// An HTTP status code.
+///
+/// Only values from 100 to 999 can be constructed, so a typo such as 2000
+/// is caught by [`StatusCode::from_u16`] instead of being sent.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StatusCode(u16);
+
+macro_rules! status_codes {
+    ( $( ($code:expr, $name:ident, $reason:expr); )+ ) => {
+        impl StatusCode {
+            $(
+                pub const $name: StatusCode = StatusCode($code);
+            )+
+
+            /// The reason phrase of a well-known status, e.g. `Not Found`
+            /// for 404.
+            pub fn canonical_reason(&self) -> Option<&'static str> {
+                match self.0 {
+                    $( $code => Some($reason), )+
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+status_codes! {
+    (100, CONTINUE, "Continue");
+    (101, SWITCHING_PROTOCOLS, "Switching Protocols");
+    (200, OK, "OK");
+    (201, CREATED, "Created");
+    (202, ACCEPTED, "Accepted");
+    (204, NO_CONTENT, "No Content");
+    (206, PARTIAL_CONTENT, "Partial Content");
+    (301, MOVED_PERMANENTLY, "Moved Permanently");
+    (302, FOUND, "Found");
+    (303, SEE_OTHER, "See Other");
+    (304, NOT_MODIFIED, "Not Modified");
+    (307, TEMPORARY_REDIRECT, "Temporary Redirect");
+    (308, PERMANENT_REDIRECT, "Permanent Redirect");
+    (400, BAD_REQUEST, "Bad Request");
+    (401, UNAUTHORIZED, "Unauthorized");
+    (403, FORBIDDEN, "Forbidden");
+    (404, NOT_FOUND, "Not Found");
+    (405, METHOD_NOT_ALLOWED, "Method Not Allowed");
+    (406, NOT_ACCEPTABLE, "Not Acceptable");
+    (408, REQUEST_TIMEOUT, "Request Timeout");
+    (409, CONFLICT, "Conflict");
+    (410, GONE, "Gone");
+    (411, LENGTH_REQUIRED, "Length Required");
+    (412, PRECONDITION_FAILED, "Precondition Failed");
+    (413, PAYLOAD_TOO_LARGE, "Payload Too Large");
+    (414, URI_TOO_LONG, "URI Too Long");
+    (415, UNSUPPORTED_MEDIA_TYPE, "Unsupported Media Type");
+    (416, RANGE_NOT_SATISFIABLE, "Range Not Satisfiable");
+    (417, EXPECTATION_FAILED, "Expectation Failed");
+    (422, UNPROCESSABLE_ENTITY, "Unprocessable Entity");
+    (429, TOO_MANY_REQUESTS, "Too Many Requests");
+    (431, REQUEST_HEADER_FIELDS_TOO_LARGE, "Request Header Fields Too Large");
+    (500, INTERNAL_SERVER_ERROR, "Internal Server Error");
+    (501, NOT_IMPLEMENTED, "Not Implemented");
+    (502, BAD_GATEWAY, "Bad Gateway");
+    (503, SERVICE_UNAVAILABLE, "Service Unavailable");
+    (504, GATEWAY_TIMEOUT, "Gateway Timeout");
+    (505, HTTP_VERSION_NOT_SUPPORTED, "HTTP Version Not Supported");
+}
+
+impl StatusCode {
+    /// `None` unless `code` has three digits.
+    pub fn from_u16(code: u16) -> Option<StatusCode> {
+        if (100..1000).contains(&code) {
+            Some(StatusCode(code))
+        } else {
+            None
+        }
+    }
+
+    pub fn as_u16(&self) -> u16 {
+        self.0
+    }
+
+    /// 1xx
+    pub fn is_informational(&self) -> bool {
+        (100..200).contains(&self.0)
+    }
+
+    /// 2xx
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.0)
+    }
+
+    /// 3xx
+    pub fn is_redirection(&self) -> bool {
+        (300..400).contains(&self.0)
+    }
+
+    /// 4xx
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.0)
+    }
+
+    /// 5xx
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.0)
+    }
+}
+
+impl Default for StatusCode {
+    fn default() -> Self {
+        StatusCode::OK
+    }
+}
+
+/// The code and, if known, the reason, e.g. `404 Not Found`.
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.canonical_reason() {
+            Some(reason) => write!(f, "{} {}", self.0, reason),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+impl fmt::Debug for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+#[derive(Debug)]
+pub struct Request {
+    pub method: Method,
+    pub uri: Uri,
+    pub version: Version,
+    pub headers: HeaderMap,
+    pub body: Body,
+    pub extensions: Extensions,
+}
+
+/// Everything in a [`Request`] except its body.
+#[derive(Debug)]
+pub struct Parts {
+    pub method: Method,
+    pub uri: Uri,
+    pub version: Version,
+    pub headers: HeaderMap,
+    pub extensions: Extensions,
+}
+
+impl Request {
+    /// Starts a `GET /` request without headers, e.g. for tests.
+    ///
+    /// ```
+    /// # use part1_app_factory::http::{Method, Request};
+    /// let req = Request::builder()
+    ///     .method(Method::Post)
+    ///     .uri("/users")
+    ///     .header("Content-Type", "application/json")
+    ///     .body(r#"{"name":"Ferris"}"#)?;
+    /// # assert_eq!(req.uri.path(), "/users");
+    /// # Ok::<_, anyhow::Error>(())
+    /// ```
+    pub fn builder() -> RequestBuilder {
+        RequestBuilder {
+            inner: Ok(Request {
+                method: Method::Get,
+                uri: Uri::default(),
+                version: Version::default(),
+                headers: HeaderMap::new(),
+                body: Body::empty(),
+                extensions: Extensions::default(),
+            }),
+        }
+    }
+
+    pub fn into_parts(self) -> (Parts, Body) {
+        let parts = Parts {
+            method: self.method,
+            uri: self.uri,
+            version: self.version,
+            headers: self.headers,
+            extensions: self.extensions,
+        };
+        (parts, self.body)
+    }
+
+    pub fn from_parts(parts: Parts, body: Body) -> Self {
+        Request {
+            method: parts.method,
+            uri: parts.uri,
+            version: parts.version,
+            headers: parts.headers,
+            body,
+            extensions: parts.extensions,
+        }
+    }
+}
+
+/// Builder returned by [`Request::builder`].
+///
+/// Like [`ResponseBuilder`], it reports the first invalid header from
+/// [`RequestBuilder::body`].
+#[derive(Debug)]
+pub struct RequestBuilder {
+    inner: Result<Request, InvalidHeader>,
+}
+
+impl RequestBuilder {
+    pub fn method(mut self, method: Method) -> Self {
+        if let Ok(req) = &mut self.inner {
+            req.method = method;
+        }
+        self
+    }
+
+    pub fn uri(mut self, uri: impl Into<Uri>) -> Self {
+        if let Ok(req) = &mut self.inner {
+            req.uri = uri.into();
+        }
+        self
+    }
+
+    pub fn version(mut self, version: Version) -> Self {
+        if let Ok(req) = &mut self.inner {
+            req.version = version;
+        }
+        self
+    }
+
+    /// Sets a header, replacing any earlier value with the same name. See
+    /// [`ResponseBuilder::header`] for what's valid.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.inner = self.inner.and_then(|mut req| {
+            let invalid = || InvalidHeader::new(name);
+            let value = value.parse().map_err(|_| invalid())?;
+            req.headers
+                .insert(name.parse().map_err(|_| invalid())?, value);
+            Ok(req)
+        });
+        self
+    }
+
+    /// Stores `val` in the request's extensions, as a server does with
+    /// connection info.
+    pub fn extension<T: Send + Sync + 'static>(mut self, val: T) -> Self {
+        if let Ok(req) = &mut self.inner {
+            req.extensions.insert(val);
+        }
+        self
+    }
+
+    pub fn body(self, body: impl Into<Body>) -> Result<Request, InvalidHeader> {
+        let mut req = self.inner?;
+        req.body = body.into();
+        Ok(req)
+    }
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: StatusCode,
+    pub version: Version,
+    pub headers: HeaderMap,
+    pub body: Body,
+    pub extensions: Extensions,
+}
+
+impl Response {
+    /// Starts a `200 OK` response without headers.
+    ///
+    /// ```
+    /// # use part1_app_factory::http::{Body, Response, StatusCode};
+    /// let resp = Response::builder()
+    ///     .status(StatusCode::CREATED)
+    ///     .header("Location", "/users/42")
+    ///     .body(Body::empty())?;
+    /// # assert_eq!(resp.status, StatusCode::CREATED);
+    /// # Ok::<_, anyhow::Error>(())
+    /// ```
+    pub fn builder() -> ResponseBuilder {
+        ResponseBuilder {
+            inner: Ok(Response {
+                status: StatusCode::OK,
+                version: Version::default(),
+                headers: HeaderMap::new(),
+                body: Body::empty(),
+                extensions: Extensions::default(),
+            }),
+        }
+    }
+}
+
+/// Builder returned by [`Response::builder`].
+///
+/// The first invalid header is remembered and reported by
+/// [`ResponseBuilder::body`], so calls can be chained without checking
+/// each one.
+#[derive(Debug)]
+pub struct ResponseBuilder {
+    inner: Result<Response, InvalidHeader>,
+}
+
+impl ResponseBuilder {
+    pub fn status(mut self, status: StatusCode) -> Self {
+        if let Ok(resp) = &mut self.inner {
+            resp.status = status;
+        }
+        self
+    }
+
+    pub fn version(mut self, version: Version) -> Self {
+        if let Ok(resp) = &mut self.inner {
+            resp.version = version;
+        }
+        self
+    }
+
+    /// Stores `val` in the response's extensions, for layers further out.
+    pub fn extension<T: Send + Sync + 'static>(mut self, val: T) -> Self {
+        if let Ok(resp) = &mut self.inner {
+            resp.extensions.insert(val);
+        }
+        self
+    }
+
+    /// Sets a header, replacing any earlier value with the same name.
+    ///
+    /// Names must be HTTP tokens, such as `Content-Type`, and values must
+    /// not contain control characters such as line breaks.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.inner = self.inner.and_then(|mut resp| {
+            let invalid = || InvalidHeader::new(name);
+            let value = value.parse().map_err(|_| invalid())?;
+            resp.headers
+                .insert(name.parse().map_err(|_| invalid())?, value);
+            Ok(resp)
+        });
+        self
+    }
+
+    pub fn body(self, body: impl Into<Body>) -> Result<Response, InvalidHeader> {
+        let mut resp = self.inner?;
+        resp.body = body.into();
+        Ok(resp)
+    }
+}
+
+/// Error from [`RequestBuilder::body`] and [`ResponseBuilder::body`] when a
+/// header's name or value wasn't valid, and from parsing a [`HeaderName`] or [`HeaderValue`].
+#[derive(Debug)]
+pub struct InvalidHeader {
+    name: String,
+}
+
+impl InvalidHeader {
+    pub(crate) fn new(name: &str) -> Self {
+        InvalidHeader {
+            name: name.to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for InvalidHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid header `{}`", self.name.escape_debug())
+    }
+}
+
+impl std::error::Error for InvalidHeader {}
+
+/// Whether `name` is a token as defined by RFC 9110, which header names and
+/// methods must be.
+fn is_token(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(is_tchar)
+}
+
+const fn is_tchar(b: u8) -> bool {
+    matches!(
+        b,
+        b'0'..=b'9'
+            | b'a'..=b'z'
+            | b'A'..=b'Z'
+            | b'!'
+            | b'#'
+            | b'$'
+            | b'%'
+            | b'&'
+            | b'\''
+            | b'*'
+            | b'+'
+            | b'-'
+            | b'.'
+            | b'^'
+            | b'_'
+            | b'`'
+            | b'|'
+            | b'~'
+    )
+}
+
+/// A type map for data attached to a request or response, such as the path
+/// parameters captured by the router or the connection info from the server.
+///
+/// Each type has at most one value, so crates should store their own types
+/// rather than e.g. a bare `String`.
+#[derive(Default)]
+pub struct Extensions {
+    map: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl Extensions {
+    pub fn insert<T: Send + Sync + 'static>(&mut self, val: T) -> Option<T> {
+        self.map
+            .insert(TypeId::of::<T>(), Box::new(val))
+            .and_then(|prev| prev.downcast().ok().map(|prev| *prev))
+    }
+
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .and_then(|val| val.downcast_ref())
+    }
+
+    pub fn get_mut<T: Send + Sync + 'static>(&mut self) -> Option<&mut T> {
+        self.map
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|val| val.downcast_mut())
+    }
+
+    pub fn remove<T: Send + Sync + 'static>(&mut self) -> Option<T> {
+        self.map
+            .remove(&TypeId::of::<T>())
+            .and_then(|prev| prev.downcast().ok().map(|prev| *prev))
+    }
+
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Moves every value of `other` in, replacing values of the same type.
+    pub fn extend(&mut self, other: Extensions) {
+        self.map.extend(other.map);
+    }
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Extensions")
+            .field("len", &self.map.len())
+            .finish()
+    }
+}
+
+/// What the server knows about a connection, handed to the app factory
+/// when it's accepted.
+#[derive(Clone, Debug)]
+pub struct ConnInfo {
+    /// Tells the connection apart from the others the process accepted,
+    /// e.g. to group its requests in logs.
+    pub id: u64,
+    /// The local address the connection was accepted on.
+    pub host_and_port: String,
+    /// The same, if the connection came over a network, e.g. to tell apart
+    /// the addresses of a server bound to several.
+    pub local_addr: Option<SocketAddr>,
+    /// The client's address, if the connection came over a network.
+    pub peer_addr: Option<SocketAddr>,
+    /// Who the client process runs as, if the connection came over a Unix
+    /// domain socket.
+    pub peer_cred: Option<PeerCred>,
+    /// What the TLS handshake negotiated, if the connection is encrypted.
+    pub tls: Option<TlsInfo>,
+}
+
+impl ConnInfo {
+    /// An [`id`](ConnInfo::id) for a new connection.
+    pub(crate) fn next_id() -> u64 {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        NEXT_ID.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+/// The credentials of the process on the other end of a Unix domain socket.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PeerCred {
+    pub uid: u32,
+    pub gid: u32,
+    /// Not every platform reports it.
+    pub pid: Option<i32>,
+}
+
+/// What was negotiated in a connection's TLS handshake.
+#[derive(Clone, Debug, Default)]
+pub struct TlsInfo {
+    /// The host name the client asked for (SNI), if it sent one.
+    pub server_name: Option<String>,
+    /// The application protocol agreed on with ALPN, e.g. `b"http/1.1"`.
+    pub alpn_protocol: Option<Vec<u8>>,
+    /// The certificate the client authenticated with, if the server asks
+    /// for one and the client sent it.
+    pub peer_cert: Option<PeerCert>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn builds_requests() {
+        let req = Request::builder()
+            .method(Method::Post)
+            .uri("/users?page=2")
+            .version(Version::Http10)
+            .header("Content-Type", "text/plain")
+            .header("content-type", "application/json")
+            .extension(7u32)
+            .body(r#"{"name":"Ferris"}"#)
+            .unwrap();
+
+        assert_eq!(req.method, Method::Post);
+        assert_eq!(req.uri.path(), "/users");
+        assert_eq!(req.uri.query(), Some("page=2"));
+        assert_eq!(req.version, Version::Http10);
+        assert_eq!(req.headers.len(), 1);
+        assert_eq!(
+            req.headers.get("Content-Type").unwrap().as_str(),
+            "application/json"
+        );
+        assert_eq!(req.extensions.get::<u32>(), Some(&7));
+        assert_eq!(
+            req.body.collect().await.unwrap(),
+            r#"{"name":"Ferris"}"#.as_bytes()
+        );
+    }
+
+    #[tokio::test]
+    async fn defaults_to_get_slash() {
+        let req = Request::builder().body(Body::empty()).unwrap();
+
+        assert_eq!(req.method, Method::Get);
+        assert_eq!(req.uri, Uri::from("/"));
+        assert_eq!(req.version, Version::Http11);
+        assert_eq!(req.headers.len(), 0);
+        assert!(req.body.collect().await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn reports_the_first_invalid_header() {
+        let err = Request::builder()
+            .header("X Bad", "1")
+            .header("X-Also-Bad", "line\nbreak")
+            .body(Body::empty())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Invalid header `X Bad`");
+
+        let err = Request::builder()
+            .header("X-Bad", "line\nbreak")
+            .header("X-Good", "1")
+            .body(Body::empty())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Invalid header `X-Bad`");
+    }
+}
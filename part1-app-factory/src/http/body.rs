@@ -0,0 +1,274 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use anyhow::Error;
+use bytes::{Bytes, BytesMut};
+use futures_util::{Stream, StreamExt, TryStreamExt};
+
+use super::HeaderMap;
+
+type BoxStream = Pin<Box<dyn Stream<Item = Result<Bytes, Error>> + Send>>;
+type BoxTrailers = Pin<Box<dyn Future<Output = Option<HeaderMap>> + Send>>;
+
+/// The body of a request or response: empty, fully in memory or produced
+/// chunk by chunk.
+///
+/// Streaming bodies let handlers send large files or generated content
+/// without buffering all of it:
+///
+/// ```
+/// # use std::convert::Infallible;
+/// # use bytes::Bytes;
+/// # use futures_util::stream::{self, StreamExt};
+/// # use part1_app_factory::{http::Body, router::get};
+/// async fn numbers() -> Body {
+///     let chunks = stream::iter(1..=3).map(|n| Ok::<_, Infallible>(Bytes::from(n.to_string())));
+///     Body::from_stream(chunks)
+/// }
+/// # let _ = get(numbers);
+/// ```
+///
+/// Either way the body is a [`Stream`] of chunks, which is how the server
+/// sends it and how extractors read it.
+///
+/// A body can be followed by trailers, header fields that are only known
+/// once it's done, such as a checksum. They're sent after the last chunk of
+/// a chunked response, see [`Body::with_trailers`], and those of a chunked
+/// request are read with [`Body::trailers`].
+pub struct Body {
+    kind: Kind,
+    trailers: Option<BoxTrailers>,
+}
+
+enum Kind {
+    Empty,
+    Full(Bytes),
+    Streaming(BoxStream, SizeHint),
+}
+
+impl Body {
+    fn new(kind: Kind) -> Self {
+        Body {
+            kind,
+            trailers: None,
+        }
+    }
+
+    pub fn empty() -> Self {
+        Body::new(Kind::Empty)
+    }
+
+    /// A body whose chunks are produced by `stream`. An error ends the body.
+    pub fn from_stream<S, E>(stream: S) -> Self
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+        E: Into<Error> + 'static,
+    {
+        Body::new(Kind::Streaming(
+            Box::pin(stream.map_err(Into::into)),
+            SizeHint::default(),
+        ))
+    }
+
+    /// A body of `len` bytes produced by `stream`, e.g. one read from a
+    /// connection with a `Content-Length`. Its length is known without
+    /// reading it, and sent as the `Content-Length` of a response.
+    pub fn from_stream_with_len<S, E>(stream: S, len: u64) -> Self
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+        E: Into<Error> + 'static,
+    {
+        Body::new(Kind::Streaming(
+            Box::pin(stream.map_err(Into::into)),
+            SizeHint::with_exact(len),
+        ))
+    }
+
+    /// Follows the body with the trailers `trailers` resolves to once the
+    /// last chunk was read. `None` means there are none.
+    ///
+    /// Only chunked responses can carry trailers, so a response body with
+    /// them is sent chunked even if its length is known, except to HTTP/1.0
+    /// clients, which don't get them.
+    pub fn with_trailers<F>(mut self, trailers: F) -> Self
+    where
+        F: Future<Output = Option<HeaderMap>> + Send + 'static,
+    {
+        self.trailers = Some(Box::pin(trailers));
+        self
+    }
+
+    pub(crate) fn has_trailers(&self) -> bool {
+        self.trailers.is_some()
+    }
+
+    /// Takes the trailers off the body, e.g. to put them on one made from
+    /// it.
+    pub(crate) fn take_trailers(
+        &mut self,
+    ) -> Option<impl Future<Output = Option<HeaderMap>> + Send + 'static> {
+        self.trailers.take()
+    }
+
+    /// The trailers that followed the body, e.g. those at the end of a
+    /// chunked request. Any chunks not read yet are skipped, and `None`
+    /// means there were no trailers.
+    pub async fn trailers(&mut self) -> Result<Option<HeaderMap>, Error> {
+        while let Some(chunk) = self.next().await {
+            chunk?;
+        }
+        match self.trailers.take() {
+            Some(trailers) => Ok(trailers.await),
+            None => Ok(None),
+        }
+    }
+
+    /// The whole body, if it's in memory rather than streamed.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match &self.kind {
+            Kind::Empty => Some(&[]),
+            Kind::Full(bytes) => Some(bytes),
+            Kind::Streaming(..) => None,
+        }
+    }
+
+    /// The whole body if it's in memory, or the body itself back if it's
+    /// streamed.
+    pub fn try_into_bytes(self) -> Result<Bytes, Body> {
+        match self.kind {
+            Kind::Empty => Ok(Bytes::new()),
+            Kind::Full(bytes) => Ok(bytes),
+            Kind::Streaming(..) => Err(self),
+        }
+    }
+
+    /// How many bytes are left. Exact unless the body is streamed without
+    /// a length.
+    pub fn size_hint(&self) -> SizeHint {
+        match &self.kind {
+            Kind::Empty => SizeHint::with_exact(0),
+            Kind::Full(bytes) => SizeHint::with_exact(bytes.len() as u64),
+            Kind::Streaming(_, size_hint) => *size_hint,
+        }
+    }
+
+    /// Waits for every chunk and joins them.
+    pub async fn collect(self) -> Result<Bytes, Error> {
+        let mut stream = match self.kind {
+            Kind::Empty => return Ok(Bytes::new()),
+            Kind::Full(bytes) => return Ok(bytes),
+            Kind::Streaming(stream, _) => stream,
+        };
+
+        let mut buf = BytesMut::new();
+        while let Some(chunk) = stream.next().await {
+            buf.extend_from_slice(&chunk?);
+        }
+        Ok(buf.freeze())
+    }
+}
+
+impl Stream for Body {
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match std::mem::replace(&mut self.kind, Kind::Empty) {
+            Kind::Empty => Poll::Ready(None),
+            Kind::Full(bytes) if bytes.is_empty() => Poll::Ready(None),
+            Kind::Full(bytes) => Poll::Ready(Some(Ok(bytes))),
+            Kind::Streaming(mut stream, size_hint) => {
+                let next = stream.as_mut().poll_next(cx);
+                let size_hint = match &next {
+                    // Done, so the stream is dropped and never polled again.
+                    Poll::Ready(None) => return next,
+                    Poll::Ready(Some(Ok(chunk))) => size_hint.consumed(chunk.len() as u64),
+                    _ => size_hint,
+                };
+                self.kind = Kind::Streaming(stream, size_hint);
+                next
+            }
+        }
+    }
+}
+
+impl Default for Body {
+    fn default() -> Self {
+        Body::empty()
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            Kind::Empty => f.write_str("Body(<empty>)"),
+            Kind::Full(bytes) => f.debug_tuple("Body").field(bytes).finish(),
+            Kind::Streaming(..) => f.write_str("Body(<stream>)"),
+        }
+    }
+}
+
+impl From<Bytes> for Body {
+    fn from(bytes: Bytes) -> Self {
+        Body::new(Kind::Full(bytes))
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Self {
+        Bytes::from(bytes).into()
+    }
+}
+
+impl From<String> for Body {
+    fn from(text: String) -> Self {
+        Bytes::from(text).into()
+    }
+}
+
+impl From<&'static str> for Body {
+    fn from(text: &'static str) -> Self {
+        Bytes::from_static(text.as_bytes()).into()
+    }
+}
+
+/// Bounds on the number of bytes left in a [`Body`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SizeHint {
+    lower: u64,
+    upper: Option<u64>,
+}
+
+impl SizeHint {
+    pub fn with_exact(len: u64) -> Self {
+        SizeHint {
+            lower: len,
+            upper: Some(len),
+        }
+    }
+
+    pub fn lower(&self) -> u64 {
+        self.lower
+    }
+
+    /// `None` if there's no known bound.
+    pub fn upper(&self) -> Option<u64> {
+        self.upper
+    }
+
+    /// The length, if the bounds agree on it.
+    pub fn exact(&self) -> Option<u64> {
+        self.upper.filter(|upper| *upper == self.lower)
+    }
+
+    /// What's left after `len` more bytes were read.
+    fn consumed(self, len: u64) -> Self {
+        SizeHint {
+            lower: self.lower.saturating_sub(len),
+            upper: self.upper.map(|upper| upper.saturating_sub(len)),
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! The certificate a client presented in a TLS handshake.
+
+use std::fmt::{self, Write};
+
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+use x509_parser::{
+    certificate::X509Certificate,
+    der_parser::asn1_rs::{Any, FromDer, Tag, ToDer},
+};
+
+/// The certificate chain a client authenticated with, for mutual TLS.
+///
+/// Only clients whose chain the server's certificate verifier accepted get
+/// this far, so handlers can authorize by [`subject`](PeerCert::subject) or
+/// pin a [`fingerprint`](PeerCert::fingerprint):
+///
+/// ```
+/// # use part1_app_factory::{http::PeerCert, router::get};
+/// async fn whoami(cert: PeerCert) -> String {
+///     format!("Hello, {}", cert.subject)
+/// }
+/// # let _ = get(whoami);
+/// ```
+///
+/// A handler that also serves clients without a certificate takes an
+/// `Option<PeerCert>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerCert {
+    /// The DER encoded certificates, the client's own first.
+    pub chain: Vec<Bytes>,
+    /// The subject of the client's certificate as an RFC 4514 string, e.g.
+    /// `CN=alice,O=Example`. Empty if the certificate couldn't be parsed.
+    pub subject: String,
+    /// The SHA-256 digest of the client's DER encoded certificate.
+    pub fingerprint: [u8; 32],
+}
+
+impl PeerCert {
+    /// Returns `None` for an empty chain.
+    pub(crate) fn from_chain(chain: Vec<Bytes>) -> Option<Self> {
+        let end_entity = chain.first()?;
+        Some(PeerCert {
+            subject: subject(end_entity).unwrap_or_default(),
+            fingerprint: Sha256::digest(end_entity).into(),
+            chain,
+        })
+    }
+
+    /// The fingerprint as lowercase hex, as `openssl x509 -fingerprint`
+    /// prints it without the colons.
+    pub fn fingerprint_hex(&self) -> String {
+        self.fingerprint
+            .iter()
+            .fold(String::new(), |mut hex, byte| {
+                let _ = write!(hex, "{:02x}", byte);
+                hex
+            })
+    }
+}
+
+/// Reads the subject out of a certificate, formatted with the last relative
+/// distinguished name first as RFC 4514 has it.
+fn subject(der: &[u8]) -> Option<String> {
+    let (_, cert) = X509Certificate::from_der(der).ok()?;
+    let mut rdns: Vec<String> = cert
+        .subject()
+        .iter_rdn()
+        .map(|rdn| {
+            let attributes: Vec<String> = rdn
+                .iter()
+                .map(|attribute| {
+                    let oid = attribute.attr_type().to_id_string();
+                    format!(
+                        "{}={}",
+                        attribute_name(&oid).unwrap_or(&oid),
+                        attribute_value(attribute.attr_value())
+                    )
+                })
+                .collect();
+            attributes.join("+")
+        })
+        .collect();
+    rdns.reverse();
+    Some(rdns.join(","))
+}
+
+/// The short names RFC 4514 gives attribute types. Others are written as
+/// their dotted OID.
+fn attribute_name(oid: &str) -> Option<&'static str> {
+    let name = match oid {
+        "2.5.4.3" => "CN",
+        "2.5.4.6" => "C",
+        "2.5.4.7" => "L",
+        "2.5.4.8" => "ST",
+        "2.5.4.9" => "STREET",
+        "2.5.4.10" => "O",
+        "2.5.4.11" => "OU",
+        "0.9.2342.19200300.100.1.1" => "UID",
+        "0.9.2342.19200300.100.1.25" => "DC",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Strings are escaped as RFC 4514 asks. Values of other types are written
+/// as `#` and their hex encoded DER.
+fn attribute_value(value: &Any<'_>) -> String {
+    let text = match value.tag() {
+        Tag::Utf8String | Tag::PrintableString | Tag::T61String | Tag::Ia5String => {
+            String::from_utf8_lossy(value.data).into_owned()
+        }
+        Tag::BmpString => {
+            let units: Vec<u16> = value
+                .data
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => {
+            let raw = value.to_der_vec().unwrap_or_default();
+            return raw.iter().fold(String::from("#"), |mut hex, byte| {
+                let _ = write!(hex, "{:02x}", byte);
+                hex
+            });
+        }
+    };
+    Escaped(&text).to_string()
+}
+
+struct Escaped<'a>(&'a str);
+
+impl fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let last = self.0.chars().count().saturating_sub(1);
+        for (i, c) in self.0.chars().enumerate() {
+            let special = matches!(c, '"' | '+' | ',' | ';' | '<' | '>' | '\\')
+                || (i == 0 && matches!(c, '#' | ' '))
+                || (i == last && c == ' ');
+            if c == '\0' {
+                f.write_str("\\00")?;
+            } else if special {
+                write!(f, "\\{}", c)?;
+            } else {
+                f.write_char(c)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: &[u8] = include_bytes!("testdata/alice.der");
+
+    #[test]
+    fn reads_subject() {
+        let cert = PeerCert::from_chain(vec![Bytes::from_static(ALICE)]).unwrap();
+        assert_eq!(
+            cert.subject,
+            "CN=alice+UID=a1,O=Example\\, Inc.,DC=example,DC=com"
+        );
+        assert_eq!(
+            cert.fingerprint_hex(),
+            "ba4c423107ecbdf36e43dc3dce0c354d9d2a92e519f5ef327bd7333c8465115f"
+        );
+    }
+
+    #[test]
+    fn escapes_values() {
+        assert_eq!(Escaped(" #a+b, c\\ ").to_string(), "\\ #a\\+b\\, c\\\\\\ ");
+    }
+
+    #[test]
+    fn tolerates_garbage() {
+        let cert = PeerCert::from_chain(vec![Bytes::from_static(b"not a certificate")]).unwrap();
+        assert_eq!(cert.subject, "");
+        assert!(PeerCert::from_chain(Vec::new()).is_none());
+    }
+}
@@ -0,0 +1,415 @@
+//! Cookies as sent by clients in `Cookie` headers and set by servers with
+//! `Set-Cookie` headers.
+//!
+//! Values are percent-decoded when parsed and percent-encoded when written,
+//! so they can hold any text.
+
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    date::{format_http_date, parse_http_date},
+    encoding::{encode, percent_decode},
+};
+
+/// A cookie sent by the client: only a name and a value, the client keeps
+/// the attributes to itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+}
+
+impl Cookie {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Cookie {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+/// As it appears in a `Cookie` header.
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, encode(&self.value, is_cookie_octet))
+    }
+}
+
+/// The cookies in the value of a `Cookie` header, e.g. `theme=dark; lang=en`.
+///
+/// Pairs without a name or an `=` are skipped.
+pub fn parse_cookie_header(value: &str) -> impl Iterator<Item = Cookie> + '_ {
+    value.split(';').filter_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+        Some(Cookie::new(name, percent_decode(unquote(value.trim()))))
+    })
+}
+
+/// A cookie for the client to store, with the attributes controlling where
+/// and for how long it sends it back.
+///
+/// ```
+/// # use std::time::Duration;
+/// # use part1_app_factory::http::cookie::{SameSite, SetCookie};
+/// let cookie = SetCookie::new("session", "1234")
+///     .with_path("/")
+///     .with_max_age(Duration::from_secs(3600))
+///     .with_same_site(SameSite::Lax)
+///     .with_http_only(true);
+/// assert_eq!(
+///     cookie.to_string(),
+///     "session=1234; Path=/; Max-Age=3600; SameSite=Lax; HttpOnly",
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SetCookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    expires: Option<SystemTime>,
+    max_age: Option<Duration>,
+    same_site: Option<SameSite>,
+    secure: bool,
+    http_only: bool,
+}
+
+impl SetCookie {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        SetCookie {
+            name: name.into(),
+            value: value.into(),
+            path: None,
+            domain: None,
+            expires: None,
+            max_age: None,
+            same_site: None,
+            secure: false,
+            http_only: false,
+        }
+    }
+
+    /// A cookie telling the client to delete its cookie named `name`. Give it
+    /// the same path and domain the cookie was set with.
+    pub fn removal(name: impl Into<String>) -> Self {
+        SetCookie::new(name, "")
+            .with_expires(UNIX_EPOCH)
+            .with_max_age(Duration::ZERO)
+    }
+
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn with_domain(mut self, domain: impl Into<String>) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    /// When the client should delete the cookie. Without this or a max age
+    /// it is deleted when the browser closes.
+    pub fn with_expires(mut self, expires: SystemTime) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    /// How long the client should keep the cookie, taking precedence over
+    /// the expiry.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn with_same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// Only send the cookie over HTTPS.
+    pub fn with_secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Hide the cookie from JavaScript.
+    pub fn with_http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    pub fn domain(&self) -> Option<&str> {
+        self.domain.as_deref()
+    }
+
+    pub fn expires(&self) -> Option<SystemTime> {
+        self.expires
+    }
+
+    pub fn max_age(&self) -> Option<Duration> {
+        self.max_age
+    }
+
+    pub fn same_site(&self) -> Option<SameSite> {
+        self.same_site
+    }
+
+    pub fn secure(&self) -> bool {
+        self.secure
+    }
+
+    pub fn http_only(&self) -> bool {
+        self.http_only
+    }
+}
+
+/// As the value of a `Set-Cookie` header. Expiry times are truncated to the
+/// second.
+impl fmt::Display for SetCookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, encode(&self.value, is_cookie_octet))?;
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", format_http_date(expires))?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site)?;
+        }
+        if self.secure {
+            f.write_str("; Secure")?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the value of a `Set-Cookie` header. Unknown attributes and ones
+/// with invalid values are ignored, as clients do.
+impl FromStr for SetCookie {
+    type Err = InvalidCookie;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut attributes = s.split(';');
+        let (name, value) = attributes
+            .next()
+            .and_then(|pair| pair.split_once('='))
+            .ok_or(InvalidCookie(()))?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(InvalidCookie(()));
+        }
+
+        let mut cookie = SetCookie::new(name, percent_decode(unquote(value.trim())));
+        for attribute in attributes {
+            let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "path" if value.starts_with('/') => cookie.path = Some(value.to_owned()),
+                "domain" if !value.is_empty() => {
+                    cookie.domain = Some(value.trim_start_matches('.').to_owned())
+                }
+                "expires" => cookie.expires = parse_http_date(value).or(cookie.expires),
+                // Zero or negative both mean the cookie has expired.
+                "max-age" => {
+                    if let Ok(secs) = value.parse::<i64>() {
+                        cookie.max_age = Some(Duration::from_secs(secs.max(0) as u64));
+                    }
+                }
+                "samesite" => cookie.same_site = value.parse().ok().or(cookie.same_site),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                _ => {}
+            }
+        }
+        Ok(cookie)
+    }
+}
+
+/// Which cross-site requests the client sends a cookie with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SameSite {
+    /// Only same-site requests.
+    Strict,
+    /// Same-site requests and top-level navigations from other sites.
+    Lax,
+    /// Every request. Clients insist on the cookie being `Secure` then.
+    None,
+}
+
+impl fmt::Display for SameSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        })
+    }
+}
+
+/// Ignores case.
+impl FromStr for SameSite {
+    type Err = InvalidCookie;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "strict" => Ok(SameSite::Strict),
+            "lax" => Ok(SameSite::Lax),
+            "none" => Ok(SameSite::None),
+            _ => Err(InvalidCookie(())),
+        }
+    }
+}
+
+/// Error parsing a `Set-Cookie` header without a `name=value` pair, or an
+/// unknown [`SameSite`] value.
+#[derive(Debug)]
+pub struct InvalidCookie(());
+
+impl fmt::Display for InvalidCookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Invalid cookie")
+    }
+}
+
+impl std::error::Error for InvalidCookie {}
+
+/// Bytes a cookie value can hold without escaping, except `%` which starts
+/// an escape.
+fn is_cookie_octet(b: u8) -> bool {
+    matches!(b, 0x21 | 0x23..=0x24 | 0x26..=0x2b | 0x2d..=0x3a | 0x3c..=0x5b | 0x5d..=0x7e)
+}
+
+/// Values may be wrapped in double quotes.
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cookie_headers() {
+        let cookies: Vec<_> =
+            parse_cookie_header("theme=dark; lang=\"en\";name=caf%C3%A9 au lait; =orphan; junk")
+                .collect();
+        assert_eq!(
+            cookies,
+            [
+                Cookie::new("theme", "dark"),
+                Cookie::new("lang", "en"),
+                Cookie::new("name", "café au lait"),
+            ]
+        );
+        assert_eq!(parse_cookie_header("").count(), 0);
+    }
+
+    #[test]
+    fn encodes_values_that_are_not_cookie_octets() {
+        assert_eq!(Cookie::new("theme", "dark").to_string(), "theme=dark");
+        assert_eq!(
+            Cookie::new("note", "a b;c,\"d\"%").to_string(),
+            "note=a%20b%3Bc%2C%22d%22%25"
+        );
+        let cookie = Cookie::new("note", "a b;c,\"d\"%");
+        let parsed: Vec<_> = parse_cookie_header(&cookie.to_string()).collect();
+        assert_eq!(parsed, [cookie]);
+    }
+
+    #[test]
+    fn writes_set_cookie_attributes() {
+        let cookie = SetCookie::new("session", "abc 123")
+            .with_path("/")
+            .with_domain("example.com")
+            .with_expires(UNIX_EPOCH + Duration::from_secs(784111777))
+            .with_max_age(Duration::from_secs(3600))
+            .with_same_site(SameSite::Lax)
+            .with_secure(true)
+            .with_http_only(true);
+        assert_eq!(
+            cookie.to_string(),
+            "session=abc%20123; Path=/; Domain=example.com; \
+             Expires=Sun, 06 Nov 1994 08:49:37 GMT; Max-Age=3600; SameSite=Lax; \
+             Secure; HttpOnly"
+        );
+        assert_eq!(cookie.to_string().parse::<SetCookie>().unwrap(), cookie);
+
+        assert_eq!(
+            SetCookie::removal("session").to_string(),
+            "session=; Expires=Thu, 01 Jan 1970 00:00:00 GMT; Max-Age=0"
+        );
+    }
+
+    #[test]
+    fn parses_set_cookie_headers_leniently() {
+        let cookie: SetCookie = "id=\"a1\"; path=/app; DOMAIN=.example.com; max-age=-5; \
+                                 samesite=strict; secure; Priority=High"
+            .parse()
+            .unwrap();
+        assert_eq!(cookie.name(), "id");
+        assert_eq!(cookie.value(), "a1");
+        assert_eq!(cookie.path(), Some("/app"));
+        assert_eq!(cookie.domain(), Some("example.com"));
+        assert_eq!(cookie.max_age(), Some(Duration::ZERO));
+        assert_eq!(cookie.same_site(), Some(SameSite::Strict));
+        assert!(cookie.secure());
+        assert!(!cookie.http_only());
+
+        // Attributes that don't parse are ignored.
+        let cookie: SetCookie =
+            "id=1; Path=app; Domain=; Expires=soon; Max-Age=often; SameSite=sometimes"
+                .parse()
+                .unwrap();
+        assert_eq!(cookie, SetCookie::new("id", "1"));
+    }
+
+    #[test]
+    fn rejects_set_cookie_headers_without_a_name() {
+        for header in ["", "id", "=1", " =1; Path=/"] {
+            assert!(header.parse::<SetCookie>().is_err(), "{:?}", header);
+        }
+        assert_eq!("Lax".parse::<SameSite>().unwrap(), SameSite::Lax);
+        assert!("relaxed".parse::<SameSite>().is_err());
+    }
+}
@@ -0,0 +1,192 @@
+//! Percent-encoding and decoding for paths, queries and locations.
+//!
+//! Decoding is lenient: malformed escapes are kept as they are and invalid
+//! UTF-8 is replaced, so a bad request target never fails to route.
+
+use std::fmt::Write;
+
+/// Decodes `%XX` escapes, as in a path segment.
+pub fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match escape_at(bytes, i) {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Decodes a key or value of an `application/x-www-form-urlencoded` string,
+/// where `+` stands for a space.
+///
+/// The [`Query`](crate::extract::Query) and [`Form`](crate::form::Form)
+/// extractors decode by the same rules.
+pub fn form_decode(input: &str) -> String {
+    percent_decode(&input.replace('+', " "))
+}
+
+/// Encodes `input` to be a single path segment, escaping `/`, `?`, `#`, `%`
+/// and everything else that isn't allowed in one.
+pub fn encode_path_segment(input: &str) -> String {
+    encode(input, is_pchar)
+}
+
+/// Encodes a key or value for an `application/x-www-form-urlencoded` string,
+/// turning spaces into `+`.
+pub fn form_encode(input: &str) -> String {
+    let mut encoded = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        match byte {
+            b' ' => encoded.push('+'),
+            b'*' | b'-' | b'.' | b'_' => encoded.push(byte as char),
+            _ if byte.is_ascii_alphanumeric() => encoded.push(byte as char),
+            _ => push_escape(&mut encoded, byte),
+        }
+    }
+    encoded
+}
+
+/// Escapes what can't appear in a URI at all, such as spaces, controls and
+/// non-ASCII text, and leaves the rest alone. Reserved characters and
+/// existing escapes are kept, so encoding twice changes nothing.
+///
+/// ```
+/// # use part1_app_factory::http::encoding::encode_uri;
+/// assert_eq!(encode_uri("/search?q=café au lait"), "/search?q=caf%C3%A9%20au%20lait");
+/// ```
+pub fn encode_uri(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut encoded = String::with_capacity(input.len());
+    for (i, &byte) in bytes.iter().enumerate() {
+        let keep = if byte == b'%' {
+            escape_at(bytes, i).is_some()
+        } else {
+            is_uri_char(byte)
+        };
+        if keep {
+            encoded.push(byte as char);
+        } else {
+            push_escape(&mut encoded, byte);
+        }
+    }
+    encoded
+}
+
+/// Escapes every byte `keep` rejects.
+pub(crate) fn encode(input: &str, keep: fn(u8) -> bool) -> String {
+    let mut encoded = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        if keep(byte) {
+            encoded.push(byte as char);
+        } else {
+            push_escape(&mut encoded, byte);
+        }
+    }
+    encoded
+}
+
+fn push_escape(encoded: &mut String, byte: u8) {
+    write!(encoded, "%{:02X}", byte).expect("writing to a String can't fail");
+}
+
+/// The byte decoded from a valid `%XX` escape starting at `i`.
+fn escape_at(bytes: &[u8], i: usize) -> Option<u8> {
+    match bytes.get(i..i + 3) {
+        Some([b'%', hi, lo]) => Some(hex_value(*hi)? << 4 | hex_value(*lo)?),
+        _ => None,
+    }
+}
+
+fn hex_value(digit: u8) -> Option<u8> {
+    match digit {
+        b'0'..=b'9' => Some(digit - b'0'),
+        b'a'..=b'f' => Some(digit - b'a' + 10),
+        b'A'..=b'F' => Some(digit - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Unreserved characters, which never need escaping.
+fn is_unreserved(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~')
+}
+
+/// Characters allowed in a path segment besides escapes.
+fn is_pchar(b: u8) -> bool {
+    is_unreserved(b) || is_sub_delim(b) || matches!(b, b':' | b'@')
+}
+
+fn is_sub_delim(b: u8) -> bool {
+    matches!(
+        b,
+        b'!' | b'$' | b'&' | b'\'' | b'(' | b')' | b'*' | b'+' | b',' | b';' | b'='
+    )
+}
+
+/// Characters allowed anywhere in a URI besides escapes.
+fn is_uri_char(b: u8) -> bool {
+    is_pchar(b) || matches!(b, b'/' | b'?' | b'#' | b'[' | b']')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_escapes() {
+        assert_eq!(percent_decode("caf%C3%A9%20au%20lait"), "café au lait");
+        assert_eq!(percent_decode("%2f%2F"), "//");
+        assert_eq!(percent_decode("a+b"), "a+b");
+        assert_eq!(form_decode("a+b%2Bc"), "a b+c");
+    }
+
+    #[test]
+    fn keeps_malformed_escapes() {
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+        assert_eq!(percent_decode("%%41"), "%A");
+        assert_eq!(percent_decode("%C3%28"), "\u{fffd}(");
+    }
+
+    #[test]
+    fn encodes_path_segments() {
+        assert_eq!(encode_path_segment("ferris crab"), "ferris%20crab");
+        assert_eq!(encode_path_segment("a/b?c#d"), "a%2Fb%3Fc%23d");
+        assert_eq!(encode_path_segment("user@host:8080"), "user@host:8080");
+        assert_eq!(encode_path_segment("café"), "caf%C3%A9");
+        assert_eq!(
+            percent_decode(&encode_path_segment("50% off/€")),
+            "50% off/€"
+        );
+    }
+
+    #[test]
+    fn encodes_form_values() {
+        assert_eq!(form_encode("ferris crab"), "ferris+crab");
+        assert_eq!(form_encode("a+b=c&d~"), "a%2Bb%3Dc%26d%7E");
+        assert_eq!(form_encode("*-._"), "*-._");
+        assert_eq!(form_decode(&form_encode("1 + 1 = 2")), "1 + 1 = 2");
+    }
+
+    #[test]
+    fn encodes_uris_without_double_encoding() {
+        assert_eq!(
+            encode_uri("/search?q=ferris crab#top"),
+            "/search?q=ferris%20crab#top"
+        );
+        assert_eq!(encode_uri("/caf%C3%A9"), "/caf%C3%A9");
+        assert_eq!(encode_uri("/100%"), "/100%25");
+        assert_eq!(encode_uri("/é\"<>"), "/%C3%A9%22%3C%3E");
+    }
+}
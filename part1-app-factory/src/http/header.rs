@@ -0,0 +1,397 @@
+//! Header names, values and the map holding them.
+
+use std::{borrow::Cow, fmt, str::FromStr};
+
+use super::{is_tchar, is_token, InvalidHeader};
+
+pub const ACCEPT: HeaderName = HeaderName::from_static("accept");
+pub const ACCEPT_ENCODING: HeaderName = HeaderName::from_static("accept-encoding");
+pub const ACCESS_CONTROL_ALLOW_CREDENTIALS: HeaderName =
+    HeaderName::from_static("access-control-allow-credentials");
+pub const ACCESS_CONTROL_ALLOW_HEADERS: HeaderName =
+    HeaderName::from_static("access-control-allow-headers");
+pub const ACCESS_CONTROL_ALLOW_METHODS: HeaderName =
+    HeaderName::from_static("access-control-allow-methods");
+pub const ACCESS_CONTROL_ALLOW_ORIGIN: HeaderName =
+    HeaderName::from_static("access-control-allow-origin");
+pub const ACCESS_CONTROL_EXPOSE_HEADERS: HeaderName =
+    HeaderName::from_static("access-control-expose-headers");
+pub const ACCESS_CONTROL_MAX_AGE: HeaderName = HeaderName::from_static("access-control-max-age");
+pub const ACCESS_CONTROL_REQUEST_HEADERS: HeaderName =
+    HeaderName::from_static("access-control-request-headers");
+pub const ACCESS_CONTROL_REQUEST_METHOD: HeaderName =
+    HeaderName::from_static("access-control-request-method");
+pub const ALLOW: HeaderName = HeaderName::from_static("allow");
+pub const AUTHORIZATION: HeaderName = HeaderName::from_static("authorization");
+pub const CACHE_CONTROL: HeaderName = HeaderName::from_static("cache-control");
+pub const CONNECTION: HeaderName = HeaderName::from_static("connection");
+pub const CONTENT_DISPOSITION: HeaderName = HeaderName::from_static("content-disposition");
+pub const CONTENT_ENCODING: HeaderName = HeaderName::from_static("content-encoding");
+pub const CONTENT_LENGTH: HeaderName = HeaderName::from_static("content-length");
+pub const CONTENT_RANGE: HeaderName = HeaderName::from_static("content-range");
+pub const CONTENT_TYPE: HeaderName = HeaderName::from_static("content-type");
+pub const COOKIE: HeaderName = HeaderName::from_static("cookie");
+pub const ETAG: HeaderName = HeaderName::from_static("etag");
+pub const EXPECT: HeaderName = HeaderName::from_static("expect");
+pub const EXPIRES: HeaderName = HeaderName::from_static("expires");
+pub const HOST: HeaderName = HeaderName::from_static("host");
+pub const IF_MODIFIED_SINCE: HeaderName = HeaderName::from_static("if-modified-since");
+pub const IF_NONE_MATCH: HeaderName = HeaderName::from_static("if-none-match");
+pub const LAST_MODIFIED: HeaderName = HeaderName::from_static("last-modified");
+pub const LOCATION: HeaderName = HeaderName::from_static("location");
+pub const ORIGIN: HeaderName = HeaderName::from_static("origin");
+pub const RETRY_AFTER: HeaderName = HeaderName::from_static("retry-after");
+pub const SET_COOKIE: HeaderName = HeaderName::from_static("set-cookie");
+pub const TRANSFER_ENCODING: HeaderName = HeaderName::from_static("transfer-encoding");
+pub const VARY: HeaderName = HeaderName::from_static("vary");
+pub const WWW_AUTHENTICATE: HeaderName = HeaderName::from_static("www-authenticate");
+
+/// The name of a header, always in lowercase.
+///
+/// Parsing lowercases the name, so `"Content-Type".parse()` and
+/// [`CONTENT_TYPE`] are equal.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct HeaderName(Cow<'static, str>);
+
+impl HeaderName {
+    /// A name known at compile time.
+    ///
+    /// # Panics
+    ///
+    /// If `name` isn't a lowercase token. In a `const` that's a compile
+    /// error.
+    pub const fn from_static(name: &'static str) -> Self {
+        let bytes = name.as_bytes();
+        assert!(!bytes.is_empty(), "header name is empty");
+        let mut i = 0;
+        while i < bytes.len() {
+            assert!(
+                is_tchar(bytes[i]) && !bytes[i].is_ascii_uppercase(),
+                "header name isn't a lowercase token"
+            );
+            i += 1;
+        }
+        HeaderName(Cow::Borrowed(name))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for HeaderName {
+    type Err = InvalidHeader;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        if !is_token(name) {
+            return Err(InvalidHeader::new(name));
+        }
+        Ok(HeaderName(Cow::Owned(name.to_ascii_lowercase())))
+    }
+}
+
+impl AsRef<str> for HeaderName {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+/// Compares ignoring case.
+impl PartialEq<str> for HeaderName {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str().eq_ignore_ascii_case(other)
+    }
+}
+
+impl fmt::Display for HeaderName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for HeaderName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+/// The value of a header: text without control characters other than tab,
+/// so it can't break out of its header line.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct HeaderValue(Cow<'static, str>);
+
+impl HeaderValue {
+    /// A value known at compile time.
+    ///
+    /// # Panics
+    ///
+    /// If `value` contains control characters.
+    pub const fn from_static(value: &'static str) -> Self {
+        let bytes = value.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            assert!(
+                is_value_byte(bytes[i]),
+                "header value contains control characters"
+            );
+            i += 1;
+        }
+        HeaderValue(Cow::Borrowed(value))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+impl FromStr for HeaderValue {
+    type Err = InvalidHeader;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if !value.bytes().all(is_value_byte) {
+            return Err(InvalidHeader::new(value));
+        }
+        Ok(HeaderValue(Cow::Owned(value.to_owned())))
+    }
+}
+
+impl From<u64> for HeaderValue {
+    fn from(value: u64) -> Self {
+        HeaderValue(Cow::Owned(value.to_string()))
+    }
+}
+
+impl From<usize> for HeaderValue {
+    fn from(value: usize) -> Self {
+        HeaderValue(Cow::Owned(value.to_string()))
+    }
+}
+
+impl AsRef<str> for HeaderValue {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq<str> for HeaderValue {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for HeaderValue {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl fmt::Display for HeaderValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for HeaderValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+const fn is_value_byte(b: u8) -> bool {
+    b == b'\t' || (b >= 0x20 && b != 0x7f)
+}
+
+/// The headers of a request or response.
+///
+/// Lookups ignore case and a name can have several values, such as one
+/// `Set-Cookie` per cookie. Iteration yields the headers in the order they
+/// were added.
+///
+/// ```
+/// # use part1_app_factory::http::{
+/// #     header::{CONTENT_TYPE, SET_COOKIE},
+/// #     HeaderMap, HeaderValue,
+/// # };
+/// let mut headers = HeaderMap::new();
+/// headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+/// headers.append(SET_COOKIE, HeaderValue::from_static("a=1"));
+/// headers.append(SET_COOKIE, HeaderValue::from_static("b=2"));
+///
+/// assert_eq!(headers.get("Content-Type").unwrap(), "text/plain");
+/// assert_eq!(headers.get_all("set-cookie").count(), 2);
+/// ```
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct HeaderMap {
+    entries: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        HeaderMap::default()
+    }
+
+    /// The number of values, counting each value of a name.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The first value of `name`.
+    pub fn get(&self, name: impl AsRef<str>) -> Option<&HeaderValue> {
+        self.get_all(name).next()
+    }
+
+    /// Every value of `name`, in order.
+    pub fn get_all(&self, name: impl AsRef<str>) -> GetAll<'_> {
+        let name = name.as_ref();
+        let first = self
+            .entries
+            .iter()
+            .position(|(existing, _)| existing.as_str().eq_ignore_ascii_case(name));
+        // Names are kept in lowercase, so the one of the first value can be
+        // compared as is with those after it.
+        match first {
+            Some(first) => GetAll {
+                entries: self.entries[first..].iter(),
+                name: self.entries[first].0.as_str(),
+            },
+            None => GetAll {
+                entries: [].iter(),
+                name: "",
+            },
+        }
+    }
+
+    pub fn contains_key(&self, name: impl AsRef<str>) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Sets `name` to `value`, removing its other values. Returns the first
+    /// value it had.
+    pub fn insert(&mut self, name: HeaderName, value: HeaderValue) -> Option<HeaderValue> {
+        let mut value = Some(value);
+        let mut previous = None;
+        self.entries.retain_mut(|(existing, current)| {
+            if *existing != name {
+                return true;
+            }
+            match value.take() {
+                Some(value) => {
+                    previous = Some(std::mem::replace(current, value));
+                    true
+                }
+                None => false,
+            }
+        });
+
+        if let Some(value) = value {
+            self.entries.push((name, value));
+        }
+        previous
+    }
+
+    /// Adds `value` to the values of `name`.
+    pub fn append(&mut self, name: HeaderName, value: HeaderValue) {
+        self.entries.push((name, value));
+    }
+
+    /// Removes every value of `name`, returning the first.
+    pub fn remove(&mut self, name: impl AsRef<str>) -> Option<HeaderValue> {
+        let name = name.as_ref();
+        let mut removed = None;
+        self.entries.retain(|(existing, value)| {
+            if !existing.as_str().eq_ignore_ascii_case(name) {
+                return true;
+            }
+            if removed.is_none() {
+                removed = Some(value.clone());
+            }
+            false
+        });
+        removed
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            entries: self.entries.iter(),
+        }
+    }
+}
+
+impl fmt::Debug for HeaderMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// Appends every pair.
+impl Extend<(HeaderName, HeaderValue)> for HeaderMap {
+    fn extend<I: IntoIterator<Item = (HeaderName, HeaderValue)>>(&mut self, iter: I) {
+        self.entries.extend(iter);
+    }
+}
+
+impl FromIterator<(HeaderName, HeaderValue)> for HeaderMap {
+    fn from_iter<I: IntoIterator<Item = (HeaderName, HeaderValue)>>(iter: I) -> Self {
+        HeaderMap {
+            entries: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl IntoIterator for HeaderMap {
+    type Item = (HeaderName, HeaderValue);
+    type IntoIter = std::vec::IntoIter<(HeaderName, HeaderValue)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a HeaderMap {
+    type Item = (&'a HeaderName, &'a HeaderValue);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator returned by [`HeaderMap::iter`].
+#[derive(Debug)]
+pub struct Iter<'a> {
+    entries: std::slice::Iter<'a, (HeaderName, HeaderValue)>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a HeaderName, &'a HeaderValue);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next().map(|(name, value)| (name, value))
+    }
+}
+
+/// Iterator returned by [`HeaderMap::get_all`].
+#[derive(Debug)]
+pub struct GetAll<'a> {
+    entries: std::slice::Iter<'a, (HeaderName, HeaderValue)>,
+    name: &'a str,
+}
+
+impl<'a> Iterator for GetAll<'a> {
+    type Item = &'a HeaderValue;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let name = self.name;
+        self.entries
+            .find(|(existing, _)| existing.as_str() == name)
+            .map(|(_, value)| value)
+    }
+}
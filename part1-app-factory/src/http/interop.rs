@@ -0,0 +1,217 @@
+//! Conversions between this crate's requests and responses and the `http`
+//! crate's, so services can be shared with hyper and tower-http.
+//!
+//! The `TryFrom` impls only convert bodies in memory, as `Bytes`. The hyper
+//! adapters in [`server::compat`](crate::server::compat) stream bodies
+//! through instead. Extensions don't carry over: the two type maps can't
+//! hold each other's values.
+
+use std::fmt;
+
+use bytes::Bytes;
+
+use super::{
+    Body, Extensions, HeaderMap, HeaderName, HeaderValue, InvalidHeader, Method, Request, Response,
+    StatusCode, Uri, Version,
+};
+
+/// Why a request or response couldn't be converted.
+#[derive(Debug)]
+pub enum ConversionError {
+    /// The method is an extension method longer than 15 bytes.
+    Method,
+    /// The version is one this crate doesn't support, such as HTTP/3.
+    Version,
+    /// The request target isn't a valid URI for the `http` crate.
+    Uri,
+    /// A header value isn't UTF-8.
+    Header(InvalidHeader),
+    /// The body is streamed, so it can't be converted without reading it.
+    StreamingBody,
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConversionError::Method => f.write_str("Method can't be converted"),
+            ConversionError::Version => f.write_str("Unsupported HTTP version"),
+            ConversionError::Uri => f.write_str("Request target isn't a valid URI"),
+            ConversionError::Header(error) => write!(f, "{}", error),
+            ConversionError::StreamingBody => {
+                f.write_str("Streaming bodies have to be collected before converting")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConversionError::Header(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Takes the path and query of the URI. Its authority, if any, becomes the
+/// `Host` header unless the request has one.
+impl TryFrom<::http::Request<Bytes>> for Request {
+    type Error = ConversionError;
+
+    fn try_from(req: ::http::Request<Bytes>) -> Result<Self, Self::Error> {
+        request_from_http(req.map(Body::from))
+    }
+}
+
+/// Fails for streamed bodies, collect those first.
+impl TryFrom<Request> for ::http::Request<Bytes> {
+    type Error = ConversionError;
+
+    fn try_from(req: Request) -> Result<Self, Self::Error> {
+        let (parts, body) = request_to_http(req)?.into_parts();
+        let body = body
+            .try_into_bytes()
+            .map_err(|_| ConversionError::StreamingBody)?;
+        Ok(::http::Request::from_parts(parts, body))
+    }
+}
+
+impl TryFrom<::http::Response<Bytes>> for Response {
+    type Error = ConversionError;
+
+    fn try_from(resp: ::http::Response<Bytes>) -> Result<Self, Self::Error> {
+        response_from_http(resp.map(Body::from))
+    }
+}
+
+/// Fails for streamed bodies, collect those first.
+impl TryFrom<Response> for ::http::Response<Bytes> {
+    type Error = ConversionError;
+
+    fn try_from(resp: Response) -> Result<Self, Self::Error> {
+        let (parts, body) = response_to_http(resp).into_parts();
+        let body = body
+            .try_into_bytes()
+            .map_err(|_| ConversionError::StreamingBody)?;
+        Ok(::http::Response::from_parts(parts, body))
+    }
+}
+
+/// Converts everything but the body, which is passed through as it is.
+pub(crate) fn request_from_http(req: ::http::Request<Body>) -> Result<Request, ConversionError> {
+    let (parts, body) = req.into_parts();
+
+    let mut headers = headers_from_http(&parts.headers)?;
+    if let Some(authority) = parts.uri.authority() {
+        if !headers.contains_key(super::header::HOST) {
+            let host = authority
+                .as_str()
+                .parse()
+                .map_err(ConversionError::Header)?;
+            headers.insert(super::header::HOST, host);
+        }
+    }
+    let target = parts
+        .uri
+        .path_and_query()
+        .map_or("/", |path_and_query| path_and_query.as_str());
+
+    Ok(Request {
+        method: parts
+            .method
+            .as_str()
+            .parse()
+            .map_err(|_| ConversionError::Method)?,
+        uri: Uri::from(target),
+        version: version_from_http(parts.version)?,
+        headers,
+        body,
+        extensions: Extensions::default(),
+    })
+}
+
+pub(crate) fn request_to_http(req: Request) -> Result<::http::Request<Body>, ConversionError> {
+    let mut converted = ::http::Request::new(req.body);
+    *converted.method_mut() = method_to_http(req.method)?;
+    *converted.uri_mut() = req
+        .uri
+        .path_and_query()
+        .parse()
+        .map_err(|_| ConversionError::Uri)?;
+    *converted.version_mut() = version_to_http(req.version);
+    *converted.headers_mut() = headers_to_http(&req.headers);
+    Ok(converted)
+}
+
+pub(crate) fn response_from_http(
+    resp: ::http::Response<Body>,
+) -> Result<Response, ConversionError> {
+    let (parts, body) = resp.into_parts();
+
+    Ok(Response {
+        status: StatusCode::from_u16(parts.status.as_u16()).expect("both crates accept 100 to 999"),
+        version: version_from_http(parts.version)?,
+        headers: headers_from_http(&parts.headers)?,
+        body,
+        extensions: Extensions::default(),
+    })
+}
+
+pub(crate) fn response_to_http(resp: Response) -> ::http::Response<Body> {
+    let mut converted = ::http::Response::new(resp.body);
+    *converted.status_mut() =
+        ::http::StatusCode::from_u16(resp.status.as_u16()).expect("both crates accept 100 to 999");
+    *converted.version_mut() = version_to_http(resp.version);
+    *converted.headers_mut() = headers_to_http(&resp.headers);
+    converted
+}
+
+fn method_to_http(method: Method) -> Result<::http::Method, ConversionError> {
+    ::http::Method::from_bytes(method.as_str().as_bytes()).map_err(|_| ConversionError::Method)
+}
+
+fn version_from_http(version: ::http::Version) -> Result<Version, ConversionError> {
+    match version {
+        ::http::Version::HTTP_10 => Ok(Version::Http10),
+        ::http::Version::HTTP_11 => Ok(Version::Http11),
+        ::http::Version::HTTP_2 => Ok(Version::Http2),
+        _ => Err(ConversionError::Version),
+    }
+}
+
+fn version_to_http(version: Version) -> ::http::Version {
+    match version {
+        Version::Http10 => ::http::Version::HTTP_10,
+        Version::Http11 => ::http::Version::HTTP_11,
+        Version::Http2 => ::http::Version::HTTP_2,
+    }
+}
+
+fn headers_from_http(headers: &::http::HeaderMap) -> Result<HeaderMap, ConversionError> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let invalid = || ConversionError::Header(InvalidHeader::new(name.as_str()));
+            let name: HeaderName = name.as_str().parse().map_err(|_| invalid())?;
+            let value: HeaderValue = std::str::from_utf8(value.as_bytes())
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(invalid)?;
+            Ok((name, value))
+        })
+        .collect()
+}
+
+fn headers_to_http(headers: &HeaderMap) -> ::http::HeaderMap {
+    let mut converted = ::http::HeaderMap::with_capacity(headers.len());
+    for (name, value) in headers {
+        // Both crates accept the same token names, and values without
+        // control characters.
+        let name = ::http::header::HeaderName::from_bytes(name.as_str().as_bytes());
+        let value = ::http::HeaderValue::from_bytes(value.as_bytes());
+        if let (Ok(name), Ok(value)) = (name, value) {
+            converted.append(name, value);
+        }
+    }
+    converted
+}
@@ -0,0 +1,134 @@
+use std::{fmt, sync::OnceLock};
+
+use super::encoding::{form_decode, percent_decode};
+
+/// The target of a request: a path, optionally followed by a query and a
+/// fragment, e.g. `/search?q=tower#results`.
+///
+/// Splitting into components is done once up front. Decoding the path
+/// segments and query pairs is left until they are asked for and then
+/// cached, so extractors can look at them as often as they like.
+#[derive(Clone)]
+pub struct Uri {
+    target: String,
+    path_end: usize,
+    query_end: usize,
+    segments: OnceLock<Vec<String>>,
+    query_pairs: OnceLock<Vec<(String, String)>>,
+}
+
+impl Uri {
+    /// The path, still percent-encoded. Never empty.
+    pub fn path(&self) -> &str {
+        match &self.target[..self.path_end] {
+            "" => "/",
+            path => path,
+        }
+    }
+
+    /// The query string without the leading `?`, still encoded.
+    pub fn query(&self) -> Option<&str> {
+        self.target[self.path_end..self.query_end].strip_prefix('?')
+    }
+
+    /// The fragment without the leading `#`. Clients don't send fragments,
+    /// but URIs built by the app, such as redirect targets, may have one.
+    pub fn fragment(&self) -> Option<&str> {
+        self.target[self.query_end..].strip_prefix('#')
+    }
+
+    /// The path and query, as sent on the request line.
+    pub fn path_and_query(&self) -> &str {
+        &self.target[..self.query_end]
+    }
+
+    /// The percent-decoded segments of the path, e.g. `["users", "a b"]` for
+    /// `/users/a%20b`.
+    pub fn path_segments(&self) -> &[String] {
+        self.segments.get_or_init(|| {
+            self.path()
+                .split('/')
+                .filter(|segment| !segment.is_empty())
+                .map(percent_decode)
+                .collect()
+        })
+    }
+
+    /// The decoded `key=value` pairs of the query, in order.
+    pub fn query_pairs(&self) -> &[(String, String)] {
+        self.query_pairs.get_or_init(|| {
+            self.query()
+                .unwrap_or_default()
+                .split('&')
+                .filter(|pair| !pair.is_empty())
+                .map(|pair| {
+                    let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                    (form_decode(key), form_decode(value))
+                })
+                .collect()
+        })
+    }
+
+    /// The decoded value of the first query pair named `key`.
+    pub fn query_param(&self, key: &str) -> Option<&str> {
+        self.query_pairs()
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The same URI with its path replaced by `path`, which must be encoded
+    /// already.
+    pub fn with_path(&self, path: &str) -> Uri {
+        let mut target = path.to_owned();
+        target.push_str(&self.target[self.path_end..]);
+        Uri::from(target)
+    }
+}
+
+impl Default for Uri {
+    fn default() -> Self {
+        Uri::from("/")
+    }
+}
+
+impl From<String> for Uri {
+    fn from(target: String) -> Self {
+        let query_end = target.find('#').unwrap_or(target.len());
+        let path_end = target[..query_end].find('?').unwrap_or(query_end);
+
+        Uri {
+            target,
+            path_end,
+            query_end,
+            segments: OnceLock::new(),
+            query_pairs: OnceLock::new(),
+        }
+    }
+}
+
+impl From<&str> for Uri {
+    fn from(target: &str) -> Self {
+        Uri::from(target.to_owned())
+    }
+}
+
+impl PartialEq for Uri {
+    fn eq(&self, other: &Self) -> bool {
+        self.target == other.target
+    }
+}
+
+impl Eq for Uri {}
+
+impl fmt::Display for Uri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.target)
+    }
+}
+
+impl fmt::Debug for Uri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.target, f)
+    }
+}
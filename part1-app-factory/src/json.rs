@@ -0,0 +1,228 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    extract::{buffer_body, has_content_type, rejection::JsonRejection, BoxFuture, FromRequest},
+    http::{header::CONTENT_TYPE, HeaderValue, Request, Response, StatusCode},
+    response::IntoResponse,
+};
+
+/// JSON extractor and response.
+///
+/// As an extractor, deserializes the request body into `T` if the request has
+/// a JSON content type (`application/json` or `application/*+json`, with any
+/// parameters such as `charset` ignored) and rejects it with a 415 otherwise.
+/// It consumes the body, so it must be the last handler argument. For clients
+/// that send JSON without the header, take the body as `Bytes` and use
+/// [`Json::from_bytes`].
+///
+/// As a response, serializes `T` and sets `Content-Type: application/json`.
+///
+/// ```
+/// # use std::sync::Mutex;
+/// # use part1_app_factory::{json::Json, router::post};
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Deserialize)]
+/// # struct NewUser {
+/// #     name: String,
+/// # }
+/// # #[derive(Serialize)]
+/// # struct User {
+/// #     id: u64,
+/// #     name: String,
+/// # }
+/// # struct Store(Mutex<u64>);
+/// # impl Store {
+/// #     fn insert(&self, user: NewUser) -> User {
+/// #         let mut id = self.0.lock().unwrap();
+/// #         *id += 1;
+/// #         User { id: *id, name: user.name }
+/// #     }
+/// # }
+/// # #[allow(non_upper_case_globals)]
+/// # static store: Store = Store(Mutex::new(0));
+/// async fn create_user(Json(user): Json<NewUser>) -> Json<User> {
+///     Json(store.insert(user))
+/// }
+/// # let _ = post(create_user);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Json<T>(pub T);
+
+impl<T: DeserializeOwned> Json<T> {
+    /// Deserializes `bytes` without looking at any headers.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, JsonRejection> {
+        serde_json::from_slice(bytes)
+            .map(Json)
+            .map_err(|error| match error.classify() {
+                serde_json::error::Category::Data => JsonRejection::InvalidData(error),
+                _ => JsonRejection::InvalidSyntax(error),
+            })
+    }
+}
+
+impl<T> FromRequest for Json<T>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    type Rejection = JsonRejection;
+
+    fn from_request(req: Request) -> BoxFuture<'static, Result<Self, Self::Rejection>> {
+        Box::pin(async move {
+            if !has_content_type(&req.headers, is_json) {
+                return Err(JsonRejection::MissingJsonContentType);
+            }
+            let body = buffer_body(req).await?;
+
+            Json::from_bytes(&body)
+        })
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        match serde_json::to_vec(&self.0) {
+            Ok(body) => {
+                let mut resp = body.into_response();
+                resp.headers
+                    .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+                resp
+            }
+            Err(error) => {
+                let mut resp = format!("Failed to serialize JSON: {}", error).into_response();
+                resp.status = StatusCode::INTERNAL_SERVER_ERROR;
+                resp
+            }
+        }
+    }
+}
+
+fn is_json(mime: &str) -> bool {
+    match mime.split_once('/') {
+        Some((ty, subtype)) => {
+            ty.eq_ignore_ascii_case("application")
+                && (subtype.eq_ignore_ascii_case("json")
+                    || subtype.to_ascii_lowercase().ends_with("+json"))
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::http::Method;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct CreateUser {
+        name: String,
+        admin: bool,
+    }
+
+    fn request(method: Method, uri: &str, headers: &[(&str, &str)], body: &'static str) -> Request {
+        let mut req = Request::builder().method(method).uri(uri);
+        for &(name, value) in headers {
+            req = req.header(name, value);
+        }
+        req.body(body).unwrap()
+    }
+
+    /// The status and body of `resp`.
+    async fn respond(resp: Response) -> (u16, String) {
+        let body = resp.body.collect().await.unwrap();
+        (
+            resp.status.as_u16(),
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    fn content_type(resp: &Response) -> Option<&str> {
+        resp.headers.get("content-type").map(|value| value.as_str())
+    }
+
+    async fn extract(content_type: &str, body: &'static str) -> Result<CreateUser, Response> {
+        let req = request(
+            Method::Post,
+            "/users",
+            &[("Content-Type", content_type)],
+            body,
+        );
+        match Json::<CreateUser>::from_request(req).await {
+            Ok(Json(user)) => Ok(user),
+            Err(rejection) => Err(rejection.into_response()),
+        }
+    }
+
+    #[tokio::test]
+    async fn deserializes_json_bodies() {
+        let body = r#"{"name": "ferris", "admin": true}"#;
+        for content_type in [
+            "application/json",
+            "application/json; charset=utf-8",
+            "application/vnd.api+json",
+        ] {
+            let user = extract(content_type, body).await.unwrap();
+            assert_eq!(
+                user,
+                CreateUser {
+                    name: "ferris".to_owned(),
+                    admin: true
+                }
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_other_content_types() {
+        for content_type in ["text/plain", "application/jsonp", "json"] {
+            let resp = extract(content_type, r#"{"name": "ferris", "admin": true}"#)
+                .await
+                .unwrap_err();
+            let (status, body) = respond(resp).await;
+            assert_eq!(status, 415, "{}", content_type);
+            assert_eq!(
+                body,
+                "Expected request with `Content-Type: application/json`"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_bodies_by_what_is_wrong_with_them() {
+        let resp = extract("application/json", r#"{"name": "ferris""#)
+            .await
+            .unwrap_err();
+        let (status, body) = respond(resp).await;
+        assert_eq!(status, 400);
+        assert!(body.starts_with("Failed to parse JSON body: "), "{}", body);
+
+        let resp = extract("application/json", r#"{"name": "ferris"}"#)
+            .await
+            .unwrap_err();
+        let (status, body) = respond(resp).await;
+        assert_eq!(status, 422);
+        assert!(
+            body.starts_with("Failed to deserialize JSON body: missing field `admin`"),
+            "{}",
+            body
+        );
+    }
+
+    #[tokio::test]
+    async fn serializes_responses() {
+        let resp = Json(BTreeMap::from([("name", "ferris")])).into_response();
+        assert_eq!(content_type(&resp), Some("application/json"));
+        assert_eq!(
+            respond(resp).await,
+            (200, r#"{"name":"ferris"}"#.to_owned())
+        );
+
+        let resp = Json(BTreeMap::from([((1, 2), "ferris")])).into_response();
+        let (status, body) = respond(resp).await;
+        assert_eq!(status, 500);
+        assert!(body.starts_with("Failed to serialize JSON: "), "{}", body);
+    }
+}
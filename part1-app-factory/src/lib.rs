@@ -0,0 +1,15 @@
+// The derive macros name items by this crate's name, so that they work in
+// other crates; this makes the same paths work in here.
+extern crate self as part1_app_factory;
+
+pub mod extract;
+pub mod form;
+pub mod handler;
+pub mod headers;
+pub mod http;
+pub mod json;
+pub mod middleware;
+pub mod response;
+pub mod router;
+pub mod server;
+pub mod util;
@@ -1,42 +1,80 @@
-use std::sync::{atomic::AtomicUsize, Arc};
+use std::{
+    convert::Infallible,
+    fs::File as StdFile,
+    io::{self, BufReader},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
-use crate::{
-    http::ConnInfo,
+use bytes::Bytes;
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::time::{sleep, Duration};
+use tower::{limit::ConcurrencyLimitLayer, Layer};
+use tracing::{error, info, Instrument};
+
+use part1_app_factory::{
+    extract::{
+        rejection::QueryRejection, Authenticated, ClientDisconnect, ConnectInfo, DefaultBodyLimit,
+        FromRequestParts, Path, Query, State, TypedHeader,
+    },
+    form::Form,
+    headers::{Accept, Host},
+    http::{Body, ConnInfo, Extensions, HeaderName, PeerCert, Request, Response, StatusCode},
+    middleware::{
+        self, AccessLogLayer, BearerAuthLayer, CacheControlLayer, CachePolicy, CatchPanicLayer,
+        CircuitBreakerLayer, CompressionLayer, ConditionalLayer, CorsLayer, CsrfLayer, CsrfToken,
+        DecompressionLayer, IpFilterLayer, LoadShedLayer, MemorySessionStore, Next,
+        NormalizePathLayer, RateLimitLayer, RequestBodyLimitLayer, RequestIdLayer,
+        RequireBasicAuth, Session, SessionLayer, TimeoutLayer, TraceLayer,
+    },
+    response::{AppendHeaders, File, Html, IntoResponse, Negotiate, Problem, Redirect},
+    router::{get, HostRouter, MatchedPath, OriginalUri, Router, TypedPath},
+    server::{rustls, serve, Server},
     util::{app_factory_fn, app_fn},
 };
 
-mod http {
-    use std::collections::HashMap;
-
-    #[derive(Debug)]
-    pub struct Request {
-        pub path_and_query: String,
-        pub headers: HashMap<String, String>,
-        pub body: Vec<u8>,
-    }
-
-    #[derive(Debug)]
-    pub struct Response {
-        pub status: u32,
-        pub headers: HashMap<String, String>,
-        pub body: Vec<u8>,
-    }
-
-    #[derive(Clone, Debug)]
-    pub struct ConnInfo {
-        pub host_and_port: String,
-    }
-}
-
 mod fakeserver {
-    use std::collections::HashMap;
+    use std::{future::Future, sync::Arc};
 
-    use tokio::time::{sleep, Duration};
+    use futures_util::StreamExt;
+    use tokio::{
+        sync::Semaphore,
+        time::{sleep, Duration},
+    };
     use tower::{Service, ServiceExt};
+    use tracing::{debug, error, info, info_span, warn, Instrument};
 
-    use crate::http::{ConnInfo, Request, Response};
+    use part1_app_factory::{
+        extract::ConnectInfo,
+        http::{Body, ConnInfo, Method, Request, Response, Version},
+        router::MatchedPath,
+        server::shutdown::{self, Shutdown},
+    };
 
-    pub async fn run<AppFactory, App>(mut app_factory: AppFactory)
+    /// Fake connections stay open until shutdown, so after this many no new
+    /// ones are made, like a real server pausing its accepts.
+    const MAX_CONNECTIONS: usize = 3;
+
+    const FAKE_HOSTS: [&str; 2] = ["localhost:3000", "admin.localhost:3000"];
+
+    const FAKE_REQUESTS: [(Method, &str); 10] = [
+        (Method::Get, "/fake/path?page=1"),
+        (Method::Get, "/health"),
+        (Method::Get, "/api/users/42/posts/7"),
+        (Method::Get, "/hello/tower%20user"),
+        (Method::Get, "/search?q=tower&page=2"),
+        (Method::Head, "/version"),
+        (Method::Get, "/version"),
+        (Method::Post, "/version"),
+        (Method::Get, "/numbers"),
+        (Method::Delete, "/not/routed"),
+    ];
+
+    /// Runs until ctrl-c is pressed, then shuts down gracefully.
+    pub async fn run<AppFactory, App>(app_factory: AppFactory)
     where
         AppFactory: Service<ConnInfo, Response = App>,
         AppFactory::Error: std::fmt::Debug + Send,
@@ -45,184 +83,601 @@ mod fakeserver {
         App: Service<Request, Response = Response>,
         App::Error: std::fmt::Debug,
         App::Future: Send + 'static,
+    {
+        run_with_graceful_shutdown(app_factory, shutdown::ctrl_c()).await
+    }
+
+    /// Runs until `signal` resolves, then stops making connections and
+    /// requests and waits for the responses in flight.
+    pub async fn run_with_graceful_shutdown<AppFactory, App, F>(
+        mut app_factory: AppFactory,
+        signal: F,
+    ) where
+        AppFactory: Service<ConnInfo, Response = App>,
+        AppFactory::Error: std::fmt::Debug + Send,
+        AppFactory::Future: Send + 'static,
+        App: Send,
+        App: Service<Request, Response = Response>,
+        App::Error: std::fmt::Debug,
+        App::Future: Send + 'static,
+        F: Future<Output = ()>,
     {
         let mut connect_number = 0;
+        let (trigger, shutdown) = shutdown::channel();
+        let open = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+        tokio::pin!(signal);
 
         loop {
-            sleep(Duration::from_secs(2)).await;
+            tokio::select! {
+                _ = &mut signal => break,
+                _ = sleep(Duration::from_secs(2)) => {}
+            }
+
+            let permit = match open.clone().try_acquire_owned() {
+                Ok(permit) => permit,
+                Err(_) => {
+                    info!(open = MAX_CONNECTIONS, "Waiting for a connection to close");
+                    tokio::select! {
+                        _ = &mut signal => break,
+                        permit = open.clone().acquire_owned() => {
+                            permit.expect("the semaphore is never closed")
+                        }
+                    }
+                }
+            };
 
             connect_number += 1;
             let conn_info = ConnInfo {
+                id: connect_number,
                 host_and_port: format!("Fake info, connection #{}", connect_number),
+                local_addr: None,
+                peer_addr: None,
+                peer_cred: None,
+                tls: None,
             };
 
             let app = match app_factory.ready().await {
                 Err(e) => {
-                    eprintln!("Service not able to accept connection {:?}", e);
+                    error!(error = ?e, "Service not able to accept a connection");
                     continue;
                 }
                 Ok(app) => app,
             };
 
             let future = app.call(conn_info.clone());
-
-            tokio::spawn(async move {
-                match future.await {
-                    Ok(app) => {
-                        println!("Accepted a connection: {:?}", conn_info);
-                        run_iner(app).await;
+            let shutdown = shutdown.clone();
+            let span = info_span!("conn", id = conn_info.id);
+
+            tokio::spawn(
+                async move {
+                    let _permit = permit;
+                    match future.await {
+                        Ok(app) => {
+                            info!("Accepted a connection");
+                            run_iner(app, conn_info, shutdown).await;
+                        }
+                        Err(e) => error!(error = ?e, "Failed to make the app"),
                     }
-                    Err(e) => eprintln!("Error occurred: {:?}", e),
                 }
-            });
+                .instrument(span),
+            );
+        }
+
+        info!("Shutting down");
+        drop(shutdown);
+        if !trigger.shutdown(Duration::from_secs(30)).await {
+            warn!("Responses still in flight after 30s, shutting down anyway");
         }
     }
 
-    async fn run_iner<App>(mut app: App)
+    async fn run_iner<App>(mut app: App, conn_info: ConnInfo, mut shutdown: Shutdown)
     where
         App: Service<Request, Response = Response>,
         App::Error: std::fmt::Debug,
         App::Future: Send + 'static,
     {
+        let mut request_number = 0;
+
         loop {
-            sleep(Duration::from_secs(1)).await;
+            tokio::select! {
+                _ = shutdown.started() => break,
+                _ = sleep(Duration::from_secs(1)) => {}
+            }
 
-            let req = Request {
-                path_and_query: "/fake/path?page=1".to_owned(),
-                headers: HashMap::new(),
-                body: Vec::new(),
+            request_number += 1;
+            let (method, path_and_query) = FAKE_REQUESTS[request_number % FAKE_REQUESTS.len()];
+            let req = Request::builder()
+                .method(method)
+                .uri(path_and_query)
+                .version(Version::Http11)
+                .header("Host", FAKE_HOSTS[request_number / FAKE_REQUESTS.len() % 2])
+                .extension(ConnectInfo(conn_info.clone()))
+                .body(Body::empty());
+            let req = match req {
+                Err(e) => {
+                    error!(error = %e, "Invalid fake request");
+                    continue;
+                }
+                Ok(req) => req,
             };
 
+            // An app at its concurrency limit isn't ready, which holds the
+            // next request back here instead of piling up tasks.
             let app = match app.ready().await {
                 Err(e) => {
-                    eprintln!("Service not able to accept request: {:?}", e);
+                    error!(error = ?e, "Service not able to accept a request");
                     continue;
                 }
                 Ok(app) => app,
             };
 
+            let version = req.version;
             let future = app.call(req);
-
-            tokio::spawn(async move {
-                match future.await {
-                    Err(e) => eprintln!("Error occurred {:?}", e),
-                    Ok(resp) => println!("Successful response {:?}", resp),
+            let in_flight = shutdown.clone();
+
+            tokio::spawn(
+                async move {
+                    let _in_flight = in_flight;
+                    let Response {
+                        status,
+                        headers,
+                        mut body,
+                        extensions,
+                        ..
+                    } = match future.await {
+                        Ok(resp) => resp,
+                        Err(e) => {
+                            error!(error = ?e, "Request failed");
+                            return;
+                        }
+                    };
+
+                    // Responses go out with the request's version, which decides
+                    // how a body of unknown length is framed.
+                    let framing = match body.size_hint().exact() {
+                        Some(len) => format!("{} bytes", len),
+                        None if version.supports_chunked_encoding() => "chunked".to_owned(),
+                        None => "until close".to_owned(),
+                    };
+                    let route = match extensions.get::<MatchedPath>() {
+                        Some(route) => route.as_str(),
+                        None => "no route",
+                    };
+                    info!(
+                        version = ?version,
+                        status = status.as_u16(),
+                        headers = ?headers,
+                        framing = %framing,
+                        route = route,
+                        "Sent a response"
+                    );
+
+                    while let Some(chunk) = body.next().await {
+                        match chunk {
+                            Ok(chunk) => debug!(chunk = ?chunk, "Sent a chunk"),
+                            Err(e) => {
+                                error!(error = ?e, "Failed while sending the body");
+                                break;
+                            }
+                        }
+                    }
                 }
-            });
+                .in_current_span(),
+            );
         }
     }
 }
 
-mod util {
-    use std::future::Future;
+async fn index() -> Html<&'static str> {
+    Html("<h1>part1-app-factory</h1><p>Try <a href=\"/hello/world\">/hello/world</a>.</p>")
+}
 
-    use crate::http::{ConnInfo, Request, Response};
-    use anyhow::Error;
-    use tower::Service;
+#[derive(FromRequestParts)]
+struct Greeting {
+    name: Path<String>,
+    conn: ConnectInfo<ConnInfo>,
+}
 
-    pub struct AppFactoryFn<F> {
-        f: F,
-    }
+async fn hello(greeting: Greeting) -> String {
+    let Greeting {
+        name: Path(name),
+        conn: ConnectInfo(conn),
+    } = greeting;
+    format!("Hello, {}! You are on {}", name, conn.host_and_port)
+}
 
-    pub fn app_factory_fn<F, Ret, App>(f: F) -> AppFactoryFn<F>
-    where
-        F: FnMut(ConnInfo) -> Ret,
-        Ret: Future<Output = Result<App, Error>>,
-    {
-        AppFactoryFn { f }
+#[derive(Deserialize)]
+struct Search {
+    q: String,
+    page: Option<u32>,
+}
+
+async fn search(
+    host: Option<TypedHeader<Host>>,
+    search: Result<Query<Search>, QueryRejection>,
+) -> Result<String, Problem> {
+    let host = match &host {
+        Some(TypedHeader(host)) => host.hostname(),
+        None => "any host",
+    };
+
+    match search {
+        Ok(Query(search)) => Ok(format!(
+            "Results for {:?} on {}, page {}",
+            search.q,
+            host,
+            search.page.unwrap_or(1)
+        )),
+        Err(rejection) => Err(Problem::new(rejection.status())
+            .with_title("Invalid search")
+            .with_detail(rejection.to_string())
+            .with_extension("example", "/search?q=tower&page=2")),
     }
+}
 
-    impl<F, Ret, App> Service<ConnInfo> for AppFactoryFn<F>
-    where
-        F: FnMut(ConnInfo) -> Ret,
-        Ret: Future<Output = Result<App, Error>>,
-    {
-        type Response = App;
-        type Error = Error;
-        type Future = Ret;
-
-        fn poll_ready(
-            &mut self,
-            _cx: &mut std::task::Context<'_>,
-        ) -> std::task::Poll<Result<(), Self::Error>> {
-            std::task::Poll::Ready(Ok(()))
-        }
+#[derive(Serialize)]
+struct Version {
+    name: &'static str,
+    version: &'static str,
+}
+
+async fn version(
+    accept: Accept,
+) -> (
+    AppendHeaders<[(&'static str, &'static str); 1]>,
+    Negotiate<Version>,
+) {
+    let version = Negotiate::new(
+        accept,
+        Version {
+            name: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+        },
+    )
+    .html(|v| format!("<p>{} <b>{}</b></p>", v.name, v.version))
+    .text(|v| format!("{} {}", v.name, v.version));
+    (AppendHeaders([("Cache-Control", "max-age=60")]), version)
+}
 
-        fn call(&mut self, conn_info: ConnInfo) -> Self::Future {
-            (self.f)(conn_info)
+/// Takes a while, in a task of its own that stops early if the client gives
+/// up waiting.
+async fn slow(disconnect: ClientDisconnect) -> &'static str {
+    let work = tokio::spawn(
+        async move {
+            tokio::select! {
+                _ = sleep(Duration::from_secs(5)) => true,
+                _ = disconnect.disconnected() => {
+                    info!("The client gave up waiting");
+                    false
+                }
+            }
         }
+        .in_current_span(),
+    );
+    match work.await {
+        Ok(true) => "Done, eventually",
+        _ => "Gave up",
+    }
+}
+
+/// Tells the client how long the app took to respond, headers only.
+async fn response_time(req: Request, next: Next) -> Response {
+    let start = std::time::Instant::now();
+    let mut resp = next.run(req).await;
+    let millis = start.elapsed().as_millis() as u64;
+    resp.headers
+        .insert(HeaderName::from_static("x-response-time-ms"), millis.into());
+    resp
+}
+
+/// Says who the client authenticated as over mutual TLS, if it did.
+async fn whoami(cert: Option<PeerCert>) -> String {
+    match cert {
+        Some(cert) => format!(
+            "You are {} (SHA-256 {})",
+            cert.subject,
+            cert.fingerprint_hex()
+        ),
+        None => "You didn't send a client certificate".to_owned(),
     }
+}
 
-    pub struct AppFn<F> {
-        f: F,
+async fn count(
+    State(counter): State<Arc<AtomicUsize>>,
+    ConnectInfo(conn): ConnectInfo<ConnInfo>,
+    mut req: Request,
+) -> Response {
+    info!(uri = %req.uri, "Handling a request");
+    let counter = counter.fetch_add(1, Ordering::SeqCst);
+
+    if counter % 4 == 2 {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failing 25% of the time, just for fun",
+        )
+            .into_response();
     }
 
-    pub fn app_fn<F, Ret>(f: F) -> AppFn<F>
-    where
-        F: FnMut(Request) -> Ret,
-        Ret: Future<Output = Result<Response, Error>>,
-    {
-        AppFn { f }
+    req.headers
+        .insert(HeaderName::from_static("x-counter"), counter.into());
+    if let Ok(conn) = conn.host_and_port.parse() {
+        req.headers.insert(HeaderName::from_static("x-conn"), conn);
     }
 
-    impl<F, Ret> Service<Request> for AppFn<F>
-    where
-        F: FnMut(Request) -> Ret,
-        Ret: Future<Output = Result<Response, Error>>,
-    {
-        type Response = Response;
-        type Error = Error;
-        type Future = Ret;
-
-        fn poll_ready(
-            &mut self,
-            _cx: &mut std::task::Context<'_>,
-        ) -> std::task::Poll<Result<(), Self::Error>> {
-            std::task::Poll::Ready(Ok(()))
-        }
+    Response {
+        status: StatusCode::OK,
+        version: req.version,
+        headers: req.headers,
+        body: req.body,
+        extensions: Extensions::default(),
+    }
+}
 
-        fn call(&mut self, req: Request) -> Self::Future {
-            (self.f)(req)
-        }
+/// Streams a few lines with a pause in between, as a stand-in for a large
+/// download or generated report.
+async fn numbers() -> ([(&'static str, &'static str); 1], Body) {
+    let lines = stream::iter(1..=3).then(|n| async move {
+        sleep(Duration::from_millis(100)).await;
+        Ok::<_, Infallible>(Bytes::from(format!("{}\n", n)))
+    });
+    (
+        [("Content-Type", "text/plain; charset=utf-8")],
+        Body::from_stream(lines),
+    )
+}
+
+/// Accepts the demo token `letmein`.
+async fn user_for_token(token: String) -> Option<String> {
+    (token == "letmein").then(|| "demo-user".to_owned())
+}
+
+async fn me(Authenticated(user): Authenticated<String>) -> String {
+    format!("Signed in as {}", user)
+}
+
+async fn download() -> Result<File, StatusCode> {
+    let file = File::open(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"))
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+    Ok(file.attachment("Cargo.toml"))
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Visits {
+    count: u32,
+}
+
+/// Counts the client's visits in its session.
+async fn visits(session: Session<Visits>) -> String {
+    let count = session.update(|visits| {
+        visits.count += 1;
+        visits.count
+    });
+    format!("You've been here {} times", count)
+}
+
+async fn forget_visits(session: Session<Visits>) -> StatusCode {
+    session.destroy();
+    StatusCode::NO_CONTENT
+}
+
+/// A form that only this site can post, thanks to the `CsrfLayer`.
+async fn note_form(token: CsrfToken) -> Html<String> {
+    Html(format!(
+        r#"<form method="post" action="/note">
+    <input type="hidden" name="csrf_token" value="{}">
+    <input name="text"> <button>Save</button>
+</form>"#,
+        token
+    ))
+}
+
+#[derive(Deserialize)]
+struct Note {
+    text: String,
+}
+
+async fn save_note(Form(note): Form<Note>) -> String {
+    format!("Saved {:?}", note.text)
+}
+
+/// Answered with a 500 by the `CatchPanicLayer`.
+async fn panic() -> &'static str {
+    panic!("the demo handler panicked")
+}
+
+#[derive(TypedPath)]
+#[typed_path("/users/:id{uint}/posts/:post_id{uint}")]
+struct PostPath {
+    id: u64,
+    post_id: u64,
+}
+
+async fn show_post(path: PostPath, OriginalUri(uri): OriginalUri, req: Request) -> String {
+    if let Some(matched_path) = req.extensions.get::<MatchedPath>() {
+        info!(route = matched_path.as_str(), uri = %uri, "Matched a route");
     }
+
+    format!("Post {} of user {} at {}", path.post_id, path.id, path)
 }
 
 #[tokio::main]
 async fn main() {
-    use crate::http::Response;
+    tracing_subscriber::fmt::init();
+
     let counter = Arc::new(AtomicUsize::new(0));
 
-    let mk_app = |conn: ConnInfo| {
-        app_fn(move |mut req| {
-            println!("Handling a request: {:?}", req.path_and_query);
-            let counter = counter.clone();
-            let conn_info = conn.clone();
-            async move {
-                let counter = counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    let app = {
+        let api = Router::new()
+            .typed_route::<PostPath>(get(show_post))
+            .route_layer(ConcurrencyLimitLayer::new(16))
+            .route_layer(DefaultBodyLimit::max(16 * 1024))
+            .route_layer(RateLimitLayer::new(5, 1.0))
+            .layer(
+                CorsLayer::new()
+                    .allow_origin("http://localhost:5173")
+                    .max_age(Duration::from_secs(600)),
+            );
+
+        let ops = Router::new()
+            .route("/health", get(|| async { "ok" }))
+            .route(
+                "/admin",
+                get(me)
+                    .layer(RequireBasicAuth::new("admin", "letmein").realm("ops"))
+                    // Only from this machine, before asking who the client is.
+                    .layer(IpFilterLayer::new().allow("127.0.0.0/8").allow("::1")),
+            );
+
+        Router::new()
+            .group("/fake", |g| {
+                g.route("/path", get(count))
+                    .layer(ConcurrencyLimitLayer::new(64));
+            })
+            .route("/", get(index))
+            .route("/index.html", get(|| async { Redirect::permanent("/") }))
+            .route("/hello/:name", get(hello))
+            .route("/search", get(search))
+            .route("/version", get(version))
+            .route("/whoami", get(whoami))
+            .route(
+                "/slow",
+                get(slow).layer(TimeoutLayer::new(Duration::from_secs(3))),
+            )
+            .route(
+                "/me",
+                get(me).layer(BearerAuthLayer::new(user_for_token).realm("demo")),
+            )
+            .route("/numbers", get(numbers))
+            .route("/download", get(download))
+            .route("/panic", get(panic))
+            .route("/visits", get(visits).delete(forget_visits))
+            .route("/note", get(note_form).post(save_note))
+            .nest("/api", api)
+            .merge(ops)
+            .fallback(app_fn(|req: Request| async move {
+                let resp = Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .header("Content-Type", "text/plain; charset=utf-8")
+                    .body(format!("Nothing to see at {}", req.uri))?;
+                Ok(resp)
+            }))
+            .with_state(counter)
+            // Over plain HTTP too, it's a demo.
+            .layer(SessionLayer::<Visits>::new(MemorySessionStore::new()).secure(false))
+            .layer(CsrfLayer::new().secure(false))
+            .layer(CatchPanicLayer::new())
+            .layer(ConditionalLayer::new())
+            .layer(
+                CacheControlLayer::new()
+                    .route("/api/*", CachePolicy::no_store())
+                    .route(
+                        "/download",
+                        CachePolicy::max_age(Duration::from_secs(60)).public(),
+                    )
+                    .content_type("text/html", CachePolicy::new("no-cache")),
+            )
+            .layer(CompressionLayer::new())
+            .layer(DecompressionLayer::new())
+            .layer(RequestBodyLimitLayer::new(8 * 1024 * 1024))
+            .layer(middleware::from_fn(response_time))
+            .layer(AccessLogLayer::new())
+            .layer(TraceLayer::new())
+            .layer(RequestIdLayer::new())
+    };
 
-                anyhow::ensure!(counter % 4 != 2, "Failing 25% of the time, just for fun");
+    for route in app.routes() {
+        info!(methods = ?route.methods, layers = ?route.layers, "Route {}", route.path);
+    }
 
-                req.headers.insert(
-                    format!("Conn: {:?}, X-Counter", conn_info),
-                    counter.to_string(),
-                );
+    let admin = Router::new().route("/health", get(|| async { "admin ok" }));
 
-                let resp = Response {
-                    status: 200,
-                    headers: req.headers,
-                    body: req.body,
-                };
+    let hosts = HostRouter::new()
+        .host("admin.localhost", admin)
+        .default_router(app);
 
-                Ok(resp)
-            }
+    // A factory that keeps failing is given a rest rather than retried for
+    // every connection.
+    let app_factory = CircuitBreakerLayer::new().layer(
+        app_factory_fn(|conn: ConnInfo| {
+            info!(conn = ?conn, "Starting a new app");
+            let app = hosts.clone().with_conn_info(&conn);
+            async move { Ok(app) }
         })
+        .layer(NormalizePathLayer::new())
+        .concurrency_limit(4)
+        .layer(LoadShedLayer::new(Duration::from_secs(2))),
+    );
+
+    // With an address, e.g. `cargo run -- 127.0.0.1:3000`, serve real
+    // connections instead of the fake ones, or `unix:PATH` for a Unix
+    // domain socket. With PEM files for a certificate chain and its key
+    // after it, serve HTTPS, and with a PEM file of CA certificates after
+    // those, also accept client certificates issued by them.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let server = match args.as_slice() {
+        [] => return fakeserver::run(app_factory).await,
+        [addr] => match addr.strip_prefix("unix:") {
+            #[cfg(unix)]
+            Some(path) => Server::bind_uds(path),
+            // Cleartext clients may speak HTTP/2, e.g. `curl --http2-prior-knowledge`.
+            _ => Server::bind(addr.as_str()).h2c(true),
+        },
+        [addr, cert, key, client_ca @ ..] if client_ca.len() <= 1 => {
+            match tls_config(cert, key, client_ca.first()) {
+                Ok(config) => Server::bind_rustls(addr.as_str(), config),
+                Err(e) => return error!(error = ?e, "Invalid TLS certificate or key"),
+            }
+        }
+        _ => {
+            return eprintln!("Usage: part1-app-factory [ADDR [CERT_PEM KEY_PEM [CLIENT_CA_PEM]]]")
+        }
     };
+    // Real connections share one app: without a `Host` header, the host
+    // router falls back to the `ConnectInfo` the server attaches.
+    if let Err(e) = serve(server, NormalizePathLayer::new().layer(hosts)).await {
+        error!(error = ?e, "Server failed");
+    }
+}
 
-    let app_factory = app_factory_fn(|conn| {
-        println!("Starting a new app for connection {:?}", conn);
-        let app = (mk_app.clone())(conn);
-        async move { Ok(app) }
-    });
-
-    fakeserver::run(app_factory).await;
+fn tls_config(
+    cert_path: &str,
+    key_path: &str,
+    client_ca_path: Option<&String>,
+) -> io::Result<Arc<rustls::ServerConfig>> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(StdFile::open(cert_path)?))?;
+    let key = rustls_pemfile::pkcs8_private_keys(&mut BufReader::new(StdFile::open(key_path)?))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no PKCS #8 key found"))?;
+
+    let builder = rustls::ServerConfig::builder().with_safe_defaults();
+    let builder = match client_ca_path {
+        // Clients without a certificate are still served, and handlers that
+        // need one reject them by taking a `PeerCert`.
+        Some(path) => {
+            let mut roots = rustls::RootCertStore::empty();
+            let (added, _) = roots.add_parsable_certificates(&rustls_pemfile::certs(
+                &mut BufReader::new(StdFile::open(path)?),
+            )?);
+            if added == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "no CA certificate found",
+                ));
+            }
+            builder.with_client_cert_verifier(
+                rustls::server::AllowAnyAnonymousOrAuthenticatedClient::new(roots).boxed(),
+            )
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let mut config = builder
+        .with_single_cert(
+            certs.into_iter().map(rustls::Certificate).collect(),
+            rustls::PrivateKey(key),
+        )
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
 }
@@ -0,0 +1,55 @@
+//! Middleware: layers that wrap apps, routers or single routes to act on
+//! requests before they reach them and on responses after.
+//!
+//! Any tower `Layer` works with [`Router::layer`](crate::router::Router::layer)
+//! and friends. The ones here are specific to this crate's types.
+
+pub use self::{
+    access_log::{
+        AccessLog, AccessLogLayer, AccessRecord, CommonLogFormat, JsonLogFormat, LogFormat,
+        LogWriter,
+    },
+    auth::{BearerAuth, BearerAuthLayer, RequireBasicAuth, RequireBasicAuthService},
+    cache_control::{CacheControlLayer, CachePolicy, SetCacheControl},
+    catch_panic::{CatchPanic, CatchPanicLayer},
+    circuit_breaker::{CircuitBreaker, CircuitBreakerLayer, CircuitOpen},
+    compression::{Compression, CompressionLayer},
+    conditional::{Conditional, ConditionalLayer},
+    cors::{Cors, CorsLayer},
+    csrf::{Csrf, CsrfLayer, CsrfToken},
+    decompression::{Decompression, DecompressionLayer},
+    from_fn::{from_fn, FromFn, FromFnLayer, Next},
+    in_flight_limit::{InFlightLimit, InFlightLimitLayer},
+    ip_filter::{IpFilter, IpFilterLayer},
+    load_shed::{LoadShed, LoadShedLayer},
+    normalize_path::{NormalizePath, NormalizePathLayer},
+    rate_limit::{MemoryStore, Quota, RateLimit, RateLimitLayer, RateLimitStore},
+    request_body_limit::{RequestBodyLimit, RequestBodyLimitLayer},
+    request_id::{RequestId, RequestIdLayer, SetRequestId},
+    session::{MemorySessionStore, Session, SessionLayer, SessionManager, SessionStore},
+    timeout::{Timeout, TimeoutLayer},
+    trace::{Trace, TraceLayer},
+};
+
+mod access_log;
+mod auth;
+mod cache_control;
+mod catch_panic;
+mod circuit_breaker;
+mod coding;
+mod compression;
+mod conditional;
+mod cors;
+mod csrf;
+mod decompression;
+mod from_fn;
+mod in_flight_limit;
+mod ip_filter;
+mod load_shed;
+mod normalize_path;
+mod rate_limit;
+mod request_body_limit;
+mod request_id;
+mod session;
+mod timeout;
+mod trace;
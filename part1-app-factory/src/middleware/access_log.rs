@@ -0,0 +1,439 @@
+use std::{
+    fmt,
+    future::Future,
+    io::{self, Write},
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
+
+use tower::{Layer, Service};
+
+use super::RequestId;
+use crate::{
+    extract::ConnectInfo,
+    http::{format_log_date, ConnInfo, Method, Request, Response, StatusCode, Uri, Version},
+    router::OriginalUri,
+};
+
+/// Logs a line for every request once its response is ready.
+///
+/// Lines are in the [`CommonLogFormat`] and go to stdout unless configured
+/// otherwise:
+///
+/// ```
+/// # use std::sync::{Arc, Mutex};
+/// # use part1_app_factory::{
+/// #     middleware::{AccessLogLayer, JsonLogFormat},
+/// #     router::{get, Router},
+/// # };
+/// # async fn index() {}
+/// let logs = Arc::new(Mutex::new(Vec::new()));
+/// let app = Router::new()
+///     .route("/", get(index))
+///     .layer(AccessLogLayer::new().format(JsonLogFormat).writer(logs.clone()));
+/// ```
+///
+/// The latency is the time until the response head is ready, so the body of
+/// a streamed response isn't part of it.
+#[derive(Clone)]
+pub struct AccessLogLayer {
+    format: Arc<dyn LogFormat>,
+    writer: Arc<dyn LogWriter>,
+}
+
+impl AccessLogLayer {
+    pub fn new() -> Self {
+        AccessLogLayer {
+            format: Arc::new(CommonLogFormat),
+            writer: Arc::new(io::stdout()),
+        }
+    }
+
+    /// Formats lines with `format`, e.g. [`JsonLogFormat`] or a closure
+    /// taking an [`AccessRecord`].
+    pub fn format(mut self, format: impl LogFormat) -> Self {
+        self.format = Arc::new(format);
+        self
+    }
+
+    /// Writes lines to `writer`, e.g. `io::stderr()` or a
+    /// `Mutex<Vec<u8>>` to read them back.
+    pub fn writer(mut self, writer: impl LogWriter) -> Self {
+        self.writer = Arc::new(writer);
+        self
+    }
+}
+
+impl Default for AccessLogLayer {
+    fn default() -> Self {
+        AccessLogLayer::new()
+    }
+}
+
+impl fmt::Debug for AccessLogLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessLogLayer").finish_non_exhaustive()
+    }
+}
+
+impl<S> Layer<S> for AccessLogLayer {
+    type Service = AccessLog<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AccessLog {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// A service logging the requests it passes on, made by [`AccessLogLayer`].
+#[derive(Clone, Debug)]
+pub struct AccessLog<S> {
+    inner: S,
+    layer: AccessLogLayer,
+}
+
+impl<S> Service<Request> for AccessLog<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let start = Instant::now();
+        let time = SystemTime::now();
+        let uri = match req.extensions.get::<OriginalUri>() {
+            Some(OriginalUri(uri)) => uri.clone(),
+            None => req.uri.clone(),
+        };
+        let conn = req
+            .extensions
+            .get::<ConnectInfo<ConnInfo>>()
+            .map(|ConnectInfo(conn)| (conn.id, conn.peer_addr));
+        let method = req.method;
+        let version = req.version;
+        let request_bytes = req.body.size_hint().exact();
+        let request_id = req
+            .extensions
+            .get::<RequestId>()
+            .map(|id| id.as_str().to_owned());
+
+        let future = self.inner.call(req);
+        let AccessLogLayer { format, writer } = self.layer.clone();
+        Box::pin(async move {
+            let result = future.await;
+            let (status, response_bytes) = match &result {
+                Ok(resp) => (resp.status, resp.body.size_hint().exact()),
+                // The server answers errors with an empty 500.
+                Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, Some(0)),
+            };
+            let record = AccessRecord {
+                time,
+                method,
+                uri,
+                version,
+                status,
+                latency: start.elapsed(),
+                request_bytes,
+                response_bytes,
+                conn_id: conn.map(|(id, _)| id),
+                peer_addr: conn.and_then(|(_, peer_addr)| peer_addr),
+                request_id,
+            };
+            writer.write_line(&format.format(&record));
+            result
+        })
+    }
+}
+
+/// What's logged about a request.
+#[derive(Clone, Debug)]
+pub struct AccessRecord {
+    /// When the request arrived.
+    pub time: SystemTime,
+    pub method: Method,
+    /// The request target as the client sent it.
+    pub uri: Uri,
+    pub version: Version,
+    pub status: StatusCode,
+    /// How long the response head took.
+    pub latency: Duration,
+    /// The size of the request body, if the client said.
+    pub request_bytes: Option<u64>,
+    /// The size of the response body, unless it's streamed without a
+    /// length.
+    pub response_bytes: Option<u64>,
+    /// The [`ConnInfo::id`] of the connection, if the server attached it.
+    pub conn_id: Option<u64>,
+    pub peer_addr: Option<SocketAddr>,
+    /// The [`RequestId`], if a [`RequestIdLayer`](super::RequestIdLayer)
+    /// outside this one attached it.
+    pub request_id: Option<String>,
+}
+
+/// Turns an [`AccessRecord`] into a line of the log.
+pub trait LogFormat: Send + Sync + 'static {
+    fn format(&self, record: &AccessRecord) -> String;
+}
+
+impl<F> LogFormat for F
+where
+    F: Fn(&AccessRecord) -> String + Send + Sync + 'static,
+{
+    fn format(&self, record: &AccessRecord) -> String {
+        self(record)
+    }
+}
+
+/// The format of the Apache and NGINX access logs, e.g.
+/// `127.0.0.1 - - [06/Nov/1994:08:49:37 +0000] "GET / HTTP/1.1" 200 512`.
+///
+/// It has no room for the latency or connection.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CommonLogFormat;
+
+impl LogFormat for CommonLogFormat {
+    fn format(&self, record: &AccessRecord) -> String {
+        let host = match record.peer_addr {
+            Some(addr) => addr.ip().to_string(),
+            None => "-".to_owned(),
+        };
+        let bytes = match record.response_bytes {
+            Some(bytes) => bytes.to_string(),
+            None => "-".to_owned(),
+        };
+        format!(
+            "{} - - [{}] \"{} {} {}\" {} {}",
+            host,
+            format_log_date(record.time),
+            record.method,
+            record.uri,
+            record.version,
+            record.status.as_u16(),
+            bytes
+        )
+    }
+}
+
+/// A JSON object per line with every field of the [`AccessRecord`], the
+/// latency in milliseconds and unknown values as `null`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonLogFormat;
+
+impl LogFormat for JsonLogFormat {
+    fn format(&self, record: &AccessRecord) -> String {
+        serde_json::json!({
+            "time": format_log_date(record.time),
+            "method": record.method.as_str(),
+            "uri": record.uri.to_string(),
+            "version": record.version.as_str(),
+            "status": record.status.as_u16(),
+            "latency_ms": record.latency.as_secs_f64() * 1000.0,
+            "request_bytes": record.request_bytes,
+            "response_bytes": record.response_bytes,
+            "conn_id": record.conn_id,
+            "peer_addr": record.peer_addr.map(|addr| addr.to_string()),
+            "request_id": record.request_id,
+        })
+        .to_string()
+    }
+}
+
+/// Where the lines of the log go.
+pub trait LogWriter: Send + Sync + 'static {
+    fn write_line(&self, line: &str);
+}
+
+impl LogWriter for io::Stdout {
+    fn write_line(&self, line: &str) {
+        let _ = writeln!(self.lock(), "{}", line);
+    }
+}
+
+impl LogWriter for io::Stderr {
+    fn write_line(&self, line: &str) {
+        let _ = writeln!(self.lock(), "{}", line);
+    }
+}
+
+/// Any writer, e.g. a file or a `Vec<u8>` to read the lines back from.
+impl<W> LogWriter for Mutex<W>
+where
+    W: Write + Send + 'static,
+{
+    fn write_line(&self, line: &str) {
+        if let Ok(mut writer) = self.lock() {
+            let _ = writeln!(writer, "{}", line);
+        }
+    }
+}
+
+impl<T> LogWriter for Arc<T>
+where
+    T: LogWriter + ?Sized,
+{
+    fn write_line(&self, line: &str) {
+        (**self).write_line(line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use anyhow::Error;
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+    use crate::{http::Body, middleware::RequestIdLayer, response::IntoResponse};
+
+    fn record() -> AccessRecord {
+        AccessRecord {
+            time: UNIX_EPOCH + Duration::from_secs(784111777),
+            method: Method::Get,
+            uri: Uri::from("/search?q=rust"),
+            version: Version::Http11,
+            status: StatusCode::OK,
+            latency: Duration::from_micros(1500),
+            request_bytes: None,
+            response_bytes: Some(512),
+            conn_id: Some(3),
+            peer_addr: Some("127.0.0.1:50000".parse().unwrap()),
+            request_id: Some("abc".to_owned()),
+        }
+    }
+
+    /// Sends `req` through the layer to `app`, returning the line it logged.
+    async fn log<F>(layer: AccessLogLayer, req: Request, app: F) -> String
+    where
+        F: Fn(Request) -> Result<Response, Error> + Clone + Send + 'static,
+    {
+        let logs = Arc::new(Mutex::new(Vec::new()));
+        let app = service_fn(move |req| {
+            let app = app.clone();
+            async move { app(req) }
+        });
+        let _ = layer.writer(logs.clone()).layer(app).oneshot(req).await;
+        let logs = String::from_utf8(logs.lock().unwrap().clone()).unwrap();
+        let line = logs.strip_suffix('\n').unwrap();
+        assert!(!line.contains('\n'), "logged more than a line: {:?}", logs);
+        line.to_owned()
+    }
+
+    #[test]
+    fn formats_common_log_lines() {
+        assert_eq!(
+            CommonLogFormat.format(&record()),
+            "127.0.0.1 - - [06/Nov/1994:08:49:37 +0000] \"GET /search?q=rust HTTP/1.1\" 200 512"
+        );
+
+        let record = AccessRecord {
+            peer_addr: None,
+            response_bytes: None,
+            ..record()
+        };
+        assert!(CommonLogFormat.format(&record).ends_with("\" 200 -"));
+        assert!(CommonLogFormat.format(&record).starts_with("- - - ["));
+    }
+
+    #[test]
+    fn formats_json_lines() {
+        let line: serde_json::Value =
+            serde_json::from_str(&JsonLogFormat.format(&record())).unwrap();
+        assert_eq!(
+            line,
+            serde_json::json!({
+                "time": "06/Nov/1994:08:49:37 +0000",
+                "method": "GET",
+                "uri": "/search?q=rust",
+                "version": "HTTP/1.1",
+                "status": 200,
+                "latency_ms": 1.5,
+                "request_bytes": null,
+                "response_bytes": 512,
+                "conn_id": 3,
+                "peer_addr": "127.0.0.1:50000",
+                "request_id": "abc",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn logs_requests() {
+        let mut req = Request::builder()
+            .method(Method::Post)
+            .uri("/users")
+            .header("x-request-id", "req-1")
+            .body("hello")
+            .unwrap();
+        req.extensions.insert(ConnectInfo(ConnInfo {
+            id: 7,
+            host_and_port: "localhost:3000".to_owned(),
+            local_addr: None,
+            peer_addr: Some("192.0.2.1:40000".parse().unwrap()),
+            peer_cred: None,
+            tls: None,
+        }));
+        // Nesting routers strip their prefix, but the original is logged.
+        req.extensions
+            .insert(OriginalUri(Uri::from("/api/users?page=2")));
+
+        let logs = Arc::new(Mutex::new(Vec::new()));
+        let layer = AccessLogLayer::new()
+            .format(JsonLogFormat)
+            .writer(logs.clone());
+        let app = service_fn(|_: Request| async {
+            Ok::<_, Error>((StatusCode::CREATED, "created!").into_response())
+        });
+        // The request ID has to be attached outside the log.
+        let app = RequestIdLayer::new().layer(layer.layer(app));
+        app.oneshot(req).await.unwrap();
+
+        let logs = String::from_utf8(logs.lock().unwrap().clone()).unwrap();
+        let line: serde_json::Value = serde_json::from_str(&logs).unwrap();
+        assert_eq!(line["method"], "POST");
+        assert_eq!(line["uri"], "/api/users?page=2");
+        assert_eq!(line["status"], 201);
+        assert_eq!(line["request_bytes"], 5);
+        assert_eq!(line["response_bytes"], 8);
+        assert_eq!(line["conn_id"], 7);
+        assert_eq!(line["peer_addr"], "192.0.2.1:40000");
+        assert_eq!(line["request_id"], "req-1");
+        assert!(line["latency_ms"].as_f64().unwrap() >= 0.0);
+    }
+
+    #[tokio::test]
+    async fn logs_errors_as_500() {
+        let req = Request::builder().uri("/").body(Body::empty()).unwrap();
+        let line = log(AccessLogLayer::new(), req, |_| {
+            Err(anyhow::anyhow!("database is down"))
+        })
+        .await;
+        assert!(line.ends_with("\"GET / HTTP/1.1\" 500 0"), "{}", line);
+    }
+
+    #[tokio::test]
+    async fn formats_with_closures() {
+        let req = Request::builder()
+            .uri("/health")
+            .body(Body::empty())
+            .unwrap();
+        let format = |record: &AccessRecord| format!("{} {}", record.uri, record.status.as_u16());
+        let line = log(AccessLogLayer::new().format(format), req, |_| {
+            Ok(StatusCode::NO_CONTENT.into_response())
+        })
+        .await;
+        assert_eq!(line, "/health 204");
+    }
+}
@@ -0,0 +1,404 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use base64::{
+    alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
+use sha2::{Digest, Sha256};
+use tower::{Layer, Service};
+
+use crate::{
+    extract::Authenticated,
+    headers::{Authorization, Header},
+    http::{
+        header::{AUTHORIZATION, WWW_AUTHENTICATE},
+        HeaderValue, Request, Response, StatusCode,
+    },
+    response::IntoResponse,
+};
+
+/// Lets through only requests with an `Authorization: Bearer` token that
+/// `validator` accepts, answering the rest with a 401.
+///
+/// The validator is an async function taking the token and returning who
+/// it belongs to, or `None` if it's no good. Handlers get the identity with
+/// [`Authenticated`]:
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use part1_app_factory::{
+/// #     extract::Authenticated,
+/// #     middleware::BearerAuthLayer,
+/// #     router::{get, Router},
+/// # };
+/// # #[derive(Clone)]
+/// # struct User {
+/// #     name: String,
+/// # }
+/// # struct Db;
+/// # impl Db {
+/// #     async fn user_for_token(&self, token: &str) -> Option<User> {
+/// #         (token == "secret").then(|| User { name: "Ferris".to_owned() })
+/// #     }
+/// # }
+/// # let db = Arc::new(Db);
+/// let app = Router::new()
+///     .route("/me", get(|Authenticated(user): Authenticated<User>| async move { user.name }))
+///     .layer(BearerAuthLayer::new(move |token: String| {
+///         let db = db.clone();
+///         async move { db.user_for_token(&token).await }
+///     }));
+/// ```
+///
+/// The 401 carries a `WWW-Authenticate` challenge, saying
+/// `error="invalid_token"` when a token was sent but rejected, as RFC 6750
+/// has it.
+pub struct BearerAuthLayer<V> {
+    validator: Arc<V>,
+    realm: Option<Arc<str>>,
+}
+
+impl<V> BearerAuthLayer<V> {
+    pub fn new(validator: V) -> Self {
+        BearerAuthLayer {
+            validator: Arc::new(validator),
+            realm: None,
+        }
+    }
+
+    /// Names the protected area in the challenge, e.g. `"api"`.
+    pub fn realm(mut self, realm: &str) -> Self {
+        self.realm = Some(realm.into());
+        self
+    }
+
+    /// The challenge of a 401, with `error` if a token was sent.
+    fn challenge(&self, error: Option<&str>) -> Response {
+        let mut params = Vec::new();
+        if let Some(realm) = &self.realm {
+            params.push(format!("realm=\"{}\"", realm));
+        }
+        if let Some(error) = error {
+            params.push(format!("error=\"{}\"", error));
+        }
+        let challenge = if params.is_empty() {
+            "Bearer".to_owned()
+        } else {
+            format!("Bearer {}", params.join(", "))
+        };
+        let mut resp = StatusCode::UNAUTHORIZED.into_response();
+        let challenge = challenge
+            .parse()
+            .unwrap_or_else(|_| HeaderValue::from_static("Bearer"));
+        resp.headers.insert(WWW_AUTHENTICATE, challenge);
+        resp
+    }
+}
+
+impl<V> Clone for BearerAuthLayer<V> {
+    fn clone(&self) -> Self {
+        BearerAuthLayer {
+            validator: self.validator.clone(),
+            realm: self.realm.clone(),
+        }
+    }
+}
+
+impl<V> fmt::Debug for BearerAuthLayer<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BearerAuthLayer")
+            .field("realm", &self.realm)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S, V> Layer<S> for BearerAuthLayer<V> {
+    type Service = BearerAuth<S, V>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BearerAuth {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// A service checking bearer tokens, made by [`BearerAuthLayer`].
+pub struct BearerAuth<S, V> {
+    inner: S,
+    layer: BearerAuthLayer<V>,
+}
+
+impl<S: Clone, V> Clone for BearerAuth<S, V> {
+    fn clone(&self) -> Self {
+        BearerAuth {
+            inner: self.inner.clone(),
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+impl<S: fmt::Debug, V> fmt::Debug for BearerAuth<S, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BearerAuth")
+            .field("inner", &self.inner)
+            .field("layer", &self.layer)
+            .finish()
+    }
+}
+
+impl<S, V, Fut, I> Service<Request> for BearerAuth<S, V>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    V: Fn(String) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Option<I>> + Send + 'static,
+    I: Clone + Send + Sync + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        let token = req
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| Authorization::decode(value.as_str()))
+            .and_then(|auth| auth.bearer_token().map(str::to_owned));
+        let Some(token) = token else {
+            let resp = self.layer.challenge(None);
+            return Box::pin(async move { Ok(resp) });
+        };
+
+        // The clone that was made ready goes with the request, leaving a
+        // fresh one for the next.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+        let validated = (self.layer.validator)(token);
+        Box::pin(async move {
+            match validated.await {
+                Some(identity) => {
+                    req.extensions.insert(Authenticated(identity));
+                    inner.call(req).await
+                }
+                None => Ok(layer.challenge(Some("invalid_token"))),
+            }
+        })
+    }
+}
+
+/// Lets through only requests with `Authorization: Basic` credentials of
+/// one of the configured users, answering the rest with a 401 whose
+/// challenge makes browsers ask for them.
+///
+/// It suits internal endpoints such as metrics or admin pages:
+///
+/// ```no_run
+/// # use std::env;
+/// # use part1_app_factory::{middleware::RequireBasicAuth, router::{get, Router}};
+/// # async fn metrics() {}
+/// let admin = Router::new()
+///     .route("/metrics", get(metrics))
+///     .layer(RequireBasicAuth::new("ops", &env::var("OPS_PASSWORD")?).realm("ops"));
+/// # Ok::<_, env::VarError>(())
+/// ```
+///
+/// Credentials are compared in constant time, so how long a check takes
+/// doesn't tell how much of a guess was right. The user name is attached as
+/// an [`Authenticated<String>`](Authenticated). The credentials travel in the
+/// clear, so serve it over TLS.
+#[derive(Clone)]
+pub struct RequireBasicAuth {
+    /// Digests of the user names and passwords.
+    users: Vec<([u8; 32], [u8; 32])>,
+    realm: Arc<str>,
+}
+
+impl RequireBasicAuth {
+    /// Lets in `username` with `password`, in the realm `"Restricted"`.
+    pub fn new(username: &str, password: &str) -> Self {
+        RequireBasicAuth {
+            users: Vec::new(),
+            realm: "Restricted".into(),
+        }
+        .user(username, password)
+    }
+
+    /// Lets in another user.
+    pub fn user(mut self, username: &str, password: &str) -> Self {
+        self.users.push((digest(username), digest(password)));
+        self
+    }
+
+    /// Names the protected area, which browsers show when asking for
+    /// credentials.
+    pub fn realm(mut self, realm: &str) -> Self {
+        self.realm = realm.into();
+        self
+    }
+
+    /// The user name, if `value` has the credentials of a user.
+    fn check(&self, value: &str) -> Option<String> {
+        let auth = Authorization::decode(value)?;
+        if !auth.scheme().eq_ignore_ascii_case("basic") {
+            return None;
+        }
+        let decoded = String::from_utf8(BASE64.decode(auth.credentials()).ok()?).ok()?;
+        let (username, password) = decoded.split_once(':')?;
+        let (username_digest, password_digest) = (digest(username), digest(password));
+        // Every user is checked, so the time doesn't depend on which matched.
+        let matched = self.users.iter().fold(false, |matched, (user, pass)| {
+            let equal =
+                constant_time_eq(user, &username_digest) & constant_time_eq(pass, &password_digest);
+            matched | equal
+        });
+        matched.then(|| username.to_owned())
+    }
+
+    fn challenge(&self) -> Response {
+        let mut resp = StatusCode::UNAUTHORIZED.into_response();
+        let challenge = format!("Basic realm=\"{}\", charset=\"UTF-8\"", self.realm)
+            .parse()
+            .unwrap_or_else(|_| HeaderValue::from_static("Basic"));
+        resp.headers.insert(WWW_AUTHENTICATE, challenge);
+        resp
+    }
+}
+
+/// Leaves the credentials out.
+impl fmt::Debug for RequireBasicAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequireBasicAuth")
+            .field("realm", &self.realm)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> Layer<S> for RequireBasicAuth {
+    type Service = RequireBasicAuthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequireBasicAuthService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Service created by [`RequireBasicAuth`].
+#[derive(Clone, Debug)]
+pub struct RequireBasicAuthService<S> {
+    inner: S,
+    layer: RequireBasicAuth,
+}
+
+impl<S> Service<Request> for RequireBasicAuthService<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        let username = req
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| self.layer.check(value.as_str()));
+        match username {
+            Some(username) => {
+                req.extensions.insert(Authenticated(username));
+                Box::pin(self.inner.call(req))
+            }
+            None => {
+                let resp = self.layer.challenge();
+                Box::pin(async move { Ok(resp) })
+            }
+        }
+    }
+}
+
+pub(super) fn digest(value: &str) -> [u8; 32] {
+    Sha256::digest(value.as_bytes()).into()
+}
+
+pub(super) fn constant_time_eq(a: &[u8; 32], b: &[u8; 32]) -> bool {
+    a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Standard base64, with or without padding, as some clients leave it out.
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+    use crate::http::Body;
+
+    /// Answers with the user name the layer attached.
+    async fn send(layer: RequireBasicAuth, authorization: Option<&str>) -> Response {
+        let app = service_fn(|req: Request| async move {
+            let user = req.extensions.get::<Authenticated<String>>().unwrap();
+            Ok::<_, Infallible>(user.0.clone().into_response())
+        });
+        let mut builder = Request::builder().uri("/");
+        if let Some(authorization) = authorization {
+            builder = builder.header("authorization", authorization);
+        }
+        let req = builder.body(Body::empty()).unwrap();
+        layer.layer(app).oneshot(req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn lets_in_users() {
+        let layer = RequireBasicAuth::new("ops", "secret").user("ci", "hunter2");
+        // "ops:secret", then "ci:hunter2" without its padding.
+        for (credentials, user) in [("b3BzOnNlY3JldA==", "ops"), ("Y2k6aHVudGVyMg", "ci")] {
+            let resp = send(layer.clone(), Some(&format!("Basic {}", credentials))).await;
+            assert_eq!(resp.status, StatusCode::OK);
+            assert_eq!(resp.body.collect().await.unwrap(), user.as_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn challenges_everyone_else() {
+        let layer = RequireBasicAuth::new("ops", "secret").realm("ops");
+        for authorization in [
+            None,
+            // "ops:wrong"
+            Some("Basic b3BzOndyb25n"),
+            Some("Basic not base64!"),
+            Some("Bearer b3BzOnNlY3JldA=="),
+        ] {
+            let resp = send(layer.clone(), authorization).await;
+            assert_eq!(resp.status, StatusCode::UNAUTHORIZED);
+            assert_eq!(
+                resp.headers.get(WWW_AUTHENTICATE).unwrap().as_str(),
+                "Basic realm=\"ops\", charset=\"UTF-8\""
+            );
+        }
+    }
+}
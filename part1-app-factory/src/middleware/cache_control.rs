@@ -0,0 +1,393 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+
+use tower::{Layer, Service};
+
+use crate::{
+    http::{
+        format_http_date,
+        header::{CACHE_CONTROL, CONTENT_TYPE, EXPIRES},
+        HeaderValue, Request, Response, StatusCode,
+    },
+    router::{OriginalUri, PathPattern},
+};
+
+/// Sets `Cache-Control`, and `Expires` for older caches, on responses by
+/// the route they're for or their content type, so handlers don't each have
+/// to:
+///
+/// ```
+/// # use std::time::Duration;
+/// # use part1_app_factory::{
+/// #     middleware::{CacheControlLayer, CachePolicy},
+/// #     router::{get, Router},
+/// # };
+/// # const ONE_DAY: Duration = Duration::from_secs(24 * 60 * 60);
+/// # async fn index() {}
+/// # async fn asset() {}
+/// # let api = Router::new();
+/// let app = Router::new()
+///     .route("/", get(index))
+///     .route("/assets/:file", get(asset))
+///     .nest("/api", api)
+///     .layer(
+///         CacheControlLayer::new()
+///             .route("/api/*", CachePolicy::no_store())
+///             .route("/assets/:file", CachePolicy::max_age(ONE_DAY).immutable())
+///             .content_type("text/html", CachePolicy::new("no-cache"))
+///             .content_type("image/*", CachePolicy::max_age(ONE_DAY).public()),
+///     );
+/// ```
+///
+/// Route patterns are written like those of the router, and one ending in
+/// `/*` also matches every path below it. They're matched against the path
+/// the client asked for, before any [`Router::nest`](crate::router::Router::nest)
+/// stripped a prefix. Content types are matched ignoring parameters, and
+/// `type/*` matches every subtype.
+///
+/// The first rule that matches applies. Only successful and `304` responses
+/// get a policy, and those whose handler set `Cache-Control` keep theirs.
+#[derive(Clone, Debug, Default)]
+pub struct CacheControlLayer {
+    rules: Arc<Vec<(Matcher, CachePolicy)>>,
+}
+
+impl CacheControlLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies `policy` to requests for paths matching `pattern`.
+    ///
+    /// # Panics
+    ///
+    /// If `pattern` doesn't start with `/`.
+    pub fn route(self, pattern: &str, policy: CachePolicy) -> Self {
+        let (pattern, below) = match pattern.strip_suffix('*') {
+            Some(prefix) if prefix.ends_with('/') => (prefix, true),
+            _ => (pattern, false),
+        };
+        let matcher = Matcher::Route {
+            pattern: PathPattern::parse(pattern),
+            below,
+        };
+        self.rule(matcher, policy)
+    }
+
+    /// Applies `policy` to responses whose content type matches `mime`,
+    /// e.g. `text/css` or `image/*`.
+    pub fn content_type(self, mime: &str, policy: CachePolicy) -> Self {
+        self.rule(Matcher::ContentType(mime.to_ascii_lowercase()), policy)
+    }
+
+    fn rule(mut self, matcher: Matcher, policy: CachePolicy) -> Self {
+        Arc::make_mut(&mut self.rules).push((matcher, policy));
+        self
+    }
+
+    /// The policy for a response to a request for `path`.
+    fn policy_for(&self, path: &str, resp: &Response) -> Option<&CachePolicy> {
+        let mime = resp
+            .headers
+            .get(CONTENT_TYPE)
+            .map(|value| value.as_str().split(';').next().unwrap_or_default().trim());
+        self.rules
+            .iter()
+            .find(|(matcher, _)| match matcher {
+                Matcher::Route { pattern, below } => match pattern.match_prefix(path, false) {
+                    Some((_, rest)) => *below || rest.is_empty() || rest == "/",
+                    None => false,
+                },
+                Matcher::ContentType(expected) => {
+                    mime.is_some_and(|mime| mime_matches(expected, mime))
+                }
+            })
+            .map(|(_, policy)| policy)
+    }
+}
+
+/// Whether `mime` is `expected`, which may be `type/*` or `*/*`.
+fn mime_matches(expected: &str, mime: &str) -> bool {
+    match expected.strip_suffix("/*") {
+        Some("*") => true,
+        Some(kind) => mime
+            .split_once('/')
+            .is_some_and(|(actual, _)| actual.eq_ignore_ascii_case(kind)),
+        None => mime.eq_ignore_ascii_case(expected),
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Matcher {
+    Route {
+        pattern: PathPattern,
+        /// Paths below the pattern match too.
+        below: bool,
+    },
+    ContentType(String),
+}
+
+/// How caches may store a response: its `Cache-Control`, and how long until
+/// it expires, for the `Expires` header.
+#[derive(Clone, Debug)]
+pub struct CachePolicy {
+    cache_control: String,
+    expires_in: Option<Duration>,
+}
+
+impl CachePolicy {
+    /// Sends `cache_control` as it is, e.g. `private, max-age=60`, without an
+    /// `Expires`.
+    ///
+    /// # Panics
+    ///
+    /// If `cache_control` isn't a valid header value.
+    pub fn new(cache_control: &str) -> Self {
+        assert!(
+            cache_control.parse::<HeaderValue>().is_ok(),
+            "invalid Cache-Control: {:?}",
+            cache_control
+        );
+        CachePolicy {
+            cache_control: cache_control.to_owned(),
+            expires_in: None,
+        }
+    }
+
+    /// Lets caches reuse the response for `max_age`, which also sets when it
+    /// expires.
+    pub fn max_age(max_age: Duration) -> Self {
+        CachePolicy {
+            cache_control: format!("max-age={}", max_age.as_secs()),
+            expires_in: Some(max_age),
+        }
+    }
+
+    /// Keeps caches from storing the response at all, e.g. for API
+    /// responses with personal data.
+    pub fn no_store() -> Self {
+        CachePolicy::new("no-store")
+    }
+
+    /// Lets shared caches, such as CDNs, store it too.
+    pub fn public(self) -> Self {
+        self.directive("public")
+    }
+
+    /// Keeps shared caches from storing it.
+    pub fn private(self) -> Self {
+        self.directive("private")
+    }
+
+    /// Tells clients it won't change while fresh, so they needn't revalidate
+    /// it on reload. It suits files with a hash in their names.
+    pub fn immutable(self) -> Self {
+        self.directive("immutable")
+    }
+
+    /// Sets `Expires` to `expires_in` after the response is sent.
+    pub fn expires_in(mut self, expires_in: Duration) -> Self {
+        self.expires_in = Some(expires_in);
+        self
+    }
+
+    fn directive(mut self, directive: &str) -> Self {
+        self.cache_control = format!("{}, {}", directive, self.cache_control);
+        self
+    }
+
+    fn apply(&self, resp: &mut Response) {
+        let cache_control = self.cache_control.parse().expect("checked by `new`");
+        resp.headers.insert(CACHE_CONTROL, cache_control);
+        if let Some(expires_in) = self.expires_in {
+            let expires = format_http_date(SystemTime::now() + expires_in);
+            resp.headers.insert(
+                EXPIRES,
+                expires.parse().expect("dates are valid header values"),
+            );
+        }
+    }
+}
+
+impl<S> Layer<S> for CacheControlLayer {
+    type Service = SetCacheControl<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SetCacheControl {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// A service setting the caching policies of responses, made by
+/// [`CacheControlLayer`].
+#[derive(Clone, Debug)]
+pub struct SetCacheControl<S> {
+    inner: S,
+    layer: CacheControlLayer,
+}
+
+impl<S> Service<Request> for SetCacheControl<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let path = match req.extensions.get::<OriginalUri>() {
+            Some(OriginalUri(uri)) => uri.path(),
+            None => req.uri.path(),
+        }
+        .to_owned();
+        let layer = self.layer.clone();
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let mut resp = future.await?;
+            let cacheable = resp.status.is_success() || resp.status == StatusCode::NOT_MODIFIED;
+            if cacheable && !resp.headers.contains_key(CACHE_CONTROL) {
+                if let Some(policy) = layer.policy_for(&path, &resp) {
+                    policy.apply(&mut resp);
+                }
+            }
+            Ok(resp)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+    use crate::{
+        http::{Method, Uri},
+        response::{Html, IntoResponse},
+    };
+
+    fn request(method: Method, uri: &str, headers: &[(&str, &str)], body: &'static str) -> Request {
+        let mut req = Request::builder().method(method).uri(uri);
+        for &(name, value) in headers {
+            req = req.header(name, value);
+        }
+        req.body(body).unwrap()
+    }
+
+    fn header<'a>(resp: &'a Response, name: &str) -> Option<&'a str> {
+        resp.headers.get(name).map(|value| value.as_str())
+    }
+
+    fn layer() -> CacheControlLayer {
+        CacheControlLayer::new()
+            .route(
+                "/assets/*",
+                CachePolicy::max_age(Duration::from_secs(31536000))
+                    .public()
+                    .immutable(),
+            )
+            .route("/users/:id", CachePolicy::new("no-cache").private())
+            .content_type("image/*", CachePolicy::max_age(Duration::from_secs(3600)))
+            .content_type("text/html", CachePolicy::no_store())
+    }
+
+    async fn app(req: Request) -> Result<Response, Infallible> {
+        let mut resp = match req.uri.path() {
+            "/missing" => StatusCode::NOT_FOUND.into_response(),
+            "/page" => Html("<h1>Hello</h1>").into_response(),
+            path if path.ends_with(".png") => {
+                let mut resp = "PNG".into_response();
+                resp.headers
+                    .insert(CONTENT_TYPE, HeaderValue::from_static("image/png"));
+                resp
+            }
+            _ => "text".into_response(),
+        };
+        if req.uri.query() == Some("fresh") {
+            resp.headers
+                .insert(CACHE_CONTROL, HeaderValue::from_static("max-age=5"));
+        }
+        Ok(resp)
+    }
+
+    async fn send(req: Request) -> Response {
+        layer().layer(service_fn(app)).oneshot(req).await.unwrap()
+    }
+
+    /// The `Cache-Control` of the response to `uri`, and whether it expires.
+    async fn cache_control(uri: &str) -> (Option<String>, bool) {
+        let resp = send(request(Method::Get, uri, &[], "")).await;
+        (
+            header(&resp, "Cache-Control").map(str::to_owned),
+            header(&resp, "Expires").is_some(),
+        )
+    }
+
+    #[tokio::test]
+    async fn applies_the_first_policy_that_matches() {
+        let immutable = Some("immutable, public, max-age=31536000".to_owned());
+        assert_eq!(
+            cache_control("/assets/app.js").await,
+            (immutable.clone(), true)
+        );
+        assert_eq!(
+            cache_control("/assets/img/logo.png").await,
+            (immutable, true)
+        );
+
+        let private = Some("private, no-cache".to_owned());
+        assert_eq!(cache_control("/users/7").await, (private.clone(), false));
+        assert_eq!(cache_control("/users/7/").await, (private, false));
+        assert_eq!(cache_control("/users/7/posts").await, (None, false));
+
+        assert_eq!(
+            cache_control("/logo.png").await,
+            (Some("max-age=3600".to_owned()), true)
+        );
+        assert_eq!(
+            cache_control("/page").await,
+            (Some("no-store".to_owned()), false)
+        );
+        assert_eq!(cache_control("/").await, (None, false));
+    }
+
+    #[tokio::test]
+    async fn leaves_errors_and_chosen_policies_alone() {
+        assert_eq!(cache_control("/missing").await, (None, false));
+        assert_eq!(
+            cache_control("/assets/app.js?fresh").await,
+            (Some("max-age=5".to_owned()), false)
+        );
+    }
+
+    #[tokio::test]
+    async fn matches_routes_before_nesting_stripped_them() {
+        let mut req = request(Method::Get, "/app.js", &[], "");
+        req.extensions
+            .insert(OriginalUri(Uri::from("/assets/app.js")));
+        let resp = send(req).await;
+        assert_eq!(
+            header(&resp, "Cache-Control"),
+            Some("immutable, public, max-age=31536000")
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid Cache-Control")]
+    fn refuses_invalid_policies() {
+        CachePolicy::new("no-store\r\nSet-Cookie: a=b");
+    }
+}
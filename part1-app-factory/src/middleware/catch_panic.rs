@@ -0,0 +1,292 @@
+use std::{
+    any::Any,
+    backtrace::{Backtrace, BacktraceStatus},
+    cell::{Cell, RefCell},
+    fmt,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{Arc, Once},
+    task::{Context, Poll},
+};
+
+use tower::{Layer, Service};
+use tracing::error;
+
+use crate::{
+    http::{Request, Response, StatusCode},
+    response::IntoResponse,
+};
+
+type PanicFn = dyn Fn(&str) -> Response + Send + Sync;
+
+/// Answers requests whose handler panics with a 500, instead of the panic
+/// taking down the connection's task and the client getting no response.
+///
+/// The panic's message is logged with where it happened, and a backtrace if
+/// `RUST_BACKTRACE` asks for them. The response can be made from the
+/// message with [`on_panic`](CatchPanicLayer::on_panic):
+///
+/// ```
+/// # use part1_app_factory::{
+/// #     http::StatusCode,
+/// #     middleware::CatchPanicLayer,
+/// #     response::{IntoResponse, Problem},
+/// #     router::{get, Router},
+/// # };
+/// # async fn index() {}
+/// let app = Router::new()
+///     .route("/", get(index))
+///     .layer(CatchPanicLayer::new().on_panic(|_message| {
+///         Problem::new(StatusCode::INTERNAL_SERVER_ERROR).into_response()
+///     }));
+/// ```
+///
+/// Panics while a streamed body is sent aren't caught, as the response
+/// head is already out then.
+#[derive(Clone)]
+pub struct CatchPanicLayer {
+    on_panic: Arc<PanicFn>,
+}
+
+impl CatchPanicLayer {
+    pub fn new() -> Self {
+        install_hook();
+        CatchPanicLayer {
+            on_panic: Arc::new(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response()),
+        }
+    }
+
+    /// Makes the response to a panic with `on_panic`, given the panic's
+    /// message.
+    pub fn on_panic<F>(mut self, on_panic: F) -> Self
+    where
+        F: Fn(&str) -> Response + Send + Sync + 'static,
+    {
+        self.on_panic = Arc::new(on_panic);
+        self
+    }
+
+    fn respond(&self, panic: Panic) -> Response {
+        let location = panic.location.as_deref().unwrap_or("unknown");
+        match &panic.backtrace {
+            Some(backtrace) => error!(
+                panic = %panic.message,
+                location = location,
+                backtrace = %backtrace,
+                "Handler panicked"
+            ),
+            None => error!(panic = %panic.message, location = location, "Handler panicked"),
+        }
+        (self.on_panic)(&panic.message)
+    }
+}
+
+impl Default for CatchPanicLayer {
+    fn default() -> Self {
+        CatchPanicLayer::new()
+    }
+}
+
+impl fmt::Debug for CatchPanicLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CatchPanicLayer").finish_non_exhaustive()
+    }
+}
+
+impl<S> Layer<S> for CatchPanicLayer {
+    type Service = CatchPanic<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CatchPanic {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// A service turning panics into responses, made by [`CatchPanicLayer`].
+#[derive(Clone, Debug)]
+pub struct CatchPanic<S> {
+    inner: S,
+    layer: CatchPanicLayer,
+}
+
+impl<S> Service<Request> for CatchPanic<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let layer = self.layer.clone();
+        // Handlers may panic before their future is first polled, too.
+        let future = match catching(|| self.inner.call(req)) {
+            Ok(future) => future,
+            Err(panic) => return Box::pin(async move { Ok(layer.respond(panic)) }),
+        };
+        let mut future = Box::pin(future);
+        Box::pin(async move {
+            let caught = std::future::poll_fn(|cx| match catching(|| future.as_mut().poll(cx)) {
+                Ok(poll) => poll.map(Ok),
+                Err(panic) => Poll::Ready(Err(panic)),
+            })
+            .await;
+            match caught {
+                Ok(result) => result,
+                Err(panic) => Ok(layer.respond(panic)),
+            }
+        })
+    }
+}
+
+/// What's known about a caught panic.
+struct Panic {
+    message: String,
+    location: Option<String>,
+    backtrace: Option<Backtrace>,
+}
+
+thread_local! {
+    /// How many [`catching`] calls the thread is in.
+    static CATCHING: Cell<usize> = const { Cell::new(0) };
+    /// Where the last panic caught on the thread happened, and how it got
+    /// there.
+    static CAUGHT: RefCell<Option<(String, Option<Backtrace>)>> = const { RefCell::new(None) };
+}
+
+/// Runs `f`, catching a panic in it.
+fn catching<R>(f: impl FnOnce() -> R) -> Result<R, Panic> {
+    CATCHING.with(|catching| catching.set(catching.get() + 1));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.with(|catching| catching.set(catching.get() - 1));
+    result.map_err(|payload| {
+        let (location, backtrace) = CAUGHT.with(|caught| caught.take()).unzip();
+        Panic {
+            message: message(&*payload),
+            location,
+            backtrace: backtrace.flatten(),
+        }
+    })
+}
+
+fn message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_owned()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Box<dyn Any>".to_owned()
+    }
+}
+
+/// Makes panics in [`catching`] leave their location and backtrace for it
+/// to log, instead of printing them as other panics are.
+fn install_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if CATCHING.with(Cell::get) == 0 {
+                return previous(info);
+            }
+            let location = info
+                .location()
+                .map_or_else(|| "unknown".to_owned(), ToString::to_string);
+            let backtrace = Backtrace::capture();
+            let backtrace = (backtrace.status() == BacktraceStatus::Captured).then_some(backtrace);
+            CAUGHT.with(|caught| *caught.borrow_mut() = Some((location, backtrace)));
+        }));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::{ready, Ready};
+
+    use anyhow::Error;
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+    use crate::http::Method;
+
+    fn request(method: Method, uri: &str, headers: &[(&str, &str)], body: &'static str) -> Request {
+        let mut req = Request::builder().method(method).uri(uri);
+        for &(name, value) in headers {
+            req = req.header(name, value);
+        }
+        req.body(body).unwrap()
+    }
+
+    /// The status and body of `resp`.
+    async fn respond(resp: Response) -> (u16, String) {
+        let body = resp.body.collect().await.unwrap();
+        (
+            resp.status.as_u16(),
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    async fn panic_in_the_future(_: Request) -> Result<Response, Error> {
+        panic!("user {} not found", 7)
+    }
+
+    fn panic_before_the_future(_: Request) -> Ready<Result<Response, Error>> {
+        panic!("before the future")
+    }
+
+    async fn send<S>(layer: CatchPanicLayer, app: S) -> (u16, String)
+    where
+        S: Service<Request, Response = Response, Error = Error>,
+        S::Future: Send + 'static,
+    {
+        let req = request(Method::Get, "/", &[], "");
+        respond(layer.layer(app).oneshot(req).await.unwrap()).await
+    }
+
+    #[tokio::test]
+    async fn passes_through_what_does_not_panic() {
+        let app = service_fn(|_: Request| ready(Ok("fine".into_response())));
+        assert_eq!(
+            send(CatchPanicLayer::new(), app).await,
+            (200, "fine".to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn answers_panics_with_500() {
+        assert_eq!(
+            send(CatchPanicLayer::new(), service_fn(panic_in_the_future)).await,
+            (500, String::new())
+        );
+
+        let app = service_fn(panic_before_the_future);
+        assert_eq!(send(CatchPanicLayer::new(), app).await.0, 500);
+    }
+
+    #[tokio::test]
+    async fn answers_with_the_response_given_the_message() {
+        let layer = CatchPanicLayer::new().on_panic(|message| {
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("panicked: {}", message),
+            )
+                .into_response()
+        });
+        assert_eq!(
+            send(layer.clone(), service_fn(panic_in_the_future)).await,
+            (503, "panicked: user 7 not found".to_owned())
+        );
+        assert_eq!(
+            send(layer, service_fn(panic_before_the_future)).await,
+            (503, "panicked: before the future".to_owned())
+        );
+    }
+}
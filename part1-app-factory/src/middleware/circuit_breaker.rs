@@ -0,0 +1,315 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use tower::{Layer, Service};
+
+use tracing::{info, warn};
+
+/// Stops calling a service that keeps failing, failing fast with
+/// [`CircuitOpen`] instead, and tries it again after a while.
+///
+/// It suits app factories, whose failures, e.g. to reach a database each
+/// connection needs, would otherwise be retried for every connection:
+///
+/// ```
+/// # use std::time::Duration;
+/// # use tower::Layer;
+/// # use part1_app_factory::{
+/// #     http::ConnInfo,
+/// #     middleware::CircuitBreakerLayer,
+/// #     router::Router,
+/// #     util::app_factory_fn,
+/// # };
+/// # async fn make_app(_conn: ConnInfo) -> anyhow::Result<Router> {
+/// #     Ok(Router::new())
+/// # }
+/// let app_factory = CircuitBreakerLayer::new()
+///     .failure_rate(0.25)
+///     .open_for(Duration::from_secs(30))
+///     .layer(app_factory_fn(make_app));
+/// ```
+///
+/// The circuit opens once at least half of the last 20 calls failed, and at
+/// least 5 were made. After 10 seconds open, a single call is let through as
+/// a probe: the circuit closes if it succeeds and opens again if it fails.
+/// Opening and closing are logged.
+///
+/// Clones of the wrapped service share the circuit.
+#[derive(Clone, Copy, Debug)]
+pub struct CircuitBreakerLayer {
+    failure_rate: f64,
+    window: usize,
+    min_calls: usize,
+    open_for: Duration,
+}
+
+impl CircuitBreakerLayer {
+    pub fn new() -> Self {
+        CircuitBreakerLayer {
+            failure_rate: 0.5,
+            window: 20,
+            min_calls: 5,
+            open_for: Duration::from_secs(10),
+        }
+    }
+
+    /// Opens the circuit once this share of the recent calls failed,
+    /// between 0 and 1.
+    pub fn failure_rate(mut self, failure_rate: f64) -> Self {
+        self.failure_rate = failure_rate;
+        self
+    }
+
+    /// Judges the failure rate on the last `window` calls.
+    pub fn window(mut self, window: usize) -> Self {
+        self.window = window.max(1);
+        self
+    }
+
+    /// Keeps the circuit closed until at least `min_calls` were made, so a
+    /// single early failure doesn't open it.
+    pub fn min_calls(mut self, min_calls: usize) -> Self {
+        self.min_calls = min_calls;
+        self
+    }
+
+    /// Keeps the circuit open for `open_for` before probing the service.
+    pub fn open_for(mut self, open_for: Duration) -> Self {
+        self.open_for = open_for;
+        self
+    }
+}
+
+impl Default for CircuitBreakerLayer {
+    fn default() -> Self {
+        CircuitBreakerLayer::new()
+    }
+}
+
+impl<S> Layer<S> for CircuitBreakerLayer {
+    type Service = CircuitBreaker<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CircuitBreaker {
+            inner,
+            circuit: Arc::new(Mutex::new(Circuit {
+                layer: *self,
+                state: State::Closed,
+                outcomes: VecDeque::new(),
+            })),
+            ticket: None,
+        }
+    }
+}
+
+/// A service failing fast while the service it wraps keeps failing, made
+/// by [`CircuitBreakerLayer`].
+pub struct CircuitBreaker<S> {
+    inner: S,
+    circuit: Arc<Mutex<Circuit>>,
+    /// Whether the next call goes through, decided when it's made ready.
+    ticket: Option<Ticket>,
+}
+
+impl<S: Clone> Clone for CircuitBreaker<S> {
+    fn clone(&self) -> Self {
+        CircuitBreaker {
+            inner: self.inner.clone(),
+            circuit: self.circuit.clone(),
+            ticket: None,
+        }
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for CircuitBreaker<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let circuit = lock(&self.circuit);
+        f.debug_struct("CircuitBreaker")
+            .field("inner", &self.inner)
+            .field("layer", &circuit.layer)
+            .field("state", &circuit.state)
+            .finish()
+    }
+}
+
+impl<S, R> Service<R> for CircuitBreaker<S>
+where
+    S: Service<R>,
+    S::Error: From<CircuitOpen>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let circuit = &self.circuit;
+        let ticket = self.ticket.get_or_insert_with(|| Ticket::new(circuit));
+        // Calls that will be rejected don't wait for the service.
+        if ticket.kind == Kind::Rejected {
+            return Poll::Ready(Ok(()));
+        }
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: R) -> Self::Future {
+        let ticket = self
+            .ticket
+            .take()
+            .expect("`poll_ready` is called before `call`");
+        if ticket.kind == Kind::Rejected {
+            return Box::pin(async { Err(CircuitOpen.into()) });
+        }
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let result = future.await;
+            ticket.record(result.is_err());
+            result
+        })
+    }
+}
+
+/// The error of calls rejected while the circuit of a [`CircuitBreaker`]
+/// is open.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CircuitOpen;
+
+impl fmt::Display for CircuitOpen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the service keeps failing, so it isn't called for now")
+    }
+}
+
+impl Error for CircuitOpen {}
+
+#[derive(Debug)]
+struct Circuit {
+    layer: CircuitBreakerLayer,
+    state: State,
+    /// Whether each of the recent calls failed, while closed.
+    outcomes: VecDeque<bool>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Closed,
+    Open {
+        until: Instant,
+    },
+    /// Open, but letting a probe through, unless one is under way.
+    HalfOpen {
+        probing: bool,
+    },
+}
+
+impl Circuit {
+    fn admit(&mut self, now: Instant) -> Kind {
+        match self.state {
+            State::Closed => Kind::Call,
+            State::Open { until } if now < until => Kind::Rejected,
+            State::Open { .. } | State::HalfOpen { probing: false } => {
+                info!("Probing the service behind the open circuit");
+                self.state = State::HalfOpen { probing: true };
+                Kind::Probe
+            }
+            State::HalfOpen { probing: true } => Kind::Rejected,
+        }
+    }
+
+    fn record(&mut self, kind: Kind, failed: bool, now: Instant) {
+        match (kind, self.state) {
+            (Kind::Probe, _) if failed => {
+                warn!("The probe failed, the circuit stays open");
+                self.open(now);
+            }
+            (Kind::Probe, _) => {
+                info!("The probe succeeded, closing the circuit");
+                self.state = State::Closed;
+            }
+            (Kind::Call, State::Closed) => {
+                self.outcomes.push_back(failed);
+                if self.outcomes.len() > self.layer.window {
+                    self.outcomes.pop_front();
+                }
+                let calls = self.outcomes.len();
+                let failures = self.outcomes.iter().filter(|&&failed| failed).count();
+                if calls >= self.layer.min_calls
+                    && failures as f64 >= self.layer.failure_rate * calls as f64
+                    && failures > 0
+                {
+                    warn!(
+                        failures = failures,
+                        calls = calls,
+                        open_for = ?self.layer.open_for,
+                        "The service keeps failing, opening the circuit"
+                    );
+                    self.open(now);
+                }
+            }
+            // Calls made before the circuit opened don't count.
+            _ => {}
+        }
+    }
+
+    fn open(&mut self, now: Instant) {
+        self.state = State::Open {
+            until: now + self.layer.open_for,
+        };
+        self.outcomes.clear();
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Rejected,
+    Call,
+    Probe,
+}
+
+/// Admission of a call, recording how it went. A probe dropped before that
+/// lets the next call probe instead.
+struct Ticket {
+    circuit: Arc<Mutex<Circuit>>,
+    kind: Kind,
+    recorded: bool,
+}
+
+impl Ticket {
+    fn new(circuit: &Arc<Mutex<Circuit>>) -> Self {
+        let kind = lock(circuit).admit(Instant::now());
+        Ticket {
+            circuit: circuit.clone(),
+            kind,
+            recorded: false,
+        }
+    }
+
+    fn record(mut self, failed: bool) {
+        self.recorded = true;
+        lock(&self.circuit).record(self.kind, failed, Instant::now());
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        if self.kind == Kind::Probe && !self.recorded {
+            let mut circuit = lock(&self.circuit);
+            if circuit.state == (State::HalfOpen { probing: true }) {
+                circuit.state = State::HalfOpen { probing: false };
+            }
+        }
+    }
+}
+
+/// A panic while the circuit was locked leaves it as consistent as it was.
+fn lock(circuit: &Mutex<Circuit>) -> MutexGuard<'_, Circuit> {
+    circuit.lock().unwrap_or_else(|e| e.into_inner())
+}
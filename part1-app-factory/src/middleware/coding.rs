@@ -0,0 +1,180 @@
+//! The content codings HTTP calls `gzip`, `deflate` and `br`, as the
+//! compression layers read and write them.
+
+use std::io::Write;
+
+use brotli::CompressorWriter;
+use bytes::Bytes;
+use flate2::{
+    write::{GzEncoder, ZlibEncoder},
+    Compression,
+};
+
+/// The content codings the layers compress and decompress.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Coding {
+    Gzip,
+    /// zlib, as `Content-Encoding: deflate` means.
+    Deflate,
+    Brotli,
+}
+
+impl Coding {
+    /// The coding named `name` in a `Content-Encoding`.
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Coding::Gzip),
+            "deflate" => Some(Coding::Deflate),
+            "br" => Some(Coding::Brotli),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Coding::Gzip => "gzip",
+            Coding::Deflate => "deflate",
+            Coding::Brotli => "br",
+        }
+    }
+}
+
+/// Brotli's quality, from 0 to 11. Higher ones compress little better for
+/// much more time, which responses compressed as they're sent can't spare.
+const BROTLI_QUALITY: u32 = 5;
+/// The base 2 logarithm of Brotli's window size, 4 MiB.
+const BROTLI_WINDOW: u32 = 22;
+
+/// Compresses a body chunk by chunk.
+///
+/// Every chunk is flushed, so what was passed in so far can be decompressed
+/// from what came out, which streamed responses such as server-sent events
+/// rely on.
+pub(crate) enum Encoder {
+    Gzip(GzEncoder<Vec<u8>>),
+    Deflate(ZlibEncoder<Vec<u8>>),
+    Brotli(Box<CompressorWriter<Vec<u8>>>),
+}
+
+impl Encoder {
+    pub(crate) fn new(coding: Coding) -> Self {
+        match coding {
+            Coding::Gzip => Encoder::Gzip(GzEncoder::new(Vec::new(), Compression::default())),
+            Coding::Deflate => {
+                Encoder::Deflate(ZlibEncoder::new(Vec::new(), Compression::default()))
+            }
+            Coding::Brotli => Encoder::Brotli(Box::new(CompressorWriter::new(
+                Vec::new(),
+                0,
+                BROTLI_QUALITY,
+                BROTLI_WINDOW,
+            ))),
+        }
+    }
+
+    /// Compresses `input`, returning what's ready to send.
+    pub(crate) fn encode(&mut self, input: &[u8]) -> Bytes {
+        if input.is_empty() {
+            return Bytes::new();
+        }
+        let writer: &mut dyn Write = match self {
+            Encoder::Gzip(encoder) => encoder,
+            Encoder::Deflate(encoder) => encoder,
+            Encoder::Brotli(encoder) => &mut **encoder,
+        };
+        writer
+            .write_all(input)
+            .and_then(|()| writer.flush())
+            .expect("writing to a Vec doesn't fail");
+        let out = match self {
+            Encoder::Gzip(encoder) => encoder.get_mut(),
+            Encoder::Deflate(encoder) => encoder.get_mut(),
+            Encoder::Brotli(encoder) => encoder.get_mut(),
+        };
+        std::mem::take(out).into()
+    }
+
+    /// Ends the stream, returning the rest of it.
+    pub(crate) fn finish(self) -> Bytes {
+        let out = match self {
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::Deflate(encoder) => encoder.finish(),
+            Encoder::Brotli(encoder) => Ok(encoder.into_inner()),
+        };
+        out.expect("writing to a Vec doesn't fail").into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    fn decompress(coding: Coding, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        match coding {
+            Coding::Gzip => flate2::read::GzDecoder::new(data).read_to_end(&mut out),
+            Coding::Deflate => flate2::read::ZlibDecoder::new(data).read_to_end(&mut out),
+            Coding::Brotli => brotli::Decompressor::new(data, 4096).read_to_end(&mut out),
+        }
+        .unwrap();
+        out
+    }
+
+    const CODINGS: [Coding; 3] = [Coding::Gzip, Coding::Deflate, Coding::Brotli];
+
+    #[test]
+    fn round_trips() {
+        let data = "the quick brown fox jumps over the lazy dog\n".repeat(500);
+        for coding in CODINGS {
+            let mut encoder = Encoder::new(coding);
+            let mut out = Vec::new();
+            for chunk in data.as_bytes().chunks(1000) {
+                out.extend_from_slice(&encoder.encode(chunk));
+            }
+            out.extend_from_slice(&encoder.finish());
+            assert!(out.len() < data.len() / 10, "{:?}", coding);
+            assert_eq!(decompress(coding, &out), data.as_bytes(), "{:?}", coding);
+        }
+    }
+
+    #[test]
+    fn flushes_each_chunk() {
+        for coding in CODINGS {
+            let mut encoder = Encoder::new(coding);
+            let out = encoder.encode(b"data: hello\n\n");
+            let decoded = match coding {
+                Coding::Gzip => {
+                    let mut decoder = flate2::write::GzDecoder::new(Vec::new());
+                    decoder.write_all(&out).unwrap();
+                    decoder.flush().unwrap();
+                    decoder.get_ref().clone()
+                }
+                Coding::Deflate => {
+                    let mut decoder = flate2::write::ZlibDecoder::new(Vec::new());
+                    decoder.write_all(&out).unwrap();
+                    decoder.flush().unwrap();
+                    decoder.get_ref().clone()
+                }
+                Coding::Brotli => {
+                    let mut decoder = brotli::DecompressorWriter::new(Vec::new(), 4096);
+                    decoder.write_all(&out).unwrap();
+                    decoder.flush().unwrap();
+                    decoder.get_ref().clone()
+                }
+            };
+            assert_eq!(decoded, b"data: hello\n\n", "{:?}", coding);
+        }
+    }
+
+    #[test]
+    fn names_codings() {
+        assert_eq!(Coding::from_name("X-Gzip"), Some(Coding::Gzip));
+        assert_eq!(Coding::from_name("br"), Some(Coding::Brotli));
+        assert_eq!(Coding::from_name("compress"), None);
+        for coding in CODINGS {
+            assert_eq!(Coding::from_name(coding.as_str()), Some(coding));
+        }
+    }
+}
@@ -0,0 +1,419 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use anyhow::Error;
+use bytes::{Bytes, BytesMut};
+use futures_util::{Stream, StreamExt};
+use tower::{Layer, Service};
+
+use super::coding::{Coding, Encoder};
+use crate::{
+    headers::{AcceptEncoding, ContentType, Header},
+    http::{
+        header::{
+            ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE,
+            CONTENT_TYPE, ETAG, VARY,
+        },
+        Body, HeaderMap, HeaderValue, Method, Request, Response,
+    },
+};
+
+type MimeFn = dyn Fn(&str) -> bool + Send + Sync;
+
+/// Compresses response bodies with Brotli, gzip or deflate, whichever the
+/// client's `Accept-Encoding` prefers, Brotli winning ties.
+///
+/// Text, JSON, XML, JavaScript and SVG responses of at least 1 KiB are
+/// compressed by default, along with those streamed without a length:
+///
+/// ```
+/// # use part1_app_factory::{middleware::CompressionLayer, router::{get, Router}};
+/// # async fn report() {}
+/// let app = Router::new()
+///     .route("/report", get(report))
+///     .layer(CompressionLayer::new().min_size(4096).deflate(false));
+/// ```
+///
+/// Streamed bodies are compressed chunk by chunk, each chunk flushed as it
+/// comes so clients don't wait on the rest. Responses that are already
+/// encoded, partial or marked `Cache-Control: no-transform` are left alone,
+/// and a strong `ETag` is made weak, as the bytes it named are changed.
+#[derive(Clone)]
+pub struct CompressionLayer {
+    br: bool,
+    gzip: bool,
+    deflate: bool,
+    min_size: u64,
+    compressible: Arc<MimeFn>,
+}
+
+impl CompressionLayer {
+    pub fn new() -> Self {
+        CompressionLayer {
+            br: true,
+            gzip: true,
+            deflate: true,
+            min_size: 1024,
+            compressible: Arc::new(is_compressible),
+        }
+    }
+
+    pub fn br(mut self, enable: bool) -> Self {
+        self.br = enable;
+        self
+    }
+
+    pub fn gzip(mut self, enable: bool) -> Self {
+        self.gzip = enable;
+        self
+    }
+
+    pub fn deflate(mut self, enable: bool) -> Self {
+        self.deflate = enable;
+        self
+    }
+
+    /// Leaves bodies shorter than `min_size` bytes alone, as compressing
+    /// them saves too little to be worth it.
+    pub fn min_size(mut self, min_size: u64) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Compresses the responses whose media type, e.g. `text/html`,
+    /// `compressible` returns `true` for, instead of text, JSON, XML,
+    /// JavaScript and SVG.
+    pub fn compress_if<F>(mut self, compressible: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.compressible = Arc::new(compressible);
+        self
+    }
+
+    /// The coding the client prefers, if it accepts one.
+    fn negotiate(&self, headers: &HeaderMap) -> Option<Coding> {
+        let values: Vec<&str> = headers
+            .get_all(ACCEPT_ENCODING)
+            .map(|value| value.as_str())
+            .collect();
+        let accept = AcceptEncoding::decode(&values.join(", ")).unwrap_or_default();
+        let mut offered = Vec::new();
+        if self.br {
+            offered.push(Coding::Brotli);
+        }
+        if self.gzip {
+            offered.push(Coding::Gzip);
+        }
+        if self.deflate {
+            offered.push(Coding::Deflate);
+        }
+        let names: Vec<&str> = offered.iter().map(|coding| coding.as_str()).collect();
+        let preferred = accept.preferred(&names)?;
+        offered
+            .into_iter()
+            .find(|coding| coding.as_str() == preferred)
+    }
+
+    /// Whether `resp` may be compressed.
+    fn applies_to(&self, resp: &Response) -> bool {
+        let status = resp.status.as_u16();
+        let headers = &resp.headers;
+        if status < 200 || matches!(status, 204 | 206 | 304) {
+            return false;
+        }
+        if headers.contains_key(CONTENT_ENCODING) || headers.contains_key(CONTENT_RANGE) {
+            return false;
+        }
+        let no_transform = headers.get_all(CACHE_CONTROL).any(|value| {
+            value
+                .as_str()
+                .split(',')
+                .any(|directive| directive.trim().eq_ignore_ascii_case("no-transform"))
+        });
+        if no_transform {
+            return false;
+        }
+        let compressible = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| ContentType::decode(value.as_str()))
+            .is_some_and(|content_type| (self.compressible)(content_type.mime()));
+        let too_small = resp
+            .body
+            .size_hint()
+            .exact()
+            .is_some_and(|len| len < self.min_size);
+        compressible && !too_small
+    }
+
+    fn compress(&self, mut resp: Response, coding: Option<Coding>) -> Response {
+        if !self.applies_to(&resp) {
+            return resp;
+        }
+        resp.headers
+            .append(VARY, HeaderValue::from_static("accept-encoding"));
+        let Some(coding) = coding else {
+            return resp;
+        };
+
+        let headers = &mut resp.headers;
+        headers.remove(CONTENT_LENGTH);
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static(coding.as_str()));
+        if let Some(etag) = headers.get(ETAG) {
+            if !etag.as_str().starts_with("W/") {
+                if let Ok(weak) = format!("W/{}", etag).parse() {
+                    headers.insert(ETAG, weak);
+                }
+            }
+        }
+
+        let mut body = std::mem::take(&mut resp.body);
+        let trailers = body.take_trailers();
+        let mut encoder = Encoder::new(coding);
+        let mut body = match body.try_into_bytes() {
+            Ok(bytes) => {
+                let mut compressed = BytesMut::from(&encoder.encode(&bytes)[..]);
+                compressed.extend_from_slice(&encoder.finish());
+                Body::from(compressed.freeze())
+            }
+            Err(body) => Body::from_stream(Encode {
+                body,
+                encoder: Some(encoder),
+            }),
+        };
+        if let Some(trailers) = trailers {
+            body = body.with_trailers(trailers);
+        }
+        resp.body = body;
+        resp
+    }
+}
+
+/// Text, and the other types of text such as JSON that aren't named so.
+fn is_compressible(mime: &str) -> bool {
+    let mime = mime.to_ascii_lowercase();
+    mime.starts_with("text/")
+        || mime.ends_with("+json")
+        || mime.ends_with("+xml")
+        || matches!(
+            mime.as_str(),
+            "application/json"
+                | "application/javascript"
+                | "application/xml"
+                | "application/wasm"
+                | "image/svg+xml"
+        )
+}
+
+impl Default for CompressionLayer {
+    fn default() -> Self {
+        CompressionLayer::new()
+    }
+}
+
+impl fmt::Debug for CompressionLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompressionLayer")
+            .field("br", &self.br)
+            .field("gzip", &self.gzip)
+            .field("deflate", &self.deflate)
+            .field("min_size", &self.min_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> Layer<S> for CompressionLayer {
+    type Service = Compression<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Compression {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// A service compressing the responses of the app it wraps, made by
+/// [`CompressionLayer`].
+#[derive(Clone, Debug)]
+pub struct Compression<S> {
+    inner: S,
+    layer: CompressionLayer,
+}
+
+impl<S> Service<Request> for Compression<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        // A `HEAD` response has to describe the uncompressed `GET` one, as
+        // its length can't be known without compressing the body.
+        let coding = match req.method {
+            Method::Head => None,
+            _ => self.layer.negotiate(&req.headers),
+        };
+        let future = self.inner.call(req);
+        let layer = self.layer.clone();
+        Box::pin(async move {
+            let resp = future.await?;
+            Ok(layer.compress(resp, coding))
+        })
+    }
+}
+
+/// A streamed body being compressed.
+struct Encode {
+    body: Body,
+    /// `None` once the body ended.
+    encoder: Option<Encoder>,
+}
+
+impl Stream for Encode {
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let Some(encoder) = &mut this.encoder else {
+            return Poll::Ready(None);
+        };
+        match this.body.poll_next_unpin(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Some(Ok(chunk))) => Poll::Ready(Some(Ok(encoder.encode(&chunk)))),
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e))),
+            Poll::Ready(None) => {
+                let encoder = this.encoder.take().expect("checked above");
+                Poll::Ready(Some(Ok(encoder.finish())))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, io::Read};
+
+    use futures_util::stream;
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+    use crate::response::IntoResponse;
+
+    const TEXT: &str = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ";
+
+    async fn send(layer: CompressionLayer, req: Request, resp: fn() -> Response) -> Response {
+        let app = service_fn(move |_req: Request| async move { Ok::<_, Infallible>(resp()) });
+        layer.layer(app).oneshot(req).await.unwrap()
+    }
+
+    fn request(method: Method, accept_encoding: &str) -> Request {
+        Request::builder()
+            .method(method)
+            .uri("/")
+            .header("accept-encoding", accept_encoding)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    fn text() -> Response {
+        TEXT.repeat(100).into_response()
+    }
+
+    fn content_encoding(resp: &Response) -> Option<&str> {
+        resp.headers.get(CONTENT_ENCODING).map(HeaderValue::as_str)
+    }
+
+    #[tokio::test]
+    async fn prefers_brotli() {
+        let resp = send(
+            CompressionLayer::new(),
+            request(Method::Get, "gzip, br"),
+            text,
+        )
+        .await;
+        assert_eq!(content_encoding(&resp), Some("br"));
+        assert_eq!(resp.headers.get(VARY).unwrap().as_str(), "accept-encoding");
+        let body = resp.body.collect().await.unwrap();
+        let mut out = String::new();
+        brotli::Decompressor::new(&body[..], 4096)
+            .read_to_string(&mut out)
+            .unwrap();
+        assert_eq!(out, TEXT.repeat(100));
+    }
+
+    #[tokio::test]
+    async fn follows_qualities_and_disabled_codings() {
+        let req = request(Method::Get, "br;q=0.5, gzip");
+        let resp = send(CompressionLayer::new(), req, text).await;
+        assert_eq!(content_encoding(&resp), Some("gzip"));
+
+        let req = request(Method::Get, "br, deflate");
+        let resp = send(CompressionLayer::new().br(false), req, text).await;
+        assert_eq!(content_encoding(&resp), Some("deflate"));
+        let body = resp.body.collect().await.unwrap();
+        let mut out = String::new();
+        flate2::read::ZlibDecoder::new(&body[..])
+            .read_to_string(&mut out)
+            .unwrap();
+        assert_eq!(out, TEXT.repeat(100));
+    }
+
+    #[tokio::test]
+    async fn compresses_streamed_bodies() {
+        fn streamed() -> Response {
+            let chunks = (0..10).map(|_| Ok::<_, Infallible>(Bytes::from_static(TEXT.as_bytes())));
+            let mut resp = Body::from_stream(stream::iter(chunks)).into_response();
+            resp.headers
+                .insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+            resp
+        }
+        let resp = send(
+            CompressionLayer::new(),
+            request(Method::Get, "gzip"),
+            streamed,
+        )
+        .await;
+        assert_eq!(content_encoding(&resp), Some("gzip"));
+        let body = resp.body.collect().await.unwrap();
+        let mut out = String::new();
+        flate2::read::GzDecoder::new(&body[..])
+            .read_to_string(&mut out)
+            .unwrap();
+        assert_eq!(out, TEXT.repeat(10));
+    }
+
+    #[tokio::test]
+    async fn leaves_some_responses_alone() {
+        let resp = send(CompressionLayer::new(), request(Method::Get, "br"), || {
+            "short".into_response()
+        })
+        .await;
+        assert_eq!(content_encoding(&resp), None);
+
+        let resp = send(CompressionLayer::new(), request(Method::Get, "br"), || {
+            let mut resp = text();
+            resp.headers
+                .insert(CACHE_CONTROL, HeaderValue::from_static("no-transform"));
+            resp
+        })
+        .await;
+        assert_eq!(content_encoding(&resp), None);
+
+        let resp = send(CompressionLayer::new(), request(Method::Head, "br"), text).await;
+        assert_eq!(content_encoding(&resp), None);
+        assert_eq!(resp.headers.get(VARY).unwrap().as_str(), "accept-encoding");
+    }
+}
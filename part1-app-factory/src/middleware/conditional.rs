@@ -0,0 +1,319 @@
+use std::{
+    fmt::Write,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use sha2::{Digest, Sha256};
+use tower::{Layer, Service};
+
+use crate::http::{
+    header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    parse_http_date, Body, HeaderMap, HeaderValue, Method, Request, Response, StatusCode,
+};
+
+/// Answers `GET` and `HEAD` requests for a representation the client already
+/// has with a `304 Not Modified` and no body, going by the `ETag` and
+/// `Last-Modified` of the response:
+///
+/// - an `If-None-Match` naming the response's `ETag`, or `*`, matches,
+/// - and without one, an `If-Modified-Since` no earlier than its
+///   `Last-Modified` does.
+///
+/// Handlers can set both headers, e.g. from a version or update time they
+/// know without rendering the page, and a [`File`](crate::response::File)
+/// sets `Last-Modified` from the file's metadata. Successful responses
+/// without an `ETag` get one hashed from their body, if it's in memory
+/// rather than streamed, unless [`compute_etags`](ConditionalLayer::compute_etags)
+/// turns that off:
+///
+/// ```
+/// # use part1_app_factory::{middleware::ConditionalLayer, router::{get, Router}};
+/// # async fn article() {}
+/// let app = Router::new()
+///     .route("/articles/:id", get(article))
+///     .layer(ConditionalLayer::new());
+/// ```
+///
+/// The handler still runs, so this saves bandwidth rather than work. Put it
+/// inside a [`CompressionLayer`](super::CompressionLayer), which marks the
+/// `ETag`s of what it compresses as weak, as they're compared weakly anyway.
+#[derive(Clone, Copy, Debug)]
+pub struct ConditionalLayer {
+    compute_etags: bool,
+}
+
+impl ConditionalLayer {
+    pub fn new() -> Self {
+        ConditionalLayer {
+            compute_etags: true,
+        }
+    }
+
+    /// Whether responses without an `ETag` get one hashed from their body.
+    pub fn compute_etags(mut self, enable: bool) -> Self {
+        self.compute_etags = enable;
+        self
+    }
+}
+
+impl Default for ConditionalLayer {
+    fn default() -> Self {
+        ConditionalLayer::new()
+    }
+}
+
+impl<S> Layer<S> for ConditionalLayer {
+    type Service = Conditional<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Conditional {
+            inner,
+            layer: *self,
+        }
+    }
+}
+
+/// A service answering conditional requests, made by [`ConditionalLayer`].
+#[derive(Clone, Debug)]
+pub struct Conditional<S> {
+    inner: S,
+    layer: ConditionalLayer,
+}
+
+impl<S> Service<Request> for Conditional<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let conditions = matches!(req.method, Method::Get | Method::Head).then(|| Conditions {
+            if_none_match: req.headers.get(IF_NONE_MATCH).cloned(),
+            if_modified_since: req.headers.get(IF_MODIFIED_SINCE).cloned(),
+        });
+        let layer = self.layer;
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let mut resp = future.await?;
+            let Some(conditions) = conditions else {
+                return Ok(resp);
+            };
+            if resp.status != StatusCode::OK {
+                return Ok(resp);
+            }
+            if layer.compute_etags && !resp.headers.contains_key(ETAG) {
+                compute_etag(&mut resp);
+            }
+            if conditions.not_modified(&resp.headers) {
+                resp = not_modified(resp);
+            }
+            Ok(resp)
+        })
+    }
+}
+
+/// The preconditions of a `GET` or `HEAD` request.
+struct Conditions {
+    if_none_match: Option<HeaderValue>,
+    if_modified_since: Option<HeaderValue>,
+}
+
+impl Conditions {
+    /// Whether the client has the representation with `headers` already.
+    fn not_modified(&self, headers: &HeaderMap) -> bool {
+        if let Some(if_none_match) = &self.if_none_match {
+            let Some(etag) = headers.get(ETAG) else {
+                return if_none_match.as_str().trim() == "*";
+            };
+            return if_none_match
+                .as_str()
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || weak_eq(tag, etag.as_str()));
+        }
+        let since = self
+            .if_modified_since
+            .as_ref()
+            .and_then(|value| parse_http_date(value.as_str()));
+        let modified = headers
+            .get(LAST_MODIFIED)
+            .and_then(|value| parse_http_date(value.as_str()));
+        match (since, modified) {
+            (Some(since), Some(modified)) => modified <= since,
+            _ => false,
+        }
+    }
+}
+
+/// Whether two entity tags match, ignoring whether they're weak.
+fn weak_eq(a: &str, b: &str) -> bool {
+    a.strip_prefix("W/").unwrap_or(a) == b.strip_prefix("W/").unwrap_or(b)
+}
+
+/// Sets a strong `ETag` hashed from the body of `resp`, unless it's
+/// streamed.
+fn compute_etag(resp: &mut Response) {
+    let mut body = std::mem::take(&mut resp.body);
+    let trailers = body.take_trailers();
+    let mut body = match body.try_into_bytes() {
+        Ok(bytes) => {
+            let hash = Sha256::digest(&bytes);
+            let mut etag = String::with_capacity(34);
+            etag.push('"');
+            for byte in &hash[..16] {
+                let _ = write!(etag, "{:02x}", byte);
+            }
+            etag.push('"');
+            resp.headers
+                .insert(ETAG, etag.parse().expect("hex is a valid header value"));
+            Body::from(bytes)
+        }
+        Err(body) => body,
+    };
+    if let Some(trailers) = trailers {
+        body = body.with_trailers(trailers);
+    }
+    resp.body = body;
+}
+
+/// `resp` as a 304, without a body or the headers describing it, but with
+/// the ones caches update their copy from.
+fn not_modified(resp: Response) -> Response {
+    let mut headers = HeaderMap::new();
+    for (name, value) in resp.headers.iter() {
+        let describes_body = match name.as_str() {
+            "content-location" => false,
+            name => name.starts_with("content-") || name == "transfer-encoding",
+        };
+        if !describes_body {
+            headers.append(name.clone(), value.clone());
+        }
+    }
+    Response {
+        status: StatusCode::NOT_MODIFIED,
+        version: resp.version,
+        headers,
+        body: Body::empty(),
+        extensions: resp.extensions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+    use crate::response::IntoResponse;
+
+    fn request(method: Method, uri: &str, headers: &[(&str, &str)], body: &'static str) -> Request {
+        let mut req = Request::builder().method(method).uri(uri);
+        for &(name, value) in headers {
+            req = req.header(name, value);
+        }
+        req.body(body).unwrap()
+    }
+
+    /// The status and body of `resp`.
+    async fn respond(resp: Response) -> (u16, String) {
+        let body = resp.body.collect().await.unwrap();
+        (
+            resp.status.as_u16(),
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    fn header<'a>(resp: &'a Response, name: &str) -> Option<&'a str> {
+        resp.headers.get(name).map(|value| value.as_str())
+    }
+
+    const LAST_MODIFIED_AT: &str = "Sun, 06 Nov 1994 08:49:37 GMT";
+
+    /// Sends a request through `layer` to an app answering with a page last
+    /// modified at [`LAST_MODIFIED_AT`].
+    async fn send(layer: ConditionalLayer, method: Method, headers: &[(&str, &str)]) -> Response {
+        let app = service_fn(|_: Request| async {
+            let mut resp = "<h1>Hello</h1>".into_response();
+            resp.headers
+                .insert(LAST_MODIFIED, HeaderValue::from_static(LAST_MODIFIED_AT));
+            Ok::<_, Infallible>(resp)
+        });
+        let req = request(method, "/", headers, "");
+        layer.layer(app).oneshot(req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn answers_matching_etags_with_304() {
+        let layer = ConditionalLayer::new();
+        let resp = send(layer, Method::Get, &[]).await;
+        let etag = header(&resp, "ETag").unwrap().to_owned();
+        assert_eq!(etag.len(), 34, "{}", etag);
+        assert_eq!(respond(resp).await, (200, "<h1>Hello</h1>".to_owned()));
+
+        let weak = format!("W/{}", etag);
+        for if_none_match in [etag.as_str(), &weak, "\"other\", *", "*"] {
+            let resp = send(layer, Method::Get, &[("If-None-Match", if_none_match)]).await;
+            assert_eq!(header(&resp, "ETag"), Some(etag.as_str()));
+            assert_eq!(header(&resp, "Content-Type"), None);
+            assert_eq!(header(&resp, "Content-Length"), None);
+            assert_eq!(
+                respond(resp).await,
+                (304, String::new()),
+                "{}",
+                if_none_match
+            );
+        }
+
+        let resp = send(layer, Method::Get, &[("If-None-Match", "\"other\"")]).await;
+        assert_eq!(respond(resp).await.0, 200);
+    }
+
+    #[tokio::test]
+    async fn answers_unmodified_pages_with_304() {
+        let layer = ConditionalLayer::new().compute_etags(false);
+        let resp = send(
+            layer,
+            Method::Head,
+            &[("If-Modified-Since", LAST_MODIFIED_AT)],
+        )
+        .await;
+        assert_eq!(header(&resp, "ETag"), None);
+        assert_eq!(header(&resp, "Last-Modified"), Some(LAST_MODIFIED_AT));
+        assert_eq!(respond(resp).await.0, 304);
+
+        for if_modified_since in ["Sun, 06 Nov 1994 08:49:36 GMT", "yesterday"] {
+            let headers = [("If-Modified-Since", if_modified_since)];
+            let resp = send(layer, Method::Get, &headers).await;
+            assert_eq!(respond(resp).await.0, 200, "{}", if_modified_since);
+        }
+
+        // An ETag takes precedence over the date.
+        let layer = ConditionalLayer::new();
+        let headers = [
+            ("If-None-Match", "\"other\""),
+            ("If-Modified-Since", LAST_MODIFIED_AT),
+        ];
+        assert_eq!(
+            respond(send(layer, Method::Get, &headers).await).await.0,
+            200
+        );
+    }
+
+    #[tokio::test]
+    async fn leaves_unsafe_methods_alone() {
+        let headers = [("If-None-Match", "*")];
+        let resp = send(ConditionalLayer::new(), Method::Post, &headers).await;
+        assert_eq!(header(&resp, "ETag"), None);
+        assert_eq!(respond(resp).await.0, 200);
+    }
+}
@@ -0,0 +1,420 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tower::{Layer, Service};
+
+use crate::{
+    http::{
+        header::{
+            ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
+            ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
+            ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_HEADERS,
+            ACCESS_CONTROL_REQUEST_METHOD, ORIGIN, VARY,
+        },
+        HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode,
+    },
+    response::IntoResponse,
+};
+
+type OriginFn = dyn Fn(&str) -> bool + Send + Sync;
+
+/// Lets browsers call the app from pages of other origins, by answering
+/// their CORS preflight requests and adding the `Access-Control-*` headers
+/// to the responses of the actual ones.
+///
+/// Nothing is allowed until configured:
+///
+/// ```
+/// # use std::time::Duration;
+/// # use part1_app_factory::{
+/// #     http::{header::CONTENT_TYPE, Method},
+/// #     middleware::CorsLayer,
+/// #     router::{get, Router},
+/// # };
+/// # async fn list_items() {}
+/// # async fn create_item() {}
+/// let app = Router::new()
+///     .route("/api/items", get(list_items).post(create_item))
+///     .layer(
+///         CorsLayer::new()
+///             .allow_origin("https://app.example.com")
+///             .allow_methods([Method::Get, Method::Post])
+///             .allow_headers([CONTENT_TYPE])
+///             .allow_credentials()
+///             .max_age(Duration::from_secs(600)),
+///     );
+/// ```
+///
+/// Preflights, `OPTIONS` requests with an `Access-Control-Request-Method`,
+/// are answered with a 204 without reaching the app. Added with
+/// [`Router::layer`](crate::router::Router::layer), the layer sees those to
+/// routes without an `OPTIONS` handler too.
+///
+/// Requests from origins that aren't allowed are passed on all the same,
+/// just without the headers, so it's the browser that refuses to hand the
+/// response to the page.
+#[derive(Clone)]
+pub struct CorsLayer {
+    origins: Origins,
+    /// `None` allows any method.
+    methods: Option<Vec<Method>>,
+    /// `None` allows any header.
+    headers: Option<Vec<HeaderName>>,
+    expose_headers: Vec<HeaderName>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+#[derive(Clone)]
+enum Origins {
+    List(Vec<String>),
+    Any,
+    Fn(Arc<OriginFn>),
+}
+
+impl CorsLayer {
+    /// Allows no origin, the methods `GET`, `HEAD` and `POST`, and no headers
+    /// but those browsers always send.
+    pub fn new() -> Self {
+        CorsLayer {
+            origins: Origins::List(Vec::new()),
+            methods: Some(vec![Method::Get, Method::Head, Method::Post]),
+            headers: Some(Vec::new()),
+            expose_headers: Vec::new(),
+            credentials: false,
+            max_age: None,
+        }
+    }
+
+    /// Allows any origin, method and header, without credentials. Suits
+    /// development, and public APIs.
+    pub fn permissive() -> Self {
+        CorsLayer::new()
+            .allow_any_origin()
+            .allow_any_method()
+            .allow_any_header()
+    }
+
+    /// Allows pages of `origin`, e.g. `https://app.example.com`, on top of
+    /// the origins allowed so far.
+    pub fn allow_origin(mut self, origin: impl Into<String>) -> Self {
+        match &mut self.origins {
+            Origins::List(origins) => origins.push(origin.into()),
+            _ => self.origins = Origins::List(vec![origin.into()]),
+        }
+        self
+    }
+
+    pub fn allow_any_origin(mut self) -> Self {
+        self.origins = Origins::Any;
+        self
+    }
+
+    /// Allows the origins `allow` returns `true` for, e.g. the subdomains of
+    /// a domain.
+    pub fn allow_origin_fn<F>(mut self, allow: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.origins = Origins::Fn(Arc::new(allow));
+        self
+    }
+
+    /// Allows `methods` instead of `GET`, `HEAD` and `POST`.
+    pub fn allow_methods(mut self, methods: impl IntoIterator<Item = Method>) -> Self {
+        self.methods = Some(methods.into_iter().collect());
+        self
+    }
+
+    pub fn allow_any_method(mut self) -> Self {
+        self.methods = None;
+        self
+    }
+
+    /// Allows pages to send `headers`, beyond those browsers always allow.
+    pub fn allow_headers(mut self, headers: impl IntoIterator<Item = HeaderName>) -> Self {
+        self.headers = Some(headers.into_iter().collect());
+        self
+    }
+
+    pub fn allow_any_header(mut self) -> Self {
+        self.headers = None;
+        self
+    }
+
+    /// Lets pages read `headers` of responses, beyond those browsers always
+    /// expose.
+    pub fn expose_headers(mut self, headers: impl IntoIterator<Item = HeaderName>) -> Self {
+        self.expose_headers = headers.into_iter().collect();
+        self
+    }
+
+    /// Lets pages send cookies and read the responses to requests that
+    /// carry them.
+    ///
+    /// Browsers don't take wildcards for such requests, so the origin,
+    /// method and headers of the request are echoed back instead where any
+    /// are allowed.
+    pub fn allow_credentials(mut self) -> Self {
+        self.credentials = true;
+        self
+    }
+
+    /// Lets browsers cache the answer to a preflight for `max_age`.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// The `Access-Control-Allow-Origin` for requests from `origin`, if it's
+    /// allowed.
+    fn allowed_origin(&self, origin: &HeaderValue) -> Option<HeaderValue> {
+        let allowed = match &self.origins {
+            Origins::Any if !self.credentials => return Some(HeaderValue::from_static("*")),
+            Origins::Any => true,
+            Origins::List(origins) => origins.iter().any(|allowed| origin.as_str() == allowed),
+            Origins::Fn(allow) => allow(origin.as_str()),
+        };
+        allowed.then(|| origin.clone())
+    }
+
+    /// Whether responses depend on the origin of the request, which caches
+    /// have to be told.
+    fn varies(&self) -> bool {
+        !matches!(self.origins, Origins::Any) || self.credentials
+    }
+
+    /// Adds the headers of an allowed preflight to `headers`.
+    fn preflight(&self, req: &Request, headers: &mut HeaderMap) {
+        let methods = match &self.methods {
+            Some(methods) => join(methods.iter().map(Method::as_str)),
+            None => req.headers.get(ACCESS_CONTROL_REQUEST_METHOD).cloned(),
+        };
+        if let Some(methods) = methods {
+            headers.insert(ACCESS_CONTROL_ALLOW_METHODS, methods);
+        }
+        let allow_headers = match &self.headers {
+            Some(allowed) => join(allowed.iter().map(HeaderName::as_str)),
+            None => req.headers.get(ACCESS_CONTROL_REQUEST_HEADERS).cloned(),
+        };
+        if let Some(allow_headers) = allow_headers {
+            headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, allow_headers);
+        }
+        if let Some(max_age) = self.max_age {
+            headers.insert(ACCESS_CONTROL_MAX_AGE, max_age.as_secs().into());
+        }
+    }
+
+    /// Adds the headers every response to an allowed origin gets.
+    fn allow(&self, origin: HeaderValue, headers: &mut HeaderMap) {
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        if self.credentials {
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+    }
+}
+
+/// `values` as a comma-separated list, or `None` if there are none.
+fn join<'a>(values: impl Iterator<Item = &'a str>) -> Option<HeaderValue> {
+    let list = values.collect::<Vec<_>>().join(", ");
+    if list.is_empty() {
+        return None;
+    }
+    list.parse().ok()
+}
+
+impl Default for CorsLayer {
+    fn default() -> Self {
+        CorsLayer::new()
+    }
+}
+
+impl fmt::Debug for CorsLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let origins: &dyn fmt::Debug = match &self.origins {
+            Origins::List(origins) => origins,
+            Origins::Any => &"*",
+            Origins::Fn(_) => &"<fn>",
+        };
+        f.debug_struct("CorsLayer")
+            .field("origins", origins)
+            .field("methods", &self.methods)
+            .field("headers", &self.headers)
+            .field("expose_headers", &self.expose_headers)
+            .field("credentials", &self.credentials)
+            .field("max_age", &self.max_age)
+            .finish()
+    }
+}
+
+impl<S> Layer<S> for CorsLayer {
+    type Service = Cors<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Cors {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// A service handling CORS for the app it wraps, made by [`CorsLayer`].
+#[derive(Clone, Debug)]
+pub struct Cors<S> {
+    inner: S,
+    layer: CorsLayer,
+}
+
+impl<S> Service<Request> for Cors<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let origin = req.headers.get(ORIGIN);
+        let allowed = origin.and_then(|origin| self.layer.allowed_origin(origin));
+
+        if origin.is_some()
+            && req.method == Method::Options
+            && req.headers.contains_key(ACCESS_CONTROL_REQUEST_METHOD)
+        {
+            let mut resp = StatusCode::NO_CONTENT.into_response();
+            resp.headers.insert(
+                VARY,
+                HeaderValue::from_static(
+                    "origin, access-control-request-method, access-control-request-headers",
+                ),
+            );
+            if let Some(origin) = allowed {
+                self.layer.allow(origin, &mut resp.headers);
+                self.layer.preflight(&req, &mut resp.headers);
+            }
+            return Box::pin(async move { Ok(resp) });
+        }
+
+        let future = self.inner.call(req);
+        let layer = self.layer.clone();
+        Box::pin(async move {
+            let mut resp = future.await?;
+            // Also without an `Origin`, so caches don't hand the response
+            // to requests with one.
+            if layer.varies() {
+                resp.headers
+                    .append(VARY, HeaderValue::from_static("origin"));
+            }
+            if let Some(origin) = allowed {
+                layer.allow(origin, &mut resp.headers);
+                let exposed = layer.expose_headers.iter().map(HeaderName::as_str);
+                if let Some(exposed) = join(exposed) {
+                    resp.headers.insert(ACCESS_CONTROL_EXPOSE_HEADERS, exposed);
+                }
+            }
+            Ok(resp)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+    use crate::http::Body;
+
+    async fn send(layer: CorsLayer, req: Request) -> Response {
+        let app = service_fn(|_req: Request| async { Ok::<_, Infallible>("ok".into_response()) });
+        layer.layer(app).oneshot(req).await.unwrap()
+    }
+
+    fn request(method: Method, origin: Option<&str>) -> Request {
+        let mut builder = Request::builder().method(method).uri("/");
+        if let Some(origin) = origin {
+            builder = builder.header("origin", origin);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    fn vary(resp: &Response) -> Vec<&str> {
+        resp.headers
+            .get_all(VARY)
+            .map(HeaderValue::as_str)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn allows_listed_origin() {
+        let layer = CorsLayer::new().allow_origin("https://example.com");
+        let resp = send(layer, request(Method::Get, Some("https://example.com"))).await;
+        assert_eq!(
+            resp.headers
+                .get(ACCESS_CONTROL_ALLOW_ORIGIN)
+                .unwrap()
+                .as_str(),
+            "https://example.com"
+        );
+        assert_eq!(vary(&resp), ["origin"]);
+    }
+
+    #[tokio::test]
+    async fn leaves_out_other_origins() {
+        let layer = CorsLayer::new().allow_origin("https://example.com");
+        let resp = send(layer, request(Method::Get, Some("https://evil.example"))).await;
+        assert!(!resp.headers.contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+        assert_eq!(vary(&resp), ["origin"]);
+    }
+
+    #[tokio::test]
+    async fn varies_without_origin() {
+        let layer = CorsLayer::new().allow_origin("https://example.com");
+        let resp = send(layer, request(Method::Get, None)).await;
+        assert!(!resp.headers.contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+        assert_eq!(vary(&resp), ["origin"]);
+
+        let resp = send(CorsLayer::permissive(), request(Method::Get, None)).await;
+        assert!(vary(&resp).is_empty());
+    }
+
+    #[tokio::test]
+    async fn answers_preflight() {
+        let layer = CorsLayer::new()
+            .allow_origin("https://example.com")
+            .max_age(Duration::from_secs(600));
+        let mut req = request(Method::Options, Some("https://example.com"));
+        req.headers.insert(
+            ACCESS_CONTROL_REQUEST_METHOD,
+            HeaderValue::from_static("POST"),
+        );
+        let resp = send(layer, req).await;
+        assert_eq!(resp.status, StatusCode::NO_CONTENT);
+        assert_eq!(
+            resp.headers
+                .get(ACCESS_CONTROL_ALLOW_ORIGIN)
+                .unwrap()
+                .as_str(),
+            "https://example.com"
+        );
+        assert_eq!(
+            resp.headers.get(ACCESS_CONTROL_MAX_AGE).unwrap().as_str(),
+            "600"
+        );
+    }
+}
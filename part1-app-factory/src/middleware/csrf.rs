@@ -0,0 +1,366 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use tower::{Layer, Service};
+
+use super::{
+    auth::{constant_time_eq, digest},
+    session::random_id,
+};
+use crate::{
+    extract::{
+        body_limit, buffer_limited, has_content_type, rejection::MissingCsrfToken, BoxFuture,
+        FromRequestParts,
+    },
+    http::{
+        cookie::{parse_cookie_header, SameSite, SetCookie},
+        header::COOKIE,
+        HeaderName, Parts, Request, Response, StatusCode,
+    },
+    response::{append_header, IntoResponse},
+};
+
+/// Protects against cross-site request forgery with double-submit cookies:
+/// every client gets a random token in a cookie, and requests other than
+/// `GET`, `HEAD`, `OPTIONS` and `TRACE` are answered with a 403 unless they
+/// send it back in an `X-CSRF-Token` header or a `csrf_token` form field.
+///
+/// Other sites can make browsers send the cookie, but can't read it, so they
+/// can't send the token back. Pages put it in their forms with the
+/// [`CsrfToken`] extractor:
+///
+/// ```
+/// # use part1_app_factory::{
+/// #     middleware::{CsrfLayer, CsrfToken},
+/// #     response::Html,
+/// #     router::{get, Router},
+/// # };
+/// # async fn save_profile() {}
+/// async fn edit_profile(token: CsrfToken) -> Html<String> {
+///     Html(format!(
+///         r#"<form method="post" action="/profile">
+///             <input type="hidden" name="csrf_token" value="{}">
+///             <input name="bio"> <button>Save</button>
+///         </form>"#,
+///         token
+///     ))
+/// }
+///
+/// let app = Router::new()
+///     .route("/profile", get(edit_profile).post(save_profile))
+///     .layer(CsrfLayer::new());
+/// ```
+///
+/// Scripts read the token from the cookie, which isn't `HttpOnly` for that,
+/// and send it in the header. Only URL-encoded forms are searched for the
+/// field, up to the body limit in effect, see
+/// [`DefaultBodyLimit`](crate::extract::DefaultBodyLimit). A sibling
+/// subdomain able to set cookies for the site can plant a token of its
+/// choosing; over HTTPS, naming the cookie `__Host-csrf_token` stops that.
+#[derive(Clone, Debug)]
+pub struct CsrfLayer {
+    cookie_name: Arc<str>,
+    header: HeaderName,
+    field: Arc<str>,
+    secure: bool,
+}
+
+impl CsrfLayer {
+    pub fn new() -> Self {
+        CsrfLayer {
+            cookie_name: "csrf_token".into(),
+            header: HeaderName::from_static("x-csrf-token"),
+            field: "csrf_token".into(),
+            secure: true,
+        }
+    }
+
+    /// Names the cookie with the token `name`, `csrf_token` by default.
+    pub fn cookie_name(mut self, name: &str) -> Self {
+        self.cookie_name = name.into();
+        self
+    }
+
+    /// Looks for the token in the header `header` instead.
+    pub fn header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Looks for the token in the form field `field` instead.
+    pub fn field(mut self, field: &str) -> Self {
+        self.field = field.into();
+        self
+    }
+
+    /// Whether the cookie is only sent over HTTPS. Turn it off to serve
+    /// forms over plain HTTP, e.g. in development.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    fn cookie(&self, token: &str) -> SetCookie {
+        SetCookie::new(&*self.cookie_name, token)
+            .with_path("/")
+            .with_same_site(SameSite::Lax)
+            .with_secure(self.secure)
+    }
+
+    /// The token the client sent in the request, if it's one of ours.
+    fn cookie_token(&self, req: &Request) -> Option<String> {
+        req.headers
+            .get_all(COOKIE)
+            .flat_map(|value| parse_cookie_header(value.as_str()))
+            .find(|cookie| cookie.name() == &*self.cookie_name)
+            .map(|cookie| cookie.value().to_owned())
+            .filter(|token| token.len() == 64 && token.bytes().all(|b| b.is_ascii_hexdigit()))
+    }
+
+    /// Whether `req` sends `token` back, in the header or in the form it
+    /// carries, whose body is put back for the handler.
+    async fn sends_back(&self, req: &mut Request, token: &str) -> Result<bool, Response> {
+        let expected = digest(token);
+        if let Some(value) = req.headers.get(&self.header) {
+            return Ok(constant_time_eq(&digest(value.as_str()), &expected));
+        }
+        if !has_content_type(&req.headers, |mime| {
+            mime.eq_ignore_ascii_case("application/x-www-form-urlencoded")
+        }) {
+            return Ok(false);
+        }
+
+        let limit = body_limit(req);
+        let body = std::mem::take(&mut req.body);
+        let bytes = buffer_limited(body, limit)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        let sent = serde_urlencoded::from_bytes::<Vec<(String, String)>>(&bytes)
+            .ok()
+            .and_then(|fields| {
+                fields
+                    .into_iter()
+                    .find(|(name, _)| name == &*self.field)
+                    .map(|(_, value)| value)
+            });
+        req.body = bytes.into();
+        Ok(sent.is_some_and(|sent| constant_time_eq(&digest(&sent), &expected)))
+    }
+}
+
+impl Default for CsrfLayer {
+    fn default() -> Self {
+        CsrfLayer::new()
+    }
+}
+
+impl<S> Layer<S> for CsrfLayer {
+    type Service = Csrf<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Csrf {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// A service rejecting forged requests, made by [`CsrfLayer`].
+#[derive(Clone, Debug)]
+pub struct Csrf<S> {
+    inner: S,
+    layer: CsrfLayer,
+}
+
+impl<S> Service<Request> for Csrf<S>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        let sent = self.layer.cookie_token(&req);
+        let issued = sent.is_none();
+        let token = sent.unwrap_or_else(random_id);
+        req.extensions.insert(CsrfToken(token.clone()));
+
+        // The clone that was made ready goes with the request, leaving a
+        // fresh one for the next.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+        Box::pin(async move {
+            if !req.method.is_safe() {
+                // A new token can't have been sent back.
+                let valid = if issued {
+                    Ok(false)
+                } else {
+                    layer.sends_back(&mut req, &token).await
+                };
+                match valid {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        let resp = (StatusCode::FORBIDDEN, "Missing or invalid CSRF token");
+                        return Ok(resp.into_response());
+                    }
+                    // The form couldn't be read.
+                    Err(resp) => return Ok(resp),
+                }
+            }
+            let mut resp = inner.call(req).await?;
+            if issued {
+                append_header(&mut resp, "Set-Cookie", &layer.cookie(&token).to_string());
+            }
+            Ok(resp)
+        })
+    }
+}
+
+/// The CSRF token of the client, for pages to put in their forms, attached
+/// by [`CsrfLayer`].
+///
+/// It goes in a hidden `csrf_token` field, or whatever
+/// [`CsrfLayer::field`] named it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CsrfToken(String);
+
+impl CsrfToken {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for CsrfToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromRequestParts for CsrfToken {
+    type Rejection = MissingCsrfToken;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>> {
+        let token = parts.extensions.get::<CsrfToken>().cloned();
+        Box::pin(async move { token.ok_or(MissingCsrfToken) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+    use crate::http::{Body, Method, RequestBuilder};
+
+    const TOKEN: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    /// Answers with the token the layer attached and the body it got.
+    async fn send(req: Request) -> Response {
+        let app = service_fn(|req: Request| async move {
+            let token = req.extensions.get::<CsrfToken>().unwrap().clone();
+            let body = req.body.collect().await.unwrap();
+            let body = format!("{} {}", token, String::from_utf8_lossy(&body));
+            Ok::<_, Infallible>(body.into_response())
+        });
+        CsrfLayer::new().layer(app).oneshot(req).await.unwrap()
+    }
+
+    fn post(cookie: Option<&str>) -> RequestBuilder {
+        let mut builder = Request::builder().method(Method::Post).uri("/");
+        if let Some(cookie) = cookie {
+            builder = builder.header("cookie", &format!("csrf_token={}", cookie));
+        }
+        builder
+    }
+
+    #[tokio::test]
+    async fn issues_tokens() {
+        let req = Request::builder().uri("/").body(Body::empty()).unwrap();
+        let resp = send(req).await;
+
+        assert_eq!(resp.status, StatusCode::OK);
+        let cookie = resp.headers.get("set-cookie").unwrap().as_str().to_owned();
+        let (token, _) = cookie["csrf_token=".len()..].split_once(';').unwrap();
+        assert_eq!(token.len(), 64);
+        assert!(cookie.contains("SameSite=Lax") && cookie.contains("Secure"));
+
+        let body = resp.body.collect().await.unwrap();
+        assert_eq!(body, format!("{} ", token).as_bytes());
+    }
+
+    #[tokio::test]
+    async fn keeps_sent_tokens() {
+        let req = Request::builder()
+            .uri("/")
+            .header("cookie", &format!("theme=dark; csrf_token={}", TOKEN))
+            .body(Body::empty())
+            .unwrap();
+        let resp = send(req).await;
+
+        assert_eq!(resp.status, StatusCode::OK);
+        assert!(resp.headers.get("set-cookie").is_none());
+        let body = resp.body.collect().await.unwrap();
+        assert_eq!(body, format!("{} ", TOKEN).as_bytes());
+    }
+
+    #[tokio::test]
+    async fn accepts_the_token_in_the_header() {
+        let req = post(Some(TOKEN))
+            .header("x-csrf-token", TOKEN)
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(send(req).await.status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn accepts_the_token_in_forms_and_puts_the_body_back() {
+        let form = format!("bio=hi&csrf_token={}", TOKEN);
+        let req = post(Some(TOKEN))
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(Body::from(form.clone()))
+            .unwrap();
+        let resp = send(req).await;
+
+        assert_eq!(resp.status, StatusCode::OK);
+        let body = resp.body.collect().await.unwrap();
+        assert_eq!(body, format!("{} {}", TOKEN, form).as_bytes());
+    }
+
+    #[tokio::test]
+    async fn rejects_forged_requests() {
+        let other = TOKEN.replace('0', "f");
+        let form = format!("csrf_token={}", TOKEN);
+        let forged = [
+            // No cookie, so a new token, which can't have been sent back.
+            post(None).header("x-csrf-token", TOKEN),
+            // A cookie that isn't one of ours.
+            post(Some("short")).header("x-csrf-token", "short"),
+            post(Some(TOKEN)),
+            post(Some(TOKEN)).header("x-csrf-token", &other),
+            // The header is checked instead of the form when both are sent.
+            post(Some(TOKEN))
+                .header("x-csrf-token", &other)
+                .header("content-type", "application/x-www-form-urlencoded"),
+            // Only URL-encoded forms are searched.
+            post(Some(TOKEN)).header("content-type", "text/plain"),
+        ];
+        for builder in forged {
+            let req = builder.body(Body::from(form.clone())).unwrap();
+            assert_eq!(send(req).await.status, StatusCode::FORBIDDEN);
+        }
+    }
+}
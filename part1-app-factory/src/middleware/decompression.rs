@@ -0,0 +1,297 @@
+use std::{
+    fmt,
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use anyhow::Error;
+use async_compression::tokio::bufread::{BrotliDecoder, GzipDecoder, ZlibDecoder};
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt, TryStreamExt};
+use tokio::io::AsyncRead;
+use tokio_util::io::{ReaderStream, StreamReader};
+use tower::{Layer, Service};
+
+use super::coding::Coding;
+use crate::{
+    extract::rejection::LengthLimitError,
+    http::{
+        header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH},
+        Body, HeaderValue, Request, Response, StatusCode,
+    },
+    response::IntoResponse,
+};
+
+/// Decompresses request bodies sent with `Content-Encoding: gzip`,
+/// `deflate` or `br`, so extractors read them as if they were sent as they are.
+///
+/// A body that decompresses to more than 16 MiB, or the size set with
+/// [`max_size`](DecompressionLayer::max_size), is cut off with a
+/// [`LengthLimitError`], so the body extractors reject it with a 413 however
+/// small it was compressed:
+///
+/// ```
+/// # use part1_app_factory::{middleware::DecompressionLayer, router::{post, Router}};
+/// # async fn ingest(_body: Vec<u8>) {}
+/// let app = Router::new()
+///     .route("/ingest", post(ingest))
+///     .layer(DecompressionLayer::new().max_size(64 * 1024 * 1024));
+/// ```
+///
+/// Bodies that aren't valid fail to read with a 400. Requests in any other
+/// coding are answered with a 415 and the `Accept-Encoding` the server does
+/// take, without reaching the app.
+#[derive(Clone, Copy, Debug)]
+pub struct DecompressionLayer {
+    max_size: usize,
+}
+
+impl DecompressionLayer {
+    pub fn new() -> Self {
+        DecompressionLayer {
+            max_size: 16 * 1024 * 1024,
+        }
+    }
+
+    /// Accepts bodies that decompress to up to `max_size` bytes.
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+}
+
+impl Default for DecompressionLayer {
+    fn default() -> Self {
+        DecompressionLayer::new()
+    }
+}
+
+impl<S> Layer<S> for DecompressionLayer {
+    type Service = Decompression<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Decompression {
+            inner,
+            layer: *self,
+        }
+    }
+}
+
+/// A service decompressing the request bodies it passes on, made by
+/// [`DecompressionLayer`].
+#[derive(Clone, Debug)]
+pub struct Decompression<S> {
+    inner: S,
+    layer: DecompressionLayer,
+}
+
+impl<S> Service<Request> for Decompression<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        let codings: Vec<String> = req
+            .headers
+            .get_all(CONTENT_ENCODING)
+            .flat_map(|value| value.as_str().split(','))
+            .map(|coding| coding.trim().to_owned())
+            .filter(|coding| !coding.is_empty() && !coding.eq_ignore_ascii_case("identity"))
+            .collect();
+        req.headers.remove(CONTENT_ENCODING);
+
+        match codings.as_slice() {
+            [] => {}
+            [coding] if Coding::from_name(coding).is_some() => {
+                let coding = Coding::from_name(coding).expect("checked above");
+                req.headers.remove(CONTENT_LENGTH);
+                let mut body = std::mem::take(&mut req.body);
+                let trailers = body.take_trailers();
+                let mut decoded = Body::from_stream(Decode::new(body, coding, self.layer.max_size));
+                if let Some(trailers) = trailers {
+                    decoded = decoded.with_trailers(trailers);
+                }
+                req.body = decoded;
+            }
+            _ => return Box::pin(async { Ok(unsupported_encoding()) }),
+        }
+        Box::pin(self.inner.call(req))
+    }
+}
+
+fn unsupported_encoding() -> Response {
+    let mut resp = StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response();
+    resp.headers.insert(
+        ACCEPT_ENCODING,
+        HeaderValue::from_static("gzip, deflate, br"),
+    );
+    resp
+}
+
+/// A compressed request body being decompressed.
+struct Decode {
+    output: ReaderStream<Pin<Box<dyn AsyncRead + Send>>>,
+    len: usize,
+    max_size: usize,
+    done: bool,
+}
+
+impl Decode {
+    fn new(body: Body, coding: Coding, max_size: usize) -> Self {
+        let input = StreamReader::new(body.map_err(|e| io::Error::other(BodyError(e))));
+        let decoder: Pin<Box<dyn AsyncRead + Send>> = match coding {
+            Coding::Gzip => Box::pin(GzipDecoder::new(input)),
+            Coding::Deflate => Box::pin(ZlibDecoder::new(input)),
+            Coding::Brotli => Box::pin(BrotliDecoder::new(input)),
+        };
+        Decode {
+            output: ReaderStream::new(decoder),
+            len: 0,
+            max_size,
+            done: false,
+        }
+    }
+}
+
+impl Stream for Decode {
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if this.done {
+            return Poll::Ready(None);
+        }
+        let result = match this.output.poll_next_unpin(cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Ready(Some(result)) => result,
+        };
+        let error = match result {
+            Ok(chunk) => {
+                this.len += chunk.len();
+                if this.len <= this.max_size {
+                    return Poll::Ready(Some(Ok(chunk)));
+                }
+                LengthLimitError::new(this.max_size).into()
+            }
+            // Errors reading the body itself are passed on as they are, so
+            // e.g. a `LengthLimitError` of the compressed size still gets a
+            // 413.
+            Err(e) if e.get_ref().is_some_and(|e| e.is::<BodyError>()) => {
+                let e = e.into_inner().expect("checked above");
+                e.downcast::<BodyError>().expect("checked above").0
+            }
+            Err(e) => Error::new(e).context("invalid compressed body"),
+        };
+        this.done = true;
+        Poll::Ready(Some(Err(error)))
+    }
+}
+
+/// An error reading the compressed body, carried through the decoder as an
+/// [`io::Error`].
+#[derive(Debug)]
+struct BodyError(Error);
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for BodyError {}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+    use crate::{extract::FromRequest, middleware::coding::Encoder};
+
+    /// Sends `body` in `coding` through `layer` to an app echoing the body
+    /// it extracts.
+    async fn send(layer: DecompressionLayer, coding: &str, body: Vec<u8>) -> Response {
+        let app = service_fn(|req: Request| async {
+            Ok::<_, Infallible>(match Bytes::from_request(req).await {
+                Ok(body) => body.into_response(),
+                Err(rejection) => rejection.into_response(),
+            })
+        });
+        let req = Request::builder()
+            .method(crate::http::Method::Post)
+            .uri("/")
+            .header("content-encoding", coding)
+            .body(Body::from(body))
+            .unwrap();
+        layer.layer(app).oneshot(req).await.unwrap()
+    }
+
+    fn compress(coding: Coding, data: &[u8]) -> Vec<u8> {
+        let mut encoder = Encoder::new(coding);
+        let mut out = encoder.encode(data).to_vec();
+        out.extend_from_slice(&encoder.finish());
+        out
+    }
+
+    #[tokio::test]
+    async fn decompresses_each_coding() {
+        let data = "hello, hello, hello world".repeat(1000);
+        let codings = [
+            (Coding::Gzip, "gzip"),
+            (Coding::Deflate, "deflate"),
+            (Coding::Brotli, "br"),
+        ];
+        for (coding, name) in codings {
+            let body = compress(coding, data.as_bytes());
+            let resp = send(DecompressionLayer::new(), name, body).await;
+            assert_eq!(resp.status, StatusCode::OK);
+            assert_eq!(resp.body.collect().await.unwrap(), data.as_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_bodies_over_max_size() {
+        let body = compress(Coding::Gzip, &[0; 64 * 1024]);
+        let resp = send(DecompressionLayer::new().max_size(1024), "gzip", body).await;
+        assert_eq!(resp.status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_bodies() {
+        let mut body = compress(Coding::Gzip, b"hello world");
+        let len = body.len();
+        body[len - 8] ^= 0xff;
+        let resp = send(DecompressionLayer::new(), "gzip", body).await;
+        assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+
+        let mut body = compress(Coding::Gzip, b"hello world");
+        body.truncate(body.len() - 4);
+        let resp = send(DecompressionLayer::new(), "gzip", body).await;
+        assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+
+        let resp = send(DecompressionLayer::new(), "deflate", b"not zlib".to_vec()).await;
+        assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn rejects_unsupported_codings() {
+        let resp = send(DecompressionLayer::new(), "compress", b"data".to_vec()).await;
+        assert_eq!(resp.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(
+            resp.headers.get(ACCEPT_ENCODING).unwrap().as_str(),
+            "gzip, deflate, br"
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use anyhow::Error;
+use tower::{util::BoxCloneService, Layer, Service, ServiceExt};
+use tracing::error;
+
+use crate::{
+    http::{Request, Response, StatusCode},
+    response::IntoResponse,
+    router::BoxRoute,
+};
+
+/// Middleware from an async function taking the request and the [`Next`]
+/// part of the app, which it may or may not run the request with:
+///
+/// ```
+/// # use part1_app_factory::{
+/// #     http::{Request, Response, StatusCode},
+/// #     middleware::{self, Next},
+/// #     response::IntoResponse,
+/// #     router::{get, Router},
+/// # };
+/// # async fn index() {}
+/// async fn require_key(req: Request, next: Next) -> Response {
+///     match req.headers.get("x-api-key") {
+///         Some(key) if key == "secret" => next.run(req).await,
+///         _ => StatusCode::UNAUTHORIZED.into_response(),
+///     }
+/// }
+///
+/// let app = Router::new()
+///     .route("/", get(index))
+///     .layer(middleware::from_fn(require_key));
+/// ```
+///
+/// Anything implementing [`IntoResponse`] can be returned, as from handlers.
+pub fn from_fn<F>(f: F) -> FromFnLayer<F> {
+    FromFnLayer { f }
+}
+
+/// The layer made by [`from_fn`].
+#[derive(Clone)]
+pub struct FromFnLayer<F> {
+    f: F,
+}
+
+impl<F, S> Layer<S> for FromFnLayer<F>
+where
+    F: Clone,
+{
+    type Service = FromFn<F, S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        FromFn {
+            f: self.f.clone(),
+            inner,
+        }
+    }
+}
+
+/// A service running a function made into middleware with [`from_fn`].
+#[derive(Clone)]
+pub struct FromFn<F, S> {
+    f: F,
+    inner: S,
+}
+
+impl<F, Fut, Out, S> Service<Request> for FromFn<F, S>
+where
+    F: FnMut(Request, Next) -> Fut,
+    Fut: Future<Output = Out> + Send + 'static,
+    Out: IntoResponse,
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Error: Into<Error>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // `Next` waits for the clone of the inner service it calls.
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let next = Next {
+            inner: BoxCloneService::new(self.inner.clone().map_err(Into::into)),
+        };
+        let future = (self.f)(req, next);
+        Box::pin(async move { Ok(future.await.into_response()) })
+    }
+}
+
+/// The rest of the app, after the middleware.
+pub struct Next {
+    inner: BoxRoute,
+}
+
+impl Next {
+    /// Runs the rest of the app with `req`. An error from it becomes a 500,
+    /// as it would at the server.
+    pub async fn run(self, req: Request) -> Response {
+        match self.inner.oneshot(req).await {
+            Ok(resp) => resp,
+            Err(e) => {
+                error!(error = ?e, "Request failed");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Next {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Next").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,139 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tower::{Layer, Service};
+
+use crate::http::{Request, Response};
+
+type Acquire = Pin<Box<dyn Future<Output = OwnedSemaphorePermit> + Send>>;
+
+/// Bounds how many requests the app it wraps works on at once. Once `max`
+/// are in flight, the app isn't ready until one of them is answered, so
+/// whoever feeds it requests waits instead of piling them up.
+///
+/// The budget is made when the layer wraps an app, so wrapped around the
+/// app of each connection, as with
+/// [`AppFactoryFn::concurrency_limit`](crate::util::AppFactoryFn::concurrency_limit),
+/// every connection has one of its own and a busy one can't starve the
+/// others:
+///
+/// ```
+/// # use part1_app_factory::{http::ConnInfo, router::Router, util::app_factory_fn};
+/// # let router = Router::new();
+/// let app_factory = app_factory_fn(move |_conn: ConnInfo| {
+///     let router = router.clone();
+///     async move { Ok(router) }
+/// })
+/// .concurrency_limit(8);
+/// ```
+///
+/// Clones of the wrapped app share its budget. With
+/// [`Router::layer`](crate::router::Router::layer), that makes one budget for
+/// each route, over all connections.
+#[derive(Clone, Copy, Debug)]
+pub struct InFlightLimitLayer {
+    max: usize,
+}
+
+impl InFlightLimitLayer {
+    pub fn new(max: usize) -> Self {
+        InFlightLimitLayer { max }
+    }
+}
+
+impl<S> Layer<S> for InFlightLimitLayer {
+    type Service = InFlightLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        InFlightLimit {
+            inner,
+            semaphore: Arc::new(Semaphore::new(self.max)),
+            acquire: None,
+            permit: None,
+        }
+    }
+}
+
+/// A service limiting the requests in flight, made by
+/// [`InFlightLimitLayer`].
+pub struct InFlightLimit<S> {
+    inner: S,
+    semaphore: Arc<Semaphore>,
+    /// Waiting for a permit, if `poll_ready` found none free.
+    acquire: Option<Acquire>,
+    /// The permit the next request is made with.
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+/// A clone shares the budget, but waits for a permit of its own.
+impl<S: Clone> Clone for InFlightLimit<S> {
+    fn clone(&self) -> Self {
+        InFlightLimit {
+            inner: self.inner.clone(),
+            semaphore: self.semaphore.clone(),
+            acquire: None,
+            permit: None,
+        }
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for InFlightLimit<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InFlightLimit")
+            .field("inner", &self.inner)
+            .field("available", &self.semaphore.available_permits())
+            .field("ready", &self.permit.is_some())
+            .finish()
+    }
+}
+
+impl<S> Service<Request> for InFlightLimit<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.permit.is_none() && self.acquire.is_none() {
+            self.permit = self.semaphore.clone().try_acquire_owned().ok();
+        }
+        if self.permit.is_none() {
+            let semaphore = &self.semaphore;
+            let acquire = self.acquire.get_or_insert_with(|| {
+                let semaphore = semaphore.clone();
+                Box::pin(async move {
+                    semaphore
+                        .acquire_owned()
+                        .await
+                        .expect("the semaphore is never closed")
+                })
+            });
+            let permit = std::task::ready!(acquire.as_mut().poll(cx));
+            self.acquire = None;
+            self.permit = Some(permit);
+        }
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let permit = self
+            .permit
+            .take()
+            .expect("`poll_ready` is called before `call`");
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let resp = future.await;
+            drop(permit);
+            resp
+        })
+    }
+}
@@ -0,0 +1,295 @@
+use std::{
+    fmt,
+    future::Future,
+    net::IpAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use tower::{Layer, Service};
+use tracing::debug;
+
+use crate::{
+    extract::ConnectInfo,
+    http::{ConnInfo, Request, Response, StatusCode},
+    response::IntoResponse,
+};
+
+/// Answers requests from clients whose IP address isn't allowed with a 403,
+/// before they reach the app.
+///
+/// The address is the peer address of the connection, from the
+/// `ConnectInfo<ConnInfo>` the server attaches. Networks are written in CIDR
+/// notation, or as a single address:
+///
+/// ```
+/// # use part1_app_factory::{middleware::IpFilterLayer, router::{get, Router}};
+/// # async fn metrics() {}
+/// let admin = Router::new()
+///     .route("/metrics", get(metrics))
+///     .layer(
+///         IpFilterLayer::new()
+///             .allow("10.0.0.0/8")
+///             .allow("::1")
+///             .deny("10.0.13.0/24"),
+///     );
+/// ```
+///
+/// Denied networks win over allowed ones. Without any allowed network, every
+/// address that isn't denied is allowed; with some, only theirs are. IPv4
+/// addresses mapped to IPv6 are matched as IPv4.
+///
+/// Requests without a peer address, e.g. over a Unix domain socket, match no
+/// network, so they're only let through if no network is allowed. Behind a
+/// proxy, the peer is the proxy.
+#[derive(Clone, Debug, Default)]
+pub struct IpFilterLayer {
+    allowed: Arc<Vec<IpNet>>,
+    denied: Arc<Vec<IpNet>>,
+}
+
+impl IpFilterLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows the addresses in `network`, e.g. `192.168.0.0/16`.
+    ///
+    /// # Panics
+    ///
+    /// If `network` isn't an address or a network in CIDR notation.
+    pub fn allow(mut self, network: &str) -> Self {
+        Arc::make_mut(&mut self.allowed).push(parse(network));
+        self
+    }
+
+    /// Denies the addresses in `network`, e.g. `203.0.113.7`.
+    ///
+    /// # Panics
+    ///
+    /// If `network` isn't an address or a network in CIDR notation.
+    pub fn deny(mut self, network: &str) -> Self {
+        Arc::make_mut(&mut self.denied).push(parse(network));
+        self
+    }
+
+    fn allows(&self, ip: Option<IpAddr>) -> bool {
+        let Some(ip) = ip.map(|ip| ip.to_canonical()) else {
+            return self.allowed.is_empty();
+        };
+        if self.denied.iter().any(|net| net.contains(ip)) {
+            return false;
+        }
+        self.allowed.is_empty() || self.allowed.iter().any(|net| net.contains(ip))
+    }
+}
+
+fn parse(network: &str) -> IpNet {
+    IpNet::parse(network).unwrap_or_else(|| panic!("invalid network {:?}", network))
+}
+
+impl<S> Layer<S> for IpFilterLayer {
+    type Service = IpFilter<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        IpFilter {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// A service turning away clients by their IP address, made by
+/// [`IpFilterLayer`].
+#[derive(Clone, Debug)]
+pub struct IpFilter<S> {
+    inner: S,
+    layer: IpFilterLayer,
+}
+
+impl<S> Service<Request> for IpFilter<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let peer = req
+            .extensions
+            .get::<ConnectInfo<ConnInfo>>()
+            .and_then(|ConnectInfo(conn)| conn.peer_addr);
+        if !self.layer.allows(peer.map(|addr| addr.ip())) {
+            debug!(peer = ?peer, "Turned away a client by its address");
+            let resp = StatusCode::FORBIDDEN.into_response();
+            return Box::pin(async move { Ok(resp) });
+        }
+        Box::pin(self.inner.call(req))
+    }
+}
+
+/// A network of IP addresses sharing their first `prefix` bits.
+#[derive(Clone, Copy)]
+struct IpNet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNet {
+    /// `network` in CIDR notation, or a single address.
+    fn parse(network: &str) -> Option<Self> {
+        let (addr, prefix) = match network.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (network, None),
+        };
+        let addr = addr.parse::<IpAddr>().ok()?.to_canonical();
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().ok().filter(|prefix| *prefix <= max)?,
+            None => max,
+        };
+        Some(IpNet { addr, prefix })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Debug for IpNet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, net::SocketAddr};
+
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+    use crate::http::Body;
+
+    fn net(network: &str) -> IpNet {
+        IpNet::parse(network).unwrap()
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    async fn send(layer: IpFilterLayer, peer: Option<&str>) -> StatusCode {
+        let app = service_fn(|_: Request| async { Ok::<_, Infallible>(().into_response()) });
+        let mut req = Request::builder().uri("/").body(Body::empty()).unwrap();
+        req.extensions.insert(ConnectInfo(ConnInfo {
+            id: ConnInfo::next_id(),
+            host_and_port: "localhost:3000".to_owned(),
+            local_addr: None,
+            peer_addr: peer.map(|peer| SocketAddr::new(ip(peer), 50000)),
+            peer_cred: None,
+            tls: None,
+        }));
+        layer.layer(app).oneshot(req).await.unwrap().status
+    }
+
+    #[test]
+    fn parses_networks() {
+        assert_eq!(format!("{:?}", net("10.0.0.0/8")), "10.0.0.0/8");
+        assert_eq!(format!("{:?}", net("203.0.113.7")), "203.0.113.7/32");
+        assert_eq!(format!("{:?}", net("::1")), "::1/128");
+        assert_eq!(format!("{:?}", net("::ffff:10.1.2.3/32")), "10.1.2.3/32");
+
+        for invalid in [
+            "",
+            "10.0.0/8",
+            "10.0.0.0/33",
+            "::/129",
+            "10.0.0.0/",
+            "example.com",
+        ] {
+            assert!(IpNet::parse(invalid).is_none(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn contains_addresses_sharing_the_prefix() {
+        assert!(net("10.0.0.0/8").contains(ip("10.255.0.1")));
+        assert!(!net("10.0.0.0/8").contains(ip("11.0.0.1")));
+        assert!(net("192.168.1.0/23").contains(ip("192.168.0.9")));
+        assert!(!net("192.168.1.0/24").contains(ip("192.168.0.9")));
+        assert!(net("0.0.0.0/0").contains(ip("8.8.8.8")));
+        assert!(net("2001:db8::/32").contains(ip("2001:db8:1::1")));
+        assert!(!net("2001:db8::/32").contains(ip("2001:db9::1")));
+        assert!(net("::/0").contains(ip("::1")));
+        assert!(!net("0.0.0.0/0").contains(ip("::1")));
+    }
+
+    #[tokio::test]
+    async fn denied_networks_win() {
+        let layer = IpFilterLayer::new()
+            .allow("10.0.0.0/8")
+            .deny("10.0.13.0/24");
+
+        assert_eq!(send(layer.clone(), Some("10.0.12.1")).await, StatusCode::OK);
+        assert_eq!(
+            send(layer.clone(), Some("10.0.13.1")).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            send(layer.clone(), Some("::ffff:10.0.13.1")).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(send(layer, Some("192.0.2.1")).await, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn allows_everyone_not_denied_without_allowed_networks() {
+        let layer = IpFilterLayer::new().deny("203.0.113.7");
+
+        assert_eq!(
+            send(layer.clone(), Some("203.0.113.8")).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            send(layer.clone(), Some("203.0.113.7")).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(send(layer, None).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn turns_away_unknown_peers_when_allowing_networks() {
+        let layer = IpFilterLayer::new().allow("::1");
+
+        assert_eq!(send(layer.clone(), Some("::1")).await, StatusCode::OK);
+        assert_eq!(send(layer, None).await, StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid network")]
+    fn panics_on_invalid_networks() {
+        IpFilterLayer::new().allow("10.0.0.0/40");
+    }
+}
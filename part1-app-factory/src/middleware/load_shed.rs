@@ -0,0 +1,251 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::time::{sleep, Sleep};
+use tower::{Layer, Service};
+
+use crate::{
+    http::{header::RETRY_AFTER, Request, Response, StatusCode},
+    response::IntoResponse,
+};
+
+/// Answers requests with a 503 and a `Retry-After` when the app it wraps
+/// hasn't been ready for a while, instead of letting them wait for it.
+///
+/// An app stays unready while it's at capacity, e.g. behind an
+/// [`InFlightLimitLayer`](super::InFlightLimitLayer). Once it's been so for
+/// longer than `wait`, requests are shed straight away until it's ready
+/// again:
+///
+/// ```
+/// # use std::time::Duration;
+/// # use part1_app_factory::{
+/// #     http::ConnInfo,
+/// #     middleware::LoadShedLayer,
+/// #     router::Router,
+/// #     util::app_factory_fn,
+/// # };
+/// # async fn make_app(_conn: ConnInfo) -> anyhow::Result<Router> {
+/// #     Ok(Router::new())
+/// # }
+/// let app_factory = app_factory_fn(make_app)
+///     .concurrency_limit(8)
+///     .layer(LoadShedLayer::new(Duration::from_millis(500)).retry_after(Duration::from_secs(2)));
+/// ```
+///
+/// Clients are asked to retry after a second unless configured otherwise.
+#[derive(Clone, Copy, Debug)]
+pub struct LoadShedLayer {
+    wait: Duration,
+    retry_after: Duration,
+}
+
+impl LoadShedLayer {
+    pub fn new(wait: Duration) -> Self {
+        LoadShedLayer {
+            wait,
+            retry_after: Duration::from_secs(1),
+        }
+    }
+
+    /// Asks clients to retry shed requests after `retry_after`, rounded up
+    /// to whole seconds.
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    fn overloaded(&self) -> Response {
+        let mut resp = StatusCode::SERVICE_UNAVAILABLE.into_response();
+        let wait = self.retry_after;
+        let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+        resp.headers.insert(RETRY_AFTER, secs.into());
+        resp
+    }
+}
+
+impl<S> Layer<S> for LoadShedLayer {
+    type Service = LoadShed<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LoadShed {
+            inner,
+            layer: *self,
+            timer: None,
+            overloaded: false,
+            shed: false,
+        }
+    }
+}
+
+/// A service shedding load while the app it wraps is overloaded, made by
+/// [`LoadShedLayer`].
+pub struct LoadShed<S> {
+    inner: S,
+    layer: LoadShedLayer,
+    /// Running while the app isn't ready, until it's taken to be overloaded.
+    timer: Option<Pin<Box<Sleep>>>,
+    /// The app has been unready for longer than the layer's `wait`.
+    overloaded: bool,
+    /// The next request is to be shed.
+    shed: bool,
+}
+
+impl<S: Clone> Clone for LoadShed<S> {
+    fn clone(&self) -> Self {
+        self.layer.layer(self.inner.clone())
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for LoadShed<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoadShed")
+            .field("inner", &self.inner)
+            .field("layer", &self.layer)
+            .field("overloaded", &self.overloaded)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> Service<Request> for LoadShed<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if let Poll::Ready(ready) = self.inner.poll_ready(cx) {
+            self.timer = None;
+            self.overloaded = false;
+            self.shed = false;
+            return Poll::Ready(ready);
+        }
+        if !self.overloaded {
+            let wait = self.layer.wait;
+            let timer = self.timer.get_or_insert_with(|| Box::pin(sleep(wait)));
+            std::task::ready!(timer.as_mut().poll(cx));
+            self.timer = None;
+            self.overloaded = true;
+        }
+        self.shed = true;
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        if std::mem::take(&mut self.shed) {
+            let resp = self.layer.overloaded();
+            return Box::pin(async move { Ok(resp) });
+        }
+        Box::pin(self.inner.call(req))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        future::{ready, Ready},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    };
+
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::http::Method;
+
+    fn request(method: Method, uri: &str, headers: &[(&str, &str)], body: &'static str) -> Request {
+        let mut req = Request::builder().method(method).uri(uri);
+        for &(name, value) in headers {
+            req = req.header(name, value);
+        }
+        req.body(body).unwrap()
+    }
+
+    /// The status and body of `resp`.
+    async fn respond(resp: Response) -> (u16, String) {
+        let body = resp.body.collect().await.unwrap();
+        (
+            resp.status.as_u16(),
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    fn header<'a>(resp: &'a Response, name: &str) -> Option<&'a str> {
+        resp.headers.get(name).map(|value| value.as_str())
+    }
+
+    /// An app that's ready only while it's open.
+    #[derive(Clone, Default)]
+    struct Gate(Arc<AtomicBool>);
+
+    impl Service<Request> for Gate {
+        type Response = Response;
+        type Error = Infallible;
+        type Future = Ready<Result<Response, Infallible>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            if self.0.load(Ordering::SeqCst) {
+                Poll::Ready(Ok(()))
+            } else {
+                Poll::Pending
+            }
+        }
+
+        fn call(&mut self, _req: Request) -> Self::Future {
+            ready(Ok("served".into_response()))
+        }
+    }
+
+    async fn send(service: &mut LoadShed<Gate>) -> Response {
+        let req = request(Method::Get, "/", &[], "");
+        service.ready().await.unwrap().call(req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn serves_while_the_app_is_ready() {
+        let gate = Gate::default();
+        gate.0.store(true, Ordering::SeqCst);
+        let mut service = LoadShedLayer::new(Duration::from_millis(10)).layer(gate);
+        assert_eq!(
+            respond(send(&mut service).await).await,
+            (200, "served".to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn sheds_load_until_the_app_is_ready_again() {
+        let gate = Gate::default();
+        let mut service = LoadShedLayer::new(Duration::from_millis(10)).layer(gate.clone());
+
+        let resp = send(&mut service).await;
+        assert_eq!(header(&resp, "Retry-After"), Some("1"));
+        assert_eq!(respond(resp).await, (503, String::new()));
+        // It stays overloaded until the app is ready.
+        assert_eq!(respond(send(&mut service).await).await.0, 503);
+
+        gate.0.store(true, Ordering::SeqCst);
+        assert_eq!(respond(send(&mut service).await).await.0, 200);
+    }
+
+    #[tokio::test]
+    async fn rounds_retry_after_up_to_whole_seconds() {
+        for (retry_after, secs) in [(1500, "2"), (3000, "3"), (1, "1")] {
+            let mut service = LoadShedLayer::new(Duration::ZERO)
+                .retry_after(Duration::from_millis(retry_after))
+                .layer(Gate::default());
+            let resp = send(&mut service).await;
+            assert_eq!(header(&resp, "Retry-After"), Some(secs));
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::task::{Context, Poll};
+
+use tower::{Layer, Service};
+
+use crate::http::Request;
+
+/// Normalizes request paths before they're routed, so one path can't be
+/// spelled another way to get past a route, or a layer guarding it:
+///
+/// - runs of slashes are collapsed, e.g. `//admin` to `/admin`,
+/// - `.` and `..` segments are resolved, percent-encoded or not, e.g.
+///   `/public/%2e%2e/admin` to `/admin`, never going above the root,
+/// - and the path is lowercased if [`lowercase`](NormalizePathLayer::lowercase)
+///   asks for it.
+///
+/// A trailing slash is kept. The layer has to wrap the router itself, as
+/// [`Router::layer`](crate::router::Router::layer) only wraps what's behind
+/// the routing:
+///
+/// ```no_run
+/// # use tower::Layer;
+/// # use part1_app_factory::{middleware::NormalizePathLayer, router::Router, server::serve};
+/// # async fn run(router: Router) -> std::io::Result<()> {
+/// let app = NormalizePathLayer::new().lowercase(true).layer(router);
+/// serve("127.0.0.1:3000", app).await
+/// # }
+/// ```
+///
+/// Handlers and the [`OriginalUri`](crate::router::OriginalUri) see the
+/// normalized path.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NormalizePathLayer {
+    lowercase: bool,
+}
+
+impl NormalizePathLayer {
+    pub fn new() -> Self {
+        NormalizePathLayer { lowercase: false }
+    }
+
+    /// Lowercases the ASCII letters of paths too.
+    pub fn lowercase(mut self, enable: bool) -> Self {
+        self.lowercase = enable;
+        self
+    }
+}
+
+impl<S> Layer<S> for NormalizePathLayer {
+    type Service = NormalizePath<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        NormalizePath {
+            inner,
+            layer: *self,
+        }
+    }
+}
+
+/// A service normalizing the paths of the requests it passes on, made by
+/// [`NormalizePathLayer`].
+#[derive(Clone, Debug)]
+pub struct NormalizePath<S> {
+    inner: S,
+    layer: NormalizePathLayer,
+}
+
+impl<S> Service<Request> for NormalizePath<S>
+where
+    S: Service<Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        let mut path = normalize(req.uri.path());
+        if self.layer.lowercase {
+            path.make_ascii_lowercase();
+        }
+        if path != req.uri.path() {
+            req.uri = req.uri.with_path(&path);
+        }
+        self.inner.call(req)
+    }
+}
+
+/// `path` without empty, `.` and `..` segments.
+fn normalize(path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    let mut ends_with_dots = false;
+    for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+        let decoded = segment.to_ascii_lowercase().replace("%2e", ".");
+        ends_with_dots = match decoded.as_str() {
+            "." => true,
+            ".." => {
+                segments.pop();
+                true
+            }
+            _ => {
+                segments.push(segment);
+                false
+            }
+        };
+    }
+    // A path ending in a dot segment names a directory, as RFC 3986 has it.
+    let trailing_slash = path.ends_with('/') || ends_with_dots;
+
+    let mut normalized = String::with_capacity(path.len());
+    for segment in &segments {
+        normalized.push('/');
+        normalized.push_str(segment);
+    }
+    if trailing_slash || segments.is_empty() {
+        normalized.push('/');
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+    use crate::http::Method;
+
+    fn request(method: Method, uri: &str, headers: &[(&str, &str)], body: &'static str) -> Request {
+        let mut req = Request::builder().method(method).uri(uri);
+        for &(name, value) in headers {
+            req = req.header(name, value);
+        }
+        req.body(body).unwrap()
+    }
+
+    /// The URI the app behind `layer` sees for `uri`.
+    async fn normalized(layer: NormalizePathLayer, uri: &str) -> String {
+        let app =
+            service_fn(|req: Request| async move { Ok::<_, Infallible>(req.uri.to_string()) });
+        let req = request(Method::Get, uri, &[], "");
+        layer.layer(app).oneshot(req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn collapses_slashes_and_dot_segments() {
+        let layer = NormalizePathLayer::new();
+        for (uri, expected) in [
+            ("/users/7", "/users/7"),
+            ("//users///7", "/users/7"),
+            ("/users/./7", "/users/7"),
+            ("/users/admin/../7?tab=posts", "/users/7?tab=posts"),
+            ("/users/%2E%2e/7", "/7"),
+            ("/../../etc/passwd", "/etc/passwd"),
+            ("/users/7/.", "/users/7/"),
+            ("/users/7/", "/users/7/"),
+            ("/..", "/"),
+        ] {
+            assert_eq!(normalized(layer, uri).await, expected, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn lowercases_paths_but_not_queries() {
+        let layer = NormalizePathLayer::new().lowercase(true);
+        assert_eq!(
+            normalized(layer, "/Users//Ferris?Tab=Posts").await,
+            "/users/ferris?Tab=Posts"
+        );
+        assert_eq!(
+            normalized(NormalizePathLayer::new(), "/Users/Ferris").await,
+            "/Users/Ferris"
+        );
+    }
+}
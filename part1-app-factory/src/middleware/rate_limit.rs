@@ -0,0 +1,347 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use hashlink::LruCache;
+use tower::{Layer, Service};
+
+use crate::{
+    extract::ConnectInfo,
+    http::{header::RETRY_AFTER, ConnInfo, Request, Response, StatusCode},
+    response::IntoResponse,
+};
+
+type KeyFn = dyn Fn(&Request) -> Option<String> + Send + Sync;
+
+/// Limits how many requests each client makes, answering those over the
+/// limit with a 429 and a `Retry-After` saying when to try again.
+///
+/// Every client has a bucket of `burst` tokens that refills at `rate` tokens
+/// a second, and each request takes one. Clients are told apart by the
+/// client's IP address unless [`key_by`](RateLimitLayer::key_by) says
+/// otherwise, e.g. by an API key:
+///
+/// ```
+/// # use part1_app_factory::{middleware::RateLimitLayer, router::{get, Router}};
+/// # async fn search() {}
+/// let app = Router::new()
+///     .route("/api/search", get(search))
+///     .layer(RateLimitLayer::new(10, 1.0).key_by(|req| {
+///         req.headers.get("x-api-key").map(|key| key.to_string())
+///     }));
+/// ```
+///
+/// Keying by the [`MatchedPath`](crate::router::MatchedPath), as
+/// [`Router::route_layer`](crate::router::Router::route_layer) sees it, gives
+/// every route a limit of its own shared by all clients.
+///
+/// Buckets are kept in a [`MemoryStore`] unless another [`RateLimitStore`]
+/// is set.
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    quota: Quota,
+    key: Arc<KeyFn>,
+    store: Arc<dyn RateLimitStore>,
+}
+
+impl RateLimitLayer {
+    /// Allows bursts of `burst` requests, and `rate` requests a second
+    /// after that.
+    ///
+    /// # Panics
+    ///
+    /// If `burst` is 0 or `rate` isn't positive.
+    pub fn new(burst: u32, rate: f64) -> Self {
+        assert!(burst > 0, "rate limit burst must be at least 1");
+        assert!(rate > 0.0, "rate limit rate must be positive");
+        RateLimitLayer {
+            quota: Quota {
+                burst: f64::from(burst),
+                rate,
+            },
+            key: Arc::new(peer_ip),
+            store: Arc::new(MemoryStore::new()),
+        }
+    }
+
+    /// Tells clients apart by what `key` returns for their requests.
+    /// Requests it returns `None` for aren't limited.
+    pub fn key_by<F>(mut self, key: F) -> Self
+    where
+        F: Fn(&Request) -> Option<String> + Send + Sync + 'static,
+    {
+        self.key = Arc::new(key);
+        self
+    }
+
+    /// Keeps the buckets in `store`, e.g. to share them between layers.
+    pub fn store(mut self, store: impl RateLimitStore) -> Self {
+        self.store = Arc::new(store);
+        self
+    }
+}
+
+impl fmt::Debug for RateLimitLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimitLayer")
+            .field("quota", &self.quota)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The client's IP address, from the `ConnectInfo<ConnInfo>` the server
+/// attached.
+fn peer_ip(req: &Request) -> Option<String> {
+    let ConnectInfo(conn) = req.extensions.get::<ConnectInfo<ConnInfo>>()?;
+    Some(conn.peer_addr?.ip().to_string())
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// A service limiting the rate of requests, made by [`RateLimitLayer`].
+#[derive(Clone, Debug)]
+pub struct RateLimit<S> {
+    inner: S,
+    layer: RateLimitLayer,
+}
+
+impl<S> Service<Request> for RateLimit<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let RateLimitLayer { quota, key, store } = &self.layer;
+        if let Some(key) = key(&req) {
+            if let Err(wait) = store.acquire(&key, *quota, Instant::now()) {
+                return Box::pin(async move { Ok(too_many_requests(wait)) });
+            }
+        }
+        Box::pin(self.inner.call(req))
+    }
+}
+
+fn too_many_requests(wait: Duration) -> Response {
+    let mut resp = StatusCode::TOO_MANY_REQUESTS.into_response();
+    // Whole seconds, rounded up so the client doesn't come back too early.
+    let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+    resp.headers.insert(RETRY_AFTER, secs.into());
+    resp
+}
+
+/// How big the buckets of a [`RateLimitLayer`] are and how fast they refill.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quota {
+    /// How many tokens a full bucket holds.
+    pub burst: f64,
+    /// How many tokens are added a second.
+    pub rate: f64,
+}
+
+/// Where a [`RateLimitLayer`] keeps the bucket of every key.
+pub trait RateLimitStore: Send + Sync + 'static {
+    /// Takes a token from the bucket of `key`, or returns how long until
+    /// there's one.
+    fn acquire(&self, key: &str, quota: Quota, now: Instant) -> Result<(), Duration>;
+}
+
+impl<T> RateLimitStore for Arc<T>
+where
+    T: RateLimitStore + ?Sized,
+{
+    fn acquire(&self, key: &str, quota: Quota, now: Instant) -> Result<(), Duration> {
+        (**self).acquire(key, quota, now)
+    }
+}
+
+/// Keeps buckets in memory, for a single process.
+///
+/// When room is needed for a new key, the bucket used least recently goes.
+/// Having had the longest to refill, it's the likeliest to be full, which is
+/// what a key without a bucket gets anyway.
+pub struct MemoryStore {
+    buckets: Mutex<LruCache<String, Bucket>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// How many tokens there are at `now`.
+    fn tokens_at(&self, quota: Quota, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        (self.tokens + elapsed * quota.rate).min(quota.burst)
+    }
+}
+
+impl MemoryStore {
+    /// A store for up to 100,000 keys.
+    pub fn new() -> Self {
+        MemoryStore::with_max_keys(100_000)
+    }
+
+    /// A store for up to `max_keys` keys.
+    pub fn with_max_keys(max_keys: usize) -> Self {
+        MemoryStore {
+            buckets: Mutex::new(LruCache::new(max_keys.max(1))),
+        }
+    }
+}
+
+impl fmt::Debug for MemoryStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        f.debug_struct("MemoryStore")
+            .field("keys", &buckets.len())
+            .field("max_keys", &buckets.capacity())
+            .finish()
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        MemoryStore::new()
+    }
+}
+
+impl RateLimitStore for MemoryStore {
+    fn acquire(&self, key: &str, quota: Quota, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if !buckets.contains_key(key) {
+            // Drops the bucket used least recently if the store is full.
+            buckets.insert(
+                key.to_owned(),
+                Bucket {
+                    tokens: quota.burst,
+                    updated: now,
+                },
+            );
+        }
+        // Makes the bucket the one used most recently.
+        let bucket = buckets.get_mut(key).expect("the bucket was just added");
+        bucket.tokens = bucket.tokens_at(quota, now);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / quota.rate))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+    use crate::http::Body;
+
+    /// Two requests at once, then one every 2s.
+    const QUOTA: Quota = Quota {
+        burst: 2.0,
+        rate: 0.5,
+    };
+
+    #[test]
+    fn allows_bursts_and_refills() {
+        let store = MemoryStore::new();
+        let start = Instant::now();
+
+        assert_eq!(store.acquire("a", QUOTA, start), Ok(()));
+        assert_eq!(store.acquire("a", QUOTA, start), Ok(()));
+        assert_eq!(
+            store.acquire("a", QUOTA, start),
+            Err(Duration::from_secs(2))
+        );
+        // Every key has a bucket of its own.
+        assert_eq!(store.acquire("b", QUOTA, start), Ok(()));
+
+        let later = start + Duration::from_secs(2);
+        assert_eq!(store.acquire("a", QUOTA, later), Ok(()));
+        assert_eq!(
+            store.acquire("a", QUOTA, later),
+            Err(Duration::from_secs(2))
+        );
+
+        // A bucket never holds more than a burst.
+        let much_later = start + Duration::from_secs(60);
+        assert_eq!(store.acquire("a", QUOTA, much_later), Ok(()));
+        assert_eq!(store.acquire("a", QUOTA, much_later), Ok(()));
+        assert!(store.acquire("a", QUOTA, much_later).is_err());
+    }
+
+    #[test]
+    fn evicts_the_bucket_used_least_recently_when_full() {
+        let store = MemoryStore::with_max_keys(2);
+        let now = Instant::now();
+        for key in ["a", "b", "a"] {
+            store.acquire(key, QUOTA, now).unwrap();
+        }
+
+        // Takes the place of `b`.
+        store.acquire("c", QUOTA, now).unwrap();
+        assert_eq!(store.buckets.lock().unwrap().len(), 2);
+        // `a` kept its empty bucket, while `b` starts over with a full one,
+        // taking the place of `c`.
+        assert!(store.acquire("a", QUOTA, now).is_err());
+        store.acquire("b", QUOTA, now).unwrap();
+        store.acquire("b", QUOTA, now).unwrap();
+        assert!(!store.buckets.lock().unwrap().contains_key("c"));
+    }
+
+    #[tokio::test]
+    async fn answers_over_the_limit_with_retry_after() {
+        let app = RateLimitLayer::new(1, 0.4)
+            .key_by(|req| req.headers.get("x-api-key").map(|key| key.to_string()))
+            .layer(service_fn(|_req: Request| async {
+                Ok::<_, Infallible>(StatusCode::OK.into_response())
+            }));
+        let call = |key: Option<&str>| {
+            let mut req = Request::builder();
+            if let Some(key) = key {
+                req = req.header("x-api-key", key);
+            }
+            app.clone().oneshot(req.body(Body::empty()).unwrap())
+        };
+
+        assert_eq!(call(Some("a")).await.unwrap().status, StatusCode::OK);
+        let resp = call(Some("a")).await.unwrap();
+        assert_eq!(resp.status, StatusCode::TOO_MANY_REQUESTS);
+        // 2.5s, rounded up.
+        assert_eq!(resp.headers.get(RETRY_AFTER).unwrap(), "3");
+        assert_eq!(call(Some("b")).await.unwrap().status, StatusCode::OK);
+
+        // Requests without a key aren't limited.
+        for _ in 0..3 {
+            assert_eq!(call(None).await.unwrap().status, StatusCode::OK);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use anyhow::Error;
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use tower::{Layer, Service};
+
+use crate::{
+    extract::rejection::LengthLimitError,
+    http::{Body, Request, Response},
+    response::IntoResponse,
+};
+
+/// Caps the size of request bodies, whoever reads them.
+///
+/// A request whose `Content-Length` is over the limit is answered with a
+/// 413 straight away, so its body isn't read at all, and a client waiting
+/// for `100 Continue` doesn't send it. A body of unknown length, e.g. a
+/// chunked one, fails with a [`LengthLimitError`] as soon as it goes over,
+/// which the body extractors turn into a 413 too:
+///
+/// ```
+/// # use part1_app_factory::{middleware::RequestBodyLimitLayer, router::{post, Router}};
+/// # async fn upload(_body: Vec<u8>) {}
+/// let app = Router::new()
+///     .route("/upload", post(upload))
+///     .layer(RequestBodyLimitLayer::new(8 * 1024 * 1024));
+/// ```
+///
+/// Unlike [`DefaultBodyLimit`](crate::extract::DefaultBodyLimit), which only
+/// the body extractors heed, this also holds for handlers and layers that
+/// read the body as a stream.
+#[derive(Clone, Copy, Debug)]
+pub struct RequestBodyLimitLayer {
+    limit: usize,
+}
+
+impl RequestBodyLimitLayer {
+    /// Accepts bodies of up to `limit` bytes.
+    pub fn new(limit: usize) -> Self {
+        RequestBodyLimitLayer { limit }
+    }
+}
+
+impl<S> Layer<S> for RequestBodyLimitLayer {
+    type Service = RequestBodyLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestBodyLimit {
+            inner,
+            limit: self.limit,
+        }
+    }
+}
+
+/// A service limiting the size of request bodies, made by
+/// [`RequestBodyLimitLayer`].
+#[derive(Clone, Debug)]
+pub struct RequestBodyLimit<S> {
+    inner: S,
+    limit: usize,
+}
+
+impl<S> Service<Request> for RequestBodyLimit<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        let limit = self.limit;
+        let size_hint = req.body.size_hint();
+        if size_hint.lower() > limit as u64 {
+            let resp = LengthLimitError::new(limit).into_response();
+            return Box::pin(async move { Ok(resp) });
+        }
+        // Bodies known to fit are left as they are.
+        if size_hint.upper().is_none_or(|upper| upper > limit as u64) {
+            let mut body = std::mem::take(&mut req.body);
+            let trailers = body.take_trailers();
+            let mut limited = Body::from_stream(Limited {
+                body,
+                remaining: limit,
+                limit,
+                done: false,
+            });
+            if let Some(trailers) = trailers {
+                limited = limited.with_trailers(trailers);
+            }
+            req.body = limited;
+        }
+        Box::pin(self.inner.call(req))
+    }
+}
+
+/// A request body that fails once it's longer than the limit.
+struct Limited {
+    body: Body,
+    /// How many more bytes it may have.
+    remaining: usize,
+    limit: usize,
+    done: bool,
+}
+
+impl Stream for Limited {
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if this.done {
+            return Poll::Ready(None);
+        }
+        match std::task::ready!(this.body.poll_next_unpin(cx)) {
+            Some(Ok(chunk)) if chunk.len() > this.remaining => {
+                this.done = true;
+                Poll::Ready(Some(Err(LengthLimitError::new(this.limit).into())))
+            }
+            Some(Ok(chunk)) => {
+                this.remaining -= chunk.len();
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Some(Err(e)) => Poll::Ready(Some(Err(e))),
+            None => {
+                this.done = true;
+                Poll::Ready(None)
+            }
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use tower::{Layer, Service};
+
+use crate::{
+    extract::{rejection::MissingRequestId, BoxFuture, FromRequestParts},
+    http::{HeaderName, HeaderValue, Parts, Request, Response},
+};
+
+type GenerateFn = dyn Fn() -> String + Send + Sync;
+
+/// Gives every request an ID to correlate what's logged about it, across
+/// services too.
+///
+/// The ID the client or a proxy in front sent in `X-Request-Id` is kept,
+/// and others get a random one. Either way it's attached to the request as a
+/// [`RequestId`] and sent back in the response:
+///
+/// ```
+/// # use part1_app_factory::{
+/// #     middleware::{AccessLogLayer, JsonLogFormat, RequestId, RequestIdLayer},
+/// #     router::{get, Router},
+/// # };
+/// let app = Router::new()
+///     .route("/", get(|id: RequestId| async move { format!("Request {}", id) }))
+///     .layer(AccessLogLayer::new().format(JsonLogFormat))
+///     .layer(RequestIdLayer::new());
+/// ```
+///
+/// Layers see the ID if they're inside this one, i.e. added before it, as
+/// the [`AccessLogLayer`](super::AccessLogLayer) is above.
+#[derive(Clone)]
+pub struct RequestIdLayer {
+    header: HeaderName,
+    generate: Arc<GenerateFn>,
+}
+
+impl RequestIdLayer {
+    pub fn new() -> Self {
+        RequestIdLayer {
+            header: HeaderName::from_static("x-request-id"),
+            generate: Arc::new(random_id),
+        }
+    }
+
+    /// Reads and sends the ID in `header` instead of `X-Request-Id`.
+    pub fn header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Makes the IDs of requests without one with `generate`.
+    pub fn generate<F>(mut self, generate: F) -> Self
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        self.generate = Arc::new(generate);
+        self
+    }
+
+    /// The ID sent with `req`, if it's sensible, or a new one.
+    fn id_for(&self, req: &Request) -> HeaderValue {
+        let sent = req
+            .headers
+            .get(&self.header)
+            .filter(|id| is_valid(id.as_str()));
+        if let Some(id) = sent {
+            return id.clone();
+        }
+        let id = (self.generate)();
+        id.parse()
+            .unwrap_or_else(|_| random_id().parse().expect("hex is a valid header value"))
+    }
+}
+
+/// 1 to 128 visible ASCII characters, so an ID sent by a client can't fill
+/// logs or break their format.
+fn is_valid(id: &str) -> bool {
+    (1..=128).contains(&id.len()) && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// 32 random hex digits, laid out like a UUID.
+fn random_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let mut halves = [0u64; 2];
+    for half in &mut halves {
+        // Every `RandomState` is keyed differently.
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(count);
+        *half = hasher.finish();
+    }
+    let [high, low] = halves;
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        high >> 32,
+        (high >> 16) & 0xffff,
+        high & 0xffff,
+        low >> 48,
+        low & 0xffff_ffff_ffff
+    )
+}
+
+impl Default for RequestIdLayer {
+    fn default() -> Self {
+        RequestIdLayer::new()
+    }
+}
+
+impl fmt::Debug for RequestIdLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestIdLayer")
+            .field("header", &self.header)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> Layer<S> for RequestIdLayer {
+    type Service = SetRequestId<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SetRequestId {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// A service giving requests IDs, made by [`RequestIdLayer`].
+#[derive(Clone, Debug)]
+pub struct SetRequestId<S> {
+    inner: S,
+    layer: RequestIdLayer,
+}
+
+impl<S> Service<Request> for SetRequestId<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        let id = self.layer.id_for(&req);
+        req.headers.insert(self.layer.header.clone(), id.clone());
+        req.extensions.insert(RequestId(id.clone()));
+
+        let future = self.inner.call(req);
+        let header = self.layer.header.clone();
+        Box::pin(async move {
+            let mut resp = future.await?;
+            if !resp.headers.contains_key(&header) {
+                resp.headers.insert(header, id);
+            }
+            Ok(resp)
+        })
+    }
+}
+
+/// The ID of a request, attached by [`RequestIdLayer`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(HeaderValue);
+
+impl RequestId {
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromRequestParts for RequestId {
+    type Rejection = MissingRequestId;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>> {
+        let id = parts.extensions.get::<RequestId>().cloned();
+        Box::pin(async move { id.ok_or(MissingRequestId) })
+    }
+}
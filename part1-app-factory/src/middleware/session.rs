@@ -0,0 +1,616 @@
+use std::{
+    any::type_name,
+    collections::HashMap,
+    fmt::{self, Write},
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Error;
+use serde::{de::DeserializeOwned, Serialize};
+use tower::{Layer, Service};
+use tracing::{error, warn};
+
+use crate::{
+    extract::{rejection::MissingSession, BoxFuture, FromRequestParts},
+    http::{
+        cookie::{parse_cookie_header, SameSite, SetCookie},
+        header::COOKIE,
+        Parts, Request, Response, StatusCode,
+    },
+    response::{append_header, IntoResponse},
+};
+
+/// Gives every client a session, data of type `T` kept in a
+/// [`SessionStore`] between its requests and found by the ID in a cookie.
+///
+/// Handlers read and change it through the [`Session`] extractor:
+///
+/// ```
+/// # use part1_app_factory::{
+/// #     extract::Path,
+/// #     middleware::{MemorySessionStore, Session, SessionLayer},
+/// #     router::{post, Router},
+/// # };
+/// # use serde::{Deserialize, Serialize};
+/// #[derive(Clone, Default, Serialize, Deserialize)]
+/// struct Cart {
+///     items: Vec<u64>,
+/// }
+///
+/// async fn add_to_cart(session: Session<Cart>, Path(item): Path<u64>) -> String {
+///     session.update(|cart| cart.items.push(item));
+///     format!("{} items in your cart", session.get().items.len())
+/// }
+///
+/// let app = Router::new()
+///     .route("/cart/:item", post(add_to_cart))
+///     .layer(SessionLayer::<Cart>::new(MemorySessionStore::new()));
+/// ```
+///
+/// A session is saved, and its cookie set, only once a handler changed it,
+/// so clients that never log in or fill a cart don't fill the store either.
+/// Sessions expire a day after they were last saved unless
+/// [`ttl`](SessionLayer::ttl) says otherwise, and the cookie is `HttpOnly`,
+/// `SameSite=Lax` and `Secure`. A failing store is answered with a 500.
+pub struct SessionLayer<T> {
+    store: Arc<dyn SessionStore>,
+    cookie_name: Arc<str>,
+    ttl: Duration,
+    secure: bool,
+    _data: PhantomData<fn() -> T>,
+}
+
+impl<T> SessionLayer<T> {
+    pub fn new(store: impl SessionStore) -> Self {
+        SessionLayer {
+            store: Arc::new(store),
+            cookie_name: "session".into(),
+            ttl: Duration::from_secs(24 * 60 * 60),
+            secure: true,
+            _data: PhantomData,
+        }
+    }
+
+    /// Names the session cookie `name`, `session` by default.
+    pub fn cookie_name(mut self, name: &str) -> Self {
+        self.cookie_name = name.into();
+        self
+    }
+
+    /// Keeps sessions for `ttl` after they were last saved.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Whether the cookie is only sent over HTTPS. Turn it off to serve
+    /// sessions over plain HTTP, e.g. in development.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    fn cookie(&self, id: &str) -> SetCookie {
+        SetCookie::new(&*self.cookie_name, id)
+            .with_path("/")
+            .with_max_age(self.ttl)
+            .with_http_only(true)
+            .with_same_site(SameSite::Lax)
+            .with_secure(self.secure)
+    }
+}
+
+impl<T> Clone for SessionLayer<T> {
+    fn clone(&self) -> Self {
+        SessionLayer {
+            store: self.store.clone(),
+            cookie_name: self.cookie_name.clone(),
+            ttl: self.ttl,
+            secure: self.secure,
+            _data: PhantomData,
+        }
+    }
+}
+
+impl<T> fmt::Debug for SessionLayer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionLayer")
+            .field("data", &type_name::<T>())
+            .field("cookie_name", &self.cookie_name)
+            .field("ttl", &self.ttl)
+            .field("secure", &self.secure)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S, T> Layer<S> for SessionLayer<T> {
+    type Service = SessionManager<S, T>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SessionManager {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// A service loading and saving the sessions of requests, made by
+/// [`SessionLayer`].
+pub struct SessionManager<S, T> {
+    inner: S,
+    layer: SessionLayer<T>,
+}
+
+impl<S: Clone, T> Clone for SessionManager<S, T> {
+    fn clone(&self) -> Self {
+        SessionManager {
+            inner: self.inner.clone(),
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+impl<S: fmt::Debug, T> fmt::Debug for SessionManager<S, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionManager")
+            .field("inner", &self.inner)
+            .field("layer", &self.layer)
+            .finish()
+    }
+}
+
+impl<S, T> Service<Request> for SessionManager<S, T>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    T: Serialize + DeserializeOwned + Default + Clone + Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        let id = req
+            .headers
+            .get_all(COOKIE)
+            .flat_map(|value| parse_cookie_header(value.as_str()))
+            .find(|cookie| cookie.name() == &*self.layer.cookie_name)
+            .map(|cookie| cookie.value().to_owned());
+
+        // The clone that was made ready goes with the request, leaving a
+        // fresh one for the next.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+        Box::pin(async move {
+            let record = match load::<T>(&*layer.store, id).await {
+                Ok(record) => record,
+                Err(e) => {
+                    error!(error = ?e, "Failed to load a session");
+                    return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+                }
+            };
+            let session = Session(Arc::new(Mutex::new(record)));
+            req.extensions.insert(session.clone());
+
+            let mut resp = inner.call(req).await?;
+            // Handlers may have kept a clone, but they're done with it.
+            let record = std::mem::take(&mut *session.lock());
+            if let Err(e) = save(&layer, record, &mut resp).await {
+                error!(error = ?e, "Failed to save a session");
+                return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+            }
+            Ok(resp)
+        })
+    }
+}
+
+/// The session with ID `id` if the store has it, or a new one.
+async fn load<T>(store: &dyn SessionStore, id: Option<String>) -> Result<Record<T>, Error>
+where
+    T: DeserializeOwned + Default,
+{
+    let Some(id) = id else {
+        return Ok(Record::default());
+    };
+    let Some(data) = store.load(&id).await? else {
+        return Ok(Record::default());
+    };
+    match serde_json::from_str(&data) {
+        Ok(data) => Ok(Record {
+            id: Some(id),
+            data,
+            ..Record::default()
+        }),
+        // E.g. saved before `T` changed shape, which shouldn't lock the
+        // client out.
+        Err(e) => {
+            warn!(error = ?e, "Dropping a session that doesn't deserialize");
+            store.delete(&id).await?;
+            Ok(Record::default())
+        }
+    }
+}
+
+/// Saves or deletes the session as the handler left it, telling the client
+/// its ID.
+async fn save<T>(
+    layer: &SessionLayer<T>,
+    record: Record<T>,
+    resp: &mut Response,
+) -> Result<(), Error>
+where
+    T: Serialize,
+{
+    let store = &*layer.store;
+    if record.destroyed {
+        if let Some(id) = &record.id {
+            store.delete(id).await?;
+            let removal = SetCookie::removal(&*layer.cookie_name).with_path("/");
+            append_header(resp, "Set-Cookie", &removal.to_string());
+        }
+        return Ok(());
+    }
+    if !record.changed {
+        return Ok(());
+    }
+
+    let id = match record.id {
+        Some(id) if !record.renew => id,
+        old => {
+            if let Some(old) = old {
+                store.delete(&old).await?;
+            }
+            random_id()
+        }
+    };
+    let data = serde_json::to_string(&record.data)?;
+    store.save(&id, data, SystemTime::now() + layer.ttl).await?;
+    append_header(resp, "Set-Cookie", &layer.cookie(&id).to_string());
+    Ok(())
+}
+
+/// 256 random bits from the operating system's generator as hex digits,
+/// too many to guess.
+pub(super) fn random_id() -> String {
+    let mut bytes = [0; 32];
+    getrandom::getrandom(&mut bytes).expect("the OS has a random number generator");
+    let mut id = String::with_capacity(64);
+    for byte in &bytes {
+        let _ = write!(id, "{:02x}", byte);
+    }
+    id
+}
+
+/// Extractor for the session of the client, loaded by a [`SessionLayer`]
+/// for the same `T`.
+///
+/// Changes are saved once the response is ready. Clones share the session.
+pub struct Session<T>(Arc<Mutex<Record<T>>>);
+
+#[derive(Debug)]
+struct Record<T> {
+    /// The ID the session was loaded with, unless it's new.
+    id: Option<String>,
+    data: T,
+    changed: bool,
+    /// Saves it under a new ID.
+    renew: bool,
+    destroyed: bool,
+}
+
+impl<T: Default> Default for Record<T> {
+    fn default() -> Self {
+        Record {
+            id: None,
+            data: T::default(),
+            changed: false,
+            renew: false,
+            destroyed: false,
+        }
+    }
+}
+
+impl<T> Session<T> {
+    /// A copy of the data.
+    pub fn get(&self) -> T
+    where
+        T: Clone,
+    {
+        self.lock().data.clone()
+    }
+
+    /// Replaces the data.
+    pub fn set(&self, data: T) {
+        self.update(|old| *old = data);
+    }
+
+    /// Changes the data with `f`.
+    pub fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let mut record = self.lock();
+        record.changed = true;
+        record.destroyed = false;
+        f(&mut record.data)
+    }
+
+    /// Saves the session under a new ID, so an ID an attacker planted
+    /// before the client logged in is no good after. Call it on login.
+    pub fn renew(&self) {
+        let mut record = self.lock();
+        record.changed = true;
+        record.renew = true;
+    }
+
+    /// Deletes the session and its cookie, e.g. on logout.
+    pub fn destroy(&self) {
+        self.lock().destroyed = true;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Record<T>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<T> Clone for Session<T> {
+    fn clone(&self) -> Self {
+        Session(self.0.clone())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Session<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Session").field(&self.lock().data).finish()
+    }
+}
+
+impl<T> FromRequestParts for Session<T>
+where
+    T: Send + 'static,
+{
+    type Rejection = MissingSession;
+
+    fn from_request_parts(parts: &mut Parts) -> BoxFuture<'_, Result<Self, Self::Rejection>> {
+        let session = parts.extensions.get::<Session<T>>().cloned();
+        Box::pin(async move {
+            session.ok_or(MissingSession {
+                type_name: type_name::<T>(),
+            })
+        })
+    }
+}
+
+/// Where a [`SessionLayer`] keeps sessions, as JSON, by their IDs.
+///
+/// Implement it to keep them in a database or cache, so they outlive the
+/// process and are shared by all of its instances.
+pub trait SessionStore: Send + Sync + 'static {
+    /// The session with ID `id`, unless there's none or it has expired.
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<String>, Error>>;
+
+    /// Saves `data` as the session with ID `id`, until `expires`.
+    fn save<'a>(
+        &'a self,
+        id: &'a str,
+        data: String,
+        expires: SystemTime,
+    ) -> BoxFuture<'a, Result<(), Error>>;
+
+    fn delete<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), Error>>;
+}
+
+impl<T> SessionStore for Arc<T>
+where
+    T: SessionStore + ?Sized,
+{
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<String>, Error>> {
+        (**self).load(id)
+    }
+
+    fn save<'a>(
+        &'a self,
+        id: &'a str,
+        data: String,
+        expires: SystemTime,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        (**self).save(id, data, expires)
+    }
+
+    fn delete<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        (**self).delete(id)
+    }
+}
+
+/// Keeps sessions in memory, for a single process. They're lost when it
+/// exits.
+///
+/// Expired sessions are dropped when they're next asked for, and all at
+/// once at most every minute, when a session is saved.
+#[derive(Debug)]
+pub struct MemorySessionStore {
+    sessions: Mutex<Entries>,
+}
+
+#[derive(Debug)]
+struct Entries {
+    map: HashMap<String, (String, SystemTime)>,
+    purged: SystemTime,
+}
+
+impl MemorySessionStore {
+    pub fn new() -> Self {
+        MemorySessionStore {
+            sessions: Mutex::new(Entries {
+                map: HashMap::new(),
+                purged: SystemTime::now(),
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Entries> {
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for MemorySessionStore {
+    fn default() -> Self {
+        MemorySessionStore::new()
+    }
+}
+
+impl SessionStore for MemorySessionStore {
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<String>, Error>> {
+        let mut sessions = self.lock();
+        let data = match sessions.map.get(id) {
+            Some((_, expires)) if *expires <= SystemTime::now() => {
+                sessions.map.remove(id);
+                None
+            }
+            Some((data, _)) => Some(data.clone()),
+            None => None,
+        };
+        Box::pin(async move { Ok(data) })
+    }
+
+    fn save<'a>(
+        &'a self,
+        id: &'a str,
+        data: String,
+        expires: SystemTime,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        let now = SystemTime::now();
+        let mut sessions = self.lock();
+        if sessions.purged + Duration::from_secs(60) <= now {
+            sessions.map.retain(|_, (_, expires)| *expires > now);
+            sessions.purged = now;
+        }
+        sessions.map.insert(id.to_owned(), (data, expires));
+        Box::pin(async { Ok(()) })
+    }
+
+    fn delete<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        self.lock().map.remove(id);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+    use crate::http::Body;
+
+    /// Changes the session as the path says and answers with its data.
+    async fn send(layer: &SessionLayer<Vec<u32>>, path: &str, cookie: Option<&str>) -> Response {
+        let app = service_fn(|req: Request| async move {
+            let session = req.extensions.get::<Session<Vec<u32>>>().unwrap();
+            match req.uri.path() {
+                "/add" => session.update(|items| items.push(1)),
+                "/login" => session.renew(),
+                "/logout" => session.destroy(),
+                _ => {}
+            }
+            Ok::<_, Infallible>(format!("{:?}", session.get()).into_response())
+        });
+        let mut builder = Request::builder().uri(path);
+        if let Some(cookie) = cookie {
+            builder = builder.header("cookie", cookie);
+        }
+        let req = builder.body(Body::empty()).unwrap();
+        layer.clone().layer(app).oneshot(req).await.unwrap()
+    }
+
+    /// The `name=value` part of the cookie the response sets.
+    fn cookie(resp: &Response) -> Option<String> {
+        let value = resp.headers.get("set-cookie")?.as_str();
+        Some(value.split(';').next().unwrap().to_owned())
+    }
+
+    async fn body(resp: Response) -> String {
+        String::from_utf8(resp.body.collect().await.unwrap().to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn saves_changed_sessions_only() {
+        let layer = SessionLayer::new(MemorySessionStore::new());
+
+        let resp = send(&layer, "/", None).await;
+        assert!(cookie(&resp).is_none());
+
+        let resp = send(&layer, "/add", None).await;
+        let set = resp.headers.get("set-cookie").unwrap().as_str().to_owned();
+        assert!(set.contains("HttpOnly") && set.contains("Max-Age=86400"));
+        let session = cookie(&resp).unwrap();
+        assert_eq!(session.len(), "session=".len() + 64);
+
+        let resp = send(&layer, "/add", Some(&session)).await;
+        assert_eq!(cookie(&resp).unwrap(), session);
+        assert_eq!(body(resp).await, "[1, 1]");
+
+        let resp = send(&layer, "/", Some(&session)).await;
+        assert!(cookie(&resp).is_none());
+        assert_eq!(body(resp).await, "[1, 1]");
+    }
+
+    #[tokio::test]
+    async fn starts_over_with_unknown_ids() {
+        let layer = SessionLayer::new(MemorySessionStore::new());
+        let resp = send(&layer, "/", Some("session=made-up")).await;
+        assert_eq!(body(resp).await, "[]");
+    }
+
+    #[tokio::test]
+    async fn renews_ids() {
+        let layer = SessionLayer::new(MemorySessionStore::new());
+        let old = cookie(&send(&layer, "/add", None).await).unwrap();
+
+        let resp = send(&layer, "/login", Some(&old)).await;
+        let new = cookie(&resp).unwrap();
+        assert_ne!(new, old);
+        assert_eq!(body(resp).await, "[1]");
+
+        assert_eq!(body(send(&layer, "/", Some(&old)).await).await, "[]");
+        assert_eq!(body(send(&layer, "/", Some(&new)).await).await, "[1]");
+    }
+
+    #[tokio::test]
+    async fn destroys_sessions() {
+        let layer = SessionLayer::new(MemorySessionStore::new());
+        let session = cookie(&send(&layer, "/add", None).await).unwrap();
+
+        let resp = send(&layer, "/logout", Some(&session)).await;
+        assert_eq!(cookie(&resp).unwrap(), "session=");
+        assert_eq!(body(send(&layer, "/", Some(&session)).await).await, "[]");
+    }
+
+    #[tokio::test]
+    async fn expires_sessions() {
+        let store = MemorySessionStore::new();
+        let past = SystemTime::now() - Duration::from_secs(1);
+        store.save("old", "[1]".to_owned(), past).await.unwrap();
+        assert!(store.load("old").await.unwrap().is_none());
+
+        let layer = SessionLayer::new(store).ttl(Duration::ZERO);
+        let session = cookie(&send(&layer, "/add", None).await).unwrap();
+        assert_eq!(body(send(&layer, "/", Some(&session)).await).await, "[]");
+    }
+
+    #[test]
+    fn random_ids_are_long_and_unique() {
+        let (a, b) = (random_id(), random_id());
+        assert_eq!(a.len(), 64);
+        assert!(a.bytes().all(|b| b.is_ascii_hexdigit()));
+        assert_ne!(a, b);
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tower::{Layer, Service};
+
+use crate::{
+    http::{Request, Response, StatusCode},
+    response::IntoResponse,
+};
+
+/// Answers requests the app takes longer than a set time to respond to with
+/// a timeout response, dropping the handler.
+///
+/// The response is an empty 408 by default. A 504 suits apps that time out
+/// waiting for services of their own:
+///
+/// ```
+/// # use std::time::Duration;
+/// # use part1_app_factory::{
+/// #     http::StatusCode,
+/// #     middleware::TimeoutLayer,
+/// #     router::{get, Router},
+/// # };
+/// # async fn report() {}
+/// let app = Router::new()
+///     .route("/report", get(report))
+///     .layer(TimeoutLayer::new(Duration::from_secs(10)).status(StatusCode::GATEWAY_TIMEOUT));
+/// ```
+///
+/// Only the time until the response head is limited, not the time it takes
+/// to send a streamed body.
+#[derive(Clone, Copy, Debug)]
+pub struct TimeoutLayer {
+    timeout: Duration,
+    status: StatusCode,
+}
+
+impl TimeoutLayer {
+    pub fn new(timeout: Duration) -> Self {
+        TimeoutLayer {
+            timeout,
+            status: StatusCode::REQUEST_TIMEOUT,
+        }
+    }
+
+    /// Answers requests that time out with `status` instead of a 408.
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+}
+
+impl<S> Layer<S> for TimeoutLayer {
+    type Service = Timeout<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Timeout {
+            inner,
+            layer: *self,
+        }
+    }
+}
+
+/// A service limiting how long requests take, made by [`TimeoutLayer`].
+#[derive(Clone, Debug)]
+pub struct Timeout<S> {
+    inner: S,
+    layer: TimeoutLayer,
+}
+
+impl<S> Service<Request> for Timeout<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let future = self.inner.call(req);
+        let TimeoutLayer { timeout, status } = self.layer;
+        Box::pin(async move {
+            match tokio::time::timeout(timeout, future).await {
+                Ok(result) => result,
+                Err(_) => Ok(status.into_response()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{anyhow, Error};
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+    use crate::http::Method;
+
+    fn request(method: Method, uri: &str, headers: &[(&str, &str)], body: &'static str) -> Request {
+        let mut req = Request::builder().method(method).uri(uri);
+        for &(name, value) in headers {
+            req = req.header(name, value);
+        }
+        req.body(body).unwrap()
+    }
+
+    /// The status and body of `resp`.
+    async fn respond(resp: Response) -> (u16, String) {
+        let body = resp.body.collect().await.unwrap();
+        (
+            resp.status.as_u16(),
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    /// Sends a request through `layer` to an app that never answers.
+    async fn stall(layer: TimeoutLayer) -> Response {
+        let app = service_fn(|_: Request| std::future::pending::<Result<Response, Error>>());
+        let req = request(Method::Get, "/slow", &[], "");
+        layer.layer(app).oneshot(req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn passes_through_what_answers_in_time() {
+        let layer = TimeoutLayer::new(Duration::from_secs(10));
+        let app = service_fn(|_: Request| async { Ok::<_, Error>("done".into_response()) });
+        let resp = layer
+            .layer(app)
+            .oneshot(request(Method::Get, "/", &[], ""))
+            .await
+            .unwrap();
+        assert_eq!(respond(resp).await, (200, "done".to_owned()));
+
+        let app = service_fn(|_: Request| async { Err::<Response, _>(anyhow!("boom")) });
+        let error = layer
+            .layer(app)
+            .oneshot(request(Method::Get, "/", &[], ""))
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "boom");
+    }
+
+    #[tokio::test]
+    async fn answers_slow_requests_with_408() {
+        let resp = stall(TimeoutLayer::new(Duration::from_millis(10))).await;
+        assert_eq!(respond(resp).await, (408, String::new()));
+    }
+
+    #[tokio::test]
+    async fn answers_with_the_status_given() {
+        let layer =
+            TimeoutLayer::new(Duration::from_millis(10)).status(StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(respond(stall(layer).await).await.0, 504);
+    }
+}
@@ -0,0 +1,107 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use tower::{Layer, Service};
+use tracing::{error, field, info, info_span, Instrument};
+
+use super::RequestId;
+use crate::{
+    http::{Request, Response},
+    router::OriginalUri,
+};
+
+/// Runs every request in a `request` [`tracing`] span, with its method, path
+/// and [`RequestId`] if it has one, and records an event once it's answered.
+///
+/// The span is made within the connection's, so the events of a request,
+/// including those of the handlers and the layers inside this one, show
+/// the connection it came on, e.g. with `tracing_subscriber`'s format:
+///
+/// ```text
+/// ... INFO conn{id=3 peer=127.0.0.1:51234}:request{method=GET path=/users}:
+///     part1_app_factory::middleware::trace: Finished status=200 latency=1.2ms
+/// ```
+///
+/// Errors and 5xx responses are recorded at
+/// [`Level::ERROR`](tracing::Level::ERROR).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TraceLayer {
+    _priv: (),
+}
+
+impl TraceLayer {
+    pub fn new() -> Self {
+        TraceLayer { _priv: () }
+    }
+}
+
+impl<S> Layer<S> for TraceLayer {
+    type Service = Trace<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Trace { inner }
+    }
+}
+
+/// A service tracing the requests it passes on, made by [`TraceLayer`].
+#[derive(Clone, Debug)]
+pub struct Trace<S> {
+    inner: S,
+}
+
+impl<S> Service<Request> for Trace<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Error: fmt::Debug,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        // The path as sent, not as left by routers it's nested in.
+        let path = match req.extensions.get::<OriginalUri>() {
+            Some(OriginalUri(uri)) => uri.path(),
+            None => req.uri.path(),
+        };
+        let span = info_span!(
+            "request",
+            method = %req.method,
+            path = %path,
+            request_id = field::Empty,
+        );
+        if let Some(id) = req.extensions.get::<RequestId>() {
+            span.record("request_id", field::display(id));
+        }
+
+        let start = Instant::now();
+        let future = span.in_scope(|| self.inner.call(req));
+        Box::pin(
+            async move {
+                let result = future.await;
+                let latency = start.elapsed();
+                match &result {
+                    Ok(resp) if resp.status.is_server_error() => {
+                        error!(status = resp.status.as_u16(), latency = ?latency, "Finished")
+                    }
+                    Ok(resp) => {
+                        info!(status = resp.status.as_u16(), latency = ?latency, "Finished")
+                    }
+                    Err(e) => error!(error = ?e, latency = ?latency, "Failed"),
+                }
+                result
+            }
+            .instrument(span),
+        )
+    }
+}
@@ -0,0 +1,429 @@
+use std::{borrow::Cow, convert::Infallible};
+
+use bytes::Bytes;
+
+use crate::http::{
+    encoding, header::CONTENT_TYPE, Body, Extensions, HeaderMap, HeaderName, HeaderValue,
+    InvalidHeader, Response, StatusCode, Version,
+};
+
+pub use self::{file::File, negotiate::Negotiate, problem::Problem};
+
+mod file;
+mod negotiate;
+mod problem;
+
+/// Converts a handler's return value into a response.
+///
+/// Handlers can return anything implementing this: text, bytes, JSON via
+/// [`Json`](crate::json::Json), a `Result` whose error is a response too, or a
+/// hand built [`Response`] when nothing else fits.
+pub trait IntoResponse {
+    fn into_response(self) -> Response;
+}
+
+/// Something that adds to a response without being one, such as a set of
+/// headers or a [`CookieJar`](crate::extract::CookieJar).
+///
+/// Returned in a tuple before the body, optionally after a [`StatusCode`]:
+///
+/// ```
+/// # use part1_app_factory::{http::StatusCode, json::Json, router::post};
+/// # #[derive(serde::Serialize)]
+/// # struct User {
+/// #     id: u64,
+/// # }
+/// async fn create() -> (StatusCode, [(&'static str, &'static str); 1], Json<User>) {
+///     (StatusCode::CREATED, [("Location", "/users/42")], Json(User { id: 42 }))
+/// }
+/// # let _ = post(create);
+/// ```
+///
+/// Parts are applied in order after the body was turned into a response, so
+/// they override headers the body set.
+pub trait IntoResponseParts {
+    fn into_response_parts(self, resp: &mut Response);
+}
+
+/// Headers, replacing any with the same name. An invalid name or value
+/// turns the response into a 500.
+impl<K, V, const N: usize> IntoResponseParts for [(K, V); N]
+where
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    fn into_response_parts(self, resp: &mut Response) {
+        for (name, value) in self {
+            insert_header(resp, name.as_ref(), value.as_ref());
+        }
+    }
+}
+
+/// Headers, replacing any with the same name. Names with several values in
+/// the map keep all of them.
+impl IntoResponseParts for HeaderMap {
+    fn into_response_parts(self, resp: &mut Response) {
+        for (name, _) in &self {
+            resp.headers.remove(name);
+        }
+        resp.headers.extend(self);
+    }
+}
+
+/// Headers to add to a response without replacing the ones it already has,
+/// unlike the arrays and maps that are also [`IntoResponseParts`].
+///
+/// ```
+/// # use part1_app_factory::{extract::CookieJar, response::AppendHeaders, router::get};
+/// async fn handler(jar: CookieJar) -> (AppendHeaders<[(&'static str, &'static str); 2]>, CookieJar) {
+///     (
+///         AppendHeaders([("Set-Cookie", "theme=dark"), ("Vary", "Cookie")]),
+///         jar,
+///     )
+/// }
+/// # let _ = get(handler);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct AppendHeaders<I>(pub I);
+
+impl<I, K, V> IntoResponseParts for AppendHeaders<I>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    fn into_response_parts(self, resp: &mut Response) {
+        for (name, value) in self.0 {
+            append_header(resp, name.as_ref(), value.as_ref());
+        }
+    }
+}
+
+/// An empty 200 response with the headers.
+impl<I, K, V> IntoResponse for AppendHeaders<I>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    fn into_response(self) -> Response {
+        (self, ()).into_response()
+    }
+}
+
+impl<R: IntoResponse> IntoResponse for (StatusCode, R) {
+    fn into_response(self) -> Response {
+        let mut resp = self.1.into_response();
+        resp.status = self.0;
+        resp
+    }
+}
+
+macro_rules! impl_into_response_for_tuple {
+    ( $($ty:ident),* ) => {
+        #[allow(non_snake_case)]
+        impl<$($ty,)* R> IntoResponse for ($($ty,)* R,)
+        where
+            $( $ty: IntoResponseParts, )*
+            R: IntoResponse,
+        {
+            fn into_response(self) -> Response {
+                let ($($ty,)* res,) = self;
+                let mut resp = res.into_response();
+                $( $ty.into_response_parts(&mut resp); )*
+                resp
+            }
+        }
+
+        #[allow(non_snake_case)]
+        impl<$($ty,)* R> IntoResponse for (StatusCode, $($ty,)* R,)
+        where
+            $( $ty: IntoResponseParts, )*
+            R: IntoResponse,
+        {
+            fn into_response(self) -> Response {
+                let (status, $($ty,)* res,) = self;
+                let mut resp = res.into_response();
+                $( $ty.into_response_parts(&mut resp); )*
+                resp.status = status;
+                resp
+            }
+        }
+    };
+}
+
+impl_into_response_for_tuple!(T1);
+impl_into_response_for_tuple!(T1, T2);
+impl_into_response_for_tuple!(T1, T2, T3);
+impl_into_response_for_tuple!(T1, T2, T3, T4);
+
+impl IntoResponse for Response {
+    fn into_response(self) -> Response {
+        self
+    }
+}
+
+impl IntoResponse for Infallible {
+    fn into_response(self) -> Response {
+        match self {}
+    }
+}
+
+/// Either response, so handlers can use `?` with errors that implement
+/// `IntoResponse`, rejections included.
+impl<T, E> IntoResponse for Result<T, E>
+where
+    T: IntoResponse,
+    E: IntoResponse,
+{
+    fn into_response(self) -> Response {
+        match self {
+            Ok(value) => value.into_response(),
+            Err(error) => error.into_response(),
+        }
+    }
+}
+
+/// An empty response with this status.
+impl IntoResponse for StatusCode {
+    fn into_response(self) -> Response {
+        let mut resp = ().into_response();
+        resp.status = self;
+        resp
+    }
+}
+
+/// An empty 200 response.
+impl IntoResponse for () {
+    fn into_response(self) -> Response {
+        Response {
+            status: StatusCode::OK,
+            version: Version::default(),
+            headers: HeaderMap::new(),
+            extensions: Extensions::default(),
+            body: Body::empty(),
+        }
+    }
+}
+
+impl IntoResponse for String {
+    fn into_response(self) -> Response {
+        with_content_type("text/plain; charset=utf-8", self)
+    }
+}
+
+impl IntoResponse for &'static str {
+    fn into_response(self) -> Response {
+        with_content_type("text/plain; charset=utf-8", self)
+    }
+}
+
+impl IntoResponse for Cow<'static, str> {
+    fn into_response(self) -> Response {
+        self.into_owned().into_response()
+    }
+}
+
+impl IntoResponse for Vec<u8> {
+    fn into_response(self) -> Response {
+        with_content_type("application/octet-stream", self)
+    }
+}
+
+/// A body without a content type, for streams the handler sets headers for
+/// itself.
+impl IntoResponse for Body {
+    fn into_response(self) -> Response {
+        Response {
+            status: StatusCode::OK,
+            version: Version::default(),
+            headers: HeaderMap::new(),
+            extensions: Extensions::default(),
+            body: self,
+        }
+    }
+}
+
+impl IntoResponse for Bytes {
+    fn into_response(self) -> Response {
+        with_content_type("application/octet-stream", self)
+    }
+}
+
+/// An HTML response, sent with `Content-Type: text/html; charset=utf-8`.
+///
+/// ```
+/// # use part1_app_factory::{response::Html, router::get};
+/// async fn index() -> Html<&'static str> {
+///     Html("<h1>Hello, World!</h1>")
+/// }
+/// # let _ = get(index);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Html<T>(pub T);
+
+impl<T: Into<Body>> IntoResponse for Html<T> {
+    fn into_response(self) -> Response {
+        with_content_type("text/html; charset=utf-8", self.0)
+    }
+}
+
+/// A response that sends the client somewhere else with a `Location`
+/// header.
+///
+/// ```
+/// # use part1_app_factory::{response::Redirect, router::get};
+/// async fn old_profile() -> Redirect {
+///     Redirect::permanent("/profile")
+/// }
+/// # let _ = get(old_profile);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Redirect {
+    status: StatusCode,
+    location: String,
+}
+
+impl Redirect {
+    /// 303 See Other. The client follows up with a `GET`, which makes this
+    /// the redirect to answer a form `POST` with.
+    pub fn to(uri: &str) -> Self {
+        Self::with_status(StatusCode::SEE_OTHER, uri)
+    }
+
+    /// 307 Temporary Redirect. The client repeats the request, method and
+    /// body included, at `uri`.
+    pub fn temporary(uri: &str) -> Self {
+        Self::with_status(StatusCode::TEMPORARY_REDIRECT, uri)
+    }
+
+    /// 308 Permanent Redirect. Like [`Redirect::temporary`], but clients and
+    /// caches may remember it.
+    pub fn permanent(uri: &str) -> Self {
+        Self::with_status(StatusCode::PERMANENT_REDIRECT, uri)
+    }
+
+    /// 302 Found. Older clients turn a `POST` into a `GET` when following it,
+    /// prefer [`Redirect::to`] or [`Redirect::temporary`] in new code.
+    pub fn found(uri: &str) -> Self {
+        Self::with_status(StatusCode::FOUND, uri)
+    }
+
+    /// 301 Moved Permanently. Older clients turn a `POST` into a `GET` when
+    /// following it, prefer [`Redirect::permanent`] in new code.
+    pub fn moved_permanently(uri: &str) -> Self {
+        Self::with_status(StatusCode::MOVED_PERMANENTLY, uri)
+    }
+
+    /// Characters that can't appear in a `Location`, such as spaces or
+    /// non-ASCII text, are percent-encoded.
+    fn with_status(status: StatusCode, uri: &str) -> Self {
+        Redirect {
+            status,
+            location: encoding::encode_uri(uri),
+        }
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn location(&self) -> &str {
+        &self.location
+    }
+}
+
+impl IntoResponse for Redirect {
+    fn into_response(self) -> Response {
+        let mut resp = self.status.into_response();
+        insert_header(&mut resp, "Location", &self.location);
+        resp
+    }
+}
+
+/// Adds `value` to the header `name`, keeping any values it already has. An
+/// invalid name or value turns the response into a 500.
+pub(crate) fn append_header(resp: &mut Response, name: &str, value: &str) {
+    match parse_header(name, value) {
+        Ok((name, value)) => resp.headers.append(name, value),
+        Err(error) => *resp = header_error(error),
+    }
+}
+
+fn insert_header(resp: &mut Response, name: &str, value: &str) {
+    match parse_header(name, value) {
+        Ok((name, value)) => {
+            resp.headers.insert(name, value);
+        }
+        Err(error) => *resp = header_error(error),
+    }
+}
+
+fn parse_header(name: &str, value: &str) -> Result<(HeaderName, HeaderValue), InvalidHeader> {
+    let invalid = || InvalidHeader::new(name);
+    Ok((
+        name.parse().map_err(|_| invalid())?,
+        value.parse().map_err(|_| invalid())?,
+    ))
+}
+
+fn header_error(error: InvalidHeader) -> Response {
+    (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()).into_response()
+}
+
+fn with_content_type(content_type: &'static str, body: impl Into<Body>) -> Response {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+
+    Response {
+        status: StatusCode::OK,
+        version: Version::default(),
+        headers,
+        extensions: Extensions::default(),
+        body: body.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The status and body of `resp`.
+    async fn respond(resp: Response) -> (u16, String) {
+        let body = resp.body.collect().await.unwrap();
+        (
+            resp.status.as_u16(),
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    fn header<'a>(resp: &'a Response, name: &str) -> Option<&'a str> {
+        resp.headers.get(name).map(|value| value.as_str())
+    }
+
+    #[tokio::test]
+    async fn redirects_with_the_status_of_each_kind() {
+        let redirects = [
+            (Redirect::to("/login"), 303),
+            (Redirect::temporary("/login"), 307),
+            (Redirect::permanent("/login"), 308),
+            (Redirect::found("/login"), 302),
+            (Redirect::moved_permanently("/login"), 301),
+        ];
+        for (redirect, status) in redirects {
+            assert_eq!(redirect.status_code().as_u16(), status);
+            assert_eq!(redirect.location(), "/login");
+
+            let resp = redirect.into_response();
+            assert_eq!(header(&resp, "Location"), Some("/login"));
+            assert_eq!(respond(resp).await, (status, String::new()));
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_the_location_as_given() {
+        let location = "https://example.com/search?q=ferris%20crab#results";
+        let resp = Redirect::to(location).into_response();
+        assert_eq!(header(&resp, "Location"), Some(location));
+    }
+}
@@ -0,0 +1,137 @@
+use std::{io, path::Path, time::SystemTime};
+
+use bytes::{Bytes, BytesMut};
+use futures_util::stream;
+use tokio::io::AsyncReadExt;
+
+use super::IntoResponse;
+use crate::http::{format_http_date, Body, Response, StatusCode};
+
+/// How much of the file is read per chunk.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A file on disk, streamed to the client in chunks.
+///
+/// The `Content-Type` is guessed from the file extension, and the
+/// `Content-Length` and `Last-Modified` are taken from the file's metadata.
+///
+/// ```
+/// # use part1_app_factory::{http::StatusCode, response::File, router::get};
+/// async fn report() -> Result<File, StatusCode> {
+///     let file = File::open("reports/latest.csv")
+///         .await
+///         .map_err(|_| StatusCode::NOT_FOUND)?;
+///     Ok(file.attachment("report.csv"))
+/// }
+/// # let _ = get(report);
+/// ```
+#[derive(Debug)]
+pub struct File {
+    file: tokio::fs::File,
+    len: u64,
+    modified: Option<SystemTime>,
+    content_type: String,
+    disposition: Option<String>,
+}
+
+impl File {
+    pub async fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = tokio::fs::File::open(path).await?;
+        let metadata = file.metadata().await?;
+
+        Ok(File {
+            file,
+            len: metadata.len(),
+            // Not every platform records it.
+            modified: metadata.modified().ok(),
+            content_type: content_type_for(path).to_owned(),
+            disposition: None,
+        })
+    }
+
+    /// Overrides the guessed content type.
+    pub fn with_content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = content_type.into();
+        self
+    }
+
+    /// Asks browsers to save the file as `filename` rather than display it.
+    pub fn attachment(mut self, filename: &str) -> Self {
+        let mut quoted = String::with_capacity(filename.len());
+        for c in filename.chars().filter(|c| !c.is_control()) {
+            if c == '"' || c == '\\' {
+                quoted.push('\\');
+            }
+            quoted.push(c);
+        }
+        self.disposition = Some(format!("attachment; filename=\"{}\"", quoted));
+        self
+    }
+}
+
+impl IntoResponse for File {
+    fn into_response(self) -> Response {
+        // Yields `None` as the next state after an error to end the body.
+        let chunks = stream::unfold(Some(self.file), |file| async move {
+            let mut file = file?;
+            let mut buf = BytesMut::with_capacity(CHUNK_SIZE);
+            match file.read_buf(&mut buf).await {
+                Ok(0) => None,
+                Ok(_) => Some((Ok::<Bytes, io::Error>(buf.freeze()), Some(file))),
+                Err(error) => Some((Err(error), None)),
+            }
+        });
+
+        let mut resp = Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", &self.content_type)
+            .header("Content-Length", &self.len.to_string());
+        if let Some(modified) = self.modified {
+            resp = resp.header("Last-Modified", &format_http_date(modified));
+        }
+        if let Some(disposition) = &self.disposition {
+            resp = resp.header("Content-Disposition", disposition);
+        }
+
+        match resp.body(Body::from_stream(chunks)) {
+            Ok(resp) => resp,
+            Err(error) => (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()).into_response(),
+        }
+    }
+}
+
+/// The media type for a file extension, `application/octet-stream` if it
+/// isn't a common one.
+fn content_type_for(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "txt" | "md" | "toml" | "rs" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "wasm" => "application/wasm",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "mp4" => "video/mp4",
+        "mp3" => "audio/mpeg",
+        _ => "application/octet-stream",
+    }
+}
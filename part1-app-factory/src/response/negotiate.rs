@@ -0,0 +1,230 @@
+use serde::Serialize;
+
+use super::{Html, IntoResponse};
+use crate::{
+    headers::Accept,
+    http::{header::VARY, HeaderValue, Response, StatusCode},
+    json::Json,
+};
+
+const JSON: &str = "application/json";
+const HTML: &str = "text/html";
+const PLAIN: &str = "text/plain";
+
+type Render<T> = Box<dyn FnOnce(&T) -> String + Send>;
+
+/// Responds with JSON, HTML or plain text, whichever the client's `Accept`
+/// header prefers.
+///
+/// JSON is always offered. HTML and plain text are offered once a way to
+/// render them is given. On a tie JSON wins, then HTML. A client accepting
+/// none of them gets a 406.
+///
+/// ```
+/// # use part1_app_factory::{
+/// #     extract::Path,
+/// #     headers::Accept,
+/// #     response::Negotiate,
+/// #     router::get,
+/// # };
+/// # #[derive(serde::Serialize)]
+/// # struct User {
+/// #     name: String,
+/// # }
+/// # fn find_user(id: u64) -> User {
+/// #     User { name: format!("user{}", id) }
+/// # }
+/// async fn show_user(accept: Accept, Path(id): Path<u64>) -> Negotiate<User> {
+///     Negotiate::new(accept, find_user(id))
+///         .html(|user| format!("<h1>{}</h1>", user.name))
+///         .text(|user| user.name.clone())
+/// }
+/// # let _ = get(show_user);
+/// ```
+///
+/// Responses carry `Vary: Accept` so caches keep the representations apart.
+pub struct Negotiate<T> {
+    accept: Accept,
+    value: T,
+    html: Option<Render<T>>,
+    text: Option<Render<T>>,
+}
+
+impl<T> Negotiate<T> {
+    pub fn new(accept: Accept, value: T) -> Self {
+        Negotiate {
+            accept,
+            value,
+            html: None,
+            text: None,
+        }
+    }
+
+    /// Offers HTML, rendered by `render`.
+    pub fn html<F>(mut self, render: F) -> Self
+    where
+        F: FnOnce(&T) -> String + Send + 'static,
+    {
+        self.html = Some(Box::new(render));
+        self
+    }
+
+    /// Offers plain text, rendered by `render`.
+    pub fn text<F>(mut self, render: F) -> Self
+    where
+        F: FnOnce(&T) -> String + Send + 'static,
+    {
+        self.text = Some(Box::new(render));
+        self
+    }
+}
+
+impl<T: Serialize> IntoResponse for Negotiate<T> {
+    fn into_response(self) -> Response {
+        let mut offered = vec![JSON];
+        if self.html.is_some() {
+            offered.push(HTML);
+        }
+        if self.text.is_some() {
+            offered.push(PLAIN);
+        }
+
+        let mut resp = match self.accept.preferred(&offered) {
+            Some(JSON) => Json(self.value).into_response(),
+            Some(HTML) => Html(rendered(self.html, &self.value)).into_response(),
+            Some(_) => rendered(self.text, &self.value).into_response(),
+            None => (
+                StatusCode::NOT_ACCEPTABLE,
+                format!("Available representations: {}", offered.join(", ")),
+            )
+                .into_response(),
+        };
+        resp.headers
+            .append(VARY, HeaderValue::from_static("accept"));
+        resp
+    }
+}
+
+/// Only called for representations that were offered, so `render` is set.
+fn rendered<T>(render: Option<Render<T>>, value: &T) -> String {
+    render.map(|render| render(value)).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::{
+        extract::FromRequestParts,
+        headers::Header,
+        http::{Method, Request},
+    };
+
+    fn request(method: Method, uri: &str, headers: &[(&str, &str)], body: &'static str) -> Request {
+        let mut req = Request::builder().method(method).uri(uri);
+        for &(name, value) in headers {
+            req = req.header(name, value);
+        }
+        req.body(body).unwrap()
+    }
+
+    /// The status and body of `resp`.
+    async fn respond(resp: Response) -> (u16, String) {
+        let body = resp.body.collect().await.unwrap();
+        (
+            resp.status.as_u16(),
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    fn header<'a>(resp: &'a Response, name: &str) -> Option<&'a str> {
+        resp.headers.get(name).map(|value| value.as_str())
+    }
+
+    /// Negotiates a user with the `Accept` header of a request, offering it
+    /// as HTML too.
+    async fn negotiate(accept: Option<&str>) -> Response {
+        let headers: Vec<_> = accept
+            .map(|accept| ("Accept", accept))
+            .into_iter()
+            .collect();
+        let (mut parts, _) = request(Method::Get, "/users/7", &headers, "").into_parts();
+        let accept = Accept::from_request_parts(&mut parts).await.unwrap();
+        Negotiate::new(accept, BTreeMap::from([("name", "ferris")]))
+            .html(|user| format!("<h1>{}</h1>", user["name"]))
+            .into_response()
+    }
+
+    #[tokio::test]
+    async fn responds_with_the_preferred_representation() {
+        let resp = negotiate(Some("text/html,application/xhtml+xml;q=0.9,*/*;q=0.8")).await;
+        assert_eq!(
+            header(&resp, "Content-Type"),
+            Some("text/html; charset=utf-8")
+        );
+        assert_eq!(header(&resp, "Vary"), Some("accept"));
+        assert_eq!(respond(resp).await, (200, "<h1>ferris</h1>".to_owned()));
+
+        let resp = negotiate(Some("application/json")).await;
+        assert_eq!(header(&resp, "Content-Type"), Some("application/json"));
+        assert_eq!(
+            respond(resp).await,
+            (200, r#"{"name":"ferris"}"#.to_owned())
+        );
+
+        // Without a preference the first offer, JSON, wins.
+        for accept in [None, Some("*/*"), Some("text/*;q=0.5, application/*;q=0.5")] {
+            let resp = negotiate(accept).await;
+            assert_eq!(header(&resp, "Content-Type"), Some("application/json"));
+        }
+        let resp = negotiate(Some("application/json;q=0, */*")).await;
+        assert_eq!(
+            header(&resp, "Content-Type"),
+            Some("text/html; charset=utf-8")
+        );
+    }
+
+    #[tokio::test]
+    async fn lists_what_is_available_when_nothing_is_acceptable() {
+        let resp = negotiate(Some("text/plain, image/*")).await;
+        assert_eq!(header(&resp, "Vary"), Some("accept"));
+        assert_eq!(
+            respond(resp).await,
+            (
+                406,
+                "Available representations: application/json, text/html".to_owned()
+            )
+        );
+
+        let resp = Negotiate::new(Accept::decode("text/plain").unwrap(), "ferris")
+            .text(|name| format!("Hello, {}!", name))
+            .into_response();
+        assert_eq!(respond(resp).await, (200, "Hello, ferris!".to_owned()));
+    }
+
+    #[test]
+    fn weighs_media_ranges() {
+        let accept = Accept::decode("text/*;q=0.5, text/html, */*;q=0.001").unwrap();
+        assert_eq!(accept.quality("text/html"), 1000);
+        assert_eq!(accept.quality("TEXT/Plain"), 500);
+        assert_eq!(accept.quality("image/png"), 1);
+        assert_eq!(accept.quality("nonsense"), 0);
+        assert_eq!(accept.to_string(), "text/*;q=0.5, text/html, */*;q=0.001");
+        assert_eq!(
+            accept.preferred(&["text/plain", "text/html"]),
+            Some("text/html")
+        );
+
+        // Malformed ranges are dropped, and nothing left means no header.
+        let accept = Accept::decode("*/html, text/plain;q=1.5, , image/png;q=0.25").unwrap();
+        assert_eq!(accept.to_string(), "image/png;q=0.25");
+        assert_eq!(Accept::decode("text, */html"), None);
+        assert_eq!(
+            Accept::decode("text/plain;q=0")
+                .unwrap()
+                .preferred(&["text/plain"]),
+            None
+        );
+    }
+}
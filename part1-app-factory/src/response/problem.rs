@@ -0,0 +1,144 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::IntoResponse;
+use crate::http::{Response, StatusCode};
+
+/// An error response in the RFC 7807 `application/problem+json` format.
+///
+/// ```
+/// # use part1_app_factory::{http::StatusCode, json::Json, response::Problem, router::post};
+/// # #[derive(serde::Deserialize)]
+/// # struct Withdrawal {
+/// #     amount: u64,
+/// # }
+/// # #[derive(serde::Serialize)]
+/// # struct Balance {
+/// #     balance: u64,
+/// # }
+/// # #[allow(non_upper_case_globals)]
+/// # const balance: u64 = 30;
+/// async fn withdraw(Json(req): Json<Withdrawal>) -> Result<Json<Balance>, Problem> {
+///     if req.amount > balance {
+///         return Err(Problem::new(StatusCode::FORBIDDEN)
+///             .with_type("https://example.com/probs/out-of-credit")
+///             .with_title("You do not have enough credit.")
+///             .with_extension("balance", balance));
+///     }
+///     // ...
+/// #     Ok(Json(Balance { balance: balance - req.amount }))
+/// }
+/// # let _ = post(withdraw);
+/// ```
+///
+/// Errors of the crate's error type convert into a 500 problem, so handlers
+/// can use `?` on them and still answer in this format.
+#[derive(Clone, Debug)]
+pub struct Problem {
+    type_uri: Option<String>,
+    title: Option<String>,
+    status: StatusCode,
+    detail: Option<String>,
+    instance: Option<String>,
+    extensions: Map<String, Value>,
+}
+
+impl Problem {
+    /// A problem with no more information than `status`. Its title is the
+    /// status' reason phrase and its type `about:blank`.
+    pub fn new(status: StatusCode) -> Self {
+        Problem {
+            type_uri: None,
+            title: None,
+            status,
+            detail: None,
+            instance: None,
+            extensions: Map::new(),
+        }
+    }
+
+    /// A URI identifying the kind of problem, ideally pointing at
+    /// documentation for it.
+    pub fn with_type(mut self, type_uri: impl Into<String>) -> Self {
+        self.type_uri = Some(type_uri.into());
+        self
+    }
+
+    /// A short summary that is the same for every occurrence of the type.
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// An explanation specific to this occurrence.
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// A URI identifying this occurrence, such as the request path.
+    pub fn with_instance(mut self, instance: impl Into<String>) -> Self {
+        self.instance = Some(instance.into());
+        self
+    }
+
+    /// Adds a member to the object. The members defined by the RFC can't be
+    /// overwritten this way.
+    pub fn with_extension(mut self, key: impl Into<String>, value: impl Serialize) -> Self {
+        let key = key.into();
+        if !matches!(
+            key.as_str(),
+            "type" | "title" | "status" | "detail" | "instance"
+        ) {
+            let value = serde_json::to_value(value).unwrap_or(Value::Null);
+            self.extensions.insert(key, value);
+        }
+        self
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    fn to_json(&self) -> Value {
+        let mut object = self.extensions.clone();
+        let type_uri = self.type_uri.as_deref().unwrap_or("about:blank");
+        object.insert("type".to_owned(), type_uri.into());
+
+        let title = self
+            .title
+            .as_deref()
+            .or_else(|| self.status.canonical_reason());
+        if let Some(title) = title {
+            object.insert("title".to_owned(), title.into());
+        }
+
+        object.insert("status".to_owned(), self.status.as_u16().into());
+        if let Some(detail) = &self.detail {
+            object.insert("detail".to_owned(), detail.as_str().into());
+        }
+        if let Some(instance) = &self.instance {
+            object.insert("instance".to_owned(), instance.as_str().into());
+        }
+        Value::Object(object)
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        let body = self.to_json().to_string();
+        (
+            self.status,
+            [("Content-Type", "application/problem+json")],
+            body,
+        )
+            .into_response()
+    }
+}
+
+/// A 500 problem with the error's message as the detail.
+impl From<anyhow::Error> for Problem {
+    fn from(error: anyhow::Error) -> Self {
+        Problem::new(StatusCode::INTERNAL_SERVER_ERROR).with_detail(error.to_string())
+    }
+}
@@ -0,0 +1,900 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use anyhow::Error;
+use tower::{
+    util::{BoxCloneService, MapErrLayer, MapRequestLayer, MapResponseLayer},
+    Layer, Service, ServiceExt,
+};
+
+use crate::{
+    extract::{rejection::PathRejection, Connected, PathError, State},
+    handler::Handler,
+    http::{
+        encoding::percent_decode, Body, Extensions, HeaderMap, Method, Request, Response,
+        StatusCode, Uri, Version,
+    },
+    response::{IntoResponse, Redirect},
+};
+
+use self::tree::{Captures, ConstraintFn, InsertError, RouteTree};
+
+pub use part1_app_factory_macros::TypedPath;
+
+pub use self::{
+    group::RouteGroup,
+    host::HostRouter,
+    into_make_service::{AddConnectInfo, IntoMakeService, IntoMakeServiceWithConnectInfo},
+    method_routing::{delete, get, head, on, options, patch, post, put, MethodRouter},
+    typed_path::TypedPath,
+};
+
+pub(crate) use self::tree::PathPattern;
+
+mod group;
+mod host;
+mod into_make_service;
+mod method_routing;
+mod tree;
+mod typed_path;
+
+/// The type-erased service stored for each route, which is what layers passed
+/// to [`Router::route_layer`] wrap.
+pub type BoxRoute = BoxCloneService<Request, Response, Error>;
+
+/// Adds a piece of state from [`Router::with_state`] to a request.
+type InsertState = Arc<dyn Fn(&mut Extensions) + Send + Sync>;
+
+/// Dispatches each request to the service registered for its path and method.
+///
+/// A `Router` is itself a `Service<Request>`, so it can be handed to
+/// `fakeserver::run` (or returned from an app factory) like any single app.
+#[derive(Clone, Default)]
+pub struct Router {
+    routes: Vec<Route>,
+    tree: RouteTree,
+    nested: Vec<Nested>,
+    fallback: Option<BoxRoute>,
+    /// Answers requests nothing matches once [`Router::layer`] wrapped the
+    /// plain 404.
+    not_found: Option<BoxRoute>,
+    trailing_slash: Option<TrailingSlash>,
+    case_insensitive: Option<bool>,
+    constraints: HashMap<String, ConstraintFn>,
+    states: Vec<InsertState>,
+}
+
+/// How a [`Router`] treats request paths that only differ from a route's
+/// pattern by a trailing slash.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TrailingSlash {
+    /// `/foo/` and `/foo` reach the same route. This is the default.
+    #[default]
+    Merge,
+    /// Requests are answered with a 301 to the form the route was registered
+    /// with.
+    Redirect,
+    /// Requests only match the form the route was registered with; the other
+    /// form falls through to nested routers and the fallback.
+    Strict,
+}
+
+#[derive(Clone)]
+struct Route {
+    pattern: PathPattern,
+    methods: MethodRouter,
+    layers: Vec<&'static str>,
+    /// The full pattern, prefixes of nesting routers included, kept so
+    /// requests don't build it again.
+    matched_path: MatchedPath,
+}
+
+#[derive(Clone)]
+struct Nested {
+    prefix: PathPattern,
+    /// The full pattern of `prefix`, prefixes of nesting routers included.
+    nested_path: NestedPath,
+    router: Router,
+}
+
+/// What a nested router uses in place of the options it doesn't set itself:
+/// those of the routers it is nested in.
+#[derive(Clone, Copy, Default)]
+struct Inherited<'a> {
+    fallback: Option<&'a BoxRoute>,
+    not_found: Option<&'a BoxRoute>,
+    trailing_slash: Option<TrailingSlash>,
+    case_insensitive: Option<bool>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the services of `methods` for requests matching the path
+    /// pattern `path`, e.g. `route("/users", get(list_users).post(create_user))`.
+    ///
+    /// Segments of the form `:name` capture whatever appears at that position
+    /// and are made available to the service as [`PathParams`]. Writing
+    /// `:name{constraint}` only captures segments the constraint accepts, so
+    /// `/users/:id{uint}` and `/users/:name` can coexist. Besides those added
+    /// with [`Router::constraint`], `int`, `uint`, `alpha`, `alnum` and `uuid`
+    /// are available. Registering the same pattern again adds to its methods.
+    ///
+    /// Panics if `path` doesn't start with `/`, uses an unknown constraint, a
+    /// method is already handled for the pattern, or the pattern only differs
+    /// from an existing one by the names of its captures.
+    pub fn route(mut self, path: &str, methods: MethodRouter) -> Self {
+        let pattern = PathPattern::parse_with(path, &self.constraints);
+        self.push_route(Route {
+            matched_path: MatchedPath::of("", &pattern),
+            pattern,
+            methods,
+            layers: Vec::new(),
+        });
+        self
+    }
+
+    /// Defines a constraint that patterns registered afterwards can refer to
+    /// as `:name{constraint}`. A segment is only captured when `check`
+    /// returns `true` for it; otherwise matching moves on to other routes.
+    pub fn constraint<F>(mut self, name: &str, check: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.constraints.insert(name.to_owned(), Arc::new(check));
+        self
+    }
+
+    /// Registers `methods` at the pattern of the typed path `P`.
+    pub fn typed_route<P: TypedPath>(self, methods: MethodRouter) -> Self {
+        self.route(P::PATH, methods)
+    }
+
+    /// Mounts `router` under `prefix`.
+    ///
+    /// Requests whose path starts with `prefix` and that no route of `self`
+    /// matches are forwarded to `router` with the prefix stripped, so the
+    /// inner routes are written relative to the mount point. The prefix may
+    /// contain captures, which are merged into the inner [`PathParams`].
+    ///
+    /// The untouched request target is kept as [`OriginalUri`] and the
+    /// accumulated mount point as [`NestedPath`].
+    pub fn nest(mut self, prefix: &str, router: Router) -> Self {
+        let mut prefix = PathPattern::parse_with(prefix, &self.constraints);
+        prefix.trailing_slash = false;
+
+        assert!(
+            !prefix.segments.is_empty(),
+            "Nesting at the root is not supported"
+        );
+
+        self.push_nested(Nested {
+            nested_path: NestedPath(prefix.to_string().into()),
+            prefix,
+            router,
+        });
+        self
+    }
+
+    /// Registers a group of routes that share `prefix` and layers.
+    ///
+    /// Unlike [`Router::nest`], the routes end up in this router with the
+    /// prefix prepended, so there is no second routing step and they share
+    /// this router's fallback and options.
+    ///
+    /// ```
+    /// # use part1_app_factory::router::{get, Router};
+    /// # use tower::layer::util::Identity;
+    /// # async fn list_users() {}
+    /// # async fn show_user() {}
+    /// # let auth_layer = Identity::new();
+    /// Router::new().group("/v1", |g| {
+    ///     g.route("/users", get(list_users))
+    ///         .route("/users/:id", get(show_user))
+    ///         .layer(auth_layer);
+    /// })
+    /// # ;
+    /// ```
+    pub fn group<F>(mut self, prefix: &str, build: F) -> Self
+    where
+        F: FnOnce(&mut RouteGroup),
+    {
+        let prefix = PathPattern::parse_with(prefix, &self.constraints);
+
+        let mut group = RouteGroup {
+            router: Router {
+                constraints: self.constraints.clone(),
+                ..Router::default()
+            },
+            layers: Vec::new(),
+        };
+        build(&mut group);
+        let router = group.finish();
+
+        for route in router.routes {
+            let pattern = prefix.join(&route.pattern);
+            self.push_route(Route {
+                matched_path: MatchedPath::of("", &pattern),
+                pattern,
+                ..route
+            });
+        }
+        for nested in router.nested {
+            let prefix = prefix.join(&nested.prefix);
+            self.push_nested(Nested {
+                nested_path: NestedPath(prefix.to_string().into()),
+                prefix,
+                router: nested.router,
+            });
+        }
+        self
+    }
+
+    /// Turns the router into an app factory that serves every connection
+    /// with a clone of it.
+    pub fn into_make_service(self) -> IntoMakeService<Self> {
+        IntoMakeService::new(self)
+    }
+
+    /// Like [`Router::into_make_service`], but adds a `ConnectInfo<C>` taken
+    /// from the connection to every request, e.g. `Option<SocketAddr>` for
+    /// the client's address.
+    pub fn into_make_service_with_connect_info<C: Connected>(
+        self,
+    ) -> IntoMakeServiceWithConnectInfo<Self, C> {
+        IntoMakeServiceWithConnectInfo::new(self)
+    }
+
+    /// Sets the handler for requests no route matches.
+    ///
+    /// Without a fallback, unmatched requests get an empty 404 response.
+    /// Nested routers that don't set their own fallback use this one.
+    pub fn fallback<H, T>(mut self, handler: H) -> Self
+    where
+        H: Handler<T>,
+        T: 'static,
+    {
+        self.fallback = Some(BoxCloneService::new(handler.into_service()));
+        self
+    }
+
+    /// Makes `state` available to every handler of this router, including
+    /// those of nested routers and the fallback, through the
+    /// [`State`](crate::extract::State) extractor.
+    ///
+    /// A router can hold state of several types. State set on a nested router
+    /// or group takes precedence over state of the same type set further out.
+    pub fn with_state<S>(mut self, state: S) -> Self
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        self.states
+            .push(Arc::new(move |extensions: &mut Extensions| {
+                extensions.insert(State(state.clone()));
+            }));
+        self
+    }
+
+    /// Sets how paths that only differ from a route by a trailing slash are
+    /// handled. Nested routers that don't set their own policy use this one.
+    ///
+    /// Patterns that only differ by a trailing slash are always the same
+    /// route; the form used in the first registration is the one `Redirect`
+    /// and `Strict` enforce.
+    pub fn trailing_slash(mut self, policy: TrailingSlash) -> Self {
+        self.trailing_slash = Some(policy);
+        self
+    }
+
+    /// Makes literal path segments match regardless of ASCII case, so
+    /// `/Users/42` reaches a route registered as `/users/:id`.
+    ///
+    /// Only matching is affected: handlers still see the path as sent and
+    /// captured values keep their case. Nested routers that don't set their
+    /// own option use this one.
+    pub fn case_insensitive(mut self, enabled: bool) -> Self {
+        self.case_insensitive = Some(enabled);
+        self
+    }
+
+    /// Wraps every route registered so far, including those of nested
+    /// routers, with `layer`.
+    ///
+    /// Routes added afterwards and the fallback are left alone, so e.g. an
+    /// auth layer only rejects requests that would have reached a route and
+    /// unknown paths still get a 404.
+    pub fn route_layer<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<BoxRoute> + Clone,
+        L::Service: Service<Request, Response = Response> + Clone + Send + 'static,
+        <L::Service as Service<Request>>::Error: Into<Error>,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        for route in &mut self.routes {
+            route.methods = std::mem::take(&mut route.methods).layer(layer.clone());
+            route.layers.push(std::any::type_name::<L>());
+        }
+
+        for nested in &mut self.nested {
+            nested.router = std::mem::take(&mut nested.router).route_layer(layer.clone());
+        }
+
+        self
+    }
+
+    /// Wraps the whole router with `layer`: every route registered so far,
+    /// including those of nested routers, the fallback, and the 404 for
+    /// requests nothing matches.
+    ///
+    /// Unlike with [`Router::route_layer`], requests for unknown paths go
+    /// through the layer too, as do those with a method a route doesn't
+    /// handle, so it suits middleware that has to see every request, such as
+    /// logging or CORS. Routes and fallbacks added afterwards are left alone.
+    pub fn layer<L>(self, layer: L) -> Self
+    where
+        L: Layer<BoxRoute> + Clone,
+        L::Service: Service<Request, Response = Response> + Clone + Send + 'static,
+        <L::Service as Service<Request>>::Error: Into<Error>,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        let mut router = self.wrap_all(&layer);
+        let not_found = router.not_found.take().unwrap_or_else(|| {
+            BoxCloneService::new(tower::service_fn(|_: Request| async { Ok(not_found()) }))
+        });
+        router.not_found = Some(wrap(&layer, not_found));
+        router
+    }
+
+    /// Transforms requests with `f`, e.g. to add a header. It's a
+    /// [`Router::layer`], so it runs after routing: changing the path doesn't
+    /// send a request to another route.
+    pub fn map_request<F>(self, f: F) -> Self
+    where
+        F: FnMut(Request) -> Request + Clone + Send + 'static,
+    {
+        self.layer(MapRequestLayer::new(f))
+    }
+
+    /// Transforms the responses of routes and fallbacks with `f`, as a
+    /// [`Router::layer`].
+    pub fn map_response<F>(self, f: F) -> Self
+    where
+        F: FnMut(Response) -> Response + Clone + Send + 'static,
+    {
+        self.layer(MapResponseLayer::new(f))
+    }
+
+    /// Transforms the errors of routes and fallbacks with `f`, as a
+    /// [`Router::layer`], e.g. to add context to them.
+    pub fn map_err<F, E>(self, f: F) -> Self
+    where
+        F: FnOnce(Error) -> E + Clone + Send + 'static,
+        E: Into<Error>,
+    {
+        self.layer(MapErrLayer::new(f))
+    }
+
+    /// Wraps the routes and fallbacks of `self` and its nested routers.
+    fn wrap_all<L>(mut self, layer: &L) -> Self
+    where
+        L: Layer<BoxRoute> + Clone,
+        L::Service: Service<Request, Response = Response> + Clone + Send + 'static,
+        <L::Service as Service<Request>>::Error: Into<Error>,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        for route in &mut self.routes {
+            route.methods = std::mem::take(&mut route.methods).layer_all(layer);
+            route.layers.push(std::any::type_name::<L>());
+        }
+        self.fallback = self.fallback.map(|fallback| wrap(layer, fallback));
+        self.not_found = self.not_found.map(|not_found| wrap(layer, not_found));
+
+        for nested in &mut self.nested {
+            nested.router = std::mem::take(&mut nested.router).wrap_all(layer);
+        }
+
+        self
+    }
+
+    /// Adds all routes and nested routers of `other` to `self`.
+    ///
+    /// This lets feature modules each build their own `Router` and have them
+    /// combined in one place. The state of both routers is available to all
+    /// routes. Panics if both routers handle the same method for a pattern,
+    /// nest at the same prefix, or both have a fallback.
+    pub fn merge(mut self, other: Router) -> Self {
+        if let Some(fallback) = other.fallback {
+            assert!(
+                self.fallback.is_none(),
+                "Cannot merge two routers that both have a fallback"
+            );
+            self.fallback = Some(fallback);
+        }
+        if self.not_found.is_none() {
+            self.not_found = other.not_found;
+        }
+
+        for route in other.routes {
+            self.push_route(route);
+        }
+        for nested in other.nested {
+            self.push_nested(nested);
+        }
+        self.states.extend(other.states);
+        self
+    }
+
+    /// Lists every route, including those of nested routers with their
+    /// prefix prepended, in registration order.
+    pub fn routes(&self) -> impl Iterator<Item = RouteInfo> {
+        let mut routes = Vec::new();
+        self.collect_routes("", &mut routes);
+        routes.into_iter()
+    }
+
+    fn collect_routes(&self, prefix: &str, routes: &mut Vec<RouteInfo>) {
+        for route in &self.routes {
+            routes.push(RouteInfo {
+                path: full_path(prefix, &route.pattern),
+                methods: route.methods.methods().collect(),
+                layers: route.layers.clone(),
+            });
+        }
+
+        for nested in &self.nested {
+            let prefix = format!("{}{}", prefix, nested.prefix);
+            nested.router.collect_routes(&prefix, routes);
+        }
+    }
+
+    /// Prepends `nested_path`, the full prefix `self` is nested at, to the
+    /// patterns its routes and nested routers report.
+    fn set_nested_path(&mut self, nested_path: &str) {
+        for route in &mut self.routes {
+            route.matched_path = MatchedPath::of(nested_path, &route.pattern);
+        }
+        for nested in &mut self.nested {
+            let nested_path = format!("{}{}", nested_path, nested.prefix);
+            nested.router.set_nested_path(&nested_path);
+            nested.nested_path = NestedPath(nested_path.into());
+        }
+    }
+
+    fn push_route(&mut self, route: Route) {
+        match self.tree.insert(&route.pattern, self.routes.len()) {
+            Ok(()) => self.routes.push(route),
+            Err(InsertError::Duplicate(index)) => {
+                let existing = &mut self.routes[index];
+                existing.methods = std::mem::take(&mut existing.methods).merge(route.methods);
+            }
+            Err(InsertError::CaptureConflict(name)) => panic!(
+                "Overlapping route: {} conflicts with an existing `:{}` capture",
+                route.pattern, name
+            ),
+        }
+    }
+
+    fn push_nested(&mut self, mut nested: Nested) {
+        assert!(
+            !self
+                .nested
+                .iter()
+                .any(|existing| existing.prefix.overlaps(&nested.prefix)),
+            "Overlapping nest prefix: {}",
+            nested.prefix
+        );
+
+        nested.router.set_nested_path(&nested.nested_path.0);
+        self.nested.push(nested);
+    }
+
+    /// Routes `req` through `self`, or through the nested router that
+    /// handles it, which takes the options it doesn't set from `inherited`.
+    fn dispatch(&self, mut req: Request, inherited: Inherited<'_>) -> RouteFuture {
+        for insert in &self.states {
+            insert(&mut req.extensions);
+        }
+
+        if req.extensions.get::<OriginalUri>().is_none() {
+            let original_uri = OriginalUri(req.uri.clone());
+            req.extensions.insert(original_uri);
+        }
+
+        let inherited = Inherited {
+            fallback: self.fallback.as_ref().or(inherited.fallback),
+            not_found: self.not_found.as_ref().or(inherited.not_found),
+            trailing_slash: self.trailing_slash.or(inherited.trailing_slash),
+            case_insensitive: self.case_insensitive.or(inherited.case_insensitive),
+        };
+
+        let path = req.uri.path();
+        let has_trailing_slash = path.len() > 1 && path.ends_with('/');
+        let policy = inherited.trailing_slash.unwrap_or_default();
+        let ignore_case = inherited.case_insensitive.unwrap_or(false);
+
+        let matched = if ignore_case {
+            self.tree.at_ignore_case(path)
+        } else {
+            self.tree.at(path)
+        };
+        let matched =
+            matched.map(|(index, captures)| (index, PathParams::from_captures(&captures)));
+
+        if let Some((index, params)) = matched {
+            let route = &self.routes[index];
+
+            if policy == TrailingSlash::Merge || has_trailing_slash == route.pattern.trailing_slash
+            {
+                let matched_path = route.matched_path.clone();
+                req.extensions.insert(matched_path.clone());
+                insert_params(&mut req, params);
+                let future = route.methods.dispatch(req);
+                return Box::pin(async move {
+                    let mut resp = future.await?;
+                    resp.extensions.insert(matched_path);
+                    Ok(resp)
+                });
+            }
+
+            if policy == TrailingSlash::Redirect {
+                let original = match req.extensions.get::<OriginalUri>() {
+                    Some(original) => &original.0,
+                    None => &req.uri,
+                };
+                let location = with_trailing_slash(original, route.pattern.trailing_slash);
+                let resp = Redirect::moved_permanently(&location).into_response();
+                return Box::pin(async { Ok(resp) });
+            }
+        }
+
+        let nested = self.nested.iter().find_map(|nested| {
+            let (captures, rest) = nested.prefix.match_prefix(path, ignore_case)?;
+            let params = PathParams::from_captures(&captures);
+            let rest = if rest.is_empty() { "/" } else { rest };
+            Some((nested, params, req.uri.with_path(rest)))
+        });
+
+        if let Some((nested, params, uri)) = nested {
+            req.uri = uri;
+            req.extensions.insert(nested.nested_path.clone());
+            insert_params(&mut req, params);
+            return nested.router.dispatch(req, inherited);
+        }
+
+        match inherited.fallback.or(inherited.not_found) {
+            Some(fallback) => Box::pin(fallback.clone().oneshot(req)),
+            None => Box::pin(async { Ok(not_found()) }),
+        }
+    }
+}
+
+/// What calling a [`Router`] returns.
+type RouteFuture = Pin<Box<dyn Future<Output = Result<Response, Error>> + Send>>;
+
+impl Service<Request> for Router {
+    type Response = Response;
+    type Error = Error;
+    type Future = RouteFuture;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        self.dispatch(req, Inherited::default())
+    }
+}
+
+fn not_found() -> Response {
+    Response {
+        status: StatusCode::NOT_FOUND,
+        version: Version::default(),
+        extensions: Extensions::default(),
+        headers: HeaderMap::new(),
+        body: Body::empty(),
+    }
+}
+
+fn wrap<L>(layer: &L, service: BoxRoute) -> BoxRoute
+where
+    L: Layer<BoxRoute>,
+    L::Service: Service<Request, Response = Response> + Clone + Send + 'static,
+    <L::Service as Service<Request>>::Error: Into<Error>,
+    <L::Service as Service<Request>>::Future: Send + 'static,
+{
+    BoxCloneService::new(layer.layer(service).map_err(Into::into))
+}
+
+/// Joins the prefix of nesting routers with a route's pattern.
+fn full_path(prefix: &str, pattern: &PathPattern) -> String {
+    let path = format!("{}{}", prefix, pattern);
+    if path.is_empty() {
+        "/".to_owned()
+    } else {
+        path
+    }
+}
+
+/// Adds or removes the trailing slash of the path in `uri`.
+fn with_trailing_slash(uri: &Uri, trailing_slash: bool) -> String {
+    let path = uri.path().trim_end_matches('/');
+
+    let mut target = match (path, trailing_slash) {
+        ("", _) => "/".to_owned(),
+        (path, true) => format!("{}/", path),
+        (path, false) => path.to_owned(),
+    };
+
+    if let Some(query) = uri.query() {
+        target.push('?');
+        target.push_str(query);
+    }
+    target
+}
+
+/// Adds `params` to any captured by outer, nesting routers.
+fn insert_params(req: &mut Request, params: PathParams) {
+    match req.extensions.get_mut::<PathParams>() {
+        Some(existing) => existing.0.extend(params.0),
+        None => {
+            req.extensions.insert(params);
+        }
+    }
+}
+
+/// A registered route, as listed by [`Router::routes`].
+#[derive(Clone, Debug)]
+pub struct RouteInfo {
+    /// The full pattern, including the prefixes of any nesting routers.
+    pub path: String,
+    pub methods: Vec<Method>,
+    /// Type names of the layers wrapping the route, innermost first.
+    pub layers: Vec<&'static str>,
+}
+
+/// The request target as it was before any nesting router stripped its
+/// prefix.
+#[derive(Clone, Debug)]
+pub struct OriginalUri(pub Uri);
+
+/// The full pattern of the route that matched the request, e.g.
+/// `/api/users/:id` rather than `/api/users/42`.
+///
+/// Available to the route's service and any layers added with
+/// [`Router::route_layer`] in the request's extensions, and to layers around
+/// the whole router in the response's. That makes it a good key for
+/// per-route metrics and logs.
+#[derive(Clone, Debug)]
+pub struct MatchedPath(Arc<str>);
+
+impl MatchedPath {
+    /// The path of `pattern` nested at `prefix`.
+    fn of(prefix: &str, pattern: &PathPattern) -> Self {
+        MatchedPath(full_path(prefix, pattern).into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// The pattern of the prefix(es) under which the current router was nested,
+/// e.g. `/api/:version` for a router mounted with `nest("/api/:version", ..)`.
+#[derive(Clone, Debug)]
+pub struct NestedPath(Arc<str>);
+
+impl NestedPath {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Path parameters captured by the route that matched the request.
+///
+/// The router stores these in the request's extensions before calling the
+/// route's service.
+#[derive(Clone, Debug, Default)]
+pub struct PathParams(Vec<(String, String)>);
+
+impl PathParams {
+    fn from_captures(captures: &Captures<'_, '_>) -> Self {
+        let params = captures
+            .iter()
+            .map(|(name, value)| (name.to_string(), percent_decode(value)))
+            .collect();
+        PathParams(params)
+    }
+
+    /// Returns the value captured for the `:name` segment, if any.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Parses the value captured for the `:name` segment.
+    pub fn parse<T: FromStr>(&self, name: &str) -> Result<T, PathRejection> {
+        let value = self.get(name).ok_or_else(|| {
+            PathRejection::FailedToDeserialize(PathError::MissingParameter {
+                key: name.to_owned(),
+            })
+        })?;
+
+        value.parse().map_err(|_| {
+            PathRejection::FailedToDeserialize(PathError::ParseErrorAtKey {
+                key: name.to_owned(),
+                value: value.to_owned(),
+                expected_type: std::any::type_name::<T>(),
+            })
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::type_name;
+
+    use super::*;
+    use crate::util::app_fn;
+
+    /// Answers with the matched path and the path it was nested at.
+    async fn paths(req: Request) -> Result<Response, Error> {
+        let matched = req.extensions.get::<MatchedPath>().map(MatchedPath::as_str);
+        let nested = req.extensions.get::<NestedPath>().map(NestedPath::as_str);
+        Ok(format!("{} {}", matched.unwrap_or("-"), nested.unwrap_or("-")).into_response())
+    }
+
+    async fn body(router: Router, uri: &str) -> String {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let resp = router.oneshot(req).await.unwrap();
+        let body = resp.body.collect().await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    async fn handle(_req: Request) -> Result<Response, Error> {
+        unreachable!("The routes are only listed")
+    }
+
+    /// Layers that leave the service alone, to be listed by name.
+    #[derive(Clone)]
+    struct Inner;
+    #[derive(Clone)]
+    struct Outer;
+
+    impl<S> Layer<S> for Inner {
+        type Service = S;
+
+        fn layer(&self, service: S) -> S {
+            service
+        }
+    }
+
+    impl<S> Layer<S> for Outer {
+        type Service = S;
+
+        fn layer(&self, service: S) -> S {
+            service
+        }
+    }
+
+    /// The routes of `router` as `(path, methods, layers)`.
+    fn routes(router: &Router) -> Vec<(String, Vec<Method>, Vec<&'static str>)> {
+        router
+            .routes()
+            .map(|route| (route.path, route.methods, route.layers))
+            .collect()
+    }
+
+    #[test]
+    fn lists_routes_in_registration_order() {
+        let router = Router::new()
+            .route("/", get(app_fn(handle)))
+            .route("/users/:id", get(app_fn(handle)).delete(app_fn(handle)))
+            .route("/users", post(app_fn(handle)))
+            .route("/users/:id", put(app_fn(handle)));
+
+        assert_eq!(
+            routes(&router),
+            [
+                ("/".to_owned(), vec![Method::Get], vec![]),
+                (
+                    "/users/:id".to_owned(),
+                    vec![Method::Get, Method::Delete, Method::Put],
+                    vec![]
+                ),
+                ("/users".to_owned(), vec![Method::Post], vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn prefixes_nested_and_grouped_routes() {
+        let users = Router::new()
+            .route("/", get(app_fn(handle)))
+            .route("/:id", get(app_fn(handle)));
+        let router = Router::new()
+            .nest("/api/:version", Router::new().nest("/users", users))
+            .group("/admin", |g| {
+                g.route("/stats", get(app_fn(handle)));
+            });
+
+        let paths: Vec<_> = router.routes().map(|route| route.path).collect();
+        assert_eq!(
+            paths,
+            [
+                "/admin/stats",
+                "/api/:version/users",
+                "/api/:version/users/:id"
+            ]
+        );
+    }
+
+    #[test]
+    fn names_layers_innermost_first() {
+        let router = Router::new()
+            .route("/login", post(app_fn(handle)))
+            .route_layer(Inner)
+            .nest("/admin", Router::new().route("/", get(app_fn(handle))))
+            .layer(Outer)
+            .route("/health", get(app_fn(handle)));
+
+        let (inner, outer) = (type_name::<Inner>(), type_name::<Outer>());
+        let layers: Vec<_> = router.routes().map(|route| route.layers).collect();
+        // The router's own routes come before the nested ones.
+        assert_eq!(layers, [vec![inner, outer], vec![], vec![outer]]);
+    }
+
+    #[test]
+    fn lists_nothing_for_an_empty_router() {
+        assert_eq!(Router::new().routes().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn reports_the_full_patterns_of_nested_routes() {
+        let users = Router::new().route("/:id", get(app_fn(paths)));
+        let api = Router::new()
+            .route("/", get(app_fn(paths)))
+            .nest("/users", users);
+        let router = Router::new()
+            .route("/", get(app_fn(paths)))
+            .group("/v1", |g| {
+                g.nest("/api/:version", api);
+            });
+
+        assert_eq!(body(router.clone(), "/").await, "/ -");
+        assert_eq!(
+            body(router.clone(), "/v1/api/2").await,
+            "/v1/api/:version /v1/api/:version"
+        );
+        assert_eq!(
+            body(router, "/v1/api/2/users/42").await,
+            "/v1/api/:version/users/:id /v1/api/:version/users"
+        );
+    }
+
+    #[tokio::test]
+    async fn nested_routers_use_the_fallback_they_are_nested_in() {
+        let fallback = |req: Request| async move { format!("No route for {}", req.uri) };
+        let router = Router::new()
+            .nest("/api", Router::new().route("/users", get(app_fn(paths))))
+            .fallback(fallback);
+
+        assert_eq!(body(router.clone(), "/api/users").await, "/api/users /api");
+        assert_eq!(body(router, "/api/posts").await, "No route for /posts");
+    }
+}
@@ -0,0 +1,84 @@
+use anyhow::Error;
+use tower::{util::MapRequestLayer, Layer, Service};
+
+use super::{BoxRoute, MethodRouter, Router};
+use crate::http::{Request, Response};
+
+type ApplyLayer = Box<dyn FnOnce(Router) -> Router>;
+
+/// Collects the routes of a [`Router::group`] block.
+///
+/// Paths are relative to the group's prefix, and layers added with
+/// [`RouteGroup::layer`] and state added with [`RouteGroup::with_state`]
+/// apply to every route of the group, whether registered before or after.
+pub struct RouteGroup {
+    pub(super) router: Router,
+    pub(super) layers: Vec<ApplyLayer>,
+}
+
+impl RouteGroup {
+    pub fn route(&mut self, path: &str, methods: MethodRouter) -> &mut Self {
+        self.router = std::mem::take(&mut self.router).route(path, methods);
+        self
+    }
+
+    pub fn nest(&mut self, prefix: &str, router: Router) -> &mut Self {
+        self.router = std::mem::take(&mut self.router).nest(prefix, router);
+        self
+    }
+
+    /// Wraps all routes of the group with `layer`, like
+    /// [`Router::route_layer`] does for a whole router.
+    pub fn layer<L>(&mut self, layer: L) -> &mut Self
+    where
+        L: Layer<BoxRoute> + Clone + 'static,
+        L::Service: Service<Request, Response = Response> + Clone + Send + 'static,
+        <L::Service as Service<Request>>::Error: Into<Error>,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.layers
+            .push(Box::new(move |router| router.route_layer(layer)));
+        self
+    }
+
+    /// Makes `state` available to the group's routes, like
+    /// [`Router::with_state`] does for a whole router.
+    pub fn with_state<S>(&mut self, state: S) -> &mut Self
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        self.router = std::mem::take(&mut self.router).with_state(state);
+        self
+    }
+
+    /// The group's routes with its layers and state applied.
+    ///
+    /// The routes are moved into the parent router, so state is attached to
+    /// each of them instead of the router they were collected in.
+    pub(super) fn finish(self) -> Router {
+        let mut router = self
+            .layers
+            .into_iter()
+            .fold(self.router, |router, apply| apply(router));
+
+        let states = std::mem::take(&mut router.states);
+        if states.is_empty() {
+            return router;
+        }
+
+        for route in &mut router.routes {
+            let states = states.clone();
+            let insert_states = MapRequestLayer::new(move |mut req: Request| {
+                for insert in &states {
+                    insert(&mut req.extensions);
+                }
+                req
+            });
+            route.methods = std::mem::take(&mut route.methods).layer(insert_states);
+        }
+        for nested in &mut router.nested {
+            nested.router.states.splice(0..0, states.iter().cloned());
+        }
+        router
+    }
+}
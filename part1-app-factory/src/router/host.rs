@@ -0,0 +1,133 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use anyhow::Error;
+use tower::{Service, ServiceExt};
+
+use super::{not_found, Router};
+use crate::{
+    extract::ConnectInfo,
+    http::{header::HOST, ConnInfo, Request, Response},
+};
+
+/// Picks a [`Router`] per request based on the host it was sent to.
+///
+/// The host is taken from the `Host` header, or from the connection's
+/// [`ConnInfo`] when the header is missing. That is the one passed to
+/// [`HostRouter::with_conn_info`], or else the `ConnectInfo<ConnInfo>` the
+/// server attached to the request. Ports are ignored and matching is
+/// case-insensitive.
+#[derive(Clone, Default)]
+pub struct HostRouter {
+    hosts: Vec<(String, Router)>,
+    default: Option<Router>,
+    conn_host: Option<String>,
+}
+
+impl HostRouter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Routes requests for `pattern` to `router`.
+    ///
+    /// `pattern` is a host name without a port, e.g. `api.example.com`, or
+    /// `*.example.com` to match any subdomain of `example.com`. Hosts are
+    /// tried in registration order.
+    pub fn host(mut self, pattern: &str, router: Router) -> Self {
+        let pattern = pattern.to_ascii_lowercase();
+
+        assert!(
+            self.hosts.iter().all(|(existing, _)| *existing != pattern),
+            "Host `{}` is already routed",
+            pattern
+        );
+
+        self.hosts.push((pattern, router));
+        self
+    }
+
+    /// Sets the router for requests whose host matches no pattern. Without
+    /// one, they get an empty 404 response.
+    pub fn default_router(mut self, router: Router) -> Self {
+        self.default = Some(router);
+        self
+    }
+
+    /// Uses the host of `conn` for requests that have no `Host` header.
+    pub fn with_conn_info(mut self, conn: &ConnInfo) -> Self {
+        self.conn_host = Some(strip_port(&conn.host_and_port).to_ascii_lowercase());
+        self
+    }
+
+    fn select(&self, host: Option<&str>) -> Option<&Router> {
+        let matched = host.and_then(|host| {
+            self.hosts
+                .iter()
+                .find(|(pattern, _)| host_matches(pattern, host))
+                .map(|(_, router)| router)
+        });
+
+        matched.or(self.default.as_ref())
+    }
+}
+
+impl Service<Request> for HostRouter {
+    type Response = Response;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let header_host = req
+            .headers
+            .get(HOST)
+            .map(|value| strip_port(value.as_str()).to_ascii_lowercase());
+
+        let conn_host = match &self.conn_host {
+            Some(conn_host) => Some(conn_host.clone()),
+            None => req
+                .extensions
+                .get::<ConnectInfo<ConnInfo>>()
+                .map(|ConnectInfo(conn)| strip_port(&conn.host_and_port).to_ascii_lowercase()),
+        };
+
+        let host = header_host.or(conn_host);
+
+        match self.select(host.as_deref()) {
+            Some(router) => Box::pin(router.clone().oneshot(req)),
+            None => Box::pin(async { Ok(not_found()) }),
+        }
+    }
+}
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => match host.strip_suffix(suffix) {
+            Some(subdomain) => subdomain.len() > 1 && subdomain.ends_with('.'),
+            None => false,
+        },
+        None => pattern == host,
+    }
+}
+
+/// Removes the port from `host:port`, leaving bracketed IPv6 literals intact.
+fn strip_port(host_and_port: &str) -> &str {
+    if host_and_port.starts_with('[') {
+        return match host_and_port.find(']') {
+            Some(end) => &host_and_port[..=end],
+            None => host_and_port,
+        };
+    }
+
+    match host_and_port.rsplit_once(':') {
+        Some((host, port)) if port.bytes().all(|b| b.is_ascii_digit()) => host,
+        _ => host_and_port,
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    convert::Infallible,
+    fmt,
+    future::{ready, Ready},
+    marker::PhantomData,
+    task::{Context, Poll},
+};
+
+use tower::Service;
+
+use crate::{
+    extract::{ConnectInfo, Connected},
+    http::{ConnInfo, Request},
+};
+
+/// An app factory that serves every connection with a clone of the same
+/// app, so an app can be run wherever an app factory is expected.
+///
+/// Created with [`Router::into_make_service`](super::Router::into_make_service).
+#[derive(Clone, Debug)]
+pub struct IntoMakeService<S> {
+    app: S,
+}
+
+impl<S> IntoMakeService<S> {
+    pub(crate) fn new(app: S) -> Self {
+        IntoMakeService { app }
+    }
+}
+
+impl<S: Clone> Service<ConnInfo> for IntoMakeService<S> {
+    type Response = S;
+    type Error = Infallible;
+    type Future = Ready<Result<S, Infallible>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _conn: ConnInfo) -> Self::Future {
+        ready(Ok(self.app.clone()))
+    }
+}
+
+/// Like [`IntoMakeService`], but every request also gets a
+/// `ConnectInfo<C>` taken from its connection's [`ConnInfo`].
+///
+/// Created with
+/// [`Router::into_make_service_with_connect_info`](super::Router::into_make_service_with_connect_info).
+pub struct IntoMakeServiceWithConnectInfo<S, C> {
+    app: S,
+    _connect_info: PhantomData<fn() -> C>,
+}
+
+impl<S, C> IntoMakeServiceWithConnectInfo<S, C> {
+    pub(crate) fn new(app: S) -> Self {
+        IntoMakeServiceWithConnectInfo {
+            app,
+            _connect_info: PhantomData,
+        }
+    }
+}
+
+impl<S: Clone, C> Clone for IntoMakeServiceWithConnectInfo<S, C> {
+    fn clone(&self) -> Self {
+        IntoMakeServiceWithConnectInfo::new(self.app.clone())
+    }
+}
+
+impl<S: fmt::Debug, C> fmt::Debug for IntoMakeServiceWithConnectInfo<S, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IntoMakeServiceWithConnectInfo")
+            .field("app", &self.app)
+            .finish()
+    }
+}
+
+impl<S, C> Service<ConnInfo> for IntoMakeServiceWithConnectInfo<S, C>
+where
+    S: Clone,
+    C: Connected,
+{
+    type Response = AddConnectInfo<S, C>;
+    type Error = Infallible;
+    type Future = Ready<Result<Self::Response, Infallible>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, conn: ConnInfo) -> Self::Future {
+        ready(Ok(AddConnectInfo {
+            app: self.app.clone(),
+            connect_info: C::connect_info(&conn),
+        }))
+    }
+}
+
+/// The app [`IntoMakeServiceWithConnectInfo`] makes for a connection.
+#[derive(Clone, Debug)]
+pub struct AddConnectInfo<S, C> {
+    app: S,
+    connect_info: C,
+}
+
+impl<S, C> Service<Request> for AddConnectInfo<S, C>
+where
+    S: Service<Request>,
+    C: Connected,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.app.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        req.extensions
+            .insert(ConnectInfo(self.connect_info.clone()));
+        self.app.call(req)
+    }
+}
@@ -0,0 +1,264 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use anyhow::Error;
+use tower::{util::BoxCloneService, Layer, Service, ServiceExt};
+
+use super::{wrap, BoxRoute};
+use crate::{
+    handler::Handler,
+    http::{
+        header::ALLOW, Body, Extensions, HeaderMap, Method, Request, Response, StatusCode, Version,
+    },
+};
+
+macro_rules! top_level_fn {
+    ($name:ident, $method:ident) => {
+        #[doc = concat!("Routes `", stringify!($method), "` requests to `handler`.")]
+        pub fn $name<H, T>(handler: H) -> MethodRouter
+        where
+            H: Handler<T>,
+            T: 'static,
+        {
+            MethodRouter::new().on(Method::$method, handler)
+        }
+    };
+}
+
+macro_rules! chained_fn {
+    ($name:ident, $method:ident) => {
+        #[doc = concat!("Also routes `", stringify!($method), "` requests to `handler`.")]
+        pub fn $name<H, T>(self, handler: H) -> Self
+        where
+            H: Handler<T>,
+            T: 'static,
+        {
+            self.on(Method::$method, handler)
+        }
+    };
+}
+
+top_level_fn!(get, Get);
+top_level_fn!(head, Head);
+top_level_fn!(post, Post);
+top_level_fn!(put, Put);
+top_level_fn!(patch, Patch);
+top_level_fn!(delete, Delete);
+top_level_fn!(options, Options);
+
+/// Routes requests with `method`, e.g. an extension method such as
+/// `PROPFIND`, to `handler`.
+pub fn on<H, T>(method: Method, handler: H) -> MethodRouter
+where
+    H: Handler<T>,
+    T: 'static,
+{
+    MethodRouter::new().on(method, handler)
+}
+
+/// Dispatches requests for a single path to a handler per method.
+///
+/// Built with [`get`], [`post`], [`put`], [`delete`] and their siblings, and
+/// chained the same way, e.g. `get(list_users).post(create_user)`. Requests
+/// with a method that has no service get a 405 listing the allowed methods.
+///
+/// `HEAD` requests go to the `GET` service unless there is a `HEAD` one, and
+/// the body it responds with is dropped.
+#[derive(Clone, Default)]
+pub struct MethodRouter {
+    endpoints: Vec<(Method, BoxRoute)>,
+    /// Answers methods without a service once [`Router::layer`](super::Router::layer)
+    /// wrapped the plain 405.
+    not_allowed: Option<BoxRoute>,
+}
+
+impl MethodRouter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Routes requests with `method` to `handler`.
+    ///
+    /// Panics if `method` already has a handler.
+    pub fn on<H, T>(mut self, method: Method, handler: H) -> Self
+    where
+        H: Handler<T>,
+        T: 'static,
+    {
+        self.push(method, BoxCloneService::new(handler.into_service()));
+        self
+    }
+
+    chained_fn!(get, Get);
+    chained_fn!(head, Head);
+    chained_fn!(post, Post);
+    chained_fn!(put, Put);
+    chained_fn!(patch, Patch);
+    chained_fn!(delete, Delete);
+    chained_fn!(options, Options);
+
+    /// Wraps the service of every method with `layer`.
+    ///
+    /// Requests for methods without a service still get a plain 405.
+    pub fn layer<L>(self, layer: L) -> Self
+    where
+        L: Layer<BoxRoute>,
+        L::Service: Service<Request, Response = Response> + Clone + Send + 'static,
+        <L::Service as Service<Request>>::Error: Into<Error>,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        let endpoints = self
+            .endpoints
+            .into_iter()
+            .map(|(method, service)| (method, wrap(&layer, service)))
+            .collect();
+
+        Self {
+            endpoints,
+            not_allowed: self.not_allowed,
+        }
+    }
+
+    /// Like [`MethodRouter::layer`], but wraps the 405 too.
+    pub(super) fn layer_all<L>(self, layer: &L) -> Self
+    where
+        L: Layer<BoxRoute>,
+        L::Service: Service<Request, Response = Response> + Clone + Send + 'static,
+        <L::Service as Service<Request>>::Error: Into<Error>,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        let not_allowed = self.not_allowed.clone().unwrap_or_else(|| {
+            BoxCloneService::new(tower::service_fn(|req: Request| async move {
+                let allow = req.extensions.get::<Allow>().cloned().unwrap_or_default();
+                Ok(method_not_allowed(allow))
+            }))
+        });
+        Self {
+            not_allowed: Some(wrap(layer, not_allowed)),
+            ..self.layer(layer)
+        }
+    }
+
+    fn endpoint(&self, method: Method) -> Option<&BoxRoute> {
+        self.endpoints
+            .iter()
+            .find(|(existing, _)| *existing == method)
+            .map(|(_, service)| service)
+    }
+
+    pub(super) fn methods(&self) -> impl Iterator<Item = Method> + '_ {
+        self.endpoints.iter().map(|(method, _)| *method)
+    }
+
+    /// Combines the endpoints of both method routers, panicking if a method
+    /// is handled by both.
+    pub(super) fn merge(mut self, other: MethodRouter) -> Self {
+        for (method, service) in other.endpoints {
+            self.push(method, service);
+        }
+        self.not_allowed = self.not_allowed.or(other.not_allowed);
+        self
+    }
+
+    fn push(&mut self, method: Method, service: BoxRoute) {
+        assert!(
+            self.endpoints
+                .iter()
+                .all(|(existing, _)| *existing != method),
+            "Overlapping method route: {} is already handled",
+            method
+        );
+
+        self.endpoints.push((method, service));
+    }
+}
+
+impl Service<Request> for MethodRouter {
+    type Response = Response;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        self.dispatch(req)
+    }
+}
+
+impl MethodRouter {
+    /// Calls the service for the method of `req`, which only needs a copy
+    /// of that one.
+    pub(super) fn dispatch(
+        &self,
+        mut req: Request,
+    ) -> Pin<Box<dyn Future<Output = Result<Response, Error>> + Send>> {
+        if let Some(service) = self.endpoint(req.method) {
+            return Box::pin(service.clone().oneshot(req));
+        }
+
+        if req.method == Method::Head {
+            if let Some(service) = self.endpoint(Method::Get) {
+                let future = service.clone().oneshot(req);
+                return Box::pin(async move {
+                    let mut resp = future.await?;
+                    resp.body = Body::empty();
+                    Ok(resp)
+                });
+            }
+        }
+
+        let allow = Allow::of(&self.endpoints);
+        match &self.not_allowed {
+            Some(not_allowed) => {
+                req.extensions.insert(allow);
+                Box::pin(not_allowed.clone().oneshot(req))
+            }
+            None => {
+                let resp = method_not_allowed(allow);
+                Box::pin(async { Ok(resp) })
+            }
+        }
+    }
+}
+
+/// The methods a path accepts, including the implicit `HEAD`, as the value
+/// of an `Allow` header.
+#[derive(Clone, Debug, Default)]
+struct Allow(String);
+
+impl Allow {
+    fn of(endpoints: &[(Method, BoxRoute)]) -> Self {
+        let mut allow: Vec<&str> = endpoints
+            .iter()
+            .map(|(method, _)| method.as_str())
+            .collect();
+        let implicit_head = endpoints.iter().any(|(method, _)| *method == Method::Get)
+            && endpoints.iter().all(|(method, _)| *method != Method::Head);
+        if implicit_head {
+            allow.push("HEAD");
+        }
+        Allow(allow.join(", "))
+    }
+}
+
+/// A 405 response whose `Allow` header lists the methods the path does
+/// accept.
+fn method_not_allowed(Allow(allow): Allow) -> Response {
+    let mut headers = HeaderMap::new();
+    if let Ok(allow) = allow.parse() {
+        headers.insert(ALLOW, allow);
+    }
+
+    Response {
+        status: StatusCode::METHOD_NOT_ALLOWED,
+        version: Version::default(),
+        extensions: Extensions::default(),
+        headers,
+        body: Body::empty(),
+    }
+}
@@ -0,0 +1,567 @@
+//! Route patterns and the trie they are looked up in.
+//!
+//! This module only depends on `std` and `smallvec` so the route matching
+//! benchmark can include it directly.
+
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use smallvec::SmallVec;
+
+/// Capture names borrowed from the tree paired with values borrowed from the
+/// request path. Up to four captures are stored inline.
+pub(crate) type Captures<'t, 'p> = SmallVec<[(&'t str, &'p str); 4]>;
+
+/// Decides whether a path segment may be captured.
+pub(crate) type ConstraintFn = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+#[derive(Clone, Debug)]
+pub(crate) enum Segment {
+    Literal(String),
+    Capture(Capture),
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Capture {
+    pub(crate) name: String,
+    pub(crate) constraint: Option<Constraint>,
+}
+
+/// A named check a segment must pass to be captured, written as
+/// `:name{constraint}` in patterns.
+#[derive(Clone)]
+pub(crate) struct Constraint {
+    name: String,
+    check: ConstraintFn,
+}
+
+impl Constraint {
+    fn allows(&self, segment: &str) -> bool {
+        (self.check)(segment)
+    }
+}
+
+impl PartialEq for Constraint {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl fmt::Debug for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Constraint").field(&self.name).finish()
+    }
+}
+
+impl Capture {
+    fn allows(&self, segment: &str) -> bool {
+        match &self.constraint {
+            Some(constraint) => constraint.allows(segment),
+            None => true,
+        }
+    }
+}
+
+/// The constraints every pattern can use without registering them.
+fn builtin_constraint(name: &str) -> Option<fn(&str) -> bool> {
+    fn int(segment: &str) -> bool {
+        uint(segment.strip_prefix('-').unwrap_or(segment))
+    }
+
+    fn uint(segment: &str) -> bool {
+        !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit())
+    }
+
+    fn alpha(segment: &str) -> bool {
+        !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_alphabetic())
+    }
+
+    fn alnum(segment: &str) -> bool {
+        !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_alphanumeric())
+    }
+
+    fn uuid(segment: &str) -> bool {
+        let groups: SmallVec<[&str; 5]> = segment.split('-').collect();
+        groups.len() == 5
+            && groups.iter().zip([8, 4, 4, 4, 12]).all(|(group, len)| {
+                group.len() == len && group.bytes().all(|b| b.is_ascii_hexdigit())
+            })
+    }
+
+    match name {
+        "int" => Some(int),
+        "uint" => Some(uint),
+        "alpha" => Some(alpha),
+        "alnum" => Some(alnum),
+        "uuid" => Some(uuid),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct PathPattern {
+    pub(crate) segments: Vec<Segment>,
+    pub(crate) trailing_slash: bool,
+}
+
+impl PathPattern {
+    pub(crate) fn parse(path: &str) -> Self {
+        Self::parse_with(path, &HashMap::new())
+    }
+
+    /// Parses `path`, resolving `{constraint}` names against `constraints`
+    /// and then the built-in `int`, `uint`, `alpha`, `alnum` and `uuid`.
+    pub(crate) fn parse_with(path: &str, constraints: &HashMap<String, ConstraintFn>) -> Self {
+        assert!(path.starts_with('/'), "Route paths must start with `/`");
+
+        let segments = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| match segment.strip_prefix(':') {
+                Some(capture) => Segment::Capture(parse_capture(path, capture, constraints)),
+                None => Segment::Literal(segment.to_owned()),
+            })
+            .collect();
+
+        Self {
+            segments,
+            trailing_slash: path.len() > 1 && path.ends_with('/'),
+        }
+    }
+
+    /// Appends the segments of `other`, which also decides whether the
+    /// result ends in a slash.
+    pub(crate) fn join(&self, other: &PathPattern) -> PathPattern {
+        PathPattern {
+            segments: self
+                .segments
+                .iter()
+                .chain(&other.segments)
+                .cloned()
+                .collect(),
+            trailing_slash: other.trailing_slash,
+        }
+    }
+
+    /// Two patterns overlap when they have the same shape, regardless of how
+    /// their captures are named or whether they end in a slash. Captures only
+    /// overlap when they have the same constraint.
+    pub(crate) fn overlaps(&self, other: &PathPattern) -> bool {
+        self.segments.len() == other.segments.len()
+            && self
+                .segments
+                .iter()
+                .zip(&other.segments)
+                .all(|pair| match pair {
+                    (Segment::Literal(a), Segment::Literal(b)) => a == b,
+                    (Segment::Capture(a), Segment::Capture(b)) => a.constraint == b.constraint,
+                    _ => false,
+                })
+    }
+
+    /// Matches the pattern against the start of `path`, returning the
+    /// captures and the unmatched remainder (empty or starting with `/`).
+    ///
+    /// With `ignore_case`, literal segments are compared ignoring ASCII case.
+    pub(crate) fn match_prefix<'t, 'p>(
+        &'t self,
+        path: &'p str,
+        ignore_case: bool,
+    ) -> Option<(Captures<'t, 'p>, &'p str)> {
+        let mut captures = Captures::new();
+        let mut rest = path;
+
+        for expected in &self.segments {
+            let (actual, tail) = split_first_segment(rest);
+
+            match expected {
+                _ if actual.is_empty() => return None,
+                Segment::Literal(literal) if literal == actual => {}
+                Segment::Literal(literal)
+                    if ignore_case && literal.eq_ignore_ascii_case(actual) => {}
+                Segment::Literal(_) => return None,
+                Segment::Capture(capture) if capture.allows(actual) => {
+                    captures.push((capture.name.as_str(), actual))
+                }
+                Segment::Capture(_) => return None,
+            }
+
+            rest = tail;
+        }
+
+        Some((captures, rest))
+    }
+}
+
+impl fmt::Display for PathPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => write!(f, "/{}", literal)?,
+                Segment::Capture(capture) => {
+                    write!(f, "/:{}", capture.name)?;
+                    if let Some(constraint) = &capture.constraint {
+                        write!(f, "{{{}}}", constraint.name)?;
+                    }
+                }
+            }
+        }
+        if self.trailing_slash && !self.segments.is_empty() {
+            f.write_str("/")?;
+        }
+        Ok(())
+    }
+}
+
+/// A trie keyed by path segment, mapping patterns to caller-chosen values.
+///
+/// Lookups walk the tree once per segment of the request path, preferring
+/// literal children over captures and backtracking when a literal branch
+/// dead-ends. Constrained captures are tried before unconstrained ones at the
+/// same position. Lookups don't allocate unless a path has more than four
+/// captures.
+/// Case-insensitive lookups additionally scan the literal children of each
+/// node they pass.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RouteTree {
+    root: Node,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Node {
+    literals: HashMap<String, Node>,
+    captures: Vec<CaptureNode>,
+    value: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+struct CaptureNode {
+    capture: Capture,
+    node: Node,
+}
+
+#[derive(Debug)]
+pub(crate) enum InsertError {
+    /// The same pattern is already in the tree with this value.
+    Duplicate(usize),
+    /// A capture with the same constraint at the same position is already in
+    /// the tree under another name.
+    CaptureConflict(String),
+}
+
+impl RouteTree {
+    pub(crate) fn insert(
+        &mut self,
+        pattern: &PathPattern,
+        value: usize,
+    ) -> Result<(), InsertError> {
+        let mut node = &mut self.root;
+
+        for segment in &pattern.segments {
+            node = match segment {
+                Segment::Literal(literal) => node.literals.entry(literal.clone()).or_default(),
+                Segment::Capture(capture) => {
+                    let existing = node
+                        .captures
+                        .iter()
+                        .position(|child| child.capture.constraint == capture.constraint);
+
+                    let index = match existing {
+                        Some(index) if node.captures[index].capture.name != capture.name => {
+                            let name = node.captures[index].capture.name.clone();
+                            return Err(InsertError::CaptureConflict(name));
+                        }
+                        Some(index) => index,
+                        None => {
+                            // Keep constrained captures ahead of the
+                            // unconstrained one so they get the first try.
+                            let index = match capture.constraint {
+                                Some(_) => node
+                                    .captures
+                                    .iter()
+                                    .position(|child| child.capture.constraint.is_none())
+                                    .unwrap_or(node.captures.len()),
+                                None => node.captures.len(),
+                            };
+                            let child = CaptureNode {
+                                capture: capture.clone(),
+                                node: Node::default(),
+                            };
+                            node.captures.insert(index, child);
+                            index
+                        }
+                    };
+
+                    &mut node.captures[index].node
+                }
+            };
+        }
+
+        match node.value {
+            Some(existing) => Err(InsertError::Duplicate(existing)),
+            None => {
+                node.value = Some(value);
+                Ok(())
+            }
+        }
+    }
+
+    pub(crate) fn at<'t, 'p>(&'t self, path: &'p str) -> Option<(usize, Captures<'t, 'p>)> {
+        let mut captures = Captures::new();
+        let value = self.root.find(path, false, &mut captures)?;
+        Some((value, captures))
+    }
+
+    /// Like [`RouteTree::at`], but literal segments match regardless of
+    /// ASCII case. Captured values keep the case of `path`.
+    pub(crate) fn at_ignore_case<'t, 'p>(
+        &'t self,
+        path: &'p str,
+    ) -> Option<(usize, Captures<'t, 'p>)> {
+        let mut captures = Captures::new();
+        let value = self.root.find(path, true, &mut captures)?;
+        Some((value, captures))
+    }
+}
+
+impl Node {
+    fn find<'t, 'p>(
+        &'t self,
+        path: &'p str,
+        ignore_case: bool,
+        captures: &mut Captures<'t, 'p>,
+    ) -> Option<usize> {
+        let (segment, rest) = split_first_segment(path);
+        if segment.is_empty() {
+            return self.value;
+        }
+
+        if let Some(child) = self.literals.get(segment) {
+            if let Some(value) = child.find(rest, ignore_case, captures) {
+                return Some(value);
+            }
+        }
+
+        if ignore_case {
+            let other_cases = self.literals.iter().filter(|(literal, _)| {
+                *literal != segment && literal.eq_ignore_ascii_case(segment)
+            });
+            for (_, child) in other_cases {
+                if let Some(value) = child.find(rest, ignore_case, captures) {
+                    return Some(value);
+                }
+            }
+        }
+
+        for child in &self.captures {
+            if !child.capture.allows(segment) {
+                continue;
+            }
+
+            captures.push((child.capture.name.as_str(), segment));
+            if let Some(value) = child.node.find(rest, ignore_case, captures) {
+                return Some(value);
+            }
+            captures.pop();
+        }
+
+        None
+    }
+}
+
+fn parse_capture(
+    path: &str,
+    capture: &str,
+    constraints: &HashMap<String, ConstraintFn>,
+) -> Capture {
+    let (name, constraint) = match capture.split_once('{') {
+        Some((name, rest)) => {
+            let constraint = rest.strip_suffix('}').unwrap_or_else(|| {
+                panic!("Unterminated path constraint in {}", path);
+            });
+            (name, Some(constraint))
+        }
+        None => (capture, None),
+    };
+
+    assert!(!name.is_empty(), "Path captures must be named: {}", path);
+
+    let constraint = constraint.map(|constraint| {
+        let check = match constraints.get(constraint) {
+            Some(check) => check.clone(),
+            None => match builtin_constraint(constraint) {
+                Some(check) => Arc::new(check) as ConstraintFn,
+                None => panic!("Unknown path constraint `{}` in {}", constraint, path),
+            },
+        };
+
+        Constraint {
+            name: constraint.to_owned(),
+            check,
+        }
+    });
+
+    Capture {
+        name: name.to_owned(),
+        constraint,
+    }
+}
+
+/// Splits `path` into its first non-empty segment and the remainder, which is
+/// empty or starts with `/`.
+fn split_first_segment(path: &str) -> (&str, &str) {
+    let path = path.trim_start_matches('/');
+    match path.find('/') {
+        Some(end) => path.split_at(end),
+        None => (path, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(paths: &[&str]) -> RouteTree {
+        let mut tree = RouteTree::default();
+        for (value, path) in paths.iter().enumerate() {
+            tree.insert(&PathPattern::parse(path), value).unwrap();
+        }
+        tree
+    }
+
+    #[test]
+    fn matches_literals_and_captures() {
+        let tree = tree(&["/", "/users", "/users/:id", "/users/:id/posts/:post"]);
+
+        assert_eq!(tree.at("/").unwrap().0, 0);
+        assert_eq!(tree.at("/users").unwrap().0, 1);
+
+        let (value, captures) = tree.at("/users/42").unwrap();
+        assert_eq!(value, 2);
+        assert_eq!(captures.as_slice(), [("id", "42")]);
+
+        let (value, captures) = tree.at("/users/42/posts/7").unwrap();
+        assert_eq!(value, 3);
+        assert_eq!(captures.as_slice(), [("id", "42"), ("post", "7")]);
+
+        assert!(tree.at("/users/42/posts").is_none());
+        assert!(tree.at("/posts").is_none());
+    }
+
+    #[test]
+    fn ignores_repeated_and_trailing_slashes() {
+        let tree = tree(&["/users/:id"]);
+
+        assert_eq!(tree.at("/users/42/").unwrap().0, 0);
+        assert_eq!(tree.at("//users//42").unwrap().0, 0);
+        assert!(PathPattern::parse("/users/").trailing_slash);
+        assert!(!PathPattern::parse("/").trailing_slash);
+    }
+
+    #[test]
+    fn backtracks_out_of_dead_end_literals() {
+        let tree = tree(&["/users/me/settings", "/users/:id/posts"]);
+
+        let (value, captures) = tree.at("/users/me/posts").unwrap();
+        assert_eq!(value, 1);
+        assert_eq!(captures.as_slice(), [("id", "me")]);
+    }
+
+    #[test]
+    fn tries_constrained_captures_first() {
+        let tree = tree(&["/items/:slug", "/items/:id{uint}", "/items/:key{uuid}"]);
+
+        assert_eq!(tree.at("/items/42").unwrap().0, 1);
+        assert_eq!(tree.at("/items/42a").unwrap().0, 0);
+        assert_eq!(
+            tree.at("/items/67e55044-10b1-426f-9247-bb680e5fe0c8")
+                .unwrap()
+                .0,
+            2
+        );
+        assert_eq!(tree.at("/items/67e55044-10b1-426f").unwrap().0, 0);
+    }
+
+    #[test]
+    fn checks_builtin_constraints() {
+        let allows = |constraint: &str, segment: &str| {
+            let pattern = PathPattern::parse(&format!("/:x{{{}}}", constraint));
+            let path = format!("/{}", segment);
+            let allowed = pattern.match_prefix(&path, false).is_some();
+            allowed
+        };
+
+        assert!(allows("int", "-12"));
+        assert!(!allows("int", "-"));
+        assert!(allows("uint", "12"));
+        assert!(!allows("uint", "-12"));
+        assert!(allows("alpha", "abc"));
+        assert!(!allows("alpha", "abc1"));
+        assert!(allows("alnum", "abc1"));
+        assert!(!allows("alnum", "abc-1"));
+        assert!(allows("uuid", "67E55044-10B1-426F-9247-BB680E5FE0C8"));
+        assert!(!allows("uuid", "67e55044-10b1-426f-9247-bb680e5fe0c"));
+    }
+
+    #[test]
+    fn resolves_custom_constraints_before_builtins() {
+        let mut constraints = HashMap::new();
+        constraints.insert(
+            "uint".to_owned(),
+            Arc::new(|segment: &str| segment == "even") as ConstraintFn,
+        );
+
+        let pattern = PathPattern::parse_with("/:n{uint}", &constraints);
+        assert!(pattern.match_prefix("/even", false).is_some());
+        assert!(pattern.match_prefix("/2", false).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown path constraint `nope`")]
+    fn panics_on_unknown_constraints() {
+        PathPattern::parse("/:id{nope}");
+    }
+
+    #[test]
+    fn matches_literals_ignoring_case() {
+        let tree = tree(&["/Users/:name", "/users/admin"]);
+
+        assert!(tree.at("/USERS/Bob").is_none());
+
+        let (value, captures) = tree.at_ignore_case("/USERS/Bob").unwrap();
+        assert_eq!(value, 0);
+        assert_eq!(captures.as_slice(), [("name", "Bob")]);
+
+        assert_eq!(tree.at_ignore_case("/users/ADMIN").unwrap().0, 1);
+    }
+
+    #[test]
+    fn rejects_conflicting_inserts() {
+        let mut tree = tree(&["/users/:id", "/files/:id{uint}"]);
+
+        assert!(matches!(
+            tree.insert(&PathPattern::parse("/users/:id/"), 5),
+            Err(InsertError::Duplicate(0))
+        ));
+        assert!(matches!(
+            tree.insert(&PathPattern::parse("/users/:user_id/posts"), 5),
+            Err(InsertError::CaptureConflict(name)) if name == "id"
+        ));
+        assert!(tree.insert(&PathPattern::parse("/files/:name"), 5).is_ok());
+    }
+
+    #[test]
+    fn displays_joins_and_overlaps() {
+        let api = PathPattern::parse("/api/");
+        let user = PathPattern::parse("/users/:id{uint}");
+        let joined = api.join(&user);
+
+        assert_eq!(joined.to_string(), "/api/users/:id{uint}");
+        assert_eq!(PathPattern::parse("/users/").to_string(), "/users/");
+        assert!(joined.overlaps(&PathPattern::parse("/api/users/:n{uint}/")));
+        assert!(!joined.overlaps(&PathPattern::parse("/api/users/:n")));
+
+        let (captures, rest) = api.match_prefix("/api/users/1", false).unwrap();
+        assert!(captures.is_empty());
+        assert_eq!(rest, "/users/1");
+    }
+}
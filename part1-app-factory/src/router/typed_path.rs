@@ -0,0 +1,29 @@
+use super::PathParams;
+use crate::extract::rejection::PathRejection;
+
+/// A struct that describes a route pattern and holds its captures.
+///
+/// Usually derived, so the pattern and the fields are checked against each
+/// other at compile time:
+///
+/// ```
+/// # use part1_app_factory::router::TypedPath;
+/// #[derive(TypedPath)]
+/// #[typed_path("/users/:id")]
+/// struct UserPath {
+///     id: u64,
+/// }
+/// # assert_eq!(UserPath { id: 42 }.to_string(), "/users/42");
+/// ```
+///
+/// The derive also implements `Display`, which renders the path for a given
+/// set of values (e.g. `/users/42`), handy for building links and redirects,
+/// and makes the struct an extractor for handlers of its route. Values are
+/// percent-encoded, so `/` or spaces in a value stay within its segment.
+pub trait TypedPath: Sized {
+    /// The pattern to register the route at.
+    const PATH: &'static str;
+
+    /// Builds the struct from the parameters captured by the router.
+    fn from_params(params: &PathParams) -> Result<Self, PathRejection>;
+}
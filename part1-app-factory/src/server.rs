@@ -0,0 +1,801 @@
+//! A server for real TCP connections, speaking HTTP/1.1, optionally over
+//! TLS, and HTTP/2 when TLS clients ask for it, or cleartext clients start
+//! with it and [`Server::h2c`] is on.
+//!
+//! It drives apps the same way `fakeserver` does: an app factory is called
+//! with the [`ConnInfo`] of every accepted connection, and the app it
+//! returns serves that connection's requests.
+//!
+//! ```no_run
+//! # use std::sync::Arc;
+//! # use part1_app_factory::{router::Router, server::{rustls::ServerConfig, Server}};
+//! # async fn run(app: Router, tls_config: Arc<ServerConfig>) -> std::io::Result<()> {
+//! # let app_factory = app.clone().into_make_service();
+//! Server::bind("0.0.0.0:3000").serve(app_factory).await?;
+//! # let app_factory = app.clone().into_make_service();
+//! Server::bind_rustls("0.0.0.0:3443", tls_config).serve(app_factory).await?;
+//! # let app_factory = app.clone().into_make_service();
+//! Server::bind_uds("/run/app.sock").serve(app_factory).await?;
+//! # let app_factory = app.clone().into_make_service();
+//! Server::bind("0.0.0.0:80").also_bind("[::]:80").serve(app_factory).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Apps that don't need a factory can be run with [`serve`].
+//!
+//! [`Server::serve`] shuts down gracefully on ctrl-c: it stops accepting
+//! connections, lets requests in flight finish and returns.
+
+use std::{fmt, future::Future, io, net::SocketAddr, sync::Arc, time::Duration};
+
+#[cfg(unix)]
+use std::path::PathBuf;
+
+use futures_util::future;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::{mpsc, OwnedSemaphorePermit, Semaphore},
+};
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+use tower::Service;
+use tracing::{error, field, info, info_span, warn, Instrument};
+
+use crate::{
+    http::{ConnInfo, PeerCert, Request, Response, StatusCode, TlsInfo},
+    response::IntoResponse,
+};
+
+pub use tokio_rustls::rustls;
+
+pub use self::handle::{serve, ServeHandle};
+
+use self::{
+    conn::Timeouts,
+    listener::{Addr, Listener, SocketOptions, Stream},
+    parse::Limits,
+    shutdown::Shutdown,
+};
+
+pub mod compat;
+mod conn;
+mod h2c;
+mod handle;
+mod listener;
+mod parse;
+mod proxy;
+pub mod shutdown;
+mod write;
+
+/// An HTTP server listening on a TCP address or Unix domain socket.
+#[derive(Clone, Debug)]
+pub struct Server {
+    /// Not empty unless made with an empty [`Server::bind_all`].
+    addrs: Vec<Addr>,
+    socket: SocketOptions,
+    limits: Limits,
+    timeouts: Timeouts,
+    hyper: bool,
+    h2c: bool,
+    proxy_protocol: bool,
+    tls: Option<Arc<rustls::ServerConfig>>,
+    shutdown_timeout: Duration,
+    max_connections: Option<usize>,
+    saturation: Saturation,
+}
+
+/// What a [`Server`] does with new connections while it has as many open as
+/// [`Server::max_connections`] allows.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Saturation {
+    /// Stop accepting connections until one closes. New ones wait in the
+    /// operating system's backlog, and are refused by it when that's full.
+    #[default]
+    Pause,
+    /// Accept connections and answer them with a 503 right away.
+    Reject,
+}
+
+impl Server {
+    /// A server for `addr`, e.g. `0.0.0.0:3000` or `localhost:8080`. Nothing
+    /// is bound until [`Server::serve`] is called.
+    pub fn bind(addr: impl Into<String>) -> Self {
+        Server {
+            addrs: vec![Addr::Tcp(addr.into())],
+            socket: SocketOptions::default(),
+            limits: Limits::default(),
+            timeouts: Timeouts::default(),
+            hyper: false,
+            h2c: false,
+            proxy_protocol: false,
+            tls: None,
+            shutdown_timeout: Duration::from_secs(30),
+            max_connections: None,
+            saturation: Saturation::Pause,
+        }
+    }
+
+    /// A server for every address in `addrs`, see [`Server::also_bind`].
+    pub fn bind_all<I>(addrs: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        Server {
+            addrs: addrs
+                .into_iter()
+                .map(|addr| Addr::Tcp(addr.into()))
+                .collect(),
+            ..Server::bind("")
+        }
+    }
+
+    /// A server for a listener that's already bound, e.g. one inherited from
+    /// a process manager. Options for the listening socket, such as the
+    /// [`backlog`](Server::backlog), don't apply to it.
+    pub fn from_listener(listener: std::net::TcpListener) -> Self {
+        Server {
+            addrs: vec![Addr::Listener(Arc::new(listener))],
+            ..Server::bind("")
+        }
+    }
+
+    /// A server for `addr` that speaks HTTPS, doing a TLS handshake with
+    /// `config` on every connection before the app factory is called.
+    ///
+    /// What the handshake negotiated is in [`ConnInfo::tls`]. Connections
+    /// that agree on `h2` with ALPN are served with HTTP/2, by hyper, and all
+    /// others with HTTP/1.1. List `h2` in the `alpn_protocols` of `config` to
+    /// offer it.
+    pub fn bind_rustls(addr: impl Into<String>, config: Arc<rustls::ServerConfig>) -> Self {
+        Server {
+            tls: Some(config),
+            ..Server::bind(addr)
+        }
+    }
+
+    /// A server for the Unix domain socket at `path`, e.g. to sit behind a
+    /// reverse proxy on the same machine. The credentials of connecting
+    /// processes are in [`ConnInfo::peer_cred`].
+    ///
+    /// A socket left at `path` by an earlier run is replaced, any other file
+    /// makes [`Server::serve`] fail.
+    #[cfg(unix)]
+    pub fn bind_uds(path: impl Into<PathBuf>) -> Self {
+        Server {
+            addrs: vec![Addr::Unix(path.into())],
+            ..Server::bind("")
+        }
+    }
+
+    /// Also listens on `addr`, e.g. `[::]:3000` next to `0.0.0.0:3000`, or
+    /// port 8080 next to port 80. Connections to every address are served
+    /// alike, by apps from the same app factory, and
+    /// [`ConnInfo::local_addr`] tells which one they came in on.
+    ///
+    /// A server made with [`Server::bind_rustls`] speaks HTTPS on all of
+    /// them.
+    pub fn also_bind(mut self, addr: impl Into<String>) -> Self {
+        self.addrs.push(Addr::Tcp(addr.into()));
+        self
+    }
+
+    /// Sets `TCP_NODELAY` on accepted connections, so small writes are sent
+    /// right away instead of being held back to fill a packet. Off by
+    /// default.
+    pub fn tcp_nodelay(mut self, enabled: bool) -> Self {
+        self.socket.nodelay = enabled;
+        self
+    }
+
+    /// Sets `SO_REUSEADDR` on the listening socket, so a restarted server
+    /// can bind the address while connections of the old one linger. On by
+    /// default on Unix, off elsewhere.
+    pub fn reuse_address(mut self, enabled: bool) -> Self {
+        self.socket.reuse_address = enabled;
+        self
+    }
+
+    /// Sets `SO_REUSEPORT` on the listening socket, so several processes can
+    /// bind the same address and share its connections. Off by default.
+    #[cfg(unix)]
+    pub fn reuse_port(mut self, enabled: bool) -> Self {
+        self.socket.reuse_port = enabled;
+        self
+    }
+
+    /// Sends TCP keepalive probes on connections that have been silent for
+    /// `time`, so ones whose client went away without closing them are
+    /// noticed. Off by default.
+    pub fn tcp_keepalive(mut self, time: Duration) -> Self {
+        self.socket.keepalive = Some(time);
+        self
+    }
+
+    /// How long to wait between unanswered keepalive probes. Defaults to
+    /// the operating system's setting, and is ignored where it can't be set.
+    pub fn tcp_keepalive_interval(mut self, interval: Duration) -> Self {
+        self.socket.keepalive_interval = Some(interval);
+        self
+    }
+
+    /// How many keepalive probes may go unanswered before the connection is
+    /// dropped. Defaults to the operating system's setting, and is ignored
+    /// where it can't be set.
+    pub fn tcp_keepalive_retries(mut self, retries: u32) -> Self {
+        self.socket.keepalive_retries = Some(retries);
+        self
+    }
+
+    /// How many connections may wait to be accepted before the operating
+    /// system refuses new ones. Defaults to 1024, and may be capped by the
+    /// operating system, e.g. by `net.core.somaxconn` on Linux.
+    pub fn backlog(mut self, backlog: u32) -> Self {
+        self.socket.backlog = backlog;
+        self
+    }
+
+    /// How many bytes the request line and headers of a request may take up
+    /// together. Larger requests get a 431. Defaults to 64 KiB.
+    pub fn max_head_size(mut self, bytes: usize) -> Self {
+        self.limits.max_head_size = bytes;
+        self
+    }
+
+    /// How many headers a request may have. Requests with more get a 431.
+    /// Defaults to 100.
+    pub fn max_headers(mut self, count: usize) -> Self {
+        self.limits.max_headers = count;
+        self
+    }
+
+    /// How long a client may take to send a request head, from its first
+    /// byte. Slower clients get a 408 and are disconnected. Defaults to 30
+    /// seconds.
+    pub fn header_read_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.header = Some(timeout);
+        self
+    }
+
+    /// How long a connection may sit idle between requests before it's
+    /// closed. Defaults to 60 seconds.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.idle = Some(timeout);
+        self
+    }
+
+    /// How long writing a response, body included, may take before the
+    /// connection is dropped. Unlimited by default, so that long streaming
+    /// responses work.
+    ///
+    /// Connections served by hyper, those speaking HTTP/2 and all of them
+    /// with [`hyper`](Server::hyper), are dropped once a single write stalls
+    /// for this long instead, as hyper doesn't tell when a response is
+    /// written.
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.write = Some(timeout);
+        self
+    }
+
+    /// Serves connections with hyper's HTTP/1.1 implementation instead of
+    /// this crate's. Off by default.
+    ///
+    /// The timeouts apply as they do to this crate's connections, except
+    /// for the [`write_timeout`](Server::write_timeout).
+    pub fn hyper(mut self, enabled: bool) -> Self {
+        self.hyper = enabled;
+        self
+    }
+
+    /// Serves HTTP/2 over connections without TLS to clients that start
+    /// with it right away, as gRPC clients do, or whose first request asks
+    /// to switch with `Upgrade: h2c`. Others are served HTTP/1.1 as usual.
+    /// Off by default.
+    ///
+    /// Only requests without a body are switched, those with one are
+    /// answered with HTTP/1.1. Over TLS, HTTP/2 is negotiated with ALPN
+    /// instead, see [`Server::bind_rustls`].
+    pub fn h2c(mut self, enabled: bool) -> Self {
+        self.h2c = enabled;
+        self
+    }
+
+    /// How many connections may be open at once. Unlimited by default.
+    pub fn max_connections(mut self, limit: usize) -> Self {
+        self.max_connections = Some(limit);
+        self
+    }
+
+    /// What to do with new connections while
+    /// [`max_connections`](Server::max_connections) are open. Defaults to
+    /// [`Saturation::Pause`].
+    pub fn on_saturation(mut self, saturation: Saturation) -> Self {
+        self.saturation = saturation;
+        self
+    }
+
+    /// Expects every connection to start with a PROXY protocol header, v1 or
+    /// v2, as load balancers such as HAProxy or AWS NLB send, and takes the
+    /// client's address in [`ConnInfo::peer_addr`] from it. Off by default.
+    ///
+    /// Connections without a valid header are closed. Only turn this on when
+    /// all connections come through such a load balancer, or clients could
+    /// claim any address.
+    pub fn proxy_protocol(mut self, enabled: bool) -> Self {
+        self.proxy_protocol = enabled;
+        self
+    }
+
+    /// How long a graceful shutdown waits for requests in flight before
+    /// giving up on them. Defaults to 30 seconds.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Binds the addresses and serves connections until ctrl-c is pressed,
+    /// then shuts down gracefully.
+    ///
+    /// Binding errors are returned, and no address is served if one of them
+    /// can't be bound. Errors accepting single connections,
+    /// their TLS handshakes, creating their app or serving them are logged
+    /// and only end that connection.
+    ///
+    /// Connections are only accepted while `app_factory` is ready to make an
+    /// app, e.g. under its concurrency limit. If it fails to get ready, the
+    /// server shuts down and returns the error.
+    pub async fn serve<AppFactory, App>(self, app_factory: AppFactory) -> io::Result<()>
+    where
+        AppFactory: Service<ConnInfo, Response = App>,
+        AppFactory::Error: fmt::Debug + Send,
+        AppFactory::Future: Send + 'static,
+        App: Service<Request, Response = Response> + Send + 'static,
+        App::Error: fmt::Debug + Send,
+        App::Future: Send + 'static,
+    {
+        self.serve_with_graceful_shutdown(app_factory, shutdown::ctrl_c())
+            .await
+    }
+
+    /// Like [`Server::serve`], but shuts down when `signal` resolves.
+    ///
+    /// Shutting down stops accepting connections and closes idle ones.
+    /// Requests in flight are answered, with `Connection: close`, and this
+    /// resolves once they are, or after the
+    /// [`shutdown_timeout`](Server::shutdown_timeout) at the latest.
+    pub async fn serve_with_graceful_shutdown<AppFactory, App, F>(
+        self,
+        mut app_factory: AppFactory,
+        signal: F,
+    ) -> io::Result<()>
+    where
+        AppFactory: Service<ConnInfo, Response = App>,
+        AppFactory::Error: fmt::Debug + Send,
+        AppFactory::Future: Send + 'static,
+        App: Service<Request, Response = Response> + Send + 'static,
+        App::Error: fmt::Debug + Send,
+        App::Future: Send + 'static,
+        F: Future<Output = ()>,
+    {
+        let listeners = self.listen().await?;
+        let acceptor = self.tls.clone().map(TlsAcceptor::from);
+        let scheme = if acceptor.is_some() { "https" } else { "http" };
+        for listener in &listeners {
+            info!("Listening on {}", listener.describe(scheme));
+        }
+
+        // PROXY headers are read and TLS handshakes done in tasks of their
+        // own, so a slow client doesn't hold up accepting others. They hand
+        // their connection back here for the app factory.
+        let (prepared_tx, mut prepared) = mpsc::unbounded_channel();
+        let (trigger, shutdown) = shutdown::channel();
+        let open = self
+            .max_connections
+            .map(|limit| Arc::new(Semaphore::new(limit)));
+        tokio::pin!(signal);
+        let mut turn = 0;
+        let mut failed = None;
+
+        let mut backoff = AcceptBackoff::default();
+        loop {
+            // Waiting here rather than once a connection is accepted keeps
+            // the signal heard while the factory isn't ready.
+            tokio::select! {
+                _ = &mut signal => break,
+                ready = future::poll_fn(|cx| app_factory.poll_ready(cx)) => {
+                    if let Err(e) = ready {
+                        error!(error = ?e, "App factory failed, shutting down");
+                        failed = Some(e);
+                        break;
+                    }
+                }
+            }
+
+            tokio::select! {
+                _ = &mut signal => break,
+                accepted = self.accept(&listeners, turn, open.as_ref()) => {
+                    turn += 1;
+                    let (stream, conn_info, permit) = match accepted {
+                        Ok(accepted) => {
+                            backoff.reset();
+                            accepted
+                        }
+                        Err(e) => {
+                            error!(error = ?e, "Failed to accept a connection");
+                            if let Some(delay) = backoff.after(&e) {
+                                tokio::time::sleep(delay).await;
+                            }
+                            continue;
+                        }
+                    };
+
+                    if acceptor.is_none() && !self.proxy_protocol {
+                        let shutdown = shutdown.clone();
+                        self.start(&mut app_factory, stream, conn_info, permit, shutdown)
+                            .await;
+                        continue;
+                    }
+
+                    let prepare = prepare(
+                        stream,
+                        conn_info,
+                        self.proxy_protocol,
+                        acceptor.clone(),
+                        self.timeouts.header,
+                    );
+                    let prepared_tx = prepared_tx.clone();
+                    tokio::spawn(async move {
+                        if let Ok((stream, conn_info)) = prepare.await {
+                            let _ = prepared_tx.send((stream, conn_info, permit));
+                        }
+                    });
+                }
+                Some((stream, conn_info, permit)) = prepared.recv() => {
+                    let shutdown = shutdown.clone();
+                    self.start(&mut app_factory, stream, conn_info, permit, shutdown)
+                        .await;
+                }
+            }
+        }
+
+        info!("Shutting down");
+        drop(listeners);
+        drop(shutdown);
+        if !trigger.shutdown(self.shutdown_timeout).await {
+            warn!(timeout = ?self.shutdown_timeout, "Connections still open, shutting down anyway");
+        }
+        match failed {
+            Some(e) => Err(io::Error::other(format!("app factory failed: {:?}", e))),
+            None => Ok(()),
+        }
+    }
+
+    /// Binds every address.
+    async fn listen(&self) -> io::Result<Vec<Listener>> {
+        if self.addrs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no address to listen on",
+            ));
+        }
+        let mut listeners = Vec::with_capacity(self.addrs.len());
+        for addr in &self.addrs {
+            listeners.push(Listener::bind(addr, self.socket).await?);
+        }
+        Ok(listeners)
+    }
+
+    /// Accepts the next connection on any of `listeners`, first waiting for
+    /// one to close if `open` is saturated and the server pauses then.
+    ///
+    /// The listeners are tried in turns starting at `turn`, so a busy one
+    /// doesn't starve the others.
+    ///
+    /// The permit is `None` if the connection is over the limit and is to be
+    /// rejected.
+    async fn accept(
+        &self,
+        listeners: &[Listener],
+        turn: usize,
+        open: Option<&Arc<Semaphore>>,
+    ) -> io::Result<(listener::Stream, ConnInfo, Option<OwnedSemaphorePermit>)> {
+        let accept = || {
+            let accepts = listeners
+                .iter()
+                .cycle()
+                .skip(turn % listeners.len())
+                .take(listeners.len())
+                .map(|listener| Box::pin(listener.accept()));
+            async { future::select_all(accepts).await.0 }
+        };
+        let Some(open) = open else {
+            let (stream, conn_info) = accept().await?;
+            return Ok((stream, conn_info, None));
+        };
+
+        let permit = match self.saturation {
+            Saturation::Pause => Some(
+                open.clone()
+                    .acquire_owned()
+                    .await
+                    .expect("the semaphore is never closed"),
+            ),
+            Saturation::Reject => None,
+        };
+        let (stream, conn_info) = accept().await?;
+        let permit = permit.or_else(|| open.clone().try_acquire_owned().ok());
+        Ok((stream, conn_info, permit))
+    }
+
+    /// Creates the app for a connection and spawns a task serving it, or
+    /// refuses the connection if it's over the limit. `app_factory` must be
+    /// ready.
+    async fn start<AppFactory, App, IO>(
+        &self,
+        app_factory: &mut AppFactory,
+        stream: IO,
+        conn_info: ConnInfo,
+        permit: Option<OwnedSemaphorePermit>,
+        shutdown: Shutdown,
+    ) where
+        AppFactory: Service<ConnInfo, Response = App>,
+        AppFactory::Error: fmt::Debug + Send,
+        AppFactory::Future: Send + 'static,
+        App: Service<Request, Response = Response> + Send + 'static,
+        App::Error: fmt::Debug + Send,
+        App::Future: Send + 'static,
+        IO: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        let http2 = conn_info
+            .tls
+            .as_ref()
+            .is_some_and(|tls| tls.alpn_protocol.as_deref() == Some(b"h2"));
+
+        if self.max_connections.is_some() && permit.is_none() {
+            warn!(peer = ?conn_info.peer_addr, "Too many connections, refusing one");
+            // An HTTP/2 client would take the HTTP/1.1 response for a
+            // protocol error, so it's just disconnected.
+            if !http2 {
+                tokio::spawn(conn::refuse_connection(
+                    stream,
+                    StatusCode::SERVICE_UNAVAILABLE.into_response(),
+                ));
+            }
+            return;
+        }
+
+        let future = app_factory.call(conn_info.clone());
+        let limits = self.limits;
+        let timeouts = self.timeouts;
+        let hyper = self.hyper;
+        let h2c = self.h2c && conn_info.tls.is_none();
+
+        let span = info_span!("conn", id = conn_info.id, peer = field::Empty);
+        if let Some(peer_addr) = conn_info.peer_addr {
+            span.record("peer", field::display(peer_addr));
+        }
+
+        tokio::spawn(
+            async move {
+                let _permit = permit;
+                match future.await {
+                    Ok(app) => {
+                        let result = if h2c {
+                            h2c::serve_connection(
+                                stream, app, conn_info, limits, timeouts, hyper, shutdown,
+                            )
+                            .await
+                        } else if hyper || http2 {
+                            compat::serve_connection(
+                                stream, app, conn_info, limits, timeouts, http2, shutdown,
+                            )
+                            .await
+                        } else {
+                            conn::serve_connection(
+                                stream, app, conn_info, limits, timeouts, shutdown,
+                            )
+                            .await
+                        };
+                        if let Err(e) = result {
+                            warn!(error = ?e, "Connection failed");
+                        }
+                    }
+                    Err(e) => error!(error = ?e, "Failed to make the app"),
+                }
+            }
+            .instrument(span),
+        );
+    }
+}
+
+/// Reads the PROXY header and does the TLS handshake a connection starts
+/// with, as far as they're enabled, within `timeout`. Failures are logged.
+async fn prepare(
+    mut stream: Stream,
+    mut conn_info: ConnInfo,
+    proxy_protocol: bool,
+    acceptor: Option<TlsAcceptor>,
+    timeout: Option<Duration>,
+) -> Result<(Stream, ConnInfo), ()> {
+    let prepare = async {
+        if proxy_protocol {
+            let (client, read) = proxy::read_header(&mut stream).await?;
+            if let Some(client) = client {
+                conn_info.peer_addr = Some(client);
+            }
+            if !read.is_empty() {
+                stream = Stream::Rewound(read, Box::new(stream));
+            }
+        }
+        if let Some(acceptor) = acceptor {
+            let tls = acceptor.accept(stream).await?;
+            conn_info.tls = Some(tls_info(&tls));
+            stream = Stream::Tls(Box::new(tls));
+        }
+        Ok::<_, io::Error>(stream)
+    };
+    let result = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, prepare)
+            .await
+            .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into())),
+        None => prepare.await,
+    };
+
+    match result {
+        Ok(stream) => Ok((stream, conn_info)),
+        Err(e) => {
+            warn!(peer = ?conn_info.peer_addr, error = ?e, "Setting up a connection failed");
+            Err(())
+        }
+    }
+}
+
+impl From<&str> for Server {
+    fn from(addr: &str) -> Self {
+        Server::bind(addr)
+    }
+}
+
+impl From<String> for Server {
+    fn from(addr: String) -> Self {
+        Server::bind(addr)
+    }
+}
+
+impl From<SocketAddr> for Server {
+    fn from(addr: SocketAddr) -> Self {
+        Server::bind(addr.to_string())
+    }
+}
+
+impl From<std::net::TcpListener> for Server {
+    fn from(listener: std::net::TcpListener) -> Self {
+        Server::from_listener(listener)
+    }
+}
+
+fn tls_info<IO>(stream: &TlsStream<IO>) -> TlsInfo {
+    let (_, conn) = stream.get_ref();
+    TlsInfo {
+        server_name: conn.server_name().map(str::to_owned),
+        alpn_protocol: conn.alpn_protocol().map(<[u8]>::to_vec),
+        peer_cert: conn.peer_certificates().and_then(|chain| {
+            PeerCert::from_chain(chain.iter().map(|cert| cert.0.clone().into()).collect())
+        }),
+    }
+}
+
+/// How long to wait before accepting again after failing to. Running out of
+/// file descriptors or memory lasts until connections close, and trying
+/// again right away would only spin.
+#[derive(Debug, Default)]
+struct AcceptBackoff(Option<Duration>);
+
+impl AcceptBackoff {
+    const MIN: Duration = Duration::from_millis(10);
+    const MAX: Duration = Duration::from_secs(1);
+
+    /// The delay after `e`, doubling with every failure in a row. None for
+    /// a connection the client gave up on before it was accepted.
+    fn after(&mut self, e: &io::Error) -> Option<Duration> {
+        if matches!(
+            e.kind(),
+            io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionRefused
+        ) {
+            return None;
+        }
+        let delay = self.0.map_or(Self::MIN, |delay| (delay * 2).min(Self::MAX));
+        self.0 = Some(delay);
+        Some(delay)
+    }
+
+    fn reset(&mut self) {
+        self.0 = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        task::{Context, Poll},
+    };
+
+    use futures_util::future::BoxFuture;
+    use tokio::{net::TcpStream, sync::oneshot, time::timeout};
+
+    use super::*;
+
+    /// An app factory whose readiness is `ready`, that never makes an app.
+    struct Factory {
+        ready: Poll<Result<(), &'static str>>,
+    }
+
+    impl Service<ConnInfo> for Factory {
+        type Response = tower::util::BoxService<Request, Response, Infallible>;
+        type Error = &'static str;
+        type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            self.ready
+        }
+
+        fn call(&mut self, _conn_info: ConnInfo) -> Self::Future {
+            unreachable!("the factory is never ready")
+        }
+    }
+
+    #[tokio::test]
+    async fn shuts_down_while_factory_is_unready() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(
+            Server::from_listener(listener).serve_with_graceful_shutdown(
+                Factory {
+                    ready: Poll::Pending,
+                },
+                async {
+                    let _ = rx.await;
+                },
+            ),
+        );
+
+        // The connection waits in the backlog, not in the accept loop.
+        let _client = TcpStream::connect(addr).await.unwrap();
+        tx.send(()).unwrap();
+        let result = timeout(Duration::from_secs(5), server).await;
+        assert!(matches!(result, Ok(Ok(Ok(())))));
+    }
+
+    #[tokio::test]
+    async fn returns_the_error_of_a_failed_factory() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let result = Server::from_listener(listener)
+            .serve_with_graceful_shutdown(
+                Factory {
+                    ready: Poll::Ready(Err("broken")),
+                },
+                future::pending(),
+            )
+            .await;
+        assert!(result.unwrap_err().to_string().contains("broken"));
+    }
+
+    #[test]
+    fn backs_off_accepting_while_it_fails() {
+        let mut backoff = AcceptBackoff::default();
+        let aborted = io::Error::from(io::ErrorKind::ConnectionAborted);
+        assert_eq!(backoff.after(&aborted), None);
+
+        let exhausted = io::Error::other("Too many open files");
+        let delays: Vec<_> = (0..9).filter_map(|_| backoff.after(&exhausted)).collect();
+        let millis: Vec<_> = delays.iter().map(Duration::as_millis).collect();
+        assert_eq!(millis, [10, 20, 40, 80, 160, 320, 640, 1000, 1000]);
+
+        backoff.reset();
+        assert_eq!(backoff.after(&exhausted), Some(AcceptBackoff::MIN));
+    }
+}
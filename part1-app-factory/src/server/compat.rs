@@ -0,0 +1,584 @@
+//! Adapters between this crate's services and hyper's, so apps can be served
+//! by hyper and hyper-based services can be mounted in apps.
+//!
+//! ```no_run
+//! # use std::convert::Infallible;
+//! # use hyper::service::make_service_fn;
+//! # use tower::service_fn;
+//! # use part1_app_factory::{
+//! #     http::ConnInfo,
+//! #     router::Router,
+//! #     server::{
+//! #         compat::{FromHyper, ToHyper},
+//! #         Server,
+//! #     },
+//! # };
+//! # async fn run(app: Router) -> anyhow::Result<()> {
+//! # let addr = ([127, 0, 0, 1], 3000).into();
+//! # let legacy = hyper::service::service_fn(|_req: hyper::Request<hyper::Body>| async {
+//! #     Ok::<_, Infallible>(hyper::Response::new(hyper::Body::empty()))
+//! # });
+//! // An app served by hyper.
+//! hyper::Server::bind(&addr)
+//!     .serve(make_service_fn(|_| {
+//!         let app = app.clone();
+//!         async move { Ok::<_, Infallible>(ToHyper::new(app)) }
+//!     }))
+//!     .await?;
+//!
+//! // A hyper service served by this crate's server.
+//! Server::bind("0.0.0.0:3000")
+//!     .serve(service_fn(|_| {
+//!         let legacy = legacy.clone();
+//!         async move { Ok::<_, Infallible>(FromHyper::new(legacy)) }
+//!     }))
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Bodies are streamed through in both directions, unlike the `TryFrom`
+//! conversions on [`Request`] and [`Response`]. Extensions don't carry over.
+
+use std::{
+    fmt,
+    future::Future,
+    io::{self, IoSlice},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use anyhow::Error;
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use hyper::body::HttpBody;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::watch,
+    time::{sleep, Sleep},
+};
+use tower::{Service, ServiceExt};
+use tracing::{error, Instrument};
+
+use super::{conn::Timeouts, parse::Limits, shutdown::Shutdown};
+use crate::{
+    extract::ConnectInfo,
+    http::{
+        request_from_http, request_to_http, response_from_http, response_to_http, Body, ConnInfo,
+        Request, Response, StatusCode,
+    },
+    response::IntoResponse,
+};
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// Serves a service of this crate's [`Request`]s and [`Response`]s to hyper.
+///
+/// Requests hyper parsed but this crate can't represent get a 400. Errors of
+/// the service are logged and answered with a 500, as [`Server`] does, so
+/// hyper keeps the connection open.
+///
+/// [`Server`]: super::Server
+#[derive(Clone, Debug)]
+pub struct ToHyper<S> {
+    inner: S,
+    /// Set when the service failed to become ready, so the next call gets a
+    /// 500 without calling it.
+    not_ready: bool,
+}
+
+impl<S> ToHyper<S> {
+    pub fn new(inner: S) -> Self {
+        ToHyper {
+            inner,
+            not_ready: false,
+        }
+    }
+}
+
+impl<S> Service<hyper::Request<hyper::Body>> for ToHyper<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Error: fmt::Debug,
+    S::Future: Send + 'static,
+{
+    type Response = hyper::Response<hyper::Body>;
+    type Error = std::convert::Infallible;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // hyper closes the connection when this fails, so the failure is
+        // answered in `call` instead.
+        match self.inner.poll_ready(cx) {
+            Poll::Ready(Err(e)) => {
+                error!(error = ?e, "Service not able to accept a request");
+                self.not_ready = true;
+                Poll::Ready(Ok(()))
+            }
+            Poll::Ready(Ok(())) => Poll::Ready(Ok(())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn call(&mut self, req: hyper::Request<hyper::Body>) -> Self::Future {
+        if std::mem::take(&mut self.not_ready) {
+            let resp = StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            return Box::pin(async move { Ok(response_to_hyper(resp)) });
+        }
+
+        let req = match request_from_http(req.map(body_from_hyper)) {
+            Ok(req) => req,
+            Err(error) => {
+                let resp = (StatusCode::BAD_REQUEST, error.to_string()).into_response();
+                return Box::pin(async move { Ok(response_to_hyper(resp)) });
+            }
+        };
+
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let resp = future.await.unwrap_or_else(|e| {
+                error!(error = ?e, "Request failed");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            });
+            Ok(response_to_hyper(resp))
+        })
+    }
+}
+
+/// Makes a hyper service usable as a service of this crate, e.g. as the app
+/// a [`Server`](super::Server) serves.
+///
+/// Requests that can't be represented for hyper, such as ones with extension
+/// methods longer than 15 bytes, fail with an error like the service's own
+/// errors do.
+#[derive(Clone, Debug)]
+pub struct FromHyper<S> {
+    inner: S,
+}
+
+impl<S> FromHyper<S> {
+    pub fn new(inner: S) -> Self {
+        FromHyper { inner }
+    }
+}
+
+impl<S> Service<Request> for FromHyper<S>
+where
+    S: Service<hyper::Request<hyper::Body>, Response = hyper::Response<hyper::Body>>,
+    S::Error: Into<Error>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Error;
+    type Future = BoxFuture<Result<Response, Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let req = match request_to_http(req) {
+            Ok(req) => req.map(body_into_hyper),
+            Err(error) => return Box::pin(async move { Err(error.into()) }),
+        };
+
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let resp = future.await.map_err(Into::into)?;
+            Ok(response_from_http(resp.map(body_from_hyper))?)
+        })
+    }
+}
+
+/// Serves `stream` with hyper, speaking HTTP/2 if `http2` and HTTP/1.1
+/// otherwise.
+///
+/// hyper has no limit on the number of headers to configure, it refuses
+/// HTTP/1.1 requests with more than 100. HTTP/2 streams are answered
+/// concurrently, each by its own call to `app`, and their bodies are subject
+/// to HTTP/2 flow control: a streaming body is only read as fast as the peer
+/// lets it be sent.
+///
+/// Of the `timeouts`, hyper applies the one for headers itself, to HTTP/1.1
+/// only. The connection is idle while no request is being answered, until
+/// the body of the last response was handed to hyper, and is closed as at
+/// shutdown once it's been idle for the idle timeout. hyper writes responses
+/// as it sees fit, so the write timeout can't cover a whole response here:
+/// instead, a write to `stream` that makes no progress for that long fails
+/// and drops the connection.
+///
+/// Once `shutdown` starts, hyper answers requests in flight and closes the
+/// connection.
+pub(super) async fn serve_connection<IO, App>(
+    stream: IO,
+    app: App,
+    conn_info: ConnInfo,
+    limits: Limits,
+    timeouts: Timeouts,
+    http2: bool,
+    mut shutdown: Shutdown,
+) -> io::Result<()>
+where
+    IO: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    App: Service<Request, Response = Response> + Send + 'static,
+    App::Error: fmt::Debug,
+    App::Future: Send + 'static,
+{
+    let in_flight = InFlight::default();
+    let idle = in_flight.idle(timeouts.idle);
+    let app = app
+        .map_request(move |mut req: Request| {
+            req.extensions.insert(ConnectInfo(conn_info.clone()));
+            req
+        })
+        .map_future(move |future| {
+            let answering = in_flight.start();
+            async move { future.await.map(|resp| answering.until_sent(resp)) }
+        });
+    let stream = WriteTimeout::new(stream, timeouts.write);
+
+    let mut http = hyper::server::conn::Http::new().with_executor(TokioExecutor);
+    if http2 {
+        http.http2_only(true)
+            .http2_max_header_list_size(limits.max_head_size.try_into().unwrap_or(u32::MAX));
+    } else {
+        http.http1_only(true)
+            // hyper's buffer holds a whole head, and can't be smaller than
+            // 8 KiB.
+            .max_buf_size(limits.max_head_size.max(8192));
+        if let Some(header) = timeouts.header {
+            http.http1_header_read_timeout(header);
+        }
+    }
+    let conn = http.serve_connection(stream, ToHyper::new(app));
+    tokio::pin!(conn);
+    tokio::select! {
+        result = conn.as_mut() => return result.map_err(io::Error::other),
+        _ = shutdown.started() => conn.as_mut().graceful_shutdown(),
+        _ = idle => conn.as_mut().graceful_shutdown(),
+    }
+    conn.await.map_err(io::Error::other)
+}
+
+/// Counts the requests of a connection being answered, for its idle
+/// timeout.
+#[derive(Clone, Default)]
+struct InFlight(Arc<watch::Sender<usize>>);
+
+impl InFlight {
+    fn start(&self) -> Answering {
+        self.0.send_modify(|count| *count += 1);
+        Answering(self.0.clone())
+    }
+
+    /// Resolves once no request was answered for `timeout`. Never resolves
+    /// without one.
+    fn idle(&self, timeout: Option<Duration>) -> impl Future<Output = ()> + Send + 'static {
+        let mut count = self.0.subscribe();
+        async move {
+            let Some(timeout) = timeout else {
+                return std::future::pending().await;
+            };
+            loop {
+                // The sender is only gone once hyper is done with the
+                // connection.
+                if count.wait_for(|count| *count == 0).await.is_err() {
+                    return std::future::pending().await;
+                }
+                tokio::select! {
+                    _ = sleep(timeout) => return,
+                    _ = count.wait_for(|count| *count > 0) => {}
+                }
+            }
+        }
+    }
+}
+
+/// A request being answered, until dropped.
+struct Answering(Arc<watch::Sender<usize>>);
+
+impl Answering {
+    /// Keeps the request counted until the body of `resp` was read to the
+    /// end, unless it's in memory already.
+    fn until_sent(self, mut resp: Response) -> Response {
+        let mut body = std::mem::take(&mut resp.body);
+        let trailers = body.take_trailers();
+        let mut body = match body.try_into_bytes() {
+            Ok(bytes) => Body::from(bytes),
+            Err(body) => {
+                let len = body.size_hint().exact();
+                let stream = Counted {
+                    body,
+                    _answering: self,
+                };
+                match len {
+                    Some(len) => Body::from_stream_with_len(stream, len),
+                    None => Body::from_stream(stream),
+                }
+            }
+        };
+        if let Some(trailers) = trailers {
+            body = body.with_trailers(trailers);
+        }
+        resp.body = body;
+        resp
+    }
+}
+
+impl Drop for Answering {
+    fn drop(&mut self) {
+        self.0.send_modify(|count| *count -= 1);
+    }
+}
+
+/// A response body whose request is counted as being answered until it's
+/// dropped.
+struct Counted {
+    body: Body,
+    _answering: Answering,
+}
+
+impl Stream for Counted {
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.body.poll_next_unpin(cx)
+    }
+}
+
+/// A stream whose writes fail once they've made no progress for `timeout`.
+struct WriteTimeout<IO> {
+    inner: IO,
+    timeout: Option<Duration>,
+    /// Set while a write is pending.
+    stalled: Option<Pin<Box<Sleep>>>,
+}
+
+impl<IO> WriteTimeout<IO> {
+    fn new(inner: IO, timeout: Option<Duration>) -> Self {
+        WriteTimeout {
+            inner,
+            timeout,
+            stalled: None,
+        }
+    }
+
+    fn check<T>(&mut self, cx: &mut Context<'_>, poll: Poll<io::Result<T>>) -> Poll<io::Result<T>> {
+        if poll.is_ready() {
+            self.stalled = None;
+            return poll;
+        }
+        let Some(timeout) = self.timeout else {
+            return poll;
+        };
+        let stalled = self.stalled.get_or_insert_with(|| Box::pin(sleep(timeout)));
+        match stalled.as_mut().poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Err(io::ErrorKind::TimedOut.into())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<IO> AsyncRead for WriteTimeout<IO>
+where
+    IO: AsyncRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+    }
+}
+
+impl<IO> AsyncWrite for WriteTimeout<IO>
+where
+    IO: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_write(cx, buf);
+        this.check(cx, poll)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_write_vectored(cx, bufs);
+        this.check(cx, poll)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_flush(cx);
+        this.check(cx, poll)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_shutdown(cx);
+        this.check(cx, poll)
+    }
+}
+
+/// Runs the tasks hyper spawns for HTTP/2 streams.
+#[derive(Clone, Copy, Debug)]
+struct TokioExecutor;
+
+impl<F> hyper::rt::Executor<F> for TokioExecutor
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    fn execute(&self, future: F) {
+        // Streams are served in the connection's span.
+        tokio::spawn(future.in_current_span());
+    }
+}
+
+fn body_from_hyper(body: hyper::Body) -> Body {
+    if HttpBody::is_end_stream(&body) {
+        return Body::empty();
+    }
+    match HttpBody::size_hint(&body).exact() {
+        Some(len) => Body::from_stream_with_len(body, len),
+        None => Body::from_stream(body),
+    }
+}
+
+/// Keeps bodies in memory whole, so hyper knows their length.
+fn body_into_hyper(body: Body) -> hyper::Body {
+    match body.try_into_bytes() {
+        Ok(bytes) => hyper::Body::from(bytes),
+        Err(body) => hyper::Body::wrap_stream(body),
+    }
+}
+
+fn response_to_hyper(resp: Response) -> hyper::Response<hyper::Body> {
+    response_to_http(resp).map(body_into_hyper)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, time::Duration};
+
+    use tokio::{
+        io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream},
+        task::JoinHandle,
+        time::timeout,
+    };
+    use tower::service_fn;
+
+    use super::*;
+    use crate::server::shutdown;
+
+    /// Serves a connection with hyper and `app`, answering after `delay`
+    /// with a body of `len` bytes.
+    fn connect(
+        delay: Duration,
+        len: usize,
+        timeouts: Timeouts,
+    ) -> (DuplexStream, JoinHandle<io::Result<()>>) {
+        let app = service_fn(move |_req: Request| async move {
+            sleep(delay).await;
+            Ok::<_, Infallible>(vec![b'a'; len].into_response())
+        });
+        let conn_info = ConnInfo {
+            id: ConnInfo::next_id(),
+            host_and_port: "localhost:3000".to_owned(),
+            local_addr: None,
+            peer_addr: None,
+            peer_cred: None,
+            tls: None,
+        };
+        let (client, server) = duplex(1024);
+        let (trigger, shutdown) = shutdown::channel();
+        let served = tokio::spawn(async move {
+            let _trigger = trigger;
+            let limits = Limits::default();
+            serve_connection(server, app, conn_info, limits, timeouts, false, shutdown).await
+        });
+        (client, served)
+    }
+
+    fn idle_timeout(idle: Duration) -> Timeouts {
+        Timeouts {
+            idle: Some(idle),
+            ..Timeouts::default()
+        }
+    }
+
+    /// Reads until the connection is closed.
+    async fn read_to_end(client: &mut DuplexStream) -> String {
+        let mut buf = Vec::new();
+        timeout(Duration::from_secs(5), client.read_to_end(&mut buf))
+            .await
+            .expect("connection left open")
+            .unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[tokio::test]
+    async fn closes_idle_connections() {
+        let (mut client, served) =
+            connect(Duration::ZERO, 2, idle_timeout(Duration::from_millis(100)));
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n")
+            .await
+            .unwrap();
+        let resp = read_to_end(&mut client).await;
+        assert!(resp.starts_with("HTTP/1.1 200 "), "{}", resp);
+        assert!(resp.ends_with("\r\n\r\naa"), "{}", resp);
+        served.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn waits_for_slow_responses() {
+        let (mut client, _served) = connect(
+            Duration::from_millis(300),
+            2,
+            idle_timeout(Duration::from_millis(100)),
+        );
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n")
+            .await
+            .unwrap();
+        let resp = read_to_end(&mut client).await;
+        assert!(resp.ends_with("\r\n\r\naa"), "{}", resp);
+    }
+
+    #[tokio::test]
+    async fn drops_connections_whose_writes_stall() {
+        let timeouts = Timeouts {
+            write: Some(Duration::from_millis(100)),
+            ..Timeouts::default()
+        };
+        let (mut client, served) = connect(Duration::ZERO, 1024 * 1024, timeouts);
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n")
+            .await
+            .unwrap();
+        // The client doesn't read the response.
+        let result = timeout(Duration::from_secs(5), served)
+            .await
+            .expect("write didn't time out")
+            .unwrap();
+        assert!(result.is_err());
+        // Closing the client would have failed the write too.
+        drop(client);
+    }
+}
@@ -0,0 +1,696 @@
+//! Serving the requests of one connection.
+
+use std::{fmt, future::Future, io, time::Duration};
+
+use bytes::{Bytes, BytesMut};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::{mpsc, oneshot},
+    time::{sleep, timeout, timeout_at, Instant},
+};
+use tower::{Service, ServiceExt};
+use tracing::error;
+
+use super::{
+    parse::{parse_chunk_size, parse_head, parse_trailer, Framing, Head, Limits, ParseError},
+    shutdown::Shutdown,
+    write::{write_response, RequestInfo},
+};
+use crate::{
+    extract::{ClientDisconnect, ConnectInfo},
+    http::{Body, ConnInfo, HeaderMap, Request, Response, StatusCode},
+    response::IntoResponse,
+};
+
+/// How long a connection may take for each step before it's closed.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Timeouts {
+    /// From the first byte of a request until its head is complete. A
+    /// client that's too slow gets a 408.
+    pub(super) header: Option<Duration>,
+    /// Between requests.
+    pub(super) idle: Option<Duration>,
+    /// For writing a whole response, body included.
+    pub(super) write: Option<Duration>,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            header: Some(Duration::from_secs(30)),
+            idle: Some(Duration::from_secs(60)),
+            write: None,
+        }
+    }
+}
+
+/// Answers requests from `stream` with `app`, one after the other, until
+/// either side closes the connection.
+///
+/// Requests the client sends before the previous response is done
+/// (pipelining) wait in the read buffer and are answered in order.
+///
+/// Request bodies that didn't arrive with the head are read as the app
+/// reads them. A client that sends `Expect: 100-continue` is only told to
+/// send the body once the app starts reading it. An app that responds
+/// without reading it, e.g. with a 413 for a `Content-Length` over the
+/// limit, saves the server from reading it, and a waiting client from
+/// sending it. Either way, the connection is closed after a response that
+/// left part of the body unread.
+///
+/// A handler whose client closes the connection before it responds is
+/// dropped, and the connection is closed without a response. The request's
+/// [`ClientDisconnect`] reports it to whatever the handler handed off. A
+/// client that only shuts down its sending half counts as gone too.
+///
+/// Once `shutdown` starts, the request being answered is the last one, and
+/// an idle connection is closed right away.
+pub(super) async fn serve_connection<IO, App>(
+    mut stream: IO,
+    mut app: App,
+    conn_info: ConnInfo,
+    limits: Limits,
+    timeouts: Timeouts,
+    mut shutdown: Shutdown,
+) -> io::Result<()>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+    App: Service<Request, Response = Response>,
+    App::Error: fmt::Debug,
+{
+    let mut buf = BytesMut::with_capacity(4096);
+
+    loop {
+        // Waiting for the next request is the only time the connection is
+        // idle.
+        if buf.is_empty() {
+            tokio::select! {
+                biased;
+                _ = shutdown.started() => break,
+                _ = sleep_for(timeouts.idle) => break,
+                read = stream.read_buf(&mut buf) => {
+                    if read? == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+
+        let header_deadline = timeouts.header.map(|header| Instant::now() + header);
+        let (mut req, streamed) =
+            match read_request(&mut stream, &mut buf, &limits, header_deadline).await? {
+                Some(Ok(read)) => read,
+                Some(Err(error)) => {
+                    let resp = error.into_response();
+                    with_timeout(
+                        timeouts.write,
+                        write_response(&mut stream, resp, RequestInfo::closing()),
+                    )
+                    .await?;
+                    break;
+                }
+                None => break,
+            };
+        req.extensions.insert(ConnectInfo(conn_info.clone()));
+        let (disconnected, disconnect) = ClientDisconnect::channel();
+        req.extensions.insert(disconnect);
+
+        let mut info = RequestInfo::new(&req);
+        let call = async {
+            match app.ready().await {
+                Ok(app) => app.call(req).await,
+                Err(e) => Err(e),
+            }
+        };
+        let resp = match streamed {
+            Some(streamed) => {
+                let (resp, body_read) = streamed
+                    .read_while(call, &mut stream, &mut buf, &limits)
+                    .await;
+                // Whatever is left of the body would be taken for the next
+                // request.
+                if !body_read {
+                    info.keep_alive = false;
+                }
+                resp
+            }
+            None => unless_closed(call, &mut stream, &mut buf, &limits).await,
+        };
+        let Some(resp) = resp else {
+            // The handler was dropped with `call`, and anything it handed
+            // off learns about it here.
+            let _ = disconnected.send(true);
+            return Ok(());
+        };
+        let resp = resp.unwrap_or_else(|e| {
+            error!(error = ?e, "Request failed");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        });
+
+        if shutdown.is_started() {
+            info.keep_alive = false;
+        }
+        if !with_timeout(timeouts.write, write_response(&mut stream, resp, info)).await? {
+            break;
+        }
+    }
+
+    stream.shutdown().await
+}
+
+/// Answers a connection with `resp` without reading any request, and closes
+/// it.
+pub(super) async fn refuse_connection<IO>(mut stream: IO, resp: Response) -> io::Result<()>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    write_response(&mut stream, resp, RequestInfo::closing()).await?;
+    stream.shutdown().await
+}
+
+/// Reads the next request, taking its bytes out of `buf` and leaving any
+/// that follow for the next call.
+///
+/// Only a body that's in `buf` already, having come with the head, is taken
+/// here. Any other isn't read here but by the [`StreamedBody`] that comes
+/// with it.
+///
+/// `None` if the client closed the connection between requests.
+async fn read_request<S>(
+    stream: &mut S,
+    buf: &mut BytesMut,
+    limits: &Limits,
+    header_deadline: Option<Instant>,
+) -> io::Result<Option<Result<(Request, Option<StreamedBody>), ParseError>>>
+where
+    S: AsyncRead + Unpin,
+{
+    let head = loop {
+        match parse_head(buf, limits) {
+            Ok(Some(head)) => break head,
+            Ok(None) => {}
+            Err(error) => return Ok(Some(Err(error))),
+        }
+        let read = match header_deadline {
+            Some(deadline) => match timeout_at(deadline, stream.read_buf(buf)).await {
+                Ok(read) => read?,
+                Err(_) => return Ok(Some(Err(ParseError::Timeout))),
+            },
+            None => stream.read_buf(buf).await?,
+        };
+        if read == 0 {
+            if buf.iter().all(|b| matches!(b, b'\r' | b'\n')) {
+                return Ok(None);
+            }
+            return Ok(Some(Err(ParseError::Incomplete)));
+        }
+    };
+
+    let Head {
+        mut req,
+        len,
+        framing,
+        expect_continue,
+    } = head;
+    let _ = buf.split_to(len);
+    match framing {
+        Framing::Length(0) => {}
+        Framing::Length(len) if !expect_continue && len <= buf.len() as u64 => {
+            req.body = Body::from(buf.split_to(len as usize).freeze());
+        }
+        _ => {
+            let (body, streamed) = StreamedBody::new(framing, expect_continue);
+            req.body = body;
+            return Ok(Some(Ok((req, Some(streamed)))));
+        }
+    }
+
+    Ok(Some(Ok((req, None))))
+}
+
+/// The body of a request that's read off the connection while the app
+/// runs, once it polls the [`Body`] it was handed: a chunked one, one whose
+/// client waits for `100 Continue` before sending it, or one that hasn't
+/// all arrived yet.
+struct StreamedBody {
+    framing: Framing,
+    expect_continue: bool,
+    polled: oneshot::Receiver<()>,
+    chunks: mpsc::Sender<io::Result<Bytes>>,
+    trailers: Option<oneshot::Sender<HeaderMap>>,
+}
+
+impl StreamedBody {
+    fn new(framing: Framing, expect_continue: bool) -> (Body, Self) {
+        let (polled_tx, polled) = oneshot::channel();
+        let (chunks, chunks_rx) = mpsc::channel(1);
+        let stream = futures_util::stream::unfold(
+            (Some(polled_tx), chunks_rx),
+            |(polled_tx, mut chunks_rx)| async move {
+                if let Some(polled_tx) = polled_tx {
+                    let _ = polled_tx.send(());
+                }
+                let chunk = chunks_rx.recv().await?;
+                Some((chunk, (None, chunks_rx)))
+            },
+        );
+        let (body, trailers) = match framing {
+            Framing::Length(len) => (Body::from_stream_with_len(stream, len), None),
+            Framing::Chunked => {
+                let (trailers, trailers_rx) = oneshot::channel();
+                let body =
+                    Body::from_stream(stream).with_trailers(async move { trailers_rx.await.ok() });
+                (body, Some(trailers))
+            }
+        };
+        let streamed = StreamedBody {
+            framing,
+            expect_continue,
+            polled,
+            chunks,
+            trailers,
+        };
+        (body, streamed)
+    }
+
+    /// Runs `call` to completion, meanwhile reading the body for the app if
+    /// it starts reading it. Also returns whether the whole body was read
+    /// off the connection.
+    ///
+    /// Once it was, `call` is dropped if the client closes the connection,
+    /// as with [`unless_closed`], and there's no output.
+    async fn read_while<IO, F>(
+        self,
+        call: F,
+        stream: &mut IO,
+        buf: &mut BytesMut,
+        limits: &Limits,
+    ) -> (Option<F::Output>, bool)
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+        F: Future,
+    {
+        tokio::pin!(call);
+        {
+            let read = self.read(stream, buf, limits);
+            tokio::pin!(read);
+            let mut reading = true;
+            loop {
+                tokio::select! {
+                    output = &mut call => return (Some(output), false),
+                    body_read = &mut read, if reading => {
+                        if body_read {
+                            break;
+                        }
+                        // What follows the body can't be told apart from it.
+                        reading = false;
+                    }
+                }
+            }
+        }
+        (unless_closed(call, stream, buf, limits).await, true)
+    }
+
+    /// Waits for the app to poll the body, asks the client for it if it's
+    /// waiting to be asked, and reads it. Returns whether all of it was read,
+    /// which it isn't if the app drops it part way, or it can't be read. The
+    /// app gets the error then.
+    async fn read<IO>(mut self, stream: &mut IO, buf: &mut BytesMut, limits: &Limits) -> bool
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        if (&mut self.polled).await.is_err() {
+            // Dropped unread, so the client is never asked for the body.
+            std::future::pending::<()>().await;
+        }
+        let read = async {
+            if self.expect_continue {
+                stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").await?;
+                stream.flush().await?;
+            }
+            match self.framing {
+                Framing::Length(len) => self.forward(stream, buf, len).await,
+                Framing::Chunked => self.read_chunked(stream, buf, limits).await,
+            }
+        };
+        match read.await {
+            Ok(complete) => complete,
+            Err(e) => {
+                let _ = self.chunks.send(Err(e)).await;
+                false
+            }
+        }
+    }
+
+    /// Reads chunks until the last one, and the trailers after it.
+    async fn read_chunked<IO>(
+        &mut self,
+        stream: &mut IO,
+        buf: &mut BytesMut,
+        limits: &Limits,
+    ) -> io::Result<bool>
+    where
+        IO: AsyncRead + Unpin,
+    {
+        loop {
+            let line = read_line(stream, buf, MAX_CHUNK_LINE).await?;
+            let size = parse_chunk_size(&line).map_err(invalid_body)?;
+            if size == 0 {
+                break;
+            }
+            if !self.forward(stream, buf, size).await? {
+                return Ok(false);
+            }
+            if !read_line(stream, buf, 0).await?.is_empty() {
+                return Err(invalid_body(ParseError::Chunk));
+            }
+        }
+
+        let mut trailers = HeaderMap::new();
+        let mut size = 0;
+        loop {
+            let line = read_line(stream, buf, limits.max_head_size.saturating_sub(size)).await?;
+            if line.is_empty() {
+                break;
+            }
+            if trailers.len() == limits.max_headers {
+                return Err(invalid_body(ParseError::TooManyHeaders));
+            }
+            size += line.len() + 2;
+            let (name, value) = parse_trailer(&line).map_err(invalid_body)?;
+            trailers.append(name, value);
+        }
+        if let Some(sender) = self.trailers.take() {
+            if !trailers.is_empty() {
+                let _ = sender.send(trailers);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Hands the next `len` bytes to the app. `false` if it dropped the body.
+    async fn forward<IO>(
+        &self,
+        stream: &mut IO,
+        buf: &mut BytesMut,
+        mut len: u64,
+    ) -> io::Result<bool>
+    where
+        IO: AsyncRead + Unpin,
+    {
+        while len > 0 {
+            if buf.is_empty() {
+                fill(stream, buf).await?;
+            }
+            let n = buf.len().min(usize::try_from(len).unwrap_or(usize::MAX));
+            len -= n as u64;
+            if self
+                .chunks
+                .send(Ok(buf.split_to(n).freeze()))
+                .await
+                .is_err()
+            {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Runs `call` to completion, unless the client closes the connection
+/// first, which drops it.
+async fn unless_closed<IO, F>(
+    call: F,
+    stream: &mut IO,
+    buf: &mut BytesMut,
+    limits: &Limits,
+) -> Option<F::Output>
+where
+    IO: AsyncRead + Unpin,
+    F: Future,
+{
+    tokio::select! {
+        biased;
+        output = call => Some(output),
+        _ = closed(stream, buf, limits.max_head_size) => None,
+    }
+}
+
+/// Resolves once the client closes the connection or it fails. Whatever the
+/// client sends meanwhile, e.g. pipelined requests, is kept in `buf` for
+/// later, and once that's `max` bytes nothing more is read and it never
+/// resolves.
+async fn closed<IO>(stream: &mut IO, buf: &mut BytesMut, max: usize)
+where
+    IO: AsyncRead + Unpin,
+{
+    while buf.len() < max {
+        match stream.read_buf(buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+    }
+    std::future::pending().await
+}
+
+/// The longest line starting a chunk, extensions included.
+const MAX_CHUNK_LINE: usize = 4096;
+
+/// Reads a line of at most `max` bytes and takes it out of `buf`, without
+/// its CRLF.
+async fn read_line<IO>(stream: &mut IO, buf: &mut BytesMut, max: usize) -> io::Result<BytesMut>
+where
+    IO: AsyncRead + Unpin,
+{
+    let mut searched = 0;
+    loop {
+        if let Some(i) = buf[searched..].windows(2).position(|w| w == b"\r\n") {
+            let mut line = buf.split_to(searched + i + 2);
+            line.truncate(searched + i);
+            return Ok(line);
+        }
+        if buf.len() > max + 1 {
+            return Err(invalid_body(ParseError::Chunk));
+        }
+        searched = buf.len().saturating_sub(1);
+        fill(stream, buf).await?;
+    }
+}
+
+/// Reads more into `buf`. The connection closing is an error, as it's only
+/// called in the middle of a body.
+async fn fill<IO>(stream: &mut IO, buf: &mut BytesMut) -> io::Result<()>
+where
+    IO: AsyncRead + Unpin,
+{
+    if stream.read_buf(buf).await? == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+fn invalid_body(error: ParseError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+/// Never resolves if there's no `duration`.
+pub(super) async fn sleep_for(duration: Option<Duration>) {
+    match duration {
+        Some(duration) => sleep(duration).await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn with_timeout<T>(
+    duration: Option<Duration>,
+    future: impl Future<Output = io::Result<T>>,
+) -> io::Result<T> {
+    match duration {
+        Some(duration) => timeout(duration, future)
+            .await
+            .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into())),
+        None => future.await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use tokio::io::{duplex, DuplexStream};
+    use tower::{service_fn, Layer};
+
+    use super::*;
+    use crate::{
+        extract::rejection::LengthLimitError, http::header::CONTENT_LENGTH,
+        middleware::RequestBodyLimitLayer, server::shutdown,
+    };
+
+    fn conn_info() -> ConnInfo {
+        ConnInfo {
+            id: ConnInfo::next_id(),
+            host_and_port: "localhost:3000".to_owned(),
+            local_addr: None,
+            peer_addr: None,
+            peer_cred: None,
+            tls: None,
+        }
+    }
+
+    /// Serves a connection with an app echoing the body of requests, up to
+    /// 16 bytes, and returns the client's end of it.
+    fn connect() -> DuplexStream {
+        let app = RequestBodyLimitLayer::new(16).layer(service_fn(|req: Request| async move {
+            let bytes = req.body.collect().await;
+            Ok::<_, Infallible>(match bytes {
+                Ok(bytes) => bytes.into_response(),
+                Err(e) if e.is::<LengthLimitError>() => {
+                    StatusCode::PAYLOAD_TOO_LARGE.into_response()
+                }
+                Err(_) => StatusCode::BAD_REQUEST.into_response(),
+            })
+        }));
+        let (client, server) = duplex(64 * 1024);
+        let (trigger, shutdown) = shutdown::channel();
+        tokio::spawn(async move {
+            let _trigger = trigger;
+            let _ = serve_connection(
+                server,
+                app,
+                conn_info(),
+                Limits::default(),
+                Timeouts::default(),
+                shutdown,
+            )
+            .await;
+        });
+        client
+    }
+
+    /// Reads until the end of the response head and body, which is short.
+    async fn read_response(client: &mut DuplexStream) -> String {
+        let mut buf = BytesMut::new();
+        let read = async {
+            loop {
+                client.read_buf(&mut buf).await.unwrap();
+                let text = String::from_utf8_lossy(&buf);
+                if let Some(end) = text.find("\r\n\r\n") {
+                    let len = text[..end]
+                        .lines()
+                        .find_map(|line| {
+                            let (name, value) = line.split_once(':')?;
+                            name.eq_ignore_ascii_case(CONTENT_LENGTH.as_str())
+                                .then(|| value.trim().parse::<usize>().ok())?
+                        })
+                        .unwrap_or(0);
+                    if buf.len() >= end + 4 + len {
+                        return text.into_owned();
+                    }
+                }
+            }
+        };
+        timeout(Duration::from_secs(5), read)
+            .await
+            .expect("no response")
+    }
+
+    #[tokio::test]
+    async fn reads_body_sent_with_head() {
+        let mut client = connect();
+        client
+            .write_all(b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhello")
+            .await
+            .unwrap();
+        let resp = read_response(&mut client).await;
+        assert!(resp.starts_with("HTTP/1.1 200 "), "{}", resp);
+        assert!(resp.ends_with("\r\n\r\nhello"), "{}", resp);
+    }
+
+    #[tokio::test]
+    async fn reads_body_sent_after_head() {
+        let mut client = connect();
+        client
+            .write_all(b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 10\r\n\r\nhello")
+            .await
+            .unwrap();
+        sleep(Duration::from_millis(50)).await;
+        client.write_all(b"world").await.unwrap();
+        let resp = read_response(&mut client).await;
+        assert!(resp.ends_with("\r\n\r\nhelloworld"), "{}", resp);
+
+        // The connection is still good for another request.
+        client
+            .write_all(b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 2\r\n\r\nok")
+            .await
+            .unwrap();
+        let resp = read_response(&mut client).await;
+        assert!(resp.ends_with("\r\n\r\nok"), "{}", resp);
+    }
+
+    #[tokio::test]
+    async fn rejects_large_content_length_without_reading_body() {
+        let mut client = connect();
+        client
+            .write_all(b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 10000000000\r\n\r\n")
+            .await
+            .unwrap();
+        // No body is sent: the response can't wait for it.
+        let resp = read_response(&mut client).await;
+        assert!(resp.starts_with("HTTP/1.1 413 "), "{}", resp);
+        assert!(
+            resp.to_ascii_lowercase().contains("connection: close"),
+            "{}",
+            resp
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_chunked_body() {
+        let mut client = connect();
+        client
+            .write_all(b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhel")
+            .await
+            .unwrap();
+        sleep(Duration::from_millis(50)).await;
+        client
+            .write_all(b"lo\r\n6;name=value\r\n world\r\n0\r\nX-Checksum: 1\r\n\r\n")
+            .await
+            .unwrap();
+        let resp = read_response(&mut client).await;
+        assert!(resp.ends_with("\r\n\r\nhello world"), "{}", resp);
+
+        // The trailers were read, so the next request is found.
+        client
+            .write_all(b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 2\r\n\r\nok")
+            .await
+            .unwrap();
+        let resp = read_response(&mut client).await;
+        assert!(resp.ends_with("\r\n\r\nok"), "{}", resp);
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_chunks() {
+        let mut client = connect();
+        client
+            .write_all(b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n")
+            .await
+            .unwrap();
+        let resp = read_response(&mut client).await;
+        assert!(resp.starts_with("HTTP/1.1 400 "), "{}", resp);
+    }
+
+    #[tokio::test]
+    async fn limits_chunked_bodies() {
+        let mut client = connect();
+        client
+            .write_all(b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n")
+            .await
+            .unwrap();
+        client
+            .write_all(b"10\r\n0123456789abcdef\r\n1\r\n!\r\n0\r\n\r\n")
+            .await
+            .unwrap();
+        let resp = read_response(&mut client).await;
+        assert!(resp.starts_with("HTTP/1.1 413 "), "{}", resp);
+    }
+}
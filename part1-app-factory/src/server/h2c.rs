@@ -0,0 +1,572 @@
+//! HTTP/2 over cleartext connections.
+//!
+//! Clients that know the server speaks it, as gRPC clients do, start the
+//! connection with the HTTP/2 preface right away ("prior knowledge").
+//! Others, such as `curl --http2`, send their first request with HTTP/1.1
+//! and ask to switch with `Upgrade: h2c`, as RFC 7540 describes: the server
+//! answers `101 Switching Protocols` and then the request itself, as the
+//! first HTTP/2 stream.
+//!
+//! hyper can't take a request that came before the connection turned into
+//! HTTP/2, so the request is handed to it as the `HEADERS` frame it stands
+//! for, right after the preface and settings the client sends once it
+//! switched. The settings of its `HTTP2-Settings` header go in front of the
+//! latter, as hyper would have to take them before anything else.
+//!
+//! Only requests without a body are upgraded. Those with one are answered
+//! with HTTP/1.1 as if they didn't ask, which the RFC allows.
+
+use std::{
+    fmt, io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use bytes::{Buf, BufMut, BytesMut};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf},
+    time::{timeout_at, Instant},
+};
+use tower::Service;
+
+use super::{
+    compat, conn,
+    conn::{sleep_for, Timeouts},
+    parse::{parse_head, Framing, Head, Limits, ParseError},
+    shutdown::Shutdown,
+};
+use crate::{
+    http::{
+        header::{CONNECTION, HOST},
+        ConnInfo, HeaderMap, Request, Response,
+    },
+    response::IntoResponse,
+};
+
+/// What an HTTP/2 client sends first.
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+const SWITCHING_PROTOCOLS: &[u8] =
+    b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: h2c\r\n\r\n";
+
+/// The largest frame payload an HTTP/2 server has to take, before it
+/// announces a larger one.
+const MAX_FRAME_SIZE: usize = 16 * 1024;
+
+/// Serves a connection with HTTP/2 if it starts with the preface or its
+/// first request asks to upgrade, and with HTTP/1.1 otherwise, by hyper if
+/// `hyper` is set.
+///
+/// A client that sends nothing before the idle timeout or shutdown is
+/// disconnected, as it would be between HTTP/1.1 requests.
+pub(super) async fn serve_connection<IO, App>(
+    stream: IO,
+    app: App,
+    conn_info: ConnInfo,
+    limits: Limits,
+    timeouts: Timeouts,
+    hyper: bool,
+    mut shutdown: Shutdown,
+) -> io::Result<()>
+where
+    IO: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    App: Service<Request, Response = Response> + Send + 'static,
+    App::Error: fmt::Debug,
+    App::Future: Send + 'static,
+{
+    let mut stream = Rewind::new(stream);
+    let http2 = tokio::select! {
+        biased;
+        _ = shutdown.started() => return Ok(()),
+        _ = sleep_for(timeouts.idle) => return Ok(()),
+        http2 = stream.read_preface() => http2?,
+    };
+
+    if http2 {
+        return compat::serve_connection(stream, app, conn_info, limits, timeouts, true, shutdown)
+            .await;
+    }
+
+    let header_deadline = timeouts.header.map(|header| Instant::now() + header);
+    match stream.read_upgrade(&limits, header_deadline).await? {
+        Ok(Some(upgrade)) => {
+            if !stream.upgrade(upgrade, header_deadline).await? {
+                return Ok(());
+            }
+            compat::serve_connection(stream, app, conn_info, limits, timeouts, true, shutdown).await
+        }
+        Ok(None) if hyper => {
+            compat::serve_connection(stream, app, conn_info, limits, timeouts, false, shutdown)
+                .await
+        }
+        Ok(None) => {
+            conn::serve_connection(stream, app, conn_info, limits, timeouts, shutdown).await
+        }
+        Err(error) => conn::refuse_connection(stream, error.into_response()).await,
+    }
+}
+
+/// A request asking to switch to HTTP/2.
+struct Upgrade {
+    /// The length of its head.
+    len: usize,
+    /// The `HEADERS` frame of the request, as the first HTTP/2 stream.
+    headers: Vec<u8>,
+    /// The payload of a `SETTINGS` frame, from `HTTP2-Settings`.
+    settings: Vec<u8>,
+}
+
+impl Upgrade {
+    /// `None` unless `head` asks to upgrade as RFC 7540 has it and can be.
+    fn from_head(head: &Head) -> Option<Self> {
+        let headers = &head.req.headers;
+        if !has_token(headers, "upgrade", "h2c")
+            || !has_token(headers, CONNECTION.as_str(), "upgrade")
+            || !has_token(headers, CONNECTION.as_str(), "http2-settings")
+            || head.framing != Framing::Length(0)
+            || head.expect_continue
+        {
+            return None;
+        }
+        let mut settings = headers.get_all("http2-settings");
+        let (Some(encoded), None) = (settings.next(), settings.next()) else {
+            return None;
+        };
+        // A list of settings, of 6 bytes each.
+        let settings = URL_SAFE_NO_PAD.decode(encoded.as_str()).ok()?;
+        if settings.len() % 6 != 0 {
+            return None;
+        }
+
+        let mut block = Vec::new();
+        let authority = headers.get(HOST).map_or("", |host| host.as_str());
+        for (name, value) in [
+            (":method", head.req.method.as_str()),
+            (":scheme", "http"),
+            (":path", head.req.uri.path_and_query()),
+            (":authority", authority),
+        ] {
+            hpack_literal(&mut block, name, value.as_bytes());
+        }
+        for (name, value) in headers {
+            let name = name.as_str();
+            // Only meant for the HTTP/1.1 connection.
+            let hop_by_hop = matches!(
+                name,
+                "connection"
+                    | "upgrade"
+                    | "http2-settings"
+                    | "keep-alive"
+                    | "proxy-connection"
+                    | "transfer-encoding"
+                    | "host"
+            ) || has_token(headers, CONNECTION.as_str(), name)
+                || (name == "te" && !value.as_str().eq_ignore_ascii_case("trailers"));
+            if !hop_by_hop {
+                hpack_literal(&mut block, name, value.as_bytes());
+            }
+        }
+        if block.len() > MAX_FRAME_SIZE {
+            return None;
+        }
+
+        let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + block.len());
+        write_frame(
+            &mut frame,
+            FrameType::Headers,
+            END_STREAM | END_HEADERS,
+            1,
+            &block,
+        );
+        Some(Upgrade {
+            len: head.len,
+            headers: frame,
+            settings,
+        })
+    }
+}
+
+/// Whether the comma separated values of the headers `name` have `token`.
+fn has_token(headers: &HeaderMap, name: &str, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .flat_map(|value| value.as_str().split(','))
+        .any(|listed| listed.trim().eq_ignore_ascii_case(token))
+}
+
+#[derive(Clone, Copy)]
+enum FrameType {
+    Headers = 0x1,
+    Settings = 0x4,
+}
+
+const FRAME_HEADER_LEN: usize = 9;
+const ACK: u8 = 0x1;
+const END_STREAM: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+
+fn write_frame(out: &mut Vec<u8>, kind: FrameType, flags: u8, stream: u32, payload: &[u8]) {
+    out.put_uint(payload.len() as u64, 3);
+    out.put_u8(kind as u8);
+    out.put_u8(flags);
+    out.put_u32(stream);
+    out.extend_from_slice(payload);
+}
+
+/// Encodes a header field as a literal that isn't added to the dynamic
+/// table, so the client's encoder and the server's decoder still agree on
+/// it afterwards.
+fn hpack_literal(block: &mut Vec<u8>, name: &str, value: &[u8]) {
+    block.put_u8(0);
+    for string in [name.as_bytes(), value] {
+        hpack_int(block, string.len());
+        block.extend_from_slice(string);
+    }
+}
+
+/// Encodes `value` with a 7 bit prefix, as string lengths are.
+fn hpack_int(block: &mut Vec<u8>, mut value: usize) {
+    const PREFIX_MAX: usize = 0x7f;
+    if value < PREFIX_MAX {
+        block.put_u8(value as u8);
+        return;
+    }
+    block.put_u8(PREFIX_MAX as u8);
+    value -= PREFIX_MAX;
+    while value >= 0x80 {
+        block.put_u8((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    block.put_u8(value as u8);
+}
+
+/// A stream that gives back what was read off it to look for the preface
+/// before reading on.
+struct Rewind<IO> {
+    inner: IO,
+    read: BytesMut,
+}
+
+impl<IO> Rewind<IO>
+where
+    IO: AsyncRead + Unpin,
+{
+    fn new(inner: IO) -> Self {
+        Rewind {
+            inner,
+            read: BytesMut::new(),
+        }
+    }
+
+    /// Reads as much of the preface as the client sends, stopping at the
+    /// first byte that differs.
+    async fn read_preface(&mut self) -> io::Result<bool> {
+        while self.read.len() < PREFACE.len() {
+            if !PREFACE.starts_with(&self.read) {
+                return Ok(false);
+            }
+            if self.inner.read_buf(&mut self.read).await? == 0 {
+                return Ok(false);
+            }
+        }
+        Ok(self.read.starts_with(PREFACE))
+    }
+
+    /// Reads the first request head and returns it if it asks to upgrade.
+    /// The only error is the timeout, as the head is read again as HTTP/1.1
+    /// otherwise, and others are answered then.
+    async fn read_upgrade(
+        &mut self,
+        limits: &Limits,
+        deadline: Option<Instant>,
+    ) -> io::Result<Result<Option<Upgrade>, ParseError>> {
+        loop {
+            match parse_head(&self.read, limits) {
+                Ok(Some(head)) => return Ok(Ok(Upgrade::from_head(&head))),
+                Ok(None) => {}
+                Err(_) => return Ok(Ok(None)),
+            }
+            let read = match deadline {
+                Some(deadline) => {
+                    match timeout_at(deadline, self.inner.read_buf(&mut self.read)).await {
+                        Ok(read) => read?,
+                        Err(_) => return Ok(Err(ParseError::Timeout)),
+                    }
+                }
+                None => self.inner.read_buf(&mut self.read).await?,
+            };
+            if read == 0 {
+                return Ok(Ok(None));
+            }
+        }
+    }
+
+    /// Switches to HTTP/2 and waits for the client's preface and the
+    /// `SETTINGS` frame that comes with it, leaving them to be read with the
+    /// request's `HEADERS` frame after them, as if the client had sent it
+    /// that way. `false` if the client doesn't follow up with them in time,
+    /// or they don't fit in a frame with those of the upgrade.
+    async fn upgrade(&mut self, upgrade: Upgrade, deadline: Option<Instant>) -> io::Result<bool>
+    where
+        IO: AsyncWrite,
+    {
+        self.inner.write_all(SWITCHING_PROTOCOLS).await?;
+        self.inner.flush().await?;
+        self.read.advance(upgrade.len);
+
+        let preface = async {
+            // The preface, and the header of the frame after it.
+            let header_end = PREFACE.len() + FRAME_HEADER_LEN;
+            let mut len = None;
+            loop {
+                if len.is_none() && self.read.len() >= header_end {
+                    let header = &self.read[PREFACE.len()..header_end];
+                    if !self.read.starts_with(PREFACE)
+                        || header[3] != FrameType::Settings as u8
+                        || header[4] & ACK != 0
+                    {
+                        return Ok(None);
+                    }
+                    len = Some(header_end + (&header[..3]).get_uint(3) as usize);
+                }
+                if len.is_some_and(|len| self.read.len() >= len) {
+                    return io::Result::Ok(len);
+                }
+                if self.inner.read_buf(&mut self.read).await? == 0 {
+                    return Ok(None);
+                }
+            }
+        };
+        let received = match deadline {
+            Some(deadline) => timeout_at(deadline, preface).await.unwrap_or(Ok(None))?,
+            None => preface.await?,
+        };
+        let Some(len) = received else {
+            return Ok(false);
+        };
+
+        // Were the settings of `HTTP2-Settings` handed to hyper as a frame
+        // of their own, hyper would acknowledge them, which clients don't
+        // expect. Put first, any the client sends again take their place, as
+        // settings are applied in order.
+        let payload = &self.read[PREFACE.len() + FRAME_HEADER_LEN..len];
+        if upgrade.settings.len() + payload.len() > MAX_FRAME_SIZE {
+            return Ok(false);
+        }
+        let mut frames = Vec::new();
+        let settings = [&upgrade.settings[..], payload].concat();
+        write_frame(&mut frames, FrameType::Settings, 0, 0, &settings);
+        frames.extend_from_slice(&upgrade.headers);
+        let rest = self.read.split_off(len);
+        self.read.truncate(PREFACE.len());
+        self.read.extend_from_slice(&frames);
+        self.read.unsplit(rest);
+        Ok(true)
+    }
+}
+
+impl<IO> AsyncRead for Rewind<IO>
+where
+    IO: AsyncRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.read.is_empty() {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        }
+        let n = this.read.len().min(buf.remaining());
+        buf.put_slice(&this.read[..n]);
+        this.read.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<IO> AsyncWrite for Rewind<IO>
+where
+    IO: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, time::Duration};
+
+    use tokio::{
+        io::{duplex, DuplexStream},
+        time::timeout,
+    };
+    use tower::service_fn;
+
+    use super::*;
+    use crate::server::shutdown;
+
+    /// Serves a connection with an app answering with the path and body of
+    /// requests, and returns the client's end of it.
+    fn connect() -> DuplexStream {
+        let app = service_fn(|req: Request| async move {
+            let body = req.body.collect().await.unwrap();
+            let text = format!("{} {}", req.uri.path(), String::from_utf8_lossy(&body));
+            Ok::<_, Infallible>(text.into_response())
+        });
+        let conn_info = ConnInfo {
+            id: ConnInfo::next_id(),
+            host_and_port: "localhost:3000".to_owned(),
+            local_addr: None,
+            peer_addr: None,
+            peer_cred: None,
+            tls: None,
+        };
+        let (client, server) = duplex(64 * 1024);
+        let (trigger, shutdown) = shutdown::channel();
+        tokio::spawn(async move {
+            let _trigger = trigger;
+            let (limits, timeouts) = (Limits::default(), Timeouts::default());
+            serve_connection(server, app, conn_info, limits, timeouts, false, shutdown).await
+        });
+        client
+    }
+
+    /// Reads the next frame: its type, flags, stream and payload.
+    async fn read_frame(client: &mut DuplexStream) -> (u8, u8, u32, Vec<u8>) {
+        let mut header = [0; FRAME_HEADER_LEN];
+        client.read_exact(&mut header).await.unwrap();
+        let mut payload = vec![0; (&header[..3]).get_uint(3) as usize];
+        client.read_exact(&mut payload).await.unwrap();
+        let stream = (&header[5..]).get_u32() & 0x7fff_ffff;
+        (header[3], header[4], stream, payload)
+    }
+
+    /// Reads frames up to the next `DATA` of the first stream and returns
+    /// its payload.
+    async fn read_data(client: &mut DuplexStream) -> Vec<u8> {
+        let read = async {
+            loop {
+                let (kind, _, stream, payload) = read_frame(client).await;
+                if kind == 0x0 && stream == 1 {
+                    return payload;
+                }
+            }
+        };
+        timeout(Duration::from_secs(5), read)
+            .await
+            .expect("no data")
+    }
+
+    const UPGRADE: &[u8] = b"GET /upgraded HTTP/1.1\r\nHost: x\r\n\
+        Connection: Upgrade, HTTP2-Settings\r\nUpgrade: h2c\r\n\
+        HTTP2-Settings: AAMAAABkAAQCAAAAAAIAAAAA\r\n\r\n";
+
+    #[tokio::test]
+    async fn answers_upgraded_request_as_first_stream() {
+        let mut client = connect();
+        client.write_all(UPGRADE).await.unwrap();
+        let mut switched = vec![0; SWITCHING_PROTOCOLS.len()];
+        client.read_exact(&mut switched).await.unwrap();
+        assert_eq!(switched, SWITCHING_PROTOCOLS);
+
+        client.write_all(PREFACE).await.unwrap();
+        let mut settings = Vec::new();
+        write_frame(&mut settings, FrameType::Settings, 0, 0, &[]);
+        client.write_all(&settings).await.unwrap();
+
+        let read = async {
+            let (mut body, mut acks) = (Vec::new(), 0);
+            loop {
+                let (kind, flags, stream, payload) = read_frame(&mut client).await;
+                match kind {
+                    // DATA
+                    0x0 if stream == 1 => {
+                        body.extend_from_slice(&payload);
+                        if flags & END_STREAM != 0 {
+                            return (body, acks);
+                        }
+                    }
+                    0x4 if flags & ACK != 0 => acks += 1,
+                    _ => {}
+                }
+            }
+        };
+        let (body, acks) = timeout(Duration::from_secs(5), read)
+            .await
+            .expect("no response");
+        assert_eq!(body, b"/upgraded ");
+        // Only the settings the client sent after switching are acknowledged.
+        assert_eq!(acks, 1);
+    }
+
+    #[tokio::test]
+    async fn applies_settings_of_the_upgrade() {
+        let mut client = connect();
+        // Sets the initial window size to 4 bytes.
+        let req = b"GET /upgraded HTTP/1.1\r\nHost: x\r\n\
+            Connection: Upgrade, HTTP2-Settings\r\nUpgrade: h2c\r\n\
+            HTTP2-Settings: AAQAAAAE\r\n\r\n";
+        client.write_all(req).await.unwrap();
+        let mut switched = vec![0; SWITCHING_PROTOCOLS.len()];
+        client.read_exact(&mut switched).await.unwrap();
+        client.write_all(PREFACE).await.unwrap();
+        let mut settings = Vec::new();
+        write_frame(&mut settings, FrameType::Settings, 0, 0, &[]);
+        client.write_all(&settings).await.unwrap();
+
+        let data = read_data(&mut client).await;
+        assert_eq!(data, b"/upg");
+
+        // WINDOW_UPDATE, for the rest.
+        let mut update = Vec::new();
+        update.put_uint(4, 3);
+        update.extend_from_slice(&[0x8, 0]);
+        update.put_u32(1);
+        update.put_u32(100);
+        client.write_all(&update).await.unwrap();
+        let data = read_data(&mut client).await;
+        assert_eq!(data, b"raded ");
+    }
+
+    #[tokio::test]
+    async fn answers_requests_with_a_body_with_http1() {
+        let mut client = connect();
+        let req = b"POST /posted HTTP/1.1\r\nHost: x\r\n\
+            Connection: Upgrade, HTTP2-Settings\r\nUpgrade: h2c\r\n\
+            HTTP2-Settings: AAMAAABkAAQCAAAAAAIAAAAA\r\nContent-Length: 2\r\n\r\nhi";
+        client.write_all(req).await.unwrap();
+        let mut resp = Vec::new();
+        let read = async {
+            while !resp.ends_with(b"/posted hi") {
+                client.read_buf(&mut resp).await.unwrap();
+            }
+        };
+        timeout(Duration::from_secs(5), read)
+            .await
+            .expect("no response");
+        assert!(resp.starts_with(b"HTTP/1.1 200 "));
+    }
+
+    #[test]
+    fn encodes_long_lengths() {
+        let mut block = Vec::new();
+        hpack_int(&mut block, 10);
+        hpack_int(&mut block, 1337);
+        assert_eq!(block, [10, 0x7f, 0xba, 0x09]);
+    }
+}
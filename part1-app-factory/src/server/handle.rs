@@ -0,0 +1,75 @@
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use tokio::task::JoinHandle;
+
+use super::Server;
+use crate::{
+    http::{Request, Response},
+    router::IntoMakeService,
+};
+
+/// Serves `app` on `listen` in a task of its own.
+///
+/// `listen` is an address such as `"0.0.0.0:3000"`, a bound
+/// `std::net::TcpListener`, or a [`Server`] configured beyond the defaults.
+/// Every connection is served by a clone of `app`:
+///
+/// ```no_run
+/// # use part1_app_factory::{router::{get, Router}, server::serve};
+/// # async fn index() {}
+/// # async fn run() -> std::io::Result<()> {
+/// let app = Router::new().route("/", get(index));
+/// serve("0.0.0.0:3000", app).await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The server shuts down gracefully on ctrl-c, and then the returned handle
+/// resolves.
+pub fn serve<S>(listen: impl Into<Server>, app: S) -> ServeHandle
+where
+    S: tower::Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Error: std::fmt::Debug + Send,
+    S::Future: Send + 'static,
+{
+    let server = listen.into();
+    ServeHandle {
+        task: tokio::spawn(server.serve(IntoMakeService::new(app))),
+    }
+}
+
+/// A server running in the background, started by [`serve`].
+///
+/// Awaiting it waits for the server to stop and returns why it did, such as
+/// an error binding its address. Dropping it leaves the server running.
+#[derive(Debug)]
+pub struct ServeHandle {
+    task: JoinHandle<io::Result<()>>,
+}
+
+impl ServeHandle {
+    /// Stops the server right away, dropping its connections. Awaiting the
+    /// handle afterwards gives an [`io::ErrorKind::Interrupted`] error.
+    pub fn abort(&self) {
+        self.task.abort();
+    }
+}
+
+impl Future for ServeHandle {
+    type Output = io::Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.task)
+            .poll(cx)
+            .map(|result| match result {
+                Ok(result) => result,
+                Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+                Err(e) => Err(io::Error::new(io::ErrorKind::Interrupted, e)),
+            })
+    }
+}
@@ -0,0 +1,291 @@
+//! Accepting connections on TCP and Unix domain sockets alike.
+
+use std::{
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+
+use bytes::{Buf, Bytes};
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream},
+};
+use tokio_rustls::server::TlsStream;
+use tracing::warn;
+
+use crate::http::{ConnInfo, PeerCred};
+
+/// Where a [`Server`](super::Server) listens.
+#[derive(Clone, Debug)]
+pub(super) enum Addr {
+    Tcp(String),
+    /// Shared, so the server stays `Clone`. Each bind uses its own handle to
+    /// the socket.
+    Listener(Arc<std::net::TcpListener>),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+/// Options for TCP sockets: the listening one and those it accepts.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct SocketOptions {
+    pub(super) nodelay: bool,
+    pub(super) reuse_address: bool,
+    #[cfg(unix)]
+    pub(super) reuse_port: bool,
+    /// How long a connection may be silent before keepalive probes are
+    /// sent. Off if `None`.
+    pub(super) keepalive: Option<Duration>,
+    pub(super) keepalive_interval: Option<Duration>,
+    pub(super) keepalive_retries: Option<u32>,
+    pub(super) backlog: u32,
+}
+
+impl Default for SocketOptions {
+    /// The options `tokio::net::TcpListener::bind` uses.
+    fn default() -> Self {
+        SocketOptions {
+            nodelay: false,
+            reuse_address: cfg!(unix),
+            #[cfg(unix)]
+            reuse_port: false,
+            keepalive: None,
+            keepalive_interval: None,
+            keepalive_retries: None,
+            backlog: 1024,
+        }
+    }
+}
+
+impl SocketOptions {
+    /// Binds the first address `addr` resolves to that can be bound.
+    async fn bind(&self, addr: &str) -> io::Result<TcpListener> {
+        let mut last_error = None;
+        for addr in tokio::net::lookup_host(addr).await? {
+            match self.bind_addr(addr) {
+                Ok(listener) => return Ok(listener),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "could not resolve to any address",
+            )
+        }))
+    }
+
+    fn bind_addr(&self, addr: SocketAddr) -> io::Result<TcpListener> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+        socket.set_reuse_address(self.reuse_address)?;
+        #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+        socket.set_reuse_port(self.reuse_port)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(self.backlog.try_into().unwrap_or(i32::MAX))?;
+        TcpListener::from_std(socket.into())
+    }
+
+    /// Applies the options for single connections to an accepted one.
+    fn configure(&self, stream: &TcpStream) -> io::Result<()> {
+        stream.set_nodelay(self.nodelay)?;
+        let Some(time) = self.keepalive else {
+            return Ok(());
+        };
+        #[allow(unused_mut)]
+        let mut keepalive = TcpKeepalive::new().with_time(time);
+        #[cfg(any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "netbsd",
+            target_vendor = "apple",
+            windows,
+        ))]
+        if let Some(interval) = self.keepalive_interval {
+            keepalive = keepalive.with_interval(interval);
+        }
+        #[cfg(any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "netbsd",
+            target_vendor = "apple",
+        ))]
+        if let Some(retries) = self.keepalive_retries {
+            keepalive = keepalive.with_retries(retries);
+        }
+        SockRef::from(stream).set_tcp_keepalive(&keepalive)
+    }
+}
+
+pub(super) enum Listener {
+    Tcp(TcpListener, SocketOptions),
+    #[cfg(unix)]
+    Unix(UnixListener, PathBuf),
+}
+
+impl Listener {
+    /// Binds `addr`. A listener that's already bound keeps the options it
+    /// was bound with, only those for accepted connections apply to it.
+    pub(super) async fn bind(addr: &Addr, options: SocketOptions) -> io::Result<Self> {
+        match addr {
+            Addr::Tcp(addr) => Ok(Listener::Tcp(options.bind(addr).await?, options)),
+            Addr::Listener(listener) => {
+                let listener = listener.try_clone()?;
+                listener.set_nonblocking(true)?;
+                Ok(Listener::Tcp(TcpListener::from_std(listener)?, options))
+            }
+            #[cfg(unix)]
+            Addr::Unix(path) => {
+                remove_stale_socket(path)?;
+                Ok(Listener::Unix(UnixListener::bind(path)?, path.clone()))
+            }
+        }
+    }
+
+    /// Accepts the next connection, with what's known about it before any
+    /// TLS handshake.
+    pub(super) async fn accept(&self) -> io::Result<(Stream, ConnInfo)> {
+        match self {
+            Listener::Tcp(listener, options) => {
+                let (stream, peer_addr) = listener.accept().await?;
+                if let Err(e) = options.configure(&stream) {
+                    warn!(peer = %peer_addr, error = ?e, "Failed to set socket options");
+                }
+                let local_addr = listener.local_addr()?;
+                let conn_info = ConnInfo {
+                    id: ConnInfo::next_id(),
+                    host_and_port: local_addr.to_string(),
+                    local_addr: Some(local_addr),
+                    peer_addr: Some(peer_addr),
+                    peer_cred: None,
+                    tls: None,
+                };
+                Ok((Stream::Tcp(stream), conn_info))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener, path) => {
+                let (stream, _) = listener.accept().await?;
+                let peer_cred = stream.peer_cred().ok().map(|cred| PeerCred {
+                    uid: cred.uid(),
+                    gid: cred.gid(),
+                    pid: cred.pid(),
+                });
+                let conn_info = ConnInfo {
+                    id: ConnInfo::next_id(),
+                    host_and_port: path.display().to_string(),
+                    local_addr: None,
+                    peer_addr: None,
+                    peer_cred,
+                    tls: None,
+                };
+                Ok((Stream::Unix(stream), conn_info))
+            }
+        }
+    }
+
+    /// Where clients reach the server, for logging.
+    pub(super) fn describe(&self, scheme: &str) -> String {
+        match self {
+            Listener::Tcp(listener, _) => match listener.local_addr() {
+                Ok(addr) => format!("{}://{}", scheme, addr),
+                Err(_) => format!("{}://<unknown address>", scheme),
+            },
+            #[cfg(unix)]
+            Listener::Unix(_, path) => format!("{} ({})", path.display(), scheme),
+        }
+    }
+}
+
+/// Removes a socket file left behind by an earlier run, so the server can be
+/// restarted. Anything else at `path` is left alone and makes binding fail.
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path),
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// An accepted connection.
+pub(super) enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+    Tls(Box<TlsStream<Stream>>),
+    /// A stream that gives back the bytes read off it ahead of time first.
+    Rewound(Bytes, Box<Stream>),
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+            Stream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+            Stream::Rewound(read, stream) if read.is_empty() => Pin::new(stream).poll_read(cx, buf),
+            Stream::Rewound(read, _) => {
+                let n = read.len().min(buf.remaining());
+                buf.put_slice(&read[..n]);
+                read.advance(n);
+                Poll::Ready(Ok(()))
+            }
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+            Stream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+            Stream::Rewound(_, stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+            Stream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+            Stream::Rewound(_, stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+            Stream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+            Stream::Rewound(_, stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
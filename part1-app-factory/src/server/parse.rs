@@ -0,0 +1,453 @@
+//! Parsing HTTP/1.1 request heads as their bytes arrive.
+
+use std::fmt;
+
+use crate::{
+    http::{
+        header::{CONTENT_LENGTH, EXPECT, HOST, TRANSFER_ENCODING},
+        Body, Extensions, HeaderMap, HeaderName, HeaderValue, Method, Request, Response,
+        StatusCode, Version,
+    },
+    response::IntoResponse,
+};
+
+/// Bounds on what a client may send in a request head.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Limits {
+    /// Bytes in the request line and headers together.
+    pub(super) max_head_size: usize,
+    pub(super) max_headers: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_head_size: 64 * 1024,
+            max_headers: 100,
+        }
+    }
+}
+
+/// A parsed request head and how to read the body following it.
+#[derive(Debug)]
+pub(super) struct Head {
+    pub(super) req: Request,
+    /// How many bytes the head took up, blank line included.
+    pub(super) len: usize,
+    pub(super) framing: Framing,
+    /// The client waits for a `100 Continue` before sending the body.
+    pub(super) expect_continue: bool,
+}
+
+/// How the end of a request body is found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Framing {
+    /// After as many bytes as the `Content-Length` says, none without one.
+    Length(u64),
+    /// After the last chunk of `Transfer-Encoding: chunked`.
+    Chunked,
+}
+
+/// Why a request was refused before reaching the app.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum ParseError {
+    RequestLine,
+    Method,
+    Target,
+    Version,
+    Header,
+    HeadTooLarge,
+    TooManyHeaders,
+    ContentLength,
+    TransferEncoding,
+    /// Both `Content-Length` and `Transfer-Encoding`.
+    AmbiguousLength,
+    /// A malformed chunk or trailer in a chunked body.
+    Chunk,
+    /// An `Expect` header asking for something other than `100-continue`.
+    Expectation,
+    /// The head took too long to arrive.
+    Timeout,
+    /// The connection ended in the middle of a request.
+    Incomplete,
+}
+
+impl ParseError {
+    pub(super) fn status(&self) -> StatusCode {
+        match self {
+            ParseError::Version => StatusCode::HTTP_VERSION_NOT_SUPPORTED,
+            ParseError::HeadTooLarge | ParseError::TooManyHeaders => {
+                StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+            }
+            ParseError::TransferEncoding => StatusCode::NOT_IMPLEMENTED,
+            ParseError::Expectation => StatusCode::EXPECTATION_FAILED,
+            ParseError::Timeout => StatusCode::REQUEST_TIMEOUT,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ParseError::RequestLine => "Malformed request line",
+            ParseError::Method => "Invalid method",
+            ParseError::Target => "Invalid request target",
+            ParseError::Version => "Unsupported HTTP version",
+            ParseError::Header => "Malformed header",
+            ParseError::HeadTooLarge => "Request head is too large",
+            ParseError::TooManyHeaders => "Too many headers",
+            ParseError::ContentLength => "Invalid Content-Length header",
+            ParseError::TransferEncoding => "Only chunked Transfer-Encoding is supported",
+            ParseError::AmbiguousLength => "Request has both Content-Length and Transfer-Encoding",
+            ParseError::Chunk => "Malformed chunked body",
+            ParseError::Expectation => "Only the 100-continue expectation is supported",
+            ParseError::Timeout => "Timed out waiting for the request head",
+            ParseError::Incomplete => "Connection closed before the request was complete",
+        })
+    }
+}
+
+impl IntoResponse for ParseError {
+    fn into_response(self) -> Response {
+        (self.status(), self.to_string()).into_response()
+    }
+}
+
+/// Parses the request head at the start of `buf`.
+///
+/// `Ok(None)` means the head isn't complete yet: read more into `buf` and
+/// call this again. Limits are checked on what has arrived so far, so an
+/// oversized head is refused without waiting for all of it.
+pub(super) fn parse_head(buf: &[u8], limits: &Limits) -> Result<Option<Head>, ParseError> {
+    // Clients may send empty lines between requests. They count toward the
+    // head size, or a client sending nothing else would fill up memory.
+    let skipped = buf
+        .iter()
+        .position(|b| !matches!(b, b'\r' | b'\n'))
+        .unwrap_or(buf.len());
+    let buf = &buf[skipped..];
+
+    let len = match buf.windows(4).position(|window| window == b"\r\n\r\n") {
+        Some(end) => end + 4,
+        None => {
+            if skipped + buf.len() > limits.max_head_size {
+                return Err(ParseError::HeadTooLarge);
+            }
+            if count_lines(buf) > limits.max_headers + 1 {
+                return Err(ParseError::TooManyHeaders);
+            }
+            return Ok(None);
+        }
+    };
+    if skipped + len > limits.max_head_size {
+        return Err(ParseError::HeadTooLarge);
+    }
+
+    let head = std::str::from_utf8(&buf[..len - 4]).map_err(|_| ParseError::Header)?;
+    let mut lines = head.split("\r\n");
+    let request_line = lines.next().ok_or(ParseError::RequestLine)?;
+    let (method, target, version) = parse_request_line(request_line)?;
+
+    let mut headers = HeaderMap::new();
+    for line in lines {
+        if headers.len() == limits.max_headers {
+            return Err(ParseError::TooManyHeaders);
+        }
+        let (name, value) = parse_header(line)?;
+        headers.append(name, value);
+    }
+
+    let framing = framing(&headers, version)?;
+    let expect_continue = version == Version::Http11 && expect_continue(&headers)?;
+
+    let uri = match target
+        .strip_prefix("http://")
+        .or_else(|| target.strip_prefix("https://"))
+    {
+        // The absolute form, sent to proxies. Its authority stands in for
+        // the `Host` header.
+        Some(rest) => {
+            let (authority, path) = rest.find('/').map_or((rest, "/"), |i| rest.split_at(i));
+            let host = authority.parse().map_err(|_| ParseError::Target)?;
+            headers.insert(HOST, host);
+            path
+        }
+        None => target,
+    };
+
+    Ok(Some(Head {
+        req: Request {
+            method,
+            uri: uri.into(),
+            version,
+            headers,
+            body: Body::empty(),
+            extensions: Extensions::default(),
+        },
+        len: skipped + len,
+        framing,
+        expect_continue,
+    }))
+}
+
+fn parse_request_line(line: &str) -> Result<(Method, &str, Version), ParseError> {
+    let mut parts = line.split(' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(ParseError::RequestLine);
+    };
+
+    let method = method.parse().map_err(|_| ParseError::Method)?;
+    let valid_target = (target.starts_with('/') || target.contains("://") || target == "*")
+        && target.bytes().all(|b| b.is_ascii_graphic());
+    if !valid_target {
+        return Err(ParseError::Target);
+    }
+    let version = match version.parse() {
+        Ok(version @ (Version::Http10 | Version::Http11)) => version,
+        Ok(_) => return Err(ParseError::Version),
+        Err(_) if version.starts_with("HTTP/") => return Err(ParseError::Version),
+        Err(_) => return Err(ParseError::RequestLine),
+    };
+
+    Ok((method, target, version))
+}
+
+fn parse_header(line: &str) -> Result<(HeaderName, HeaderValue), ParseError> {
+    // Lines folded onto the previous one are obsolete, and whitespace
+    // before the colon has been used to smuggle requests past proxies.
+    let (name, value) = line.split_once(':').ok_or(ParseError::Header)?;
+    let name = name.parse().map_err(|_| ParseError::Header)?;
+    let value = value
+        .trim_matches(|c| c == ' ' || c == '\t')
+        .parse()
+        .map_err(|_| ParseError::Header)?;
+    Ok((name, value))
+}
+
+/// Only the `chunked` transfer coding is understood. A request with both a
+/// `Transfer-Encoding` and a `Content-Length` is refused, as proxies that
+/// disagree on which one counts can be used to smuggle requests.
+fn framing(headers: &HeaderMap, version: Version) -> Result<Framing, ParseError> {
+    if !headers.contains_key(TRANSFER_ENCODING) {
+        return content_length(headers).map(Framing::Length);
+    }
+    if headers.contains_key(CONTENT_LENGTH) {
+        return Err(ParseError::AmbiguousLength);
+    }
+    let mut codings = headers
+        .get_all(TRANSFER_ENCODING)
+        .flat_map(|value| value.as_str().split(','))
+        .map(str::trim)
+        .filter(|coding| !coding.is_empty());
+    match (codings.next(), codings.next()) {
+        (Some(coding), None)
+            if coding.eq_ignore_ascii_case("chunked") && version == Version::Http11 =>
+        {
+            Ok(Framing::Chunked)
+        }
+        _ => Err(ParseError::TransferEncoding),
+    }
+}
+
+/// Parses the line starting a chunk, e.g. `1a;name=value`, without its
+/// CRLF. Extensions are ignored.
+pub(super) fn parse_chunk_size(line: &[u8]) -> Result<u64, ParseError> {
+    let size = line.split(|b| *b == b';').next().unwrap_or_default();
+    let size = std::str::from_utf8(size).map_err(|_| ParseError::Chunk)?;
+    let size = size.trim_end_matches([' ', '\t']);
+    if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(ParseError::Chunk);
+    }
+    u64::from_str_radix(size, 16).map_err(|_| ParseError::Chunk)
+}
+
+/// Parses a trailer field following the last chunk, without its CRLF.
+pub(super) fn parse_trailer(line: &[u8]) -> Result<(HeaderName, HeaderValue), ParseError> {
+    let line = std::str::from_utf8(line).map_err(|_| ParseError::Chunk)?;
+    parse_header(line).map_err(|_| ParseError::Chunk)
+}
+
+/// Several `Content-Length` headers are only accepted if they agree.
+fn content_length(headers: &HeaderMap) -> Result<u64, ParseError> {
+    let mut content_length = None;
+    for value in headers.get_all(CONTENT_LENGTH) {
+        let value = value.as_str();
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ParseError::ContentLength);
+        }
+        let len = value.parse().map_err(|_| ParseError::ContentLength)?;
+        if content_length.is_some_and(|existing| existing != len) {
+            return Err(ParseError::ContentLength);
+        }
+        content_length = Some(len);
+    }
+    Ok(content_length.unwrap_or(0))
+}
+
+/// Whether the client asked for `100 Continue`, and refuses expectations
+/// it can't meet.
+fn expect_continue(headers: &HeaderMap) -> Result<bool, ParseError> {
+    let mut expect_continue = false;
+    for value in headers.get_all(EXPECT) {
+        for expectation in value.as_str().split(',').map(str::trim) {
+            if !expectation.eq_ignore_ascii_case("100-continue") {
+                return Err(ParseError::Expectation);
+            }
+            expect_continue = true;
+        }
+    }
+    Ok(expect_continue)
+}
+
+fn count_lines(buf: &[u8]) -> usize {
+    buf.windows(2).filter(|window| *window == b"\r\n").count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(head: &str) -> Result<Option<Head>, ParseError> {
+        parse_head(head.as_bytes(), &Limits::default())
+    }
+
+    #[test]
+    fn parses_head() {
+        let req = "\r\nPOST /users?page=2 HTTP/1.1\r\nHost: example.com\r\n\
+            Content-Length: 5\r\nX-Tag:  a\t\r\n\r\nhello";
+        let head = parse(req).unwrap().unwrap();
+        assert_eq!(head.req.method, Method::Post);
+        assert_eq!(head.req.uri.path(), "/users");
+        assert_eq!(head.req.uri.query(), Some("page=2"));
+        assert_eq!(head.req.version, Version::Http11);
+        assert_eq!(head.req.headers.get("x-tag").unwrap().as_str(), "a");
+        assert_eq!(head.framing, Framing::Length(5));
+        assert!(!head.expect_continue);
+        // Up to the body, the empty line before the request included.
+        assert_eq!(head.len, req.len() - "hello".len());
+    }
+
+    #[test]
+    fn waits_for_the_rest_of_the_head() {
+        assert!(parse("GET / HTTP/1.1\r\nHost: x\r\n").unwrap().is_none());
+    }
+
+    #[test]
+    fn takes_authority_of_absolute_form() {
+        let head = parse("GET http://example.com:8080 HTTP/1.1\r\nHost: other\r\n\r\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(head.req.uri.path(), "/");
+        assert_eq!(
+            head.req.headers.get(HOST).unwrap().as_str(),
+            "example.com:8080"
+        );
+    }
+
+    #[test]
+    fn frames_bodies() {
+        let head = parse("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(head.framing, Framing::Chunked);
+        let req = "POST / HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 3\r\n\
+            Expect: 100-continue\r\n\r\n";
+        let head = parse(req).unwrap().unwrap();
+        assert_eq!(head.framing, Framing::Length(3));
+        assert!(head.expect_continue);
+    }
+
+    #[test]
+    fn refuses_malformed_heads() {
+        let cases = [
+            ("GET /\r\n\r\n", ParseError::RequestLine),
+            ("GET / HTTP/2.0\r\n\r\n", ParseError::Version),
+            ("GET nowhere HTTP/1.1\r\n\r\n", ParseError::Target),
+            ("GET / HTTP/1.1\r\nHost : x\r\n\r\n", ParseError::Header),
+            ("GET / HTTP/1.1\r\n folded\r\n\r\n", ParseError::Header),
+            (
+                "POST / HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 4\r\n\r\n",
+                ParseError::ContentLength,
+            ),
+            (
+                "POST / HTTP/1.1\r\nContent-Length: +3\r\n\r\n",
+                ParseError::ContentLength,
+            ),
+            (
+                "POST / HTTP/1.1\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n",
+                ParseError::AmbiguousLength,
+            ),
+            (
+                "POST / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n",
+                ParseError::TransferEncoding,
+            ),
+            (
+                "POST / HTTP/1.0\r\nTransfer-Encoding: chunked\r\n\r\n",
+                ParseError::TransferEncoding,
+            ),
+            (
+                "GET / HTTP/1.1\r\nExpect: magic\r\n\r\n",
+                ParseError::Expectation,
+            ),
+        ];
+        for (head, error) in cases {
+            assert_eq!(parse(head).err(), Some(error), "{:?}", head);
+        }
+    }
+
+    #[test]
+    fn refuses_oversized_heads_early() {
+        let limits = Limits {
+            max_head_size: 64,
+            max_headers: 2,
+        };
+        let long = format!("GET /{} HTTP/1.1\r\n", "a".repeat(64));
+        assert_eq!(
+            parse_head(long.as_bytes(), &limits).err(),
+            Some(ParseError::HeadTooLarge)
+        );
+        let many = "GET / HTTP/1.1\r\na: 1\r\nb: 2\r\nc: 3\r\n";
+        assert_eq!(
+            parse_head(many.as_bytes(), &limits).err(),
+            Some(ParseError::TooManyHeaders)
+        );
+    }
+
+    #[test]
+    fn counts_empty_lines_toward_the_head_size() {
+        let limits = Limits {
+            max_head_size: 64,
+            max_headers: 2,
+        };
+        let mut buf = Vec::new();
+        let result = loop {
+            buf.extend_from_slice(b"\r\n");
+            match parse_head(&buf, &limits) {
+                Ok(None) => continue,
+                result => break result,
+            }
+        };
+        assert_eq!(result.err(), Some(ParseError::HeadTooLarge));
+        assert_eq!(buf.len(), 66);
+
+        let head = parse_head(b"\r\n\r\nGET / HTTP/1.1\r\n\r\n", &limits)
+            .unwrap()
+            .unwrap();
+        assert_eq!(head.len, 22);
+    }
+
+    #[test]
+    fn parses_chunk_sizes() {
+        assert_eq!(parse_chunk_size(b"1a"), Ok(26));
+        assert_eq!(parse_chunk_size(b"FF;name=value"), Ok(255));
+        assert_eq!(parse_chunk_size(b"0 "), Ok(0));
+        for line in [&b""[..], b"-1", b"0x10", b" 1", b"ffffffffffffffffff"] {
+            assert_eq!(parse_chunk_size(line), Err(ParseError::Chunk), "{:?}", line);
+        }
+        assert!(parse_trailer(b"X-Checksum: abc").is_ok());
+        assert_eq!(parse_trailer(b"no colon").err(), Some(ParseError::Chunk));
+    }
+}
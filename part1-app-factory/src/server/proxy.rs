@@ -0,0 +1,253 @@
+//! Reading the PROXY protocol header load balancers put in front of a
+//! connection to pass on the client's address.
+//!
+//! Both the text format (v1) and the binary one (v2) are understood. See
+//! <https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt>.
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+
+use bytes::Bytes;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+/// The longest v1 header, `PROXY UNKNOWN` followed by two IPv6 addresses
+/// and ports, CRLF included.
+const V1_MAX_LEN: usize = 107;
+
+/// Reads the PROXY header at the start of `stream`.
+///
+/// Returns the address of the client the proxy accepted the connection
+/// from, or `None` if the proxy didn't say, as it doesn't for its own health
+/// checks, along with what was read past the header. A connection without a
+/// valid header is an error: once a server expects the header, a client
+/// sending its own could claim any address.
+pub(super) async fn read_header<IO>(stream: &mut IO) -> io::Result<(Option<SocketAddr>, Bytes)>
+where
+    IO: AsyncRead + Unpin,
+{
+    let mut start = [0; 6];
+    stream.read_exact(&mut start).await?;
+
+    if start == V1_PREFIX {
+        read_v1(stream).await
+    } else if start == V2_SIGNATURE[..6] {
+        Ok((read_v2(stream).await?, Bytes::new()))
+    } else {
+        Err(invalid("missing PROXY protocol header"))
+    }
+}
+
+async fn read_v1<IO>(stream: &mut IO) -> io::Result<(Option<SocketAddr>, Bytes)>
+where
+    IO: AsyncRead + Unpin,
+{
+    // Reads as much as the longest header at most, as the client may well
+    // send its first request along with it.
+    let mut buf = [0; V1_MAX_LEN - V1_PREFIX.len()];
+    let mut len = 0;
+    let end = loop {
+        if let Some(end) = buf[..len].windows(2).position(|window| window == b"\r\n") {
+            break end;
+        }
+        if len == buf.len() {
+            return Err(invalid("PROXY protocol v1 header is too long"));
+        }
+        match stream.read(&mut buf[len..]).await? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            read => len += read,
+        }
+    };
+    let client = parse_v1(&buf[..end])?;
+    Ok((client, Bytes::copy_from_slice(&buf[end + 2..len])))
+}
+
+fn parse_v1(line: &[u8]) -> io::Result<Option<SocketAddr>> {
+    let line = std::str::from_utf8(line).map_err(|_| invalid("invalid PROXY v1 header"))?;
+    let mut parts = line.split(' ');
+    match parts.next() {
+        Some("UNKNOWN") => Ok(None),
+        Some("TCP4") | Some("TCP6") => {
+            let (Some(src), Some(_dst), Some(src_port), Some(_dst_port), None) = (
+                parts.next(),
+                parts.next(),
+                parts.next(),
+                parts.next(),
+                parts.next(),
+            ) else {
+                return Err(invalid("invalid PROXY v1 header"));
+            };
+            let ip: IpAddr = src
+                .parse()
+                .map_err(|_| invalid("invalid PROXY v1 address"))?;
+            let port: u16 = src_port
+                .parse()
+                .map_err(|_| invalid("invalid PROXY v1 port"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(invalid("invalid PROXY v1 protocol")),
+    }
+}
+
+async fn read_v2<IO>(stream: &mut IO) -> io::Result<Option<SocketAddr>>
+where
+    IO: AsyncRead + Unpin,
+{
+    let mut rest = [0; 10];
+    stream.read_exact(&mut rest).await?;
+    if rest[..6] != V2_SIGNATURE[6..] {
+        return Err(invalid("invalid PROXY v2 signature"));
+    }
+    let version_command = rest[6];
+    let family = rest[7];
+    let len = u16::from_be_bytes([rest[8], rest[9]]) as usize;
+
+    let mut addresses = vec![0; len];
+    stream.read_exact(&mut addresses).await?;
+
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+    match version_command & 0x0F {
+        // LOCAL: the proxy's own connection, e.g. a health check.
+        0 => return Ok(None),
+        1 => {}
+        _ => return Err(invalid("unsupported PROXY v2 command")),
+    }
+
+    // Addresses of other families, such as Unix sockets, aren't of use and
+    // are skipped along with any TLVs after the addresses.
+    match family >> 4 {
+        1 if len >= 12 => {
+            let ip = Ipv4Addr::new(addresses[0], addresses[1], addresses[2], addresses[3]);
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+            Ok(Some(SocketAddr::new(ip.into(), port)))
+        }
+        2 if len >= 36 => {
+            let mut octets = [0; 16];
+            octets.copy_from_slice(&addresses[..16]);
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            Ok(Some(SocketAddr::new(Ipv6Addr::from(octets).into(), port)))
+        }
+        1 | 2 => Err(invalid("truncated PROXY v2 addresses")),
+        _ => Ok(None),
+    }
+}
+
+fn invalid(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read(mut header: &[u8]) -> io::Result<(Option<SocketAddr>, Bytes)> {
+        read_header(&mut header).await
+    }
+
+    fn v2(command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x20 | command, family]);
+        header.extend_from_slice(&(addresses.len() as u16).to_be_bytes());
+        header.extend_from_slice(addresses);
+        header
+    }
+
+    #[tokio::test]
+    async fn reads_v1_headers() {
+        let (client, rest) = read(b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\nGET / HTTP/1.1")
+            .await
+            .unwrap();
+        assert_eq!(client, Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(rest, "GET / HTTP/1.1");
+
+        let (client, rest) = read(b"PROXY TCP6 2001:db8::1 2001:db8::2 56324 443\r\n")
+            .await
+            .unwrap();
+        assert_eq!(client, Some("[2001:db8::1]:56324".parse().unwrap()));
+        assert!(rest.is_empty());
+
+        let (client, _) = read(b"PROXY UNKNOWN ffff:f::1 ffff:f::2 65535 65535\r\n")
+            .await
+            .unwrap();
+        assert_eq!(client, None);
+    }
+
+    #[tokio::test]
+    async fn refuses_malformed_v1_headers() {
+        let longest = format!(
+            "PROXY UNKNOWN {0} {0} 65535 65535\r\n",
+            "ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff"
+        );
+        assert_eq!(longest.len(), V1_MAX_LEN);
+        assert!(read(longest.as_bytes()).await.is_ok());
+        let overlong = longest.replace("UNKNOWN", "UNKNOWN ");
+        let error = read(overlong.as_bytes()).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let error = read(b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443")
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        for header in [
+            &b"PROXY TCP4 192.0.2.1 198.51.100.1 56324\r\n"[..],
+            b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443 1\r\n",
+            b"PROXY TCP4 nowhere 198.51.100.1 56324 443\r\n",
+            b"PROXY UDP4 192.0.2.1 198.51.100.1 56324 443\r\n",
+            b"GET / HTTP/1.1\r\n",
+        ] {
+            let error = read(header).await.unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{:?}", header);
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_v2_headers() {
+        let mut addresses = vec![192, 0, 2, 1, 198, 51, 100, 1];
+        addresses.extend_from_slice(&56324u16.to_be_bytes());
+        addresses.extend_from_slice(&443u16.to_be_bytes());
+        // A TLV after the addresses.
+        addresses.extend_from_slice(&[0x04, 0, 1, 0]);
+        let (client, _) = read(&v2(1, 0x11, &addresses)).await.unwrap();
+        assert_eq!(client, Some("192.0.2.1:56324".parse().unwrap()));
+
+        let mut addresses = [0; 36];
+        addresses[15] = 1;
+        addresses[31] = 2;
+        addresses[32..34].copy_from_slice(&56324u16.to_be_bytes());
+        let (client, _) = read(&v2(1, 0x21, &addresses)).await.unwrap();
+        assert_eq!(client, Some("[::1]:56324".parse().unwrap()));
+
+        // LOCAL, e.g. the proxy's health check.
+        let (client, _) = read(&v2(0, 0x11, &[0; 12])).await.unwrap();
+        assert_eq!(client, None);
+        // Unix sockets.
+        let (client, _) = read(&v2(1, 0x31, &[0; 216])).await.unwrap();
+        assert_eq!(client, None);
+    }
+
+    #[tokio::test]
+    async fn refuses_malformed_v2_headers() {
+        let error = read(&v2(1, 0x11, &[0; 8])).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut header = v2(1, 0x11, &[0; 12]);
+        header.truncate(header.len() - 1);
+        let error = read(&header).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut header = v2(1, 0x11, &[0; 12]);
+        header[10] = b'X';
+        let error = read(&header).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut header = v2(1, 0x11, &[0; 12]);
+        header[12] = 0x11;
+        let error = read(&header).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,73 @@
+//! Telling connections to finish up, and waiting until they have.
+
+use std::time::Duration;
+
+use tokio::sync::{mpsc, watch};
+
+use tracing::error;
+
+/// Starts a shutdown and waits for every [`Shutdown`] to be dropped.
+#[derive(Debug)]
+pub struct Trigger {
+    started: watch::Sender<bool>,
+    finished: mpsc::Receiver<()>,
+}
+
+/// Held by everything a shutdown waits for, e.g. a connection's task, which
+/// should finish soon after [`Shutdown::started`] resolves and then drop it.
+#[derive(Clone, Debug)]
+pub struct Shutdown {
+    started: watch::Receiver<bool>,
+    _in_flight: mpsc::Sender<()>,
+}
+
+pub fn channel() -> (Trigger, Shutdown) {
+    let (started_tx, started_rx) = watch::channel(false);
+    // Nothing is ever sent: `recv` returns `None` once every sender is gone.
+    let (in_flight, finished) = mpsc::channel(1);
+    let trigger = Trigger {
+        started: started_tx,
+        finished,
+    };
+    let shutdown = Shutdown {
+        started: started_rx,
+        _in_flight: in_flight,
+    };
+    (trigger, shutdown)
+}
+
+impl Trigger {
+    /// Tells every [`Shutdown`] that the shutdown started and waits up to
+    /// `deadline` for all of them to be dropped. Returns whether they were.
+    pub async fn shutdown(mut self, deadline: Duration) -> bool {
+        let _ = self.started.send(true);
+        tokio::time::timeout(deadline, self.finished.recv())
+            .await
+            .is_ok()
+    }
+}
+
+impl Shutdown {
+    pub fn is_started(&self) -> bool {
+        *self.started.borrow()
+    }
+
+    /// Resolves once the shutdown started. Cancel safe.
+    pub async fn started(&mut self) {
+        while !*self.started.borrow_and_update() {
+            if self.started.changed().await.is_err() {
+                // The trigger is gone, so nothing is waiting on us anymore.
+                return;
+            }
+        }
+    }
+}
+
+/// Resolves on ctrl-c. If listening for it fails, that's logged and this
+/// never resolves.
+pub async fn ctrl_c() {
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        error!(error = ?e, "Failed to listen for ctrl-c");
+        std::future::pending::<()>().await;
+    }
+}
@@ -0,0 +1,167 @@
+//! Writing HTTP/1.1 responses onto a connection.
+
+use std::io;
+
+use futures_util::StreamExt;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::http::{
+    header::{CONNECTION, CONTENT_LENGTH, TRANSFER_ENCODING},
+    HeaderMap, HeaderValue, Method, Request, Response, StatusCode, Version,
+};
+
+/// What the request says about how to answer it.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct RequestInfo {
+    pub(super) method: Method,
+    pub(super) version: Version,
+    pub(super) keep_alive: bool,
+}
+
+impl RequestInfo {
+    pub(super) fn new(req: &Request) -> Self {
+        RequestInfo {
+            method: req.method,
+            version: req.version,
+            keep_alive: wants_keep_alive(req.version, &req.headers),
+        }
+    }
+
+    /// For responses to requests that couldn't be parsed, after which the
+    /// connection is closed.
+    pub(super) fn closing() -> Self {
+        RequestInfo {
+            method: Method::Get,
+            version: Version::Http11,
+            keep_alive: false,
+        }
+    }
+}
+
+/// Writes `resp` for the request described by `info` and returns whether
+/// the connection can be kept open for another request.
+///
+/// Bodies of known length get a `Content-Length`. Others are sent chunked to
+/// HTTP/1.1 clients and delimited by closing the connection for HTTP/1.0
+/// ones. So are bodies with trailers, which follow the last chunk. Responses
+/// to `HEAD` requests and 1xx, 204 and 304 responses have no body.
+pub(super) async fn write_response<S>(
+    stream: &mut S,
+    resp: Response,
+    info: RequestInfo,
+) -> io::Result<bool>
+where
+    S: AsyncWrite + Unpin,
+{
+    let Response {
+        status,
+        mut headers,
+        mut body,
+        ..
+    } = resp;
+
+    let has_body = info.method != Method::Head && status_has_body(status);
+    let mut keep_alive = info.keep_alive && !has_token(&headers, "close");
+    let len = body
+        .size_hint()
+        .exact()
+        .filter(|_| !(body.has_trailers() && info.version.supports_chunked_encoding()));
+    let chunked = match len {
+        _ if !status_has_body(status) => {
+            headers.remove(CONTENT_LENGTH);
+            false
+        }
+        Some(len) => {
+            // A `HEAD` response keeps the length the app gave it, which is
+            // the length of the `GET` response.
+            if has_body || (len > 0 && !headers.contains_key(CONTENT_LENGTH)) {
+                headers.insert(CONTENT_LENGTH, len.into());
+            }
+            false
+        }
+        None if info.version.supports_chunked_encoding() => {
+            headers.remove(CONTENT_LENGTH);
+            headers.insert(TRANSFER_ENCODING, HeaderValue::from_static("chunked"));
+            true
+        }
+        None => {
+            headers.remove(CONTENT_LENGTH);
+            keep_alive = false;
+            false
+        }
+    };
+
+    if !keep_alive {
+        headers.insert(CONNECTION, HeaderValue::from_static("close"));
+    } else if !info.version.is_keep_alive_default() {
+        headers.insert(CONNECTION, HeaderValue::from_static("keep-alive"));
+    }
+
+    let mut head = format!("{} {}\r\n", Version::Http11, status).into_bytes();
+    write_headers(&mut head, &headers);
+    head.extend_from_slice(b"\r\n");
+    stream.write_all(&head).await?;
+
+    if has_body {
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(io::Error::other)?;
+            if chunk.is_empty() {
+                continue;
+            }
+            if chunked {
+                stream
+                    .write_all(format!("{:X}\r\n", chunk.len()).as_bytes())
+                    .await?;
+                stream.write_all(&chunk).await?;
+                stream.write_all(b"\r\n").await?;
+            } else {
+                stream.write_all(&chunk).await?;
+            }
+        }
+        if chunked {
+            let mut end = b"0\r\n".to_vec();
+            if let Some(trailers) = body.trailers().await.map_err(io::Error::other)? {
+                write_headers(&mut end, &trailers);
+            }
+            end.extend_from_slice(b"\r\n");
+            stream.write_all(&end).await?;
+        }
+    }
+    stream.flush().await?;
+
+    Ok(keep_alive)
+}
+
+fn write_headers(buf: &mut Vec<u8>, headers: &HeaderMap) {
+    for (name, value) in headers {
+        buf.extend_from_slice(name.as_str().as_bytes());
+        buf.extend_from_slice(b": ");
+        buf.extend_from_slice(value.as_bytes());
+        buf.extend_from_slice(b"\r\n");
+    }
+}
+
+/// HTTP/1.1 connections stay open unless the client asks to close them,
+/// HTTP/1.0 ones only if it asks to keep them.
+fn wants_keep_alive(version: Version, headers: &HeaderMap) -> bool {
+    if version.is_keep_alive_default() {
+        !has_token(headers, "close")
+    } else {
+        has_token(headers, "keep-alive")
+    }
+}
+
+/// Whether a `Connection` header lists `token`.
+fn has_token(headers: &HeaderMap, token: &str) -> bool {
+    headers.get_all(CONNECTION).any(|value| {
+        value
+            .as_str()
+            .split(',')
+            .any(|item| item.trim().eq_ignore_ascii_case(token))
+    })
+}
+
+fn status_has_body(status: StatusCode) -> bool {
+    let code = status.as_u16();
+    !(100..200).contains(&code) && code != 204 && code != 304
+}
@@ -0,0 +1,297 @@
+//! Apps and app factories made from functions, for
+//! [`serve`](crate::server::serve) and [`Server`](crate::server::Server).
+
+use std::{future::Future, pin::Pin};
+
+use crate::{
+    extract::Connected,
+    http::{ConnInfo, Request, Response},
+    middleware::InFlightLimitLayer,
+    router::{IntoMakeService, IntoMakeServiceWithConnectInfo},
+};
+use anyhow::Error;
+use tower::{
+    layer::util::Stack,
+    util::{BoxCloneService, MapErrLayer, MapRequestLayer, MapResponseLayer},
+    Layer, Service, ServiceExt,
+};
+
+pub struct AppFactoryFn<F> {
+    f: F,
+}
+
+pub fn app_factory_fn<F, Ret, App>(f: F) -> AppFactoryFn<F>
+where
+    F: FnMut(ConnInfo) -> Ret,
+    Ret: Future<Output = Result<App, Error>>,
+{
+    AppFactoryFn { f }
+}
+
+impl<F> AppFactoryFn<F> {
+    /// Wraps every app the factory makes with `layer`.
+    pub fn layer<L>(self, layer: L) -> LayeredAppFactory<Self, L> {
+        LayeredAppFactory {
+            factory: self,
+            layer,
+        }
+    }
+
+    /// Lets the app of each connection work on at most `max` requests
+    /// at once, the connection waiting for one to be answered before
+    /// its next is read.
+    pub fn concurrency_limit(self, max: usize) -> LayeredAppFactory<Self, InFlightLimitLayer> {
+        self.layer(InFlightLimitLayer::new(max))
+    }
+}
+
+impl<F, Ret, App> Service<ConnInfo> for AppFactoryFn<F>
+where
+    F: FnMut(ConnInfo) -> Ret,
+    Ret: Future<Output = Result<App, Error>>,
+{
+    type Response = App;
+    type Error = Error;
+    type Future = Ret;
+
+    fn poll_ready(
+        &mut self,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, conn_info: ConnInfo) -> Self::Future {
+        (self.f)(conn_info)
+    }
+}
+
+#[derive(Clone)]
+pub struct AppFn<F> {
+    f: F,
+}
+
+pub fn app_fn<F, Ret>(f: F) -> AppFn<F>
+where
+    F: FnMut(Request) -> Ret,
+    Ret: Future<Output = Result<Response, Error>>,
+{
+    AppFn { f }
+}
+
+impl<F> AppFn<F> {
+    /// An app factory serving every connection with a clone of this app.
+    pub fn into_make_service(self) -> IntoMakeService<Self> {
+        IntoMakeService::new(self)
+    }
+
+    /// Like [`AppFn::into_make_service`], adding a `ConnectInfo<C>` to
+    /// every request.
+    pub fn into_make_service_with_connect_info<C: Connected>(
+        self,
+    ) -> IntoMakeServiceWithConnectInfo<Self, C> {
+        IntoMakeServiceWithConnectInfo::new(self)
+    }
+
+    /// Wraps the app with `layer`, e.g. a tower `ConcurrencyLimitLayer`.
+    pub fn layer<L, Ret>(self, layer: L) -> BoxApp
+    where
+        F: FnMut(Request) -> Ret + Clone + Send + 'static,
+        Ret: Future<Output = Result<Response, Error>> + Send + 'static,
+        L: Layer<Self>,
+        L::Service: Service<Request, Response = Response> + Clone + Send + 'static,
+        <L::Service as Service<Request>>::Error: Into<Error>,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        BoxApp::new(layer.layer(self))
+    }
+
+    /// Transforms requests with `f` before the app gets them.
+    pub fn map_request<G, Ret>(self, f: G) -> BoxApp
+    where
+        F: FnMut(Request) -> Ret + Clone + Send + 'static,
+        Ret: Future<Output = Result<Response, Error>> + Send + 'static,
+        G: FnMut(Request) -> Request + Clone + Send + 'static,
+    {
+        BoxApp::new(self).map_request(f)
+    }
+
+    /// Transforms the app's responses with `f`.
+    pub fn map_response<G, Ret>(self, f: G) -> BoxApp
+    where
+        F: FnMut(Request) -> Ret + Clone + Send + 'static,
+        Ret: Future<Output = Result<Response, Error>> + Send + 'static,
+        G: FnMut(Response) -> Response + Clone + Send + 'static,
+    {
+        BoxApp::new(self).map_response(f)
+    }
+
+    /// Transforms the app's errors with `f`.
+    pub fn map_err<G, E, Ret>(self, f: G) -> BoxApp
+    where
+        F: FnMut(Request) -> Ret + Clone + Send + 'static,
+        Ret: Future<Output = Result<Response, Error>> + Send + 'static,
+        G: FnOnce(Error) -> E + Clone + Send + 'static,
+        E: Into<Error>,
+    {
+        BoxApp::new(self).map_err(f)
+    }
+}
+
+impl<F, Ret> Service<Request> for AppFn<F>
+where
+    F: FnMut(Request) -> Ret,
+    Ret: Future<Output = Result<Response, Error>>,
+{
+    type Response = Response;
+    type Error = Error;
+    type Future = Ret;
+
+    fn poll_ready(
+        &mut self,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        (self.f)(req)
+    }
+}
+
+/// An app wrapped in layers, with its type erased so it can be named,
+/// e.g. as the app of an app factory.
+#[derive(Clone)]
+pub struct BoxApp(BoxCloneService<Request, Response, Error>);
+
+impl BoxApp {
+    pub fn new<S>(app: S) -> Self
+    where
+        S: Service<Request, Response = Response> + Clone + Send + 'static,
+        S::Error: Into<Error>,
+        S::Future: Send + 'static,
+    {
+        BoxApp(BoxCloneService::new(app.map_err(Into::into)))
+    }
+
+    /// Wraps the app with another layer, outside those already around
+    /// it.
+    pub fn layer<L>(self, layer: L) -> BoxApp
+    where
+        L: Layer<Self>,
+        L::Service: Service<Request, Response = Response> + Clone + Send + 'static,
+        <L::Service as Service<Request>>::Error: Into<Error>,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        BoxApp::new(layer.layer(self))
+    }
+
+    /// Transforms requests with `f` before the app gets them.
+    pub fn map_request<F>(self, f: F) -> BoxApp
+    where
+        F: FnMut(Request) -> Request + Clone + Send + 'static,
+    {
+        self.layer(MapRequestLayer::new(f))
+    }
+
+    /// Transforms the app's responses with `f`.
+    pub fn map_response<F>(self, f: F) -> BoxApp
+    where
+        F: FnMut(Response) -> Response + Clone + Send + 'static,
+    {
+        self.layer(MapResponseLayer::new(f))
+    }
+
+    /// Transforms the app's errors with `f`, e.g. to add context to them.
+    pub fn map_err<F, E>(self, f: F) -> BoxApp
+    where
+        F: FnOnce(Error) -> E + Clone + Send + 'static,
+        E: Into<Error>,
+    {
+        self.layer(MapErrLayer::new(f))
+    }
+
+    /// An app factory serving every connection with a clone of this app.
+    pub fn into_make_service(self) -> IntoMakeService<Self> {
+        IntoMakeService::new(self)
+    }
+
+    /// Like [`BoxApp::into_make_service`], adding a `ConnectInfo<C>` to
+    /// every request.
+    pub fn into_make_service_with_connect_info<C: Connected>(
+        self,
+    ) -> IntoMakeServiceWithConnectInfo<Self, C> {
+        IntoMakeServiceWithConnectInfo::new(self)
+    }
+}
+
+impl Service<Request> for BoxApp {
+    type Response = Response;
+    type Error = Error;
+    type Future = <BoxCloneService<Request, Response, Error> as Service<Request>>::Future;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        self.0.call(req)
+    }
+}
+
+/// An app factory whose apps are wrapped with a layer, made with
+/// [`AppFactoryFn::layer`].
+pub struct LayeredAppFactory<S, L> {
+    factory: S,
+    layer: L,
+}
+
+impl<S, L> LayeredAppFactory<S, L> {
+    /// Wraps the apps with another layer, outside those already around
+    /// them.
+    pub fn layer<Outer>(self, layer: Outer) -> LayeredAppFactory<S, Stack<L, Outer>> {
+        LayeredAppFactory {
+            factory: self.factory,
+            layer: Stack::new(self.layer, layer),
+        }
+    }
+
+    /// Like [`AppFactoryFn::concurrency_limit`], outside the layers
+    /// already around the apps.
+    pub fn concurrency_limit(
+        self,
+        max: usize,
+    ) -> LayeredAppFactory<S, Stack<L, InFlightLimitLayer>> {
+        self.layer(InFlightLimitLayer::new(max))
+    }
+}
+
+impl<S, L> Service<ConnInfo> for LayeredAppFactory<S, L>
+where
+    S: Service<ConnInfo>,
+    S::Future: Send + 'static,
+    L: Layer<S::Response> + Clone + Send + 'static,
+{
+    type Response = L::Service;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<L::Service, S::Error>> + Send>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.factory.poll_ready(cx)
+    }
+
+    fn call(&mut self, conn_info: ConnInfo) -> Self::Future {
+        let app = self.factory.call(conn_info);
+        let layer = self.layer.clone();
+        Box::pin(async move {
+            let app = app.await?;
+            Ok(layer.layer(app))
+        })
+    }
+}
@@ -0,0 +1,184 @@
+//! The derive macros, used from outside the crate the way apps use them.
+
+use tower::ServiceExt;
+
+use part1_app_factory::{
+    extract::{rejection::PathRejection, FromRequest, FromRequestParts},
+    http::{Method, Request, Response},
+    response::IntoResponse,
+    router::{get, post, PathParams, Router, TypedPath},
+};
+
+#[derive(TypedPath, Debug, PartialEq)]
+#[typed_path("/users/:id/posts/:post_id")]
+struct PostPath {
+    id: u64,
+    post_id: u64,
+}
+
+#[derive(TypedPath, Debug, PartialEq)]
+#[typed_path("/files/:name/")]
+struct DirPath {
+    name: String,
+}
+
+#[derive(TypedPath, Debug, PartialEq)]
+#[typed_path("/health")]
+struct HealthPath;
+
+/// A post and how it was asked for.
+#[derive(FromRequestParts)]
+struct Post {
+    path: PostPath,
+    method: Method,
+}
+
+/// A comment on a post, the body being its text.
+#[derive(FromRequest)]
+struct Comment {
+    path: PostPath,
+    text: String,
+}
+
+/// A post, rejected with the app's own error.
+#[derive(FromRequestParts)]
+#[from_request(rejection(ApiError))]
+struct StrictPost {
+    path: PostPath,
+}
+
+struct ApiError(String);
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        ApiError(rejection.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        format!("API error: {}", self.0).into_response()
+    }
+}
+
+fn request(method: Method, uri: &str, headers: &[(&str, &str)], body: &'static str) -> Request {
+    let mut req = Request::builder().method(method).uri(uri);
+    for &(name, value) in headers {
+        req = req.header(name, value);
+    }
+    req.body(body).unwrap()
+}
+
+/// The status and body of `resp`.
+async fn respond(resp: Response) -> (u16, String) {
+    let body = resp.body.collect().await.unwrap();
+    (
+        resp.status.as_u16(),
+        String::from_utf8(body.to_vec()).unwrap(),
+    )
+}
+
+/// Routes `uri` to a handler answering with the typed path it extracted,
+/// rendered again.
+async fn round_trip(uri: &str) -> (u16, String) {
+    let app = Router::new()
+        .typed_route::<PostPath>(get(|path: PostPath| async move { path.to_string() }))
+        .typed_route::<DirPath>(get(|path: DirPath| async move { path.to_string() }))
+        .typed_route::<HealthPath>(get(|path: HealthPath| async move { path.to_string() }));
+    let resp = app.oneshot(request(Method::Get, uri, &[], "")).await;
+    respond(resp.unwrap()).await
+}
+
+#[test]
+fn renders_typed_paths() {
+    assert_eq!(PostPath::PATH, "/users/:id/posts/:post_id");
+    assert_eq!(
+        PostPath { id: 7, post_id: 42 }.to_string(),
+        "/users/7/posts/42"
+    );
+    assert_eq!(
+        DirPath {
+            name: "docs".to_owned()
+        }
+        .to_string(),
+        "/files/docs/"
+    );
+    assert_eq!(HealthPath.to_string(), "/health");
+}
+
+#[tokio::test]
+async fn extracts_the_paths_they_render() {
+    let path = PostPath { id: 7, post_id: 42 }.to_string();
+    assert_eq!(round_trip(&path).await, (200, path));
+
+    let path = DirPath {
+        name: "docs".to_owned(),
+    }
+    .to_string();
+    assert_eq!(round_trip(&path).await, (200, path));
+    assert_eq!(round_trip("/health").await, (200, "/health".to_owned()));
+}
+
+#[tokio::test]
+async fn rejects_captures_that_do_not_parse() {
+    let error = PostPath::from_params(&PathParams::default()).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Invalid URL: Missing path parameter `id`"
+    );
+
+    let (status, body) = round_trip("/users/seven/posts/42").await;
+    assert_eq!(status, 400);
+    assert_eq!(
+        body,
+        "Invalid URL: Cannot parse `id` with value `seven` as `u64`"
+    );
+}
+
+/// Routes `req` to handlers taking the derived extractors.
+async fn extract(req: Request) -> (u16, String) {
+    let app = Router::new()
+        .typed_route::<PostPath>(
+            get(|post: Post| async move { format!("{:?} {}", post.method, post.path) }).post(
+                |comment: Comment| async move { format!("{}: {}", comment.path, comment.text) },
+            ),
+        )
+        .route(
+            "/strict/users/:id/posts/:post_id",
+            post(|post: StrictPost| async move { post.path.to_string() }),
+        );
+    respond(app.oneshot(req).await.unwrap()).await
+}
+
+#[tokio::test]
+async fn derives_extractors_from_their_fields() {
+    let req = request(Method::Get, "/users/7/posts/42", &[], "");
+    assert_eq!(
+        extract(req).await,
+        (200, "Get /users/7/posts/42".to_owned())
+    );
+
+    let req = request(Method::Post, "/users/7/posts/42", &[], "Nice post!");
+    assert_eq!(
+        extract(req).await,
+        (200, "/users/7/posts/42: Nice post!".to_owned())
+    );
+}
+
+#[tokio::test]
+async fn rejects_with_the_first_field_that_fails() {
+    let req = request(Method::Post, "/users/seven/posts/42", &[], "Nice post!");
+    let (status, body) = extract(req).await;
+    assert_eq!(status, 400);
+    assert_eq!(
+        body,
+        "Invalid URL: Cannot parse `id` with value `seven` as `u64`"
+    );
+
+    let req = request(Method::Post, "/strict/users/seven/posts/42", &[], "");
+    let (_, body) = extract(req).await;
+    assert_eq!(
+        body,
+        "API error: Invalid URL: Cannot parse `id` with value `seven` as `u64`"
+    );
+}